@@ -0,0 +1,210 @@
+//! Persistence throughput at several scales.
+//!
+//! Run with `cargo bench`. Each benchmark starts from a fresh in-memory store and only
+//! times the operation itself.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bdk_chain::bitcoin::{
+    Amount, BlockHash, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Txid, absolute, hashes::Hash,
+    transaction,
+};
+use bdk_chain::{
+    BlockId, ConfirmationBlockTime, DescriptorId, keychain_txout, local_chain, tx_graph,
+};
+use bdk_sqlite::Store;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use tokio::runtime::Runtime;
+
+const SCALES: [u32; 3] = [1_000, 10_000, 100_000];
+
+/// A chain of `count` blocks and a graph of `count` transactions, each anchored to one
+/// of the blocks.
+fn synthetic(
+    count: u32,
+) -> (
+    local_chain::ChangeSet,
+    tx_graph::ChangeSet<ConfirmationBlockTime>,
+) {
+    let mut chain = local_chain::ChangeSet::default();
+    let mut graph = tx_graph::ChangeSet::default();
+    for i in 0..count {
+        let hash = BlockHash::hash(&i.to_le_bytes());
+        chain.blocks.insert(i, Some(hash));
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&i.to_be_bytes()), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(u64::from(i) + 1_000),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+                },
+                TxOut {
+                    value: Amount::from_sat(546),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0x52]),
+                },
+            ],
+        };
+        graph.anchors.insert((
+            ConfirmationBlockTime {
+                block_id: BlockId { height: i, hash },
+                confirmation_time: u64::from(i),
+            },
+            tx.compute_txid(),
+        ));
+        graph.first_seen.insert(tx.compute_txid(), u64::from(i));
+        graph.txs.insert(Arc::new(tx));
+    }
+    (chain, graph)
+}
+
+/// An spk cache of `count` scripts of one descriptor, as persisted by a freshly created
+/// wallet with a big lookahead.
+fn spk_cache(count: u32) -> keychain_txout::ChangeSet {
+    let mut indexer = keychain_txout::ChangeSet::default();
+    indexer.spk_cache.insert(
+        DescriptorId::hash(b"descriptor"),
+        (0..count)
+            .map(|i| {
+                let mut script = vec![0x00, 0x14];
+                script.extend_from_slice(&Txid::hash(&i.to_le_bytes())[..20]);
+                (i, ScriptBuf::from_bytes(script))
+            })
+            .collect(),
+    );
+    indexer
+}
+
+async fn store() -> Store {
+    let store = Store::new_memory().await.expect("store must open");
+    store.migrate().await.expect("migrations must apply");
+    store
+}
+
+/// Time `op` on a fresh store prepared by `setup`, `iters` times.
+async fn timed<S, O>(iters: u64, setup: S, op: O) -> Duration
+where
+    S: AsyncFn(&Store),
+    O: AsyncFn(&Store),
+{
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let store = store().await;
+        setup(&store).await;
+        let start = Instant::now();
+        op(&store).await;
+        total += start.elapsed();
+    }
+    total
+}
+
+fn persistence(c: &mut Criterion) {
+    let rt = Runtime::new().expect("runtime must start");
+    let mut group = c.benchmark_group("persistence");
+    group.sample_size(10);
+
+    for count in SCALES {
+        let (chain, graph) = synthetic(count);
+        let txs = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: graph.txs.clone(),
+            first_seen: graph.first_seen.clone(),
+            ..Default::default()
+        };
+        let anchors = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            anchors: graph.anchors.clone(),
+            ..Default::default()
+        };
+        group.throughput(Throughput::Elements(count.into()));
+
+        group.bench_with_input(BenchmarkId::new("tx_insert", count), &txs, |b, txs| {
+            b.to_async(&rt).iter_custom(|iters| {
+                timed(
+                    iters,
+                    async |_| {},
+                    async |store| {
+                        store.write_tx_graph(txs).await.expect("write must succeed");
+                    },
+                )
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("anchor_insert", count),
+            &anchors,
+            |b, anchors| {
+                b.to_async(&rt).iter_custom(|iters| {
+                    timed(
+                        iters,
+                        async |store| {
+                            store
+                                .write_local_chain(&chain)
+                                .await
+                                .expect("write must succeed");
+                            store
+                                .write_tx_graph(&txs)
+                                .await
+                                .expect("write must succeed");
+                        },
+                        async |store| {
+                            store
+                                .write_tx_graph(anchors)
+                                .await
+                                .expect("write must succeed");
+                        },
+                    )
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("spk_cache_insert", count),
+            &spk_cache(count),
+            |b, indexer| {
+                b.to_async(&rt).iter_custom(|iters| {
+                    timed(
+                        iters,
+                        async |_| {},
+                        async |store| {
+                            store
+                                .write_keychain_txout(indexer)
+                                .await
+                                .expect("write must succeed");
+                        },
+                    )
+                })
+            },
+        );
+
+        group.bench_function(BenchmarkId::new("full_read", count), |b| {
+            b.to_async(&rt).iter_custom(|iters| {
+                timed(
+                    iters,
+                    async |store| {
+                        store
+                            .write_local_chain(&chain)
+                            .await
+                            .expect("write must succeed");
+                        store
+                            .write_tx_graph(&graph)
+                            .await
+                            .expect("write must succeed");
+                    },
+                    async |store| {
+                        let changeset = store.read_changeset().await.expect("read must succeed");
+                        assert_eq!(changeset.tx_graph.txs.len(), count as usize);
+                    },
+                )
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, persistence);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+-- 0003_schema_up.sql
+
+-- Label table
+--
+-- Follows the BIP-329 `type` and `ref` conventions, e.g. `tx` labels reference a txid
+-- and `output` labels reference an outpoint of the form `txid:vout`.
+CREATE TABLE IF NOT EXISTS label(
+    type TEXT NOT NULL,
+    ref TEXT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY(type, ref)
+);
+
+-- Index the anchor table by txid
+CREATE INDEX IF NOT EXISTS anchor_txid ON anchor(txid);
@@ -0,0 +1,17 @@
+-- 0004_schema_up.sql
+
+-- Multipath descriptor table
+CREATE TABLE IF NOT EXISTS multipath_descriptor(
+    descriptor TEXT NOT NULL,
+    PRIMARY KEY(descriptor)
+);
+
+-- Multipath descriptor expansion table
+--
+-- Each row is the single-path descriptor at `path_index` of the multipath descriptor.
+CREATE TABLE IF NOT EXISTS multipath_descriptor_path(
+    path_index INTEGER NOT NULL,
+    descriptor_id TEXT NOT NULL,
+    descriptor TEXT NOT NULL,
+    PRIMARY KEY(path_index)
+);
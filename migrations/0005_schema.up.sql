@@ -0,0 +1,12 @@
+-- 0005_schema_up.sql
+
+-- Orphaned block table
+--
+-- Blocks removed from the block table are recorded here along with the (unix) time of
+-- their removal when block tombstones are enabled.
+CREATE TABLE IF NOT EXISTS block_orphaned(
+    height INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    orphaned_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS block_orphaned_height ON block_orphaned(height);
@@ -0,0 +1,27 @@
+-- 0006_schema_up.sql
+
+-- ************************************************************* --
+-- Canonicalize anchor rows to lowercase hex and dedupe them on  --
+-- (block_height, block_hash, txid) keeping the earliest         --
+-- confirmation_time.                                            --
+-- ************************************************************* --
+
+-- Create new table
+CREATE TABLE IF NOT EXISTS anchor_new(
+    block_height INTEGER NOT NULL,
+    block_hash TEXT NOT NULL CHECK(block_hash = lower(block_hash)),
+    txid TEXT NOT NULL CHECK(txid = lower(txid)),
+    confirmation_time INTEGER NOT NULL,
+    PRIMARY KEY(block_height, block_hash, txid)
+);
+-- Copy deduped data
+INSERT INTO anchor_new(block_height, block_hash, txid, confirmation_time)
+SELECT block_height, lower(block_hash), lower(txid), MIN(confirmation_time)
+FROM anchor
+GROUP BY block_height, lower(block_hash), lower(txid);
+-- Drop old table
+DROP TABLE anchor;
+-- Rename new table to old
+ALTER TABLE anchor_new RENAME TO anchor;
+-- Recreate index
+CREATE INDEX IF NOT EXISTS anchor_txid ON anchor(txid);
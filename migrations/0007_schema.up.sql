@@ -0,0 +1,11 @@
+-- 0007_schema_up.sql
+
+-- Application data table
+--
+-- Namespaced key-value store for applications. Values are JSON text.
+CREATE TABLE IF NOT EXISTS app_data(
+    namespace TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY(namespace, key)
+);
@@ -0,0 +1,16 @@
+-- 0008_schema_up.sql
+
+-- Time (unix) at which the keychain descriptor became active, NULL if unknown
+ALTER TABLE keychain ADD COLUMN activated_at INTEGER;
+
+-- Keychain history table
+--
+-- Descriptors which were replaced by `Store::rotate_descriptors`. Spk cache and last
+-- revealed rows of a retired descriptor remain attributed to its `descriptor_id`.
+CREATE TABLE IF NOT EXISTS keychain_history(
+    keychain INTEGER NOT NULL,
+    descriptor TEXT NOT NULL,
+    descriptor_id TEXT NOT NULL,
+    activated_at INTEGER,
+    retired_at INTEGER NOT NULL
+);
@@ -0,0 +1,9 @@
+-- 0009_schema_up.sql
+
+-- Transaction size and shape columns, computed when the full transaction is written.
+--
+-- Rows written before this migration are backfilled by `Store::migrate`.
+ALTER TABLE tx ADD COLUMN weight INTEGER;
+ALTER TABLE tx ADD COLUMN vsize INTEGER;
+ALTER TABLE tx ADD COLUMN input_count INTEGER;
+ALTER TABLE tx ADD COLUMN output_count INTEGER;
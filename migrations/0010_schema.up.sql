@@ -0,0 +1,12 @@
+-- 0010_schema_up.sql
+
+-- Frozen UTXO table
+--
+-- Outputs the user excluded from coin selection, with an optional reason.
+CREATE TABLE IF NOT EXISTS frozen_utxo(
+    txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    reason TEXT,
+    frozen_at INTEGER NOT NULL,
+    PRIMARY KEY(txid, vout)
+);
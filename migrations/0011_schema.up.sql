@@ -0,0 +1,65 @@
+-- 0011_schema_up.sql
+
+-- Transaction output table
+--
+-- Outputs of the full transactions in the tx table, written along with the transaction.
+CREATE TABLE IF NOT EXISTS tx_output(
+    txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    value INTEGER NOT NULL,
+    script BLOB NOT NULL,
+    PRIMARY KEY(txid, vout)
+);
+CREATE INDEX IF NOT EXISTS tx_output_script ON tx_output(script);
+
+-- Transactions with their confirmation in the best chain, if any, and label
+CREATE VIEW IF NOT EXISTS v_transactions AS
+SELECT
+    tx.txid,
+    tx.first_seen,
+    tx.last_seen,
+    tx.last_evicted,
+    tx.weight,
+    tx.vsize,
+    best.block_height,
+    best.block_hash,
+    best.confirmation_time,
+    label.label
+FROM tx
+LEFT JOIN (
+    SELECT anchor.txid, MIN(anchor.block_height) AS block_height, anchor.block_hash, anchor.confirmation_time
+    FROM anchor
+    JOIN block ON block.height = anchor.block_height AND block.hash = anchor.block_hash
+    GROUP BY anchor.txid
+) AS best ON best.txid = tx.txid
+LEFT JOIN label ON label.type = 'tx' AND label.ref = tx.txid;
+
+-- Outputs paying to a script of the spk cache, from full transactions and floating txouts
+--
+-- Whether an output is spent isn't known here, see `Store::utxos`.
+CREATE VIEW IF NOT EXISTS v_utxos AS
+SELECT
+    output.txid,
+    output.vout,
+    output.value,
+    output.script,
+    spk.descriptor_id,
+    spk.derivation_index
+FROM (
+    SELECT txid, vout, value, script FROM tx_output
+    UNION
+    SELECT txid, vout, value, script FROM txout
+) AS output
+JOIN keychain_script_pubkey AS spk ON spk.script = output.script;
+
+-- Cached scripts with whether they are revealed and whether any output pays to them
+CREATE VIEW IF NOT EXISTS v_addresses AS
+SELECT
+    spk.descriptor_id,
+    spk.derivation_index,
+    spk.script,
+    spk.derivation_index <= COALESCE(revealed.last_revealed, -1) AS revealed,
+    EXISTS(SELECT 1 FROM tx_output WHERE tx_output.script = spk.script)
+        OR EXISTS(SELECT 1 FROM txout WHERE txout.script = spk.script) AS used
+FROM keychain_script_pubkey AS spk
+LEFT JOIN keychain_last_revealed AS revealed ON revealed.descriptor_id = spk.descriptor_id;
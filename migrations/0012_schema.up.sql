@@ -0,0 +1,13 @@
+-- 0012_schema_up.sql
+
+-- Watch table
+--
+-- Scripts and outpoints to monitor outside of any descriptor. `ref` is the hex of the
+-- script pubkey for `script` items and `txid:vout` for `outpoint` items.
+CREATE TABLE IF NOT EXISTS watch(
+    type TEXT NOT NULL CHECK(type IN ('script', 'outpoint')),
+    ref TEXT NOT NULL,
+    label TEXT,
+    added_at INTEGER NOT NULL,
+    PRIMARY KEY(type, ref)
+);
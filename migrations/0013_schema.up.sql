@@ -0,0 +1,12 @@
+-- 0013_schema_up.sql
+
+-- Chain source table
+--
+-- The backend the wallet syncs against. Holds at most one row.
+CREATE TABLE IF NOT EXISTS chain_source(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    kind TEXT NOT NULL CHECK(kind IN ('esplora', 'electrum', 'bitcoind_rpc')),
+    url TEXT NOT NULL,
+    stop_gap INTEGER,
+    parallel_requests INTEGER
+);
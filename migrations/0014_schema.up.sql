@@ -0,0 +1,10 @@
+-- 0014_schema_up.sql
+
+-- Replication sequence table
+--
+-- The sequence number of the latest changeset passed to the replication sink. Holds at
+-- most one row.
+CREATE TABLE IF NOT EXISTS replication_sequence(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    sequence INTEGER NOT NULL
+);
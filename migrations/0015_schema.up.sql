@@ -0,0 +1,10 @@
+-- 0015_schema_up.sql
+
+-- Descriptor settings table
+--
+-- The indexer lookahead and the stop gap of the latest full scan of each descriptor.
+CREATE TABLE IF NOT EXISTS descriptor_settings(
+    descriptor_id TEXT PRIMARY KEY NOT NULL,
+    lookahead INTEGER,
+    stop_gap INTEGER
+);
@@ -0,0 +1,11 @@
+-- 0016_schema_up.sql
+
+-- Tenant table
+--
+-- The tenant owning a database created by `TenantDir`, along with the SHA-256 hash of its
+-- authorization token. Holds at most one row.
+CREATE TABLE IF NOT EXISTS tenant(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    tenant_id TEXT NOT NULL,
+    token_hash TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- 0017_schema_up.sql
+
+-- Sync log table
+--
+-- An entry per sync attempt. Only the latest entries are kept, see `Store::record_sync`.
+CREATE TABLE IF NOT EXISTS sync_log(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at INTEGER NOT NULL,
+    backend TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    spks_scanned INTEGER NOT NULL,
+    error TEXT
+);
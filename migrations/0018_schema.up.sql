@@ -0,0 +1,48 @@
+-- 0018_schema_up.sql
+
+-- ************************************************************* --
+-- Allow anchors with an unknown (NULL) confirmation_time.       --
+-- ************************************************************* --
+
+-- The view references the anchor table, which is about to be replaced
+DROP VIEW IF EXISTS v_transactions;
+
+-- Create new table
+CREATE TABLE IF NOT EXISTS anchor_new(
+    block_height INTEGER NOT NULL,
+    block_hash TEXT NOT NULL CHECK(block_hash = lower(block_hash)),
+    txid TEXT NOT NULL CHECK(txid = lower(txid)),
+    confirmation_time INTEGER,
+    PRIMARY KEY(block_height, block_hash, txid)
+);
+-- Copy data
+INSERT INTO anchor_new(block_height, block_hash, txid, confirmation_time)
+SELECT block_height, block_hash, txid, confirmation_time FROM anchor;
+-- Drop old table
+DROP TABLE anchor;
+-- Rename new table to old
+ALTER TABLE anchor_new RENAME TO anchor;
+-- Recreate index
+CREATE INDEX IF NOT EXISTS anchor_txid ON anchor(txid);
+
+-- Recreate view
+CREATE VIEW IF NOT EXISTS v_transactions AS
+SELECT
+    tx.txid,
+    tx.first_seen,
+    tx.last_seen,
+    tx.last_evicted,
+    tx.weight,
+    tx.vsize,
+    best.block_height,
+    best.block_hash,
+    best.confirmation_time,
+    label.label
+FROM tx
+LEFT JOIN (
+    SELECT anchor.txid, MIN(anchor.block_height) AS block_height, anchor.block_hash, anchor.confirmation_time
+    FROM anchor
+    JOIN block ON block.height = anchor.block_height AND block.hash = anchor.block_hash
+    GROUP BY anchor.txid
+) AS best ON best.txid = tx.txid
+LEFT JOIN label ON label.type = 'tx' AND label.ref = tx.txid;
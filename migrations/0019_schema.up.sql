@@ -0,0 +1,8 @@
+-- 0019_schema_up.sql
+
+-- Anchor kind column
+--
+-- `confirmation_block_time` anchors have a confirmation time, `block_id` anchors only
+-- identify the block, e.g. when synced by way of compact block filters.
+ALTER TABLE anchor ADD COLUMN kind TEXT NOT NULL DEFAULT 'confirmation_block_time' CHECK(kind IN ('block_id', 'confirmation_block_time'));
+UPDATE anchor SET kind = 'block_id' WHERE confirmation_time IS NULL;
@@ -0,0 +1,19 @@
+-- 0020_schema_up.sql
+
+-- ************************************************************* --
+-- Move raw transactions out of the tx table into the tx_blob    --
+-- table, referenced by tx.blob_id.                              --
+-- ************************************************************* --
+
+-- Transaction blob table
+CREATE TABLE IF NOT EXISTS tx_blob(
+    id INTEGER PRIMARY KEY,
+    tx BLOB NOT NULL
+);
+-- Add reference column
+ALTER TABLE tx ADD COLUMN blob_id INTEGER REFERENCES tx_blob(id);
+-- Move data
+INSERT INTO tx_blob(id, tx) SELECT rowid, tx FROM tx WHERE tx IS NOT NULL;
+UPDATE tx SET blob_id = rowid WHERE tx IS NOT NULL;
+-- Drop old column
+ALTER TABLE tx DROP COLUMN tx;
@@ -0,0 +1,12 @@
+-- 0021_schema_up.sql
+
+-- Import journal table
+--
+-- Progress of each chunked import, see `Store::write_changeset_chunked`.
+CREATE TABLE IF NOT EXISTS import_journal(
+    id TEXT PRIMARY KEY NOT NULL,
+    chunk_count INTEGER NOT NULL,
+    chunks_written INTEGER NOT NULL,
+    started_at INTEGER NOT NULL,
+    completed_at INTEGER
+);
@@ -0,0 +1,10 @@
+-- 0022_schema_up.sql
+
+-- Changeset overflow table
+--
+-- Fields of the wallet changeset which the schema doesn't model, as a JSON object of the
+-- same shape as the serialized changeset. Holds at most one row.
+CREATE TABLE IF NOT EXISTS changeset_overflow(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    data TEXT NOT NULL
+);
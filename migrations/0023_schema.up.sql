@@ -0,0 +1,40 @@
+-- 0023_schema_up.sql
+
+-- Transaction input table
+--
+-- The previous outputs spent by the full transactions in the tx table, written along with
+-- the transaction. Coinbase transactions have no rows.
+CREATE TABLE IF NOT EXISTS txin(
+    txid TEXT NOT NULL,
+    vin INTEGER NOT NULL,
+    prev_txid TEXT NOT NULL,
+    prev_vout INTEGER NOT NULL,
+    PRIMARY KEY(txid, vin)
+);
+CREATE INDEX IF NOT EXISTS txin_prevout ON txin(prev_txid, prev_vout);
+
+-- Reset the computed columns so that `Store::migrate` backfills the inputs of the stored
+-- transactions.
+UPDATE tx SET weight = NULL WHERE blob_id IS NOT NULL;
+
+-- Outputs paying to a script of the spk cache, from full transactions and floating txouts,
+-- with the stored transaction spending each, if any
+DROP VIEW IF EXISTS v_utxos;
+CREATE VIEW IF NOT EXISTS v_utxos AS
+SELECT
+    output.txid,
+    output.vout,
+    output.value,
+    output.script,
+    spk.descriptor_id,
+    spk.derivation_index,
+    (
+        SELECT MIN(txin.txid) FROM txin
+        WHERE txin.prev_txid = output.txid AND txin.prev_vout = output.vout
+    ) AS spent_by
+FROM (
+    SELECT txid, vout, value, script FROM tx_output
+    UNION
+    SELECT txid, vout, value, script FROM txout
+) AS output
+JOIN keychain_script_pubkey AS spk ON spk.script = output.script;
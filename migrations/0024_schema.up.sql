@@ -0,0 +1,5 @@
+-- 0024_schema_up.sql
+
+-- The lowest derivation index of each descriptor known to be used, e.g. from the backup
+-- a wallet is restored from. Indexes below it need not be scanned.
+ALTER TABLE descriptor_settings ADD COLUMN first_used_index INTEGER;
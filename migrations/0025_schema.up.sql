@@ -0,0 +1,10 @@
+-- 0025_schema_up.sql
+
+-- Changeset hash table
+--
+-- The hash of the last committed wallet changeset, see `Store::with_changeset_dedup`.
+-- Holds at most one row.
+CREATE TABLE IF NOT EXISTS changeset_hash(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    hash TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+-- 0026_schema_up.sql
+
+-- Transaction summary table
+--
+-- The value each transaction pays to and spends from the scripts of the spk cache, as of
+-- the `v_utxos` view, maintained on write. `net` is `received - sent`. Populated for
+-- transactions written by earlier versions by `Store::migrate`.
+CREATE TABLE IF NOT EXISTS tx_summary(
+    txid TEXT PRIMARY KEY NOT NULL,
+    received INTEGER NOT NULL,
+    sent INTEGER NOT NULL,
+    net INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tx_summary_net ON tx_summary(net);
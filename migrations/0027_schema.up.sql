@@ -0,0 +1,5 @@
+-- 0027_schema_up.sql
+
+-- Whether `label.label` holds a ciphertext, see `Store::with_label_key`. The views show
+-- the ciphertext of encrypted labels.
+ALTER TABLE label ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
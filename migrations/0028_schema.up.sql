@@ -0,0 +1,9 @@
+-- 0028_schema_up.sql
+
+-- Persisted table
+--
+-- Unix time of the last write of each component of a wallet, see `Store::last_persisted`.
+CREATE TABLE IF NOT EXISTS persisted(
+    component TEXT PRIMARY KEY NOT NULL CHECK(component IN ('chain', 'graph', 'indexer', 'descriptors')),
+    persisted_at INTEGER NOT NULL
+);
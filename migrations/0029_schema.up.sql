@@ -0,0 +1,15 @@
+-- 0029_schema_up.sql
+
+-- Output tag table
+--
+-- Values attached to outputs by protocols layered on top of the wallet, e.g. RGB or
+-- ordinals trackers, keyed by the namespace of the protocol and a key within it.
+CREATE TABLE IF NOT EXISTS output_tag(
+    txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    namespace TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY(txid, vout, namespace, key)
+);
+CREATE INDEX IF NOT EXISTS output_tag_namespace ON output_tag(namespace, key);
@@ -0,0 +1,9 @@
+-- 0031_schema_up.sql
+
+-- Provenance
+--
+-- Source which last provided a transaction, txout or anchor, if the write said so, see
+-- `WriteOptions::source`.
+ALTER TABLE tx ADD COLUMN source TEXT;
+ALTER TABLE txout ADD COLUMN source TEXT;
+ALTER TABLE anchor ADD COLUMN source TEXT;
@@ -0,0 +1,13 @@
+-- 0032_schema_up.sql
+
+-- Custom anchor and indexer data
+--
+-- Anchors of types other than those of `bdk_chain` are stored as `block_id` anchors with
+-- the name of their `AnchorCodec` in `codec` and their encoding in `data`. The changesets of
+-- custom indexers are stored by the name of their `IndexerCodec`.
+ALTER TABLE anchor ADD COLUMN codec TEXT;
+ALTER TABLE anchor ADD COLUMN data BLOB;
+CREATE TABLE IF NOT EXISTS indexer_data(
+    codec TEXT PRIMARY KEY NOT NULL,
+    data BLOB NOT NULL
+);
@@ -0,0 +1,13 @@
+-- 0033_schema_up.sql
+
+-- Cosigner table
+--
+-- Metadata of the cosigners of a multisig wallet, keyed by the master fingerprint which
+-- the key origins of the descriptors reference.
+CREATE TABLE IF NOT EXISTS cosigner(
+    fingerprint TEXT PRIMARY KEY NOT NULL CHECK(fingerprint = lower(fingerprint)),
+    label TEXT NOT NULL,
+    xpub TEXT,
+    contact TEXT,
+    last_signed_at INTEGER
+);
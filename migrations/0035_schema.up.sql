@@ -0,0 +1,272 @@
+-- 0035_schema_up.sql
+
+-- ************************************************************* --
+-- Rebuild the tables of heights, timestamps and amounts as      --
+-- STRICT tables, so that a value of the wrong type fails the    --
+-- write rather than being stored as is.                         --
+-- ************************************************************* --
+
+-- Rows of the rebuilt tables which hold a value of the wrong type, e.g. text written into
+-- an INTEGER column by an external writer, and so can't be copied into the STRICT table.
+-- `data` is a JSON object of the row, mapping each column to the SQL literal of its value.
+CREATE TABLE IF NOT EXISTS strict_rejected(
+    table_name TEXT NOT NULL,
+    data TEXT NOT NULL
+) STRICT;
+
+-- The views reference the tables which are about to be replaced
+DROP VIEW IF EXISTS v_addresses;
+DROP VIEW IF EXISTS v_transactions;
+DROP VIEW IF EXISTS v_utxos;
+
+-- Transaction table
+CREATE TABLE IF NOT EXISTS tx_new(
+    txid TEXT NOT NULL,
+    first_seen INTEGER,
+    last_seen INTEGER,
+    last_evicted INTEGER,
+    weight INTEGER,
+    vsize INTEGER,
+    input_count INTEGER,
+    output_count INTEGER,
+    blob_id INTEGER REFERENCES tx_blob(id),
+    source TEXT,
+    PRIMARY KEY(txid)
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'tx', json_object('txid', quote(txid), 'first_seen', quote(first_seen), 'last_seen', quote(last_seen), 'last_evicted', quote(last_evicted), 'weight', quote(weight), 'vsize', quote(vsize), 'input_count', quote(input_count), 'output_count', quote(output_count), 'blob_id', quote(blob_id), 'source', quote(source))
+FROM tx
+WHERE NOT (
+    typeof(txid) = 'text'
+    AND typeof(first_seen) IN ('integer', 'null')
+    AND typeof(last_seen) IN ('integer', 'null')
+    AND typeof(last_evicted) IN ('integer', 'null')
+    AND typeof(weight) IN ('integer', 'null')
+    AND typeof(vsize) IN ('integer', 'null')
+    AND typeof(input_count) IN ('integer', 'null')
+    AND typeof(output_count) IN ('integer', 'null')
+    AND typeof(blob_id) IN ('integer', 'null')
+    AND typeof(source) IN ('text', 'null')
+);
+INSERT INTO tx_new(txid, first_seen, last_seen, last_evicted, weight, vsize, input_count, output_count, blob_id, source)
+SELECT txid, first_seen, last_seen, last_evicted, weight, vsize, input_count, output_count, blob_id, source
+FROM tx
+WHERE typeof(txid) = 'text'
+    AND typeof(first_seen) IN ('integer', 'null')
+    AND typeof(last_seen) IN ('integer', 'null')
+    AND typeof(last_evicted) IN ('integer', 'null')
+    AND typeof(weight) IN ('integer', 'null')
+    AND typeof(vsize) IN ('integer', 'null')
+    AND typeof(input_count) IN ('integer', 'null')
+    AND typeof(output_count) IN ('integer', 'null')
+    AND typeof(blob_id) IN ('integer', 'null')
+    AND typeof(source) IN ('text', 'null');
+DROP TABLE tx;
+ALTER TABLE tx_new RENAME TO tx;
+
+-- Transaction output table
+CREATE TABLE IF NOT EXISTS txout_new(
+    txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    value INTEGER NOT NULL,
+    script BLOB NOT NULL,
+    source TEXT,
+    PRIMARY KEY(txid, vout)
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'txout', json_object('txid', quote(txid), 'vout', quote(vout), 'value', quote(value), 'script', quote(script), 'source', quote(source))
+FROM txout
+WHERE NOT (
+    typeof(txid) = 'text'
+    AND typeof(vout) = 'integer'
+    AND typeof(value) = 'integer'
+    AND typeof(script) = 'blob'
+    AND typeof(source) IN ('text', 'null')
+);
+INSERT INTO txout_new(txid, vout, value, script, source)
+SELECT txid, vout, value, script, source
+FROM txout
+WHERE typeof(txid) = 'text'
+    AND typeof(vout) = 'integer'
+    AND typeof(value) = 'integer'
+    AND typeof(script) = 'blob'
+    AND typeof(source) IN ('text', 'null');
+DROP TABLE txout;
+ALTER TABLE txout_new RENAME TO txout;
+
+-- Anchor table
+CREATE TABLE IF NOT EXISTS anchor_new(
+    block_height INTEGER NOT NULL,
+    block_hash TEXT NOT NULL CHECK(block_hash = lower(block_hash)),
+    txid TEXT NOT NULL CHECK(txid = lower(txid)),
+    confirmation_time INTEGER,
+    kind TEXT NOT NULL DEFAULT 'confirmation_block_time' CHECK(kind IN ('block_id', 'confirmation_block_time')),
+    source TEXT,
+    codec TEXT,
+    data BLOB,
+    PRIMARY KEY(block_height, block_hash, txid)
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'anchor', json_object('block_height', quote(block_height), 'block_hash', quote(block_hash), 'txid', quote(txid), 'confirmation_time', quote(confirmation_time), 'kind', quote(kind), 'source', quote(source), 'codec', quote(codec), 'data', quote(data))
+FROM anchor
+WHERE NOT (
+    typeof(block_height) = 'integer'
+    AND typeof(block_hash) = 'text'
+    AND typeof(txid) = 'text'
+    AND typeof(confirmation_time) IN ('integer', 'null')
+    AND typeof(kind) = 'text'
+    AND typeof(source) IN ('text', 'null')
+    AND typeof(codec) IN ('text', 'null')
+    AND typeof(data) IN ('blob', 'null')
+);
+INSERT INTO anchor_new(block_height, block_hash, txid, confirmation_time, kind, source, codec, data)
+SELECT block_height, block_hash, txid, confirmation_time, kind, source, codec, data
+FROM anchor
+WHERE typeof(block_height) = 'integer'
+    AND typeof(block_hash) = 'text'
+    AND typeof(txid) = 'text'
+    AND typeof(confirmation_time) IN ('integer', 'null')
+    AND typeof(kind) = 'text'
+    AND typeof(source) IN ('text', 'null')
+    AND typeof(codec) IN ('text', 'null')
+    AND typeof(data) IN ('blob', 'null');
+DROP TABLE anchor;
+ALTER TABLE anchor_new RENAME TO anchor;
+CREATE INDEX IF NOT EXISTS anchor_txid ON anchor(txid);
+
+-- Block table
+--
+-- The height is the rowid, which only ever holds integers.
+CREATE TABLE IF NOT EXISTS block_new(
+    height INTEGER PRIMARY KEY NOT NULL,
+    hash TEXT NOT NULL
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'block', json_object('height', quote(height), 'hash', quote(hash))
+FROM block
+WHERE typeof(hash) != 'text';
+INSERT INTO block_new(height, hash)
+SELECT height, hash FROM block WHERE typeof(hash) = 'text';
+DROP TABLE block;
+ALTER TABLE block_new RENAME TO block;
+
+-- Orphaned block table
+CREATE TABLE IF NOT EXISTS block_orphaned_new(
+    height INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    orphaned_at INTEGER NOT NULL
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'block_orphaned', json_object('height', quote(height), 'hash', quote(hash), 'orphaned_at', quote(orphaned_at))
+FROM block_orphaned
+WHERE NOT (
+    typeof(height) = 'integer'
+    AND typeof(hash) = 'text'
+    AND typeof(orphaned_at) = 'integer'
+);
+INSERT INTO block_orphaned_new(height, hash, orphaned_at)
+SELECT height, hash, orphaned_at
+FROM block_orphaned
+WHERE typeof(height) = 'integer'
+    AND typeof(hash) = 'text'
+    AND typeof(orphaned_at) = 'integer';
+DROP TABLE block_orphaned;
+ALTER TABLE block_orphaned_new RENAME TO block_orphaned;
+CREATE INDEX IF NOT EXISTS block_orphaned_height ON block_orphaned(height);
+
+-- Keychain last revealed table
+CREATE TABLE IF NOT EXISTS keychain_last_revealed_new(
+    descriptor_id TEXT NOT NULL,
+    last_revealed INTEGER,
+    PRIMARY KEY(descriptor_id)
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'keychain_last_revealed', json_object('descriptor_id', quote(descriptor_id), 'last_revealed', quote(last_revealed))
+FROM keychain_last_revealed
+WHERE NOT (
+    typeof(descriptor_id) = 'text'
+    AND typeof(last_revealed) IN ('integer', 'null')
+);
+INSERT INTO keychain_last_revealed_new(descriptor_id, last_revealed)
+SELECT descriptor_id, last_revealed
+FROM keychain_last_revealed
+WHERE typeof(descriptor_id) = 'text'
+    AND typeof(last_revealed) IN ('integer', 'null');
+DROP TABLE keychain_last_revealed;
+ALTER TABLE keychain_last_revealed_new RENAME TO keychain_last_revealed;
+
+-- Keychain script pubkey table
+CREATE TABLE IF NOT EXISTS keychain_script_pubkey_new(
+    descriptor_id TEXT NOT NULL,
+    derivation_index INTEGER,
+    script BLOB,
+    PRIMARY KEY(descriptor_id, derivation_index)
+) STRICT;
+INSERT INTO strict_rejected(table_name, data)
+SELECT 'keychain_script_pubkey', json_object('descriptor_id', quote(descriptor_id), 'derivation_index', quote(derivation_index), 'script', quote(script))
+FROM keychain_script_pubkey
+WHERE NOT (
+    typeof(descriptor_id) = 'text'
+    AND typeof(derivation_index) IN ('integer', 'null')
+    AND typeof(script) IN ('blob', 'null')
+);
+INSERT INTO keychain_script_pubkey_new(descriptor_id, derivation_index, script)
+SELECT descriptor_id, derivation_index, script
+FROM keychain_script_pubkey
+WHERE typeof(descriptor_id) = 'text'
+    AND typeof(derivation_index) IN ('integer', 'null')
+    AND typeof(script) IN ('blob', 'null');
+DROP TABLE keychain_script_pubkey;
+ALTER TABLE keychain_script_pubkey_new RENAME TO keychain_script_pubkey;
+
+-- Recreate views
+CREATE VIEW IF NOT EXISTS v_addresses AS
+SELECT
+    spk.descriptor_id,
+    spk.derivation_index,
+    spk.script,
+    spk.derivation_index <= COALESCE(revealed.last_revealed, -1) AS revealed,
+    EXISTS(SELECT 1 FROM tx_output WHERE tx_output.script = spk.script)
+        OR EXISTS(SELECT 1 FROM txout WHERE txout.script = spk.script) AS used
+FROM keychain_script_pubkey AS spk
+LEFT JOIN keychain_last_revealed AS revealed ON revealed.descriptor_id = spk.descriptor_id;
+
+CREATE VIEW IF NOT EXISTS v_transactions AS
+SELECT
+    tx.txid,
+    tx.first_seen,
+    tx.last_seen,
+    tx.last_evicted,
+    tx.weight,
+    tx.vsize,
+    best.block_height,
+    best.block_hash,
+    best.confirmation_time,
+    label.label
+FROM tx
+LEFT JOIN (
+    SELECT anchor.txid, MIN(anchor.block_height) AS block_height, anchor.block_hash, anchor.confirmation_time
+    FROM anchor
+    JOIN block ON block.height = anchor.block_height AND block.hash = anchor.block_hash
+    GROUP BY anchor.txid
+) AS best ON best.txid = tx.txid
+LEFT JOIN label ON label.type = 'tx' AND label.ref = tx.txid;
+
+CREATE VIEW IF NOT EXISTS v_utxos AS
+SELECT
+    output.txid,
+    output.vout,
+    output.value,
+    output.script,
+    spk.descriptor_id,
+    spk.derivation_index,
+    (
+        SELECT MIN(txin.txid) FROM txin
+        WHERE txin.prev_txid = output.txid AND txin.prev_vout = output.vout
+    ) AS spent_by
+FROM (
+    SELECT txid, vout, value, script FROM tx_output
+    UNION
+    SELECT txid, vout, value, script FROM txout
+) AS output
+JOIN keychain_script_pubkey AS spk ON spk.script = output.script;
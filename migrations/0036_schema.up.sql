@@ -0,0 +1,11 @@
+-- 0036_schema_up.sql
+
+-- Idempotency key table
+--
+-- The keys of the changesets committed by `Store::write_changeset_idempotent`, so that a
+-- changeset delivered again with the same key is skipped.
+CREATE TABLE IF NOT EXISTS idempotency_key(
+    key TEXT PRIMARY KEY NOT NULL,
+    committed_at INTEGER NOT NULL
+) STRICT;
+CREATE INDEX IF NOT EXISTS idempotency_key_committed_at ON idempotency_key(committed_at);
@@ -0,0 +1,25 @@
+-- 0037_schema_up.sql
+
+-- Used address table
+--
+-- The script pubkeys which any stored transaction or floating txout ever paid to, kept by
+-- triggers on the output tables, see `Store::is_address_used`. Rows are never deleted, so
+-- a script stays used after the outputs paying to it are pruned or archived.
+CREATE TABLE IF NOT EXISTS used_address(
+    script BLOB PRIMARY KEY NOT NULL
+) STRICT;
+INSERT OR IGNORE INTO used_address(script)
+SELECT script FROM tx_output UNION SELECT script FROM txout;
+
+CREATE TRIGGER IF NOT EXISTS tx_output_used_address AFTER INSERT ON tx_output
+BEGIN
+    INSERT OR IGNORE INTO used_address(script) VALUES(NEW.script);
+END;
+CREATE TRIGGER IF NOT EXISTS txout_used_address AFTER INSERT ON txout
+BEGIN
+    INSERT OR IGNORE INTO used_address(script) VALUES(NEW.script);
+END;
+CREATE TRIGGER IF NOT EXISTS txout_script_used_address AFTER UPDATE OF script ON txout
+BEGIN
+    INSERT OR IGNORE INTO used_address(script) VALUES(NEW.script);
+END;
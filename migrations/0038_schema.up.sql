@@ -0,0 +1,16 @@
+-- 0038_schema_up.sql
+
+-- Network magic
+--
+-- The magic bytes of the P2P messages of the stored network, so that a network whose name
+-- isn't known, e.g. one added by a later version of `bitcoin`, can still be identified, see
+-- `Store::with_lenient_network`.
+ALTER TABLE network ADD COLUMN magic BLOB;
+
+UPDATE network SET magic = CASE network
+    WHEN 'bitcoin' THEN X'f9beb4d9'
+    WHEN 'testnet' THEN X'0b110907'
+    WHEN 'testnet4' THEN X'1c163f28'
+    WHEN 'signet' THEN X'0a03cf40'
+    WHEN 'regtest' THEN X'fabfb5da'
+END;
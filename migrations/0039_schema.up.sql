@@ -0,0 +1,12 @@
+-- 0039_schema_up.sql
+
+-- Wallet table
+--
+-- The id of the wallet of the database, generated by a `WalletIdScheme` when the wallet is
+-- first written or set by the caller, see `Store::list_wallets`. A database holds a single
+-- wallet, so the table has at most one row.
+CREATE TABLE IF NOT EXISTS wallet(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    wallet_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+) STRICT;
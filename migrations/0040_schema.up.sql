@@ -0,0 +1,13 @@
+-- 0040_schema_up.sql
+
+-- Transaction metadata table
+--
+-- JSON metadata attached to transactions by applications, e.g. invoice ids or accounting
+-- categories, see `Store::set_tx_meta`. `version` is the version of the application's
+-- metadata schema the value was written with. The transaction doesn't have to be stored.
+CREATE TABLE IF NOT EXISTS tx_meta(
+    txid TEXT PRIMARY KEY NOT NULL,
+    meta TEXT NOT NULL CHECK(json_valid(meta)),
+    version INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
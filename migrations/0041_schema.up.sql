@@ -0,0 +1,8 @@
+-- 0041_schema_up.sql
+
+-- Keychain script pubkey index
+--
+-- Outputs are matched against the spk cache by script, e.g. by `v_utxos` and when the
+-- summaries of transactions are refreshed on every write. Without an index each match
+-- scans the whole cache.
+CREATE INDEX IF NOT EXISTS keychain_script_pubkey_script ON keychain_script_pubkey(script);
@@ -0,0 +1,28 @@
+-- 0042_schema_up.sql
+
+-- Whether the label of a transaction is encrypted
+--
+-- An encrypted label is the hex of its ciphertext, which readers must decrypt, see
+-- `Store::with_label_key`.
+DROP VIEW IF EXISTS v_transactions;
+CREATE VIEW IF NOT EXISTS v_transactions AS
+SELECT
+    tx.txid,
+    tx.first_seen,
+    tx.last_seen,
+    tx.last_evicted,
+    tx.weight,
+    tx.vsize,
+    best.block_height,
+    best.block_hash,
+    best.confirmation_time,
+    label.label,
+    label.encrypted AS label_encrypted
+FROM tx
+LEFT JOIN (
+    SELECT anchor.txid, MIN(anchor.block_height) AS block_height, anchor.block_hash, anchor.confirmation_time
+    FROM anchor
+    JOIN block ON block.height = anchor.block_height AND block.hash = anchor.block_hash
+    GROUP BY anchor.txid
+) AS best ON best.txid = tx.txid
+LEFT JOIN label ON label.type = 'tx' AND label.ref = tx.txid;
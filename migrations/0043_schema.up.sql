@@ -0,0 +1,7 @@
+-- 0043_schema_up.sql
+
+-- Transaction first seen index
+--
+-- `Store::transactions_page` reads pages of transactions ordered by first seen time and
+-- txid. Without an index each page sorts the whole table.
+CREATE INDEX IF NOT EXISTS tx_first_seen ON tx(first_seen, txid);
@@ -0,0 +1,8 @@
+-- 0044_schema_up.sql
+
+-- Floating txout script index
+--
+-- `Store::revealed_addresses` looks up the outputs paying to each revealed script, among
+-- the outputs of full transactions, which `tx_output_script` indexes, and the floating
+-- txouts. Without an index each lookup scans the `txout` table.
+CREATE INDEX IF NOT EXISTS txout_script ON txout(script);
@@ -0,0 +1,17 @@
+-- 0030_schema_up.sql
+
+-- Encrypted backup table
+--
+-- A single secret, e.g. a mnemonic or the secret descriptors of the wallet, encrypted with a
+-- key derived from a passphrase by Argon2id with the stored salt and costs, see
+-- `Store::write_backup`. It is kept apart from the wallet tables, which never hold secrets.
+CREATE TABLE IF NOT EXISTS encrypted_backup(
+    id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+    salt BLOB NOT NULL,
+    m_cost INTEGER NOT NULL,
+    t_cost INTEGER NOT NULL,
+    p_cost INTEGER NOT NULL,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,12 @@
+-- 0034_schema_up.sql
+
+-- HTTP cache table
+--
+-- Bodies of chain source responses keyed by the SHA256 of their URL, see
+-- `Store::http_cache_put`. Only used with the `http-cache` feature.
+CREATE TABLE IF NOT EXISTS http_cache(
+    url_hash TEXT PRIMARY KEY NOT NULL,
+    body BLOB NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS http_cache_fetched_at ON http_cache(fetched_at);
@@ -0,0 +1,95 @@
+//! Address reuse prevention.
+
+use bdk_chain::bitcoin::{Address, Script};
+
+use crate::Error;
+use crate::Store;
+
+impl Store {
+    /// Whether any stored transaction or floating txout ever paid to `address`.
+    ///
+    /// The script pubkeys paid to are recorded as outputs are written and never forgotten,
+    /// even once the outputs are pruned or archived, so a payment processor can refuse to
+    /// hand out an address twice by checking it here before showing it.
+    pub async fn is_address_used(&self, address: &Address) -> Result<bool, Error> {
+        self.is_script_used(&address.script_pubkey()).await
+    }
+
+    /// Whether any stored transaction or floating txout ever paid to `script`, see
+    /// [`is_address_used`](Self::is_address_used).
+    pub async fn is_script_used(&self, script: &Script) -> Result<bool, Error> {
+        let row = sqlx::query("SELECT 1 FROM used_address WHERE script = $1")
+            .bind(script.as_bytes())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{
+        Amount, Network, OutPoint, ScriptBuf, Transaction, TxOut, WPubkeyHash, absolute,
+        transaction,
+    };
+    use bdk_chain::{ConfirmationBlockTime, tx_graph};
+
+    #[tokio::test]
+    async fn is_address_used() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let address = |data: &[u8]| {
+            Address::from_script(
+                &ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(data)),
+                Network::Signet,
+            )
+        };
+        let paid = address(b"paid")?;
+        let floating = address(b"floating")?;
+        let fresh = address(b"fresh")?;
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: paid.script_pubkey(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        store
+            .write_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(tx)].into(),
+                txouts: [(
+                    OutPoint::new(Hash::hash(b"other"), 0),
+                    TxOut {
+                        value: Amount::from_sat(500),
+                        script_pubkey: floating.script_pubkey(),
+                    },
+                )]
+                .into(),
+                ..Default::default()
+            })
+            .await?;
+        assert!(store.is_address_used(&paid).await?);
+        assert!(store.is_address_used(&floating).await?);
+        assert!(!store.is_address_used(&fresh).await?);
+
+        // The address stays used once the transaction paying to it is gone.
+        sqlx::query("DELETE FROM tx_output WHERE txid = $1")
+            .bind(txid.to_string())
+            .execute(&store.pool)
+            .await?;
+        assert!(store.is_address_used(&paid).await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+//! Revealed addresses of a keychain.
+
+use std::str::FromStr;
+
+use bdk_chain::{DescriptorExt, bitcoin, miniscript};
+use bdk_wallet::KeychainKind;
+use bitcoin::hex::DisplayHex;
+use bitcoin::{Address, ScriptBuf};
+use futures_core::Stream;
+use futures_util::{StreamExt, TryStreamExt, stream};
+use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::convert::from_sql_opt;
+use crate::wallet::keychain_to_int;
+
+/// A revealed address, see [`Store::revealed_addresses`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevealedAddress {
+    /// Derivation index
+    pub index: u32,
+    /// Script pubkey
+    pub script_pubkey: ScriptBuf,
+    /// Address, if the network is stored and the script pubkey has an address form
+    pub address: Option<Address>,
+    /// Whether any known output pays to the script pubkey
+    pub used: bool,
+    /// Earliest first seen time of the transactions paying to the script pubkey
+    pub first_seen: Option<u64>,
+}
+
+impl Store {
+    /// Stream the revealed addresses of `keychain` ordered by derivation index.
+    ///
+    /// Scripts are derived from the stored descriptor up to the last revealed index and
+    /// checked against the known outputs in a single query, so that a service monitoring
+    /// deposit addresses can do so without instantiating a `Wallet`. The stream is empty if
+    /// no descriptor is stored for `keychain` or no index of it is revealed.
+    pub async fn revealed_addresses(
+        &self,
+        keychain: KeychainKind,
+    ) -> Result<impl Stream<Item = Result<RevealedAddress, Error>> + '_, Error> {
+        let row = sqlx::query("SELECT descriptor FROM keychain WHERE keychain = $1")
+            .bind(keychain_to_int(keychain))
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(stream::empty().left_stream());
+        };
+        let descriptor: String = row.get("descriptor");
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&descriptor)?;
+        let last_revealed: Option<u32> = sqlx::query(
+            "SELECT last_revealed FROM keychain_last_revealed WHERE descriptor_id = $1",
+        )
+        .bind(descriptor.descriptor_id().to_string())
+        .fetch_optional(&self.pool)
+        .await?
+        .and_then(|row| row.get("last_revealed"));
+        let Some(last_revealed) = last_revealed else {
+            return Ok(stream::empty().left_stream());
+        };
+        let network = self.read_network().await?;
+
+        // The scripts are passed as a JSON array of hex strings, whose keys are the indices.
+        let scripts = (0..=last_revealed)
+            .map(|index| {
+                let script_pubkey = descriptor
+                    .at_derivation_index(index)
+                    .map_err(Error::other)?
+                    .script_pubkey();
+                Ok(script_pubkey.as_bytes().to_lower_hex_string())
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let stream = sqlx::query(
+            "WITH revealed(idx, script) AS (SELECT key, unhex(value) FROM json_each($1))
+            SELECT
+                idx,
+                script,
+                EXISTS(SELECT 1 FROM tx_output WHERE tx_output.script = revealed.script)
+                    OR EXISTS(SELECT 1 FROM txout WHERE txout.script = revealed.script) AS used,
+                (SELECT MIN(first_seen) FROM tx WHERE txid IN (
+                    SELECT txid FROM tx_output WHERE tx_output.script = revealed.script
+                    UNION
+                    SELECT txid FROM txout WHERE txout.script = revealed.script
+                )) AS first_seen
+            FROM revealed
+            ORDER BY idx",
+        )
+        .bind(serde_json::to_string(&scripts)?)
+        .fetch(&self.pool)
+        .map_err(Error::from)
+        .and_then(move |row| async move {
+            let script_pubkey = ScriptBuf::from_bytes(row.get("script"));
+            let address =
+                network.and_then(|network| Address::from_script(&script_pubkey, network).ok());
+            Ok(RevealedAddress {
+                index: row.get("idx"),
+                script_pubkey,
+                address,
+                used: row.get("used"),
+                first_seen: from_sql_opt("tx.first_seen", row.get("first_seen"))?,
+            })
+        });
+
+        Ok(stream.right_stream())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::{ConfirmationBlockTime, tx_graph};
+    use bdk_wallet::Wallet;
+    use bitcoin::{Amount, Network, Transaction, TxOut, absolute, transaction};
+    use futures_util::TryStreamExt;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+
+    #[tokio::test]
+    async fn revealed_addresses() -> anyhow::Result<()> {
+        let mut store = Store::new_memory().await?;
+        store.migrate().await?;
+        let addresses: Vec<_> = store
+            .revealed_addresses(KeychainKind::External)
+            .await?
+            .try_collect()
+            .await?;
+        assert!(addresses.is_empty());
+
+        let mut wallet = Wallet::create_single(DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut store)
+            .await?;
+        let first = wallet.reveal_next_address(KeychainKind::External);
+        let second = wallet.reveal_next_address(KeychainKind::External);
+        wallet.persist_async(&mut store).await?;
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: second.script_pubkey(),
+            }],
+        };
+        let graph = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            first_seen: [(tx.compute_txid(), 100)].into(),
+            txs: [Arc::new(tx)].into(),
+            ..Default::default()
+        };
+        store.write_tx_graph(&graph).await?;
+
+        let addresses: Vec<_> = store
+            .revealed_addresses(KeychainKind::External)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses[0].index, 0);
+        assert_eq!(addresses[0].address, Some(first.address));
+        assert!(!addresses[0].used);
+        assert_eq!(addresses[0].first_seen, None);
+        assert_eq!(addresses[1].index, 1);
+        assert_eq!(addresses[1].address, Some(second.address));
+        assert!(addresses[1].used);
+        assert_eq!(addresses[1].first_seen, Some(100));
+
+        Ok(())
+    }
+}
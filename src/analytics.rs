@@ -0,0 +1,252 @@
+//! Export of the chain data to Parquet files.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::Error;
+use crate::Store;
+use crate::rt;
+
+/// Type of an exported column.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// INTEGER, as `INT64`
+    Int,
+    /// TEXT, as `BINARY` with the `UTF8` annotation
+    Text,
+    /// BLOB, as `BINARY`
+    Blob,
+}
+
+/// An exported column: name, type and whether it is nullable.
+type Column = (&'static str, Kind, bool);
+
+/// The exported tables: file name, query and columns of the query.
+const TABLES: &[(&str, &str, &[Column])] = &[
+    (
+        "tx",
+        "SELECT txid, tx_blob.tx AS raw, first_seen, last_seen, last_evicted, weight, vsize, input_count, output_count, source
+        FROM tx LEFT JOIN tx_blob ON tx_blob.id = tx.blob_id ORDER BY txid",
+        &[
+            ("txid", Kind::Text, false),
+            ("raw", Kind::Blob, true),
+            ("first_seen", Kind::Int, true),
+            ("last_seen", Kind::Int, true),
+            ("last_evicted", Kind::Int, true),
+            ("weight", Kind::Int, true),
+            ("vsize", Kind::Int, true),
+            ("input_count", Kind::Int, true),
+            ("output_count", Kind::Int, true),
+            ("source", Kind::Text, true),
+        ],
+    ),
+    (
+        "txout",
+        "SELECT txid, vout, value, script FROM tx_output
+        UNION SELECT txid, vout, value, script FROM txout ORDER BY txid, vout",
+        &[
+            ("txid", Kind::Text, false),
+            ("vout", Kind::Int, false),
+            ("value", Kind::Int, false),
+            ("script", Kind::Blob, false),
+        ],
+    ),
+    (
+        "anchor",
+        "SELECT block_height, block_hash, txid, confirmation_time, source FROM anchor ORDER BY block_height, block_hash, txid",
+        &[
+            ("block_height", Kind::Int, false),
+            ("block_hash", Kind::Text, false),
+            ("txid", Kind::Text, false),
+            ("confirmation_time", Kind::Int, true),
+            ("source", Kind::Text, true),
+        ],
+    ),
+    (
+        "block",
+        "SELECT height, hash FROM block ORDER BY height",
+        &[("height", Kind::Int, false), ("hash", Kind::Text, false)],
+    ),
+];
+
+impl Store {
+    /// Write the transactions, txouts, anchors and blocks to the Parquet files `tx.parquet`,
+    /// `txout.parquet`, `anchor.parquet` and `block.parquet` in `dir`, which is created if
+    /// missing, returning the number of rows written to each.
+    ///
+    /// This lets data teams analyze wallet activity with their usual tools rather than by
+    /// reading a production database. The txouts include the outputs of the full
+    /// transactions. The tables are read in a single transaction, so the files are
+    /// consistent with each other. Existing files are replaced.
+    pub async fn export_parquet(
+        &self,
+        dir: impl AsRef<Path>,
+    ) -> Result<BTreeMap<String, u64>, Error> {
+        let dir = dir.as_ref();
+        let mut files = vec![];
+        {
+            let mut tx = self.pool.begin().await?;
+            for &(name, query, columns) in TABLES {
+                let rows = sqlx::query(query).fetch_all(&mut *tx).await?;
+                files.push((name, rows.len() as u64, to_parquet(name, columns, &rows)?));
+            }
+        }
+
+        rt::create_dir_all(dir).await?;
+        let mut counts = BTreeMap::new();
+        for (name, count, data) in files {
+            rt::write(&dir.join(format!("{name}.parquet")), &data).await?;
+            counts.insert(name.to_string(), count);
+        }
+
+        Ok(counts)
+    }
+}
+
+/// Encode `rows` with `columns` as a Parquet file with a single row group.
+fn to_parquet(name: &str, columns: &[Column], rows: &[SqliteRow]) -> Result<Vec<u8>, Error> {
+    let fields: String = columns
+        .iter()
+        .map(|&(column, kind, nullable)| {
+            let repetition = if nullable { "OPTIONAL" } else { "REQUIRED" };
+            match kind {
+                Kind::Int => format!("{repetition} INT64 {column}; "),
+                Kind::Text => format!("{repetition} BINARY {column} (UTF8); "),
+                Kind::Blob => format!("{repetition} BINARY {column}; "),
+            }
+        })
+        .collect();
+    let schema = Arc::new(parse_message_type(&format!(
+        "message {name} {{ {fields}}}"
+    ))?);
+    let properties = Arc::new(WriterProperties::builder().build());
+
+    let mut writer = SerializedFileWriter::new(vec![], schema, properties)?;
+    let mut row_group = writer.next_row_group()?;
+    for (i, &(_, kind, nullable)) in columns.iter().enumerate() {
+        let mut column = row_group
+            .next_column()?
+            .expect("a writer for every column of the schema");
+        match kind {
+            Kind::Int => {
+                let values: Vec<Option<i64>> = rows.iter().map(|row| row.get(i)).collect();
+                let levels = def_levels(&values);
+                let values: Vec<i64> = values.into_iter().flatten().collect();
+                column.typed::<Int64Type>().write_batch(
+                    &values,
+                    nullable.then_some(&levels),
+                    None,
+                )?;
+            }
+            Kind::Text | Kind::Blob => {
+                let values: Vec<Option<Vec<u8>>> = rows
+                    .iter()
+                    .map(|row| match kind {
+                        Kind::Text => row.get::<Option<String>, _>(i).map(String::into_bytes),
+                        _ => row.get(i),
+                    })
+                    .collect();
+                let levels = def_levels(&values);
+                let values: Vec<ByteArray> =
+                    values.into_iter().flatten().map(ByteArray::from).collect();
+                column.typed::<ByteArrayType>().write_batch(
+                    &values,
+                    nullable.then_some(&levels),
+                    None,
+                )?;
+            }
+        }
+        column.close()?;
+    }
+    row_group.close()?;
+
+    Ok(writer.into_inner()?)
+}
+
+/// Definition levels of the values of an optional column.
+fn def_levels<T>(values: &[Option<T>]) -> Vec<i16> {
+    values.iter().map(|v| i16::from(v.is_some())).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{Amount, Transaction, TxOut, absolute, transaction};
+    use bdk_chain::{BlockId, ConfirmationBlockTime, local_chain, tx_graph};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[tokio::test]
+    async fn export_parquet() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: Default::default(),
+                };
+                2
+            ],
+        };
+        let txid = tx.compute_txid();
+        let block = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        store
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: [(0, Some(Hash::hash(b"0"))), (1, Some(block.hash))].into(),
+            })
+            .await?;
+        store
+            .write_tx_graph(&tx_graph::ChangeSet {
+                txs: [Arc::new(tx)].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block,
+                        confirmation_time: 100,
+                    },
+                    txid,
+                )]
+                .into(),
+                last_seen: [(Hash::hash(b"unknown"), 200)].into(),
+                ..Default::default()
+            })
+            .await?;
+
+        let root = tempfile::tempdir()?;
+        let dir = root.path().join("parquet");
+        let counts = store.export_parquet(&dir).await?;
+        assert_eq!(
+            counts,
+            [
+                ("anchor".to_string(), 1),
+                ("block".to_string(), 2),
+                ("tx".to_string(), 2),
+                ("txout".to_string(), 2),
+            ]
+            .into()
+        );
+        for (name, count) in counts {
+            let file = std::fs::File::open(dir.join(format!("{name}.parquet")))?;
+            let reader = SerializedFileReader::new(file)?;
+            assert_eq!(reader.metadata().file_metadata().num_rows() as u64, count);
+        }
+
+        Ok(())
+    }
+}
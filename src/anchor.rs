@@ -0,0 +1,146 @@
+//! Anchors of different kinds.
+
+use std::collections::BTreeSet;
+
+use bdk_chain::{Anchor, BlockId, ConfirmationBlockTime, bitcoin};
+use bitcoin::{BlockHash, Txid};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::convert::from_sql;
+
+/// An anchor as stored in the `anchor` table, of the kind given by its `kind` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StoredAnchor {
+    /// Anchor with a block only, see [`Store::write_untimed_anchors`].
+    BlockId(BlockId),
+    /// Anchor with a block and confirmation time.
+    ConfirmationBlockTime(ConfirmationBlockTime),
+}
+
+impl StoredAnchor {
+    /// Block of the anchor.
+    pub fn block_id(&self) -> BlockId {
+        match self {
+            Self::BlockId(block_id) => *block_id,
+            Self::ConfirmationBlockTime(anchor) => anchor.anchor_block(),
+        }
+    }
+
+    /// Confirmation time of the anchor, if known.
+    pub fn confirmation_time(&self) -> Option<u64> {
+        match self {
+            Self::BlockId(_) => None,
+            Self::ConfirmationBlockTime(anchor) => Some(anchor.confirmation_time),
+        }
+    }
+
+    /// The [`ConfirmationBlockTime`], if the confirmation time is known.
+    pub fn to_confirmation_block_time(&self) -> Option<ConfirmationBlockTime> {
+        match self {
+            Self::BlockId(_) => None,
+            Self::ConfirmationBlockTime(anchor) => Some(*anchor),
+        }
+    }
+}
+
+impl From<BlockId> for StoredAnchor {
+    fn from(block_id: BlockId) -> Self {
+        Self::BlockId(block_id)
+    }
+}
+
+impl From<ConfirmationBlockTime> for StoredAnchor {
+    fn from(anchor: ConfirmationBlockTime) -> Self {
+        Self::ConfirmationBlockTime(anchor)
+    }
+}
+
+impl Store {
+    /// Read the anchors of every kind.
+    pub async fn read_anchors(&self) -> Result<BTreeSet<(StoredAnchor, Txid)>, Error> {
+        let rows = sqlx::query(
+            "SELECT block_height, block_hash, txid, confirmation_time, kind FROM anchor ORDER BY txid, block_height, block_hash",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut anchors = BTreeSet::new();
+        for row in rows {
+            let hash: String = row.get("block_hash");
+            let block_id = BlockId {
+                height: row.get("block_height"),
+                hash: hash.parse::<BlockHash>()?,
+            };
+            let txid: String = row.get("txid");
+            let kind: String = row.get("kind");
+            let confirmation_time: Option<i64> = row.get("confirmation_time");
+            let anchor = match (kind.as_str(), confirmation_time) {
+                ("block_id", _) => StoredAnchor::BlockId(block_id),
+                ("confirmation_block_time", Some(confirmation_time)) => {
+                    StoredAnchor::ConfirmationBlockTime(ConfirmationBlockTime {
+                        block_id,
+                        confirmation_time: from_sql("anchor.confirmation_time", confirmation_time)?,
+                    })
+                }
+                _ => {
+                    debug_assert!(false, "invalid anchor kind: {kind}");
+                    continue;
+                }
+            };
+            anchors.insert((anchor, txid.parse()?));
+        }
+
+        Ok(anchors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::tx_graph;
+    use bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn read_anchors_of_every_kind() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let block = |height: u32| BlockId {
+            height,
+            hash: Hash::hash(&height.to_le_bytes()),
+        };
+        let electrum: Txid = Hash::hash(b"electrum");
+        let cbf: Txid = Hash::hash(b"cbf");
+        let timed = ConfirmationBlockTime {
+            block_id: block(1),
+            confirmation_time: 100,
+        };
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        graph.anchors.insert((timed, electrum));
+        store.write_tx_graph(&graph).await?;
+        store
+            .write_untimed_anchors(&[(block(2), cbf)].into())
+            .await?;
+
+        let anchors = store.read_anchors().await?;
+        assert_eq!(
+            anchors,
+            [
+                (StoredAnchor::from(timed), electrum),
+                (StoredAnchor::from(block(2)), cbf),
+            ]
+            .into()
+        );
+        let richest: Vec<_> = anchors
+            .iter()
+            .map(|(anchor, _)| anchor.to_confirmation_block_time())
+            .collect();
+        assert!(richest.contains(&Some(timed)));
+        assert!(richest.contains(&None));
+
+        Ok(())
+    }
+}
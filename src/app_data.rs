@@ -0,0 +1,112 @@
+//! Namespaced key-value storage for application data.
+
+use serde::{Serialize, de::DeserializeOwned};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+
+impl Store {
+    /// Set the value of `key` in namespace `ns`, replacing any existing value.
+    ///
+    /// The value is stored as JSON in the `app_data` table.
+    pub async fn set_app_value<T: Serialize + ?Sized>(
+        &self,
+        ns: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let value = serde_json::to_string(value)?;
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO app_data(namespace, key, value) VALUES($1, $2, $3) ON CONFLICT DO UPDATE SET value = $3",
+            )
+            .bind(ns)
+            .bind(key)
+            .bind(value)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get the value of `key` in namespace `ns`, if any.
+    pub async fn get_app_value<T: DeserializeOwned>(
+        &self,
+        ns: &str,
+        key: &str,
+    ) -> Result<Option<T>, Error> {
+        let row = sqlx::query("SELECT value FROM app_data WHERE namespace = $1 AND key = $2")
+            .bind(ns)
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|row| {
+            let value: String = row.get("value");
+            serde_json::from_str(&value).map_err(Error::Json)
+        })
+        .transpose()
+    }
+
+    /// Remove `key` from namespace `ns`.
+    pub async fn remove_app_value(&self, ns: &str, key: &str) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query("DELETE FROM app_data WHERE namespace = $1 AND key = $2")
+                .bind(ns)
+                .bind(key)
+                .execute(&mut *conn)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// List the keys of namespace `ns`.
+    pub async fn app_keys(&self, ns: &str) -> Result<Vec<String>, Error> {
+        let rows = sqlx::query("SELECT key FROM app_data WHERE namespace = $1 ORDER BY key")
+            .bind(ns)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.get("key")).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Settings {
+        fee_rate: u64,
+        dark_mode: bool,
+    }
+
+    #[tokio::test]
+    async fn app_value_round_trip() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let settings = Settings {
+            fee_rate: 5,
+            dark_mode: true,
+        };
+        store.set_app_value("ui", "settings", &settings).await?;
+        store.set_app_value("other", "settings", &1).await?;
+
+        let value: Option<Settings> = store.get_app_value("ui", "settings").await?;
+        assert_eq!(value, Some(settings));
+        assert_eq!(store.app_keys("ui").await?, vec!["settings".to_string()]);
+
+        store.remove_app_value("ui", "settings").await?;
+        let value: Option<Settings> = store.get_app_value("ui", "settings").await?;
+        assert!(value.is_none());
+        let value: Option<u32> = store.get_app_value("other", "settings").await?;
+        assert_eq!(value, Some(1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+//! Archival of old confirmed transactions to a separate database.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use bdk_chain::bitcoin::{
+    Amount, BlockHash, OutPoint, ScriptBuf, Transaction, TxOut, Txid, consensus,
+};
+use bdk_chain::{BlockId, ConfirmationBlockTime, tx_graph};
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, from_sql_opt, to_sql};
+use crate::tx_summary::refresh_tx_summaries;
+
+/// Tables of the archive database, created when first archiving to it.
+const ARCHIVE_SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS archive.tx(
+        txid TEXT PRIMARY KEY NOT NULL,
+        tx BLOB,
+        first_seen INTEGER,
+        last_seen INTEGER,
+        last_evicted INTEGER,
+        archived_at INTEGER NOT NULL
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.anchor(
+        block_height INTEGER NOT NULL,
+        block_hash TEXT NOT NULL,
+        txid TEXT NOT NULL,
+        confirmation_time INTEGER,
+        PRIMARY KEY(block_height, block_hash, txid)
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.txin(
+        txid TEXT NOT NULL,
+        vin INTEGER NOT NULL,
+        prev_txid TEXT NOT NULL,
+        prev_vout INTEGER NOT NULL,
+        PRIMARY KEY(txid, vin)
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.tx_output(
+        txid TEXT NOT NULL,
+        vout INTEGER NOT NULL,
+        value INTEGER NOT NULL,
+        script BLOB NOT NULL,
+        PRIMARY KEY(txid, vout)
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.txout(
+        txid TEXT NOT NULL,
+        vout INTEGER NOT NULL,
+        value INTEGER NOT NULL,
+        script BLOB NOT NULL,
+        source TEXT,
+        PRIMARY KEY(txid, vout)
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.label(
+        type TEXT NOT NULL,
+        ref TEXT NOT NULL,
+        label TEXT NOT NULL,
+        encrypted INTEGER NOT NULL,
+        PRIMARY KEY(type, ref)
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.tx_meta(
+        txid TEXT PRIMARY KEY NOT NULL,
+        meta TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    ) STRICT",
+    "CREATE TABLE IF NOT EXISTS archive.output_tag(
+        txid TEXT NOT NULL,
+        vout INTEGER NOT NULL,
+        namespace TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value BLOB NOT NULL,
+        PRIMARY KEY(txid, vout, namespace, key)
+    ) STRICT",
+];
+
+/// Labels of the archived transactions and of their outputs.
+const ARCHIVED_LABELS: &str = "(type = 'tx' AND ref IN (SELECT txid FROM temp.archived))
+    OR (type = 'output' AND substr(ref, 1, 64) IN (SELECT txid FROM temp.archived))";
+
+/// Transactions confirmed in a block of the local chain below `$1` none of whose outputs
+/// paying to the spk cache is unspent, or spent by a transaction which isn't itself
+/// confirmed below `$1`.
+const ARCHIVABLE: &str = "SELECT tx.txid, tx.blob_id FROM tx
+    WHERE EXISTS(
+        SELECT 1 FROM anchor JOIN block ON block.height = anchor.block_height AND block.hash = anchor.block_hash
+        WHERE anchor.txid = tx.txid AND anchor.block_height < $1
+    )
+    AND NOT EXISTS(
+        SELECT 1 FROM v_utxos AS u WHERE u.txid = tx.txid AND NOT EXISTS(
+            SELECT 1 FROM anchor JOIN block ON block.height = anchor.block_height AND block.hash = anchor.block_hash
+            WHERE anchor.txid = u.spent_by AND anchor.block_height < $1
+        )
+    )";
+
+impl Store {
+    /// Move the history confirmed below `height` to the archive database at
+    /// `archive_path`, returning the number of transactions moved.
+    ///
+    /// A transaction is archived once it is confirmed in a block of the local chain below
+    /// `height` and each of its outputs paying to the spk cache is spent by a transaction
+    /// also confirmed below `height`, so the balance and the UTXOs of the wallet are
+    /// unaffected. The transaction, its anchors, inputs and outputs, as well as its txouts,
+    /// labels, metadata and output tags, are copied to the archive, which is created if it
+    /// doesn't exist, and deleted from this store in a single write. Archiving to the same
+    /// file again adds to it, so the archive can be filled in buckets of heights over time.
+    ///
+    /// The outputs spent by a transaction which isn't archived are kept as txouts, so that
+    /// the amounts sent by the remaining history are unchanged.
+    ///
+    /// The write is queued, checked and made durable like any other write of the store.
+    /// A sync which reports an archived transaction again writes it back to this store.
+    /// The archive of an in-memory store is attached in memory as well. Use
+    /// [`read_archive`](Self::read_archive) to query the archived history.
+    pub async fn archive_before(
+        &self,
+        height: u32,
+        archive_path: impl AsRef<Path>,
+    ) -> Result<u64, Error> {
+        let now = to_sql("tx.archived_at", self.now())?;
+        let attach = ("archive", archive_path.as_ref());
+        self.write_attached(WriteOptions::default(), Some(attach), async |conn| {
+            for sql in ARCHIVE_SCHEMA {
+                sqlx::query(sql).execute(&mut *conn).await?;
+            }
+
+            sqlx::query(&format!(
+                "CREATE TEMP TABLE archived AS SELECT * FROM ({ARCHIVABLE})"
+            ))
+            .bind(height)
+            .execute(&mut *conn)
+            .await?;
+            for sql in [
+                "INSERT OR IGNORE INTO archive.tx(txid, tx, first_seen, last_seen, last_evicted, archived_at) SELECT tx.txid, tx_blob.tx, first_seen, last_seen, last_evicted, $1 FROM tx LEFT JOIN tx_blob ON tx_blob.id = tx.blob_id WHERE txid IN (SELECT txid FROM temp.archived)",
+                "INSERT OR IGNORE INTO archive.anchor(block_height, block_hash, txid, confirmation_time) SELECT block_height, block_hash, txid, confirmation_time FROM main.anchor WHERE txid IN (SELECT txid FROM temp.archived)",
+                "INSERT OR IGNORE INTO archive.txin SELECT txid, vin, prev_txid, prev_vout FROM main.txin WHERE txid IN (SELECT txid FROM temp.archived)",
+                "INSERT OR IGNORE INTO archive.tx_output SELECT txid, vout, value, script FROM main.tx_output WHERE txid IN (SELECT txid FROM temp.archived)",
+                "INSERT OR IGNORE INTO archive.txout SELECT txid, vout, value, script, source FROM main.txout WHERE txid IN (SELECT txid FROM temp.archived)",
+                "INSERT OR REPLACE INTO archive.tx_meta SELECT txid, meta, version, updated_at FROM main.tx_meta WHERE txid IN (SELECT txid FROM temp.archived)",
+                "INSERT OR REPLACE INTO archive.output_tag SELECT txid, vout, namespace, key, value FROM main.output_tag WHERE txid IN (SELECT txid FROM temp.archived)",
+            ] {
+                sqlx::query(sql).bind(now).execute(&mut *conn).await?;
+            }
+            sqlx::query(&format!(
+                "INSERT OR REPLACE INTO archive.label SELECT type, ref, label, encrypted FROM main.label WHERE {ARCHIVED_LABELS}"
+            ))
+            .execute(&mut *conn)
+            .await?;
+
+            let rows = sqlx::query(
+                "SELECT DISTINCT txid FROM main.txin WHERE prev_txid IN (SELECT txid FROM temp.archived) AND txid NOT IN (SELECT txid FROM temp.archived)",
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            let spenders: BTreeSet<String> = rows.iter().map(|row| row.get("txid")).collect();
+
+            for table in [
+                "anchor",
+                "txin",
+                "txout",
+                "tx_summary",
+                "tx_meta",
+                "output_tag",
+            ] {
+                sqlx::query(&format!(
+                    "DELETE FROM main.{table} WHERE txid IN (SELECT txid FROM temp.archived)"
+                ))
+                .execute(&mut *conn)
+                .await?;
+            }
+            sqlx::query(&format!("DELETE FROM main.label WHERE {ARCHIVED_LABELS}"))
+                .execute(&mut *conn)
+                .await?;
+            // The outputs spent by the remaining history stay as txouts of the spends.
+            sqlx::query(
+                "INSERT OR IGNORE INTO main.txout(txid, vout, value, script)
+                SELECT o.txid, o.vout, o.value, o.script FROM main.tx_output AS o
+                JOIN main.txin ON txin.prev_txid = o.txid AND txin.prev_vout = o.vout
+                WHERE o.txid IN (SELECT txid FROM temp.archived)",
+            )
+            .execute(&mut *conn)
+            .await?;
+            sqlx::query(
+                "DELETE FROM main.tx_output WHERE txid IN (SELECT txid FROM temp.archived)",
+            )
+            .execute(&mut *conn)
+            .await?;
+            let res =
+                sqlx::query("DELETE FROM main.tx WHERE txid IN (SELECT txid FROM temp.archived)")
+                    .execute(&mut *conn)
+                    .await?;
+            sqlx::query("DELETE FROM main.tx_blob WHERE id IN (SELECT blob_id FROM temp.archived)")
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query("DROP TABLE temp.archived")
+                .execute(&mut *conn)
+                .await?;
+            refresh_tx_summaries(conn, &spenders).await?;
+
+            Ok(res.rows_affected())
+        })
+        .await
+    }
+
+    /// Read the transactions, seen times, anchors and txouts of the archive database at
+    /// `archive_path`, see [`archive_before`](Self::archive_before).
+    pub async fn read_archive(
+        &self,
+        archive_path: impl AsRef<Path>,
+    ) -> Result<tx_graph::ChangeSet<ConfirmationBlockTime>, Error> {
+        self.with_archive(archive_path.as_ref(), async |conn| {
+            let mut changeset = tx_graph::ChangeSet::default();
+
+            let rows = sqlx::query(
+                "SELECT txid, tx, first_seen, last_seen, last_evicted FROM archive.tx ORDER BY txid",
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            for row in rows {
+                let txid: String = row.get("txid");
+                let txid: Txid = txid.parse()?;
+                if let Some(data) = row.get::<Option<Vec<u8>>, _>("tx") {
+                    let tx: Transaction = consensus::encode::deserialize(&data)?;
+                    changeset.txs.insert(Arc::new(tx));
+                }
+                if let Some(t) = from_sql_opt("tx.first_seen", row.get("first_seen"))? {
+                    changeset.first_seen.insert(txid, t);
+                }
+                if let Some(t) = from_sql_opt("tx.last_seen", row.get("last_seen"))? {
+                    changeset.last_seen.insert(txid, t);
+                }
+                if let Some(t) = from_sql_opt("tx.last_evicted", row.get("last_evicted"))? {
+                    changeset.last_evicted.insert(txid, t);
+                }
+            }
+
+            let rows = sqlx::query(
+                "SELECT block_height, block_hash, txid, confirmation_time FROM archive.anchor WHERE confirmation_time IS NOT NULL ORDER BY txid, block_height, block_hash",
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            for row in rows {
+                let hash: String = row.get("block_hash");
+                let hash: BlockHash = hash.parse()?;
+                let txid: String = row.get("txid");
+                let anchor = ConfirmationBlockTime {
+                    block_id: BlockId {
+                        height: row.get("block_height"),
+                        hash,
+                    },
+                    confirmation_time: from_sql(
+                        "anchor.confirmation_time",
+                        row.get::<i64, _>("confirmation_time"),
+                    )?,
+                };
+                changeset.anchors.insert((anchor, txid.parse()?));
+            }
+
+            let rows =
+                sqlx::query("SELECT txid, vout, value, script FROM archive.txout ORDER BY txid, vout")
+                    .fetch_all(&mut *conn)
+                    .await?;
+            for row in rows {
+                let txid: String = row.get("txid");
+                let value: i64 = row.get("value");
+                changeset.txouts.insert(
+                    OutPoint::new(txid.parse()?, row.get("vout")),
+                    TxOut {
+                        value: Amount::from_sat(from_sql("txout.value", value)?),
+                        script_pubkey: ScriptBuf::from_bytes(row.get("script")),
+                    },
+                );
+            }
+
+            Ok(changeset)
+        })
+        .await
+    }
+
+    /// Run the read `f` on a connection with the database at `path` attached as `archive`.
+    async fn with_archive<T>(
+        &self,
+        path: &Path,
+        f: impl AsyncFnOnce(&mut SqliteConnection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.timed(self.timeout, async {
+            let mut conn = self.pool.acquire().await?;
+            sqlx::query("ATTACH DATABASE $1 AS archive")
+                .bind(path.to_string_lossy())
+                .execute(&mut *conn)
+                .await?;
+            let res = f(&mut conn).await;
+            let detached = sqlx::query("DETACH DATABASE archive")
+                .execute(&mut *conn)
+                .await;
+            let t = res?;
+            detached?;
+            Ok(t)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{TxIn, absolute, transaction};
+    use bdk_chain::{DescriptorId, keychain_txout, local_chain};
+
+    use crate::LabelRef;
+
+    fn tx(prevout: OutPoint, script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn archive_before() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = Store::new(&dir.path().join("hot.db").to_string_lossy()).await?;
+        store.migrate().await?;
+        let archive = dir.path().join("archive.db");
+
+        let ours = ScriptBuf::from_bytes(vec![0x51]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            DescriptorId::from_byte_array([1; 32]),
+            [(0, ours.clone())].into(),
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        // `spent` is spent by `spend` at height 2, whose output is unspent, and both are
+        // confirmed below height 5.
+        let spent = tx(OutPoint::new(Hash::hash(b"in"), 0), ours.clone());
+        let spend = tx(OutPoint::new(spent.compute_txid(), 0), ours.clone());
+        let blocks: Vec<_> = (0..10u32)
+            .map(|height| BlockId {
+                height,
+                hash: Hash::hash(&height.to_le_bytes()),
+            })
+            .collect();
+        store
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: blocks.iter().map(|b| (b.height, Some(b.hash))).collect(),
+            })
+            .await?;
+        let anchor = |height: usize| ConfirmationBlockTime {
+            block_id: blocks[height],
+            confirmation_time: 100 + height as u64,
+        };
+        let graph = tx_graph::ChangeSet {
+            txs: [Arc::new(spent.clone()), Arc::new(spend.clone())].into(),
+            anchors: [
+                (anchor(1), spent.compute_txid()),
+                (anchor(2), spend.compute_txid()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        store.write_tx_graph(&graph).await?;
+        let spent_txid = spent.compute_txid();
+        store.set_label(&LabelRef::Tx(spent_txid), "salary").await?;
+        store
+            .set_label(&LabelRef::Output(OutPoint::new(spent_txid, 0)), "payslip")
+            .await?;
+        store
+            .set_tx_meta(spent_txid, &serde_json::json!({}))
+            .await?;
+        let sent = store.tx_summary(spend.compute_txid()).await?.unwrap().sent;
+
+        assert_eq!(store.archive_before(2, &archive).await?, 0);
+        assert_eq!(store.archive_before(5, &archive).await?, 1);
+        let hot = store.read_tx_graph().await?;
+        assert_eq!(hot.txs.len(), 1);
+        assert!(hot.txs.contains(&Arc::new(spend.clone())));
+        assert!(store.tx_summary(spent_txid).await?.is_none());
+        assert!(store.read_labels().await?.is_empty());
+        assert!(store.get_tx_meta(spent_txid).await?.is_none());
+
+        // The spent output stays a txout of `spend`, which sends the same amount even once
+        // its summary is refreshed.
+        assert_eq!(
+            hot.txouts.keys().collect::<Vec<_>>(),
+            [&OutPoint::new(spent_txid, 0)]
+        );
+        assert_eq!(sent, Amount::from_sat(1_000));
+        let mut conn = store.pool.acquire().await?;
+        refresh_tx_summaries(&mut conn, &[spend.compute_txid().to_string()].into()).await?;
+        drop(conn);
+        assert_eq!(
+            store.tx_summary(spend.compute_txid()).await?.unwrap().sent,
+            sent
+        );
+
+        let archived = store.read_archive(&archive).await?;
+        assert_eq!(archived.txs.len(), 1);
+        assert!(archived.txs.contains(&Arc::new(spent.clone())));
+        assert_eq!(archived.anchors, [(anchor(1), spent_txid)].into());
+        let mut conn = store.pool.acquire().await?;
+        sqlx::query("ATTACH DATABASE $1 AS archive")
+            .bind(archive.to_string_lossy())
+            .execute(&mut *conn)
+            .await?;
+        let row = sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM archive.label), (SELECT COUNT(*) FROM archive.tx_meta)",
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        assert_eq!((row.get::<i64, _>(0), row.get::<i64, _>(1)), (2, 1));
+        sqlx::query("DETACH DATABASE archive")
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,36 +1,355 @@
 //! [`Store`] provides async read and write methods of persisting BDK change sets by way of [`sqlx`].
 
-use std::str::FromStr;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Duration;
 
 use bdk_chain::{
-    BlockId, ConfirmationBlockTime, DescriptorId, bitcoin, keychain_txout, local_chain, tx_graph,
+    BlockId, ConfirmationBlockTime, DescriptorId, Merge, bitcoin, indexed_tx_graph, keychain_txout,
+    local_chain, tx_graph,
 };
-use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, Transaction, TxOut, Txid, consensus};
+use bitcoin::{
+    Amount, BlockHash, Network, OutPoint, ScriptBuf, Transaction, TxOut, Txid, consensus,
+    p2p::Magic,
+};
+use futures_util::TryStreamExt;
 use sqlx::{
-    Row,
-    sqlite::{SqliteConnectOptions, SqlitePool as Pool},
+    Connection, FromRow, QueryBuilder, Row, Sqlite,
+    migrate::{Migration, Migrator},
+    pool::PoolConnection,
+    query::Query,
+    sqlite::{
+        SqliteArguments, SqliteConnectOptions, SqliteConnection, SqlitePool as Pool,
+        SqlitePoolOptions, SqliteRow,
+    },
 };
 
+use crate::Clock;
+use crate::Component;
+use crate::DataSource;
 use crate::Error;
+use crate::RetentionPolicy;
+use crate::SystemClock;
+use crate::convert::{from_sql, to_sql};
+use crate::progress::Progress;
+use crate::provenance::record_source_in;
+use crate::rt;
+use crate::tx_summary::{affected_by_scripts, affected_by_tx, refresh_tx_summaries};
+
+/// Migrations of the core schema, embedded from the `migrations` directory.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Migrations of the `encrypted_backup` table.
+#[cfg(feature = "encrypted-backup")]
+static ENCRYPTED_BACKUP_MIGRATOR: Migrator = sqlx::migrate!("./migrations/encrypted-backup");
+
+/// Migrations of the `http_cache` table.
+#[cfg(feature = "http-cache")]
+static HTTP_CACHE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/http-cache");
+
+/// The migrations of the core schema and of the enabled features, ordered by version.
+///
+/// The migrations of a feature are in a subdirectory of `migrations` named after it, and
+/// share the version sequence of the core schema, so that they are applied after the
+/// migrations they depend on. Applied migrations of disabled features are ignored, so a
+/// database migrated with a feature can still be opened without it.
+pub(crate) fn migrator() -> Migrator {
+    let migrations = MIGRATOR.iter();
+    #[cfg(feature = "encrypted-backup")]
+    let migrations = migrations.chain(ENCRYPTED_BACKUP_MIGRATOR.iter());
+    #[cfg(feature = "http-cache")]
+    let migrations = migrations.chain(HTTP_CACHE_MIGRATOR.iter());
+    let mut migrations: Vec<Migration> = migrations.cloned().collect();
+    migrations.sort_by_key(|migration| migration.version);
+
+    Migrator {
+        migrations: migrations.into(),
+        ignore_missing: true,
+        ..Migrator::DEFAULT
+    }
+}
 
 /// Store.
+///
+/// Reads are deterministic: rows are read in an explicit order, e.g. transactions by txid
+/// and blocks by height, so the same database yields identical results on any machine.
 #[derive(Debug, Clone)]
 pub struct Store {
     /// Pool.
     pub(crate) pool: Pool,
+    /// Default timeout of store operations.
+    pub(crate) timeout: Option<Duration>,
+    /// Whether to record removed blocks in the `block_orphaned` table.
+    pub(crate) block_tombstones: bool,
+    /// Default durability of writes.
+    pub(crate) durability: Option<Durability>,
+    /// Whether to read independent tables concurrently.
+    pub(crate) parallel_reads: bool,
+    /// Data to discard after every write.
+    pub(crate) retention: Option<RetentionPolicy>,
+    /// Number of sync attempts to keep in the `sync_log` table.
+    pub(crate) sync_log_capacity: usize,
+    /// Whether to skip writing a wallet changeset identical to the last one committed.
+    #[cfg(feature = "wallet")]
+    pub(crate) changeset_dedup: bool,
+    /// Source of the timestamps recorded by the store.
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Tenant the store was opened for by [`TenantDir`](crate::TenantDir).
+    pub(crate) tenant: Option<String>,
+    /// Sink of committed changesets.
+    #[cfg(feature = "wallet")]
+    pub(crate) replication: Option<Arc<dyn crate::ReplicationSink>>,
+    /// Whether to validate every wallet changeset written.
+    #[cfg(feature = "wallet")]
+    pub(crate) validate: bool,
+    /// Check of the spk cache before a wallet changeset is read.
+    #[cfg(feature = "wallet")]
+    pub(crate) verify_spk_cache: Option<crate::SpkCheck>,
+    /// Scheme generating the ids of wallets.
+    #[cfg(feature = "wallet")]
+    pub(crate) wallet_id_scheme: Option<Arc<dyn crate::WalletIdScheme>>,
+    /// Network writes are restricted to.
+    pub(crate) network: Option<Network>,
+    /// Whether to identify a stored network of an unknown name by its magic bytes.
+    pub(crate) lenient_network: bool,
+    /// Retries of opening operations failing with [`Error::DatabaseLocked`].
+    pub(crate) lock_retry: LockRetry,
+    /// Limits of the size of a single write.
+    pub(crate) write_limits: WriteLimits,
+    /// Version of the metadata schema recorded with transaction metadata.
+    pub(crate) tx_meta_version: u32,
+    /// Queue of the writes of the store and its clones.
+    pub(crate) write_queue: Option<Arc<crate::write_queue::WriteQueue>>,
+    /// Key encrypting labels.
+    #[cfg(feature = "label-encryption")]
+    pub(crate) label_key: Option<crate::LabelKey>,
+}
+
+/// Durability of a write, applied by way of `PRAGMA synchronous`.
+///
+/// See <https://www.sqlite.org/pragma.html#pragma_synchronous>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Sync at every critical moment. Survives power loss.
+    Full,
+    /// Sync less often. With WAL journaling, a power loss may roll back the latest writes
+    /// but can't corrupt the database.
+    Normal,
+    /// Don't sync, leaving it to the OS. Fastest, but a power loss or OS crash may corrupt
+    /// the database.
+    Off,
+}
+
+impl Durability {
+    fn pragma_value(&self) -> &'static str {
+        match self {
+            Self::Full => "FULL",
+            Self::Normal => "NORMAL",
+            Self::Off => "OFF",
+        }
+    }
+}
+
+/// Number of rows changed in a single table by a write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableChanges {
+    /// Rows inserted
+    pub inserted: u64,
+    /// Rows updated
+    pub updated: u64,
+    /// Rows deleted
+    pub deleted: u64,
+}
+
+/// Rows changed by a write, per table.
+///
+/// Writes which leave a row as it was, e.g. re-inserting a known transaction, don't count as
+/// changes. A row inserted and then updated by the same write is counted as both.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteSummary {
+    /// Changes keyed by table name
+    pub tables: BTreeMap<&'static str, TableChanges>,
+}
+
+impl WriteSummary {
+    /// Changes to `table`.
+    pub fn table(&self, table: &str) -> TableChanges {
+        self.tables.get(table).copied().unwrap_or_default()
+    }
+
+    /// Whether the write changed no rows.
+    pub fn is_empty(&self) -> bool {
+        self.tables.values().all(|c| *c == TableChanges::default())
+    }
+
+    /// Add the changes of `other` to `self`.
+    pub fn merge(&mut self, other: WriteSummary) {
+        for (table, changes) in other.tables {
+            let c = self.table_mut(table);
+            c.inserted += changes.inserted;
+            c.updated += changes.updated;
+            c.deleted += changes.deleted;
+        }
+    }
+
+    pub(crate) fn table_mut(&mut self, table: &'static str) -> &mut TableChanges {
+        self.tables.entry(table).or_default()
+    }
+}
+
+/// A block which was removed from the local chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrphanedBlock {
+    /// Block id
+    pub block_id: BlockId,
+    /// Unix time at which the block was removed
+    pub orphaned_at: u64,
+}
+
+/// Options of a single write.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct WriteOptions {
+    /// Timeout of the write, overriding the default timeout of the [`Store`].
+    pub timeout: Option<Duration>,
+    /// Durability of the write, overriding the default durability of the [`Store`].
+    pub durability: Option<Durability>,
+    /// Source of the transaction data of the write, recorded along with the transactions,
+    /// txouts and anchors it provides, see [`Store::tx_source`].
+    pub source: Option<DataSource>,
+    /// Whether the write maintains the hash of the last changeset written itself, rather
+    /// than clearing it, see [`Store::with_changeset_dedup`].
+    pub(crate) keep_changeset_hash: bool,
+}
+
+/// Retries of an operation failing with [`Error::DatabaseLocked`], see
+/// [`Store::with_lock_retry`].
+///
+/// Each retry waits for an exponentially growing delay with random jitter, so that
+/// processes racing for the database don't retry in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockRetry {
+    /// Maximum number of attempts, including the first one.
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for every further retry.
+    pub base_delay: Duration,
+}
+
+impl Default for LockRetry {
+    /// 5 attempts, waiting 100ms before the first retry.
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl LockRetry {
+    /// Set the maximum number of attempts.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Delay before retry number `retry`, counting from 0: a random duration between
+    /// half and all of `base_delay * 2^retry`, or half of it if the operating system
+    /// provides no randomness.
+    fn delay(&self, retry: u32) -> Duration {
+        let max = self.base_delay.saturating_mul(1 << retry.min(16));
+        let mut random = [0; 2];
+        if getrandom::getrandom(&mut random).is_err() {
+            return max / 2;
+        }
+        max / 2 + max.mul_f64((u16::from_le_bytes(random) % 1000) as f64 / 2000.0)
+    }
+}
+
+/// Limits of the size of a single write, see [`Store::with_write_limits`].
+///
+/// A write exceeding a limit fails with [`Error::ChangesetTooLarge`] before anything is
+/// written. No limit is set by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct WriteLimits {
+    /// Maximum number of full transactions written at once.
+    pub max_txs: Option<usize>,
+    /// Maximum size of a full transaction in bytes, as serialized.
+    pub max_tx_size: Option<usize>,
+}
+
+impl WriteLimits {
+    /// Set the maximum number of full transactions written at once.
+    pub fn max_txs(mut self, max: usize) -> Self {
+        self.max_txs = Some(max);
+        self
+    }
+
+    /// Set the maximum size of a full transaction in bytes.
+    pub fn max_tx_size(mut self, max: usize) -> Self {
+        self.max_tx_size = Some(max);
+        self
+    }
+
+    /// Fail with [`Error::ChangesetTooLarge`] if `tx_graph` exceeds the limits.
+    fn check(&self, tx_graph: &tx_graph::ChangeSet<ConfirmationBlockTime>) -> Result<(), Error> {
+        if let Some(max) = self.max_txs.filter(|&max| tx_graph.txs.len() > max) {
+            return Err(Error::ChangesetTooLarge {
+                limit: "max_txs",
+                size: tx_graph.txs.len(),
+                max,
+            });
+        }
+        if let Some(max) = self.max_tx_size {
+            if let Some(size) = tx_graph
+                .txs
+                .iter()
+                .map(|tx| tx.total_size())
+                .find(|&size| size > max)
+            {
+                return Err(Error::ChangesetTooLarge {
+                    limit: "max_tx_size",
+                    size,
+                    max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteOptions {
+    /// Set the timeout of the write.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the durability of the write.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = Some(durability);
+        self
+    }
+
+    /// Set the source of the transaction data of the write.
+    pub fn source(mut self, source: DataSource) -> Self {
+        self.source = Some(source);
+        self
+    }
 }
 
 impl Store {
     /// New in memory.
     pub async fn new_memory() -> Result<Self, Error> {
-        let mut options = sqlx::sqlite::SqlitePoolOptions::new();
-        // Don't test the health of the connection before returning it.
-        // See docs for `Pool::acquire`.
-        options = options.test_before_acquire(false);
-        let pool = options.connect("sqlite::memory:").await?;
-
-        Ok(Self { pool })
+        Self::memory_builder().build().await
     }
 
     /// Create a new [`Store`] instance.
@@ -39,23 +358,428 @@ impl Store {
     ///
     /// Note that `path` can be a filename, e.g. `foo.db` or a standard URL,
     /// e.g. `sqlite://foo.db`.
+    ///
+    /// Use [`Store::builder`] to configure the connection pool.
     pub async fn new(path: &str) -> Result<Self, Error> {
-        let options = SqliteConnectOptions::from_str(path)?.create_if_missing(true);
-        let pool = Pool::connect_with(options).await?;
+        Self::builder(path).build().await
+    }
+
+    /// Create a new [`Store`] with full control over the connection setup.
+    ///
+    /// Use this to configure e.g. extensions, collations, pragmas or the size of the pool.
+    /// Note that unlike [`Store::new`], the database is only created if missing when
+    /// `connect_options` says so, and the crate's SQL functions are only available if
+    /// registered with [`register_functions`](crate::register_functions).
+    pub async fn new_with_options(
+        connect_options: SqliteConnectOptions,
+        pool_options: SqlitePoolOptions,
+    ) -> Result<Self, Error> {
+        let pool = pool_options.connect_with(connect_options).await?;
 
-        Ok(Self { pool })
+        Ok(Self::from_pool(pool))
     }
 
     /// Create a new [`Store`] from an existing [`Pool`].
+    ///
+    /// The crate's SQL functions, e.g. `spk_to_address`, are only available on the
+    /// connections of `pool` if registered with [`register_functions`](crate::register_functions),
+    /// e.g. from the [`after_connect`](sqlx::pool::PoolOptions::after_connect) callback of
+    /// the pool options. The store itself doesn't rely on them.
     pub async fn new_pool(pool: Pool) -> Result<Self, Error> {
-        let store = Self { pool };
+        let store = Self::from_pool(pool);
 
         Ok(store)
     }
 
+    pub(crate) fn from_pool(pool: Pool) -> Self {
+        Self {
+            pool,
+            timeout: None,
+            block_tombstones: false,
+            durability: None,
+            parallel_reads: true,
+            retention: None,
+            sync_log_capacity: 100,
+            #[cfg(feature = "wallet")]
+            changeset_dedup: false,
+            clock: Arc::new(SystemClock),
+            tenant: None,
+            #[cfg(feature = "wallet")]
+            replication: None,
+            #[cfg(feature = "wallet")]
+            validate: false,
+            #[cfg(feature = "wallet")]
+            verify_spk_cache: None,
+            #[cfg(feature = "wallet")]
+            wallet_id_scheme: None,
+            network: None,
+            lenient_network: false,
+            lock_retry: LockRetry::default(),
+            write_limits: WriteLimits::default(),
+            tx_meta_version: 0,
+            write_queue: None,
+            #[cfg(feature = "label-encryption")]
+            label_key: None,
+        }
+    }
+
+    /// Pass every changeset committed by [`write_changeset`](Self::write_changeset) to
+    /// `sink`, see [`ReplicationSink`](crate::ReplicationSink).
+    #[cfg(feature = "wallet")]
+    pub fn with_replication(mut self, sink: impl crate::ReplicationSink + 'static) -> Self {
+        self.replication = Some(Arc::new(sink));
+        self
+    }
+
+    /// Restrict the store to `network`.
+    ///
+    /// Every write first checks that the stored network, if any, is `network`, failing with
+    /// [`Error::NetworkMismatch`] otherwise, and writing another network fails the same way.
+    /// A service running variants for several networks can't mix up their databases this
+    /// way, e.g. write signet data to a mainnet wallet.
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Set whether a stored network whose name isn't known is identified by its magic bytes.
+    ///
+    /// The network is stored both as its name and as the magic bytes of its P2P messages.
+    /// Reading a name which isn't known, e.g. one written by a later version, fails with
+    /// [`Error::UnknownNetwork`] by default. In lenient mode the network is taken from its
+    /// magic bytes or from the alias used by Bitcoin Core's `-chain` argument instead, and
+    /// only fails if neither is known either. Defaults to `false`.
+    pub fn with_lenient_network(mut self, lenient: bool) -> Self {
+        self.lenient_network = lenient;
+        self
+    }
+
+    /// Set the retries of [`migrate`](Self::migrate) and of the initial read of a wallet
+    /// failing with [`Error::DatabaseLocked`], e.g. on mobile when an app is relaunched
+    /// while the previous process still holds the database. Defaults to
+    /// [`LockRetry::default`].
+    pub fn with_lock_retry(mut self, retry: LockRetry) -> Self {
+        self.lock_retry = retry;
+        self
+    }
+
+    /// Set the limits of the size of a single write, see [`WriteLimits`].
+    ///
+    /// This protects devices with little memory from a pathological changeset, e.g. the
+    /// history of a wallet hit by a dusting attack, which would run out of memory or time
+    /// out in a single transaction. Such a changeset fails with
+    /// [`Error::ChangesetTooLarge`] and can be written with
+    /// [`write_changeset_chunked`](Self::write_changeset_chunked) instead, with a chunk size
+    /// of at most [`WriteLimits::max_txs`].
+    pub fn with_write_limits(mut self, limits: WriteLimits) -> Self {
+        self.write_limits = limits;
+        self
+    }
+
+    /// Run `f`, retrying it according to the [`LockRetry`] of the store while it fails
+    /// with [`Error::DatabaseLocked`].
+    pub(crate) async fn retry_locked<T, F>(&self, f: impl Fn() -> F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let mut retry = 0;
+        loop {
+            match f().await {
+                Err(Error::DatabaseLocked(_)) if retry + 1 < self.lock_retry.attempts => {
+                    rt::sleep(self.lock_retry.delay(retry)).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Seconds since the unix epoch according to the store's [`Clock`].
+    pub(crate) fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Read the current time of the timestamps the store records from `clock`, e.g. a
+    /// [`FixedClock`](crate::FixedClock) in tests. Defaults to [`SystemClock`].
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Apply the retention `policy` after every write, in the same transaction.
+    pub fn with_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Set the number of sync attempts kept by [`record_sync`](Self::record_sync).
+    ///
+    /// Defaults to 100.
+    pub fn with_sync_log_capacity(mut self, capacity: usize) -> Self {
+        self.sync_log_capacity = capacity;
+        self
+    }
+
+    /// Set whether [`write_changeset`](Self::write_changeset) skips a changeset identical
+    /// to the last one it committed.
+    ///
+    /// The hash of the last committed changeset is persisted, so a sync loop producing the
+    /// same changeset over and over doesn't rewrite it, even across restarts. This saves
+    /// writes on flash storage at the cost of serializing and hashing every changeset.
+    /// Every other write of the store, e.g. [`write_tx_graph`](Self::write_tx_graph),
+    /// clears the hash, so the next changeset is written. Defaults to `false`.
+    #[cfg(feature = "wallet")]
+    pub fn with_changeset_dedup(mut self, dedup: bool) -> Self {
+        self.changeset_dedup = dedup;
+        self
+    }
+
+    /// Set whether reads of independent tables, e.g. by
+    /// [`read_changeset`](Self::read_changeset), run concurrently on separate pooled
+    /// connections.
+    ///
+    /// This is enabled by default except for [`new_memory`](Self::new_memory). Disabling
+    /// it avoids contention for pools with a single connection.
+    pub fn with_parallel_reads(mut self, enabled: bool) -> Self {
+        self.parallel_reads = enabled;
+        self
+    }
+
+    /// Set the default durability of writes.
+    ///
+    /// By default the `synchronous` setting of the connection is left as configured, which
+    /// is [`Durability::Full`] unless set otherwise in the connect options.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = Some(durability);
+        self
+    }
+
+    /// Record blocks removed by [`write_local_chain`](Self::write_local_chain) in the
+    /// `block_orphaned` table instead of discarding them.
+    ///
+    /// Recorded blocks can be read back with [`orphaned_blocks`](Self::orphaned_blocks).
+    pub fn with_block_tombstones(mut self, enabled: bool) -> Self {
+        self.block_tombstones = enabled;
+        self
+    }
+
+    /// Set the default timeout of store operations.
+    ///
+    /// Operations which don't complete in time fail with [`Error::Timeout`]. By default
+    /// operations never time out.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run `fut` to completion, failing with [`Error::Timeout`] if it takes longer than
+    /// `timeout`.
+    ///
+    /// A timeout acquiring a connection from the pool is reported with the limits of the
+    /// pool.
+    pub(crate) async fn timed<T>(
+        &self,
+        timeout: Option<Duration>,
+        fut: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let res = match timeout {
+            Some(timeout) => rt::timeout(timeout, fut)
+                .await
+                .map_err(|_| Error::Timeout(timeout))?,
+            None => fut.await,
+        };
+
+        res.map_err(|e| match e {
+            Error::PoolTimeout { .. } => {
+                let options = self.pool.options();
+                Error::PoolTimeout {
+                    max_connections: Some(options.get_max_connections()),
+                    acquire_timeout: Some(options.get_acquire_timeout()),
+                }
+            }
+            e => e,
+        })
+    }
+
+    /// Run the write `f` in a single transaction, applying `opts`.
+    ///
+    /// With [`with_fair_writes`](Self::with_fair_writes), the write first waits for its
+    /// turn, which counts towards the timeout.
+    ///
+    /// If a durability applies, the `synchronous` setting of the connection is restored once
+    /// the write completes. If the write is cancelled, e.g. by a timeout, the connection keeps
+    /// the setting until its next write. If the setting can't be restored, the connection is
+    /// closed rather than returned to the pool, and the outcome of the write is returned.
+    pub(crate) async fn write<T>(
+        &self,
+        opts: WriteOptions,
+        f: impl AsyncFnOnce(&mut SqliteConnection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.write_attached(opts, None, f).await
+    }
+
+    /// Like [`write`](Self::write), with the database at `path` attached as `schema` for
+    /// the duration of the write if `attach` is `(schema, path)`.
+    ///
+    /// A database can't be attached within a transaction, so it is attached to the
+    /// connection before the transaction begins and detached after it completes. If it
+    /// can't be detached, the connection is closed rather than returned to the pool.
+    pub(crate) async fn write_attached<T>(
+        &self,
+        opts: WriteOptions,
+        attach: Option<(&str, &std::path::Path)>,
+        f: impl AsyncFnOnce(&mut SqliteConnection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.timed(opts.timeout.or(self.timeout), async {
+            let _turn = self.write_turn().await;
+            let mut conn = self.pool.acquire().await?;
+            let Some((schema, path)) = attach else {
+                return self.write_on(&mut conn, opts, f).await;
+            };
+
+            sqlx::query(&format!("ATTACH DATABASE $1 AS {schema}"))
+                .bind(path.to_string_lossy())
+                .execute(&mut *conn)
+                .await?;
+            let res = self.write_on(&mut conn, opts, f).await;
+            let detached = sqlx::query(&format!("DETACH DATABASE {schema}"))
+                .execute(&mut *conn)
+                .await;
+            if detached.is_err() {
+                conn.close_on_drop();
+            }
+            res
+        })
+        .await
+    }
+
+    /// Run the write `f` in a single transaction on `conn`, see [`write`](Self::write).
+    async fn write_on<T>(
+        &self,
+        conn: &mut PoolConnection<Sqlite>,
+        opts: WriteOptions,
+        f: impl AsyncFnOnce(&mut SqliteConnection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let restore = match opts.durability.or(self.durability) {
+            Some(durability) => {
+                let row = sqlx::query("PRAGMA synchronous")
+                    .fetch_one(&mut **conn)
+                    .await?;
+                let prev: i64 = row.get(0);
+                sqlx::query(&format!(
+                    "PRAGMA synchronous = {}",
+                    durability.pragma_value()
+                ))
+                .execute(&mut **conn)
+                .await?;
+                Some(prev)
+            }
+            None => None,
+        };
+
+        let res = async {
+            let mut tx = conn.begin().await?;
+            if let Some(network) = self.network {
+                check_network(&mut tx, network, self.lenient_network).await?;
+            }
+            // The store no longer matches the last changeset written to it.
+            if !opts.keep_changeset_hash {
+                sqlx::query("DELETE FROM changeset_hash")
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            let t = f(&mut tx).await?;
+            if let Some(policy) = &self.retention {
+                policy.apply(&mut tx, self.now()).await?;
+            }
+            tx.commit().await?;
+            Ok(t)
+        }
+        .await;
+
+        if let Some(prev) = restore {
+            let restored = sqlx::query(&format!("PRAGMA synchronous = {prev}"))
+                .execute(&mut **conn)
+                .await;
+            if restored.is_err() {
+                conn.close_on_drop();
+            }
+        }
+        res
+    }
+
     /// Runs pending migrations against the database.
+    ///
+    /// Only the tables of the enabled features are created, e.g. the `http_cache` table with
+    /// the `http-cache` feature. This also backfills the computed columns and the
+    /// transaction summaries of rows written by earlier versions.
+    ///
+    /// The database is then stamped with [`APPLICATION_ID`](crate::APPLICATION_ID) and the
+    /// version of the last migration as `user_version`, see
+    /// [`DatabaseStamp`](crate::DatabaseStamp).
+    ///
+    /// Fails with [`Error::StrictRejected`] once the migration is complete if it set aside
+    /// rows of a database created by an earlier version, see
+    /// [`strict_rejected`](Self::strict_rejected).
+    ///
+    /// Retried while the database is locked, see [`with_lock_retry`](Self::with_lock_retry).
     pub async fn migrate(&self) -> Result<(), Error> {
-        Ok(sqlx::migrate!().run(&self.pool).await?)
+        self.retry_locked(|| async {
+            let rejected = self.strict_rejected_count().await?;
+            migrator().run(&self.pool).await?;
+            self.stamp().await?;
+            self.backfill_tx_derived().await?;
+            self.backfill_tx_summary().await?;
+            let rows = self.strict_rejected_count().await? - rejected;
+            if rows > 0 {
+                return Err(Error::StrictRejected { rows });
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Populate the data derived from full transactions, i.e. the computed columns of the
+    /// `tx` table and the `tx_output` and `txin` tables, for transactions written by
+    /// earlier versions.
+    ///
+    /// The transactions are read in batches of [`BACKFILL_BATCH`], each written by a write
+    /// of its own, which applies the retention policy like any other.
+    async fn backfill_tx_derived(&self) -> Result<(), Error> {
+        let mut last = String::new();
+        loop {
+            let rows = sqlx::query("SELECT txid, tx_blob.tx FROM tx JOIN tx_blob ON tx_blob.id = tx.blob_id WHERE txid > $1 AND (weight IS NULL OR NOT EXISTS(SELECT 1 FROM tx_output WHERE tx_output.txid = tx.txid)) ORDER BY txid LIMIT $2")
+                .bind(&last)
+                .bind(BACKFILL_BATCH)
+                .fetch_all(&self.pool)
+                .await?;
+            let Some(row) = rows.last() else { break };
+            last = row.get("txid");
+
+            self.write(WriteOptions::default(), async |conn| {
+                for row in &rows {
+                    let txid: String = row.get("txid");
+                    let data: Vec<u8> = row.get("tx");
+                    let tx: Transaction = consensus::encode::deserialize(&data)?;
+                    let stats = TxStats::new(&tx)?;
+                    sqlx::query("UPDATE tx SET weight = $2, vsize = $3, input_count = $4, output_count = $5 WHERE txid = $1")
+                        .bind(&txid)
+                        .bind(stats.weight)
+                        .bind(stats.vsize)
+                        .bind(stats.input_count)
+                        .bind(stats.output_count)
+                        .execute(&mut *conn)
+                        .await?;
+                    write_tx_outputs(conn, &txid, &tx).await?;
+                    write_tx_inputs(conn, &txid, &tx).await?;
+                }
+                Ok(())
+            })
+            .await?;
+        }
+
+        Ok(())
     }
 }
 
@@ -64,7 +788,87 @@ impl Store {
     pub async fn write_tx_graph(
         &self,
         tx_graph: &tx_graph::ChangeSet<ConfirmationBlockTime>,
-    ) -> Result<(), Error> {
+    ) -> Result<WriteSummary, Error> {
+        self.write_tx_graph_with(tx_graph, WriteOptions::default())
+            .await
+    }
+
+    /// Write tx_graph with the given [`WriteOptions`].
+    pub async fn write_tx_graph_with(
+        &self,
+        tx_graph: &tx_graph::ChangeSet<ConfirmationBlockTime>,
+        opts: WriteOptions,
+    ) -> Result<WriteSummary, Error> {
+        self.write(opts, async |conn| {
+            let summary = self.write_tx_graph_in(conn, tx_graph).await?;
+            if let Some(source) = opts.source {
+                record_source_in(conn, tx_graph, source).await?;
+            }
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Write anchors of which the confirmation time is unknown, e.g. because the chain
+    /// source provides block ids only.
+    ///
+    /// These are stored with a NULL `confirmation_time`, which is filled in if the same
+    /// anchor is later written with a confirmation time. Anchors which already have a
+    /// confirmation time are left as is.
+    pub async fn write_untimed_anchors(
+        &self,
+        anchors: &BTreeSet<(BlockId, Txid)>,
+    ) -> Result<WriteSummary, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let mut summary = WriteSummary::default();
+            for (block_id, txid) in anchors {
+                let res = sqlx::query(
+                    "INSERT OR IGNORE INTO anchor(block_height, block_hash, txid, kind) VALUES($1, $2, $3, 'block_id')",
+                )
+                .bind(block_id.height)
+                .bind(block_id.hash.to_string())
+                .bind(txid.to_string())
+                .execute(&mut *conn)
+                .await?;
+                summary.table_mut("anchor").inserted += res.rows_affected();
+            }
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Write local_chain.
+    pub async fn write_local_chain(
+        &self,
+        local_chain: &local_chain::ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            self.write_local_chain_in(conn, local_chain).await
+        })
+        .await
+    }
+
+    /// Write keychain_txout.
+    pub async fn write_keychain_txout(
+        &self,
+        keychain_txout: &keychain_txout::ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            self.write_keychain_txout_in(conn, keychain_txout).await
+        })
+        .await
+    }
+
+    pub(crate) async fn write_tx_graph_in(
+        &self,
+        conn: &mut SqliteConnection,
+        tx_graph: &tx_graph::ChangeSet<ConfirmationBlockTime>,
+    ) -> Result<WriteSummary, Error> {
+        self.write_limits.check(tx_graph)?;
+        let mut summary = WriteSummary::default();
+        if !tx_graph.is_empty() {
+            self.touch_persisted(conn, Component::Graph).await?;
+        }
         let txs = &tx_graph.txs;
         let txouts = &tx_graph.txouts;
         let anchors = &tx_graph.anchors;
@@ -73,35 +877,64 @@ impl Store {
         let last_evicted = &tx_graph.last_evicted;
 
         for tx in txs {
-            let txid = tx.compute_txid();
-            sqlx::query(
-                "INSERT INTO tx(txid, tx) VALUES($1, $2) ON CONFLICT DO UPDATE SET tx = $2",
-            )
-            .bind(txid.to_string())
-            .bind(consensus::encode::serialize(tx))
-            .execute(&self.pool)
-            .await?;
+            let txid = tx.compute_txid().to_string();
+            let data = consensus::encode::serialize(tx);
+            let stats = TxStats::new(tx)?;
+            write_tx_blob(conn, &mut summary, &txid, &data, &stats).await?;
+            summary.table_mut("tx_output").inserted += write_tx_outputs(conn, &txid, tx).await?;
+            summary.table_mut("txin").inserted += write_tx_inputs(conn, &txid, tx).await?;
         }
         for (txid, t) in first_seen {
-            sqlx::query("INSERT INTO tx(txid, first_seen) VALUES($1, $2) ON CONFLICT DO UPDATE SET first_seen = $2")
-                .bind(txid.to_string())
-                .bind(i64::try_from(*t)?)
-                .execute(&self.pool)
-                .await?;
+            let txid = txid.to_string();
+            let t = to_sql("tx.first_seen", *t)?;
+            upsert(
+                conn,
+                &mut summary,
+                "tx",
+                sqlx::query("INSERT OR IGNORE INTO tx(txid, first_seen) VALUES($1, $2)")
+                    .bind(&txid)
+                    .bind(t),
+                sqlx::query(
+                    "UPDATE tx SET first_seen = $2 WHERE txid = $1 AND first_seen IS NOT $2",
+                )
+                .bind(&txid)
+                .bind(t),
+            )
+            .await?;
         }
         for (txid, t) in last_seen {
-            sqlx::query("INSERT INTO tx(txid, last_seen) VALUES($1, $2) ON CONFLICT DO UPDATE SET last_seen = $2")
-                .bind(txid.to_string())
-                .bind(i64::try_from(*t)?)
-                .execute(&self.pool)
-                .await?;
+            let txid = txid.to_string();
+            let t = to_sql("tx.last_seen", *t)?;
+            upsert(
+                conn,
+                &mut summary,
+                "tx",
+                sqlx::query("INSERT OR IGNORE INTO tx(txid, last_seen) VALUES($1, $2)")
+                    .bind(&txid)
+                    .bind(t),
+                sqlx::query("UPDATE tx SET last_seen = $2 WHERE txid = $1 AND last_seen IS NOT $2")
+                    .bind(&txid)
+                    .bind(t),
+            )
+            .await?;
         }
         for (txid, t) in last_evicted {
-            sqlx::query("INSERT INTO tx(txid, last_evicted) VALUES($1, $2) ON CONFLICT DO UPDATE SET last_evicted = $2")
-                .bind(txid.to_string())
-                .bind(i64::try_from(*t)?)
-                .execute(&self.pool)
-                .await?;
+            let txid = txid.to_string();
+            let t = to_sql("tx.last_evicted", *t)?;
+            upsert(
+                conn,
+                &mut summary,
+                "tx",
+                sqlx::query("INSERT OR IGNORE INTO tx(txid, last_evicted) VALUES($1, $2)")
+                    .bind(&txid)
+                    .bind(t),
+                sqlx::query(
+                    "UPDATE tx SET last_evicted = $2 WHERE txid = $1 AND last_evicted IS NOT $2",
+                )
+                .bind(&txid)
+                .bind(t),
+            )
+            .await?;
         }
         for (op, txout) in txouts {
             let OutPoint { txid, vout } = op;
@@ -109,121 +942,272 @@ impl Store {
                 value,
                 script_pubkey,
             } = txout;
-            sqlx::query("INSERT INTO txout(txid, vout, value, script) VALUES($1, $2, $3, $4) ON CONFLICT DO UPDATE SET value = $3, script = $4")
-                .bind(txid.to_string())
-                .bind(vout)
-                .bind(i64::try_from(value.to_sat())?)
-                .bind(script_pubkey.to_bytes())
-                .execute(&self.pool)
-                .await?;
+            let txid = txid.to_string();
+            let value = to_sql("txout.value", value.to_sat())?;
+            let script = script_pubkey.to_bytes();
+            upsert(
+                conn,
+                &mut summary,
+                "txout",
+                sqlx::query("INSERT OR IGNORE INTO txout(txid, vout, value, script) VALUES($1, $2, $3, $4)")
+                    .bind(&txid)
+                    .bind(vout)
+                    .bind(value)
+                    .bind(&script),
+                sqlx::query("UPDATE txout SET value = $3, script = $4 WHERE txid = $1 AND vout = $2 AND (value IS NOT $3 OR script IS NOT $4)")
+                    .bind(&txid)
+                    .bind(vout)
+                    .bind(value)
+                    .bind(&script),
+            )
+            .await?;
         }
         for (anchor, txid) in anchors {
             let BlockId { height, hash } = anchor.block_id;
-            let confirmation_time = anchor.confirmation_time;
-            sqlx::query("INSERT OR IGNORE INTO anchor(block_height, block_hash, txid, confirmation_time) VALUES($1, $2, $3, $4)")
-                .bind(height)
-                .bind(hash.to_string())
-                .bind(txid.to_string())
-                .bind(i64::try_from(confirmation_time)?)
-                .execute(&self.pool)
-                .await?;
+            let hash = hash.to_string();
+            let txid = txid.to_string();
+            let confirmation_time = to_sql("anchor.confirmation_time", anchor.confirmation_time)?;
+            // Keep the earliest confirmation time if the anchor is already stored.
+            upsert(
+                conn,
+                &mut summary,
+                "anchor",
+                sqlx::query("INSERT OR IGNORE INTO anchor(block_height, block_hash, txid, confirmation_time, kind) VALUES($1, $2, $3, $4, 'confirmation_block_time')")
+                    .bind(height)
+                    .bind(&hash)
+                    .bind(&txid)
+                    .bind(confirmation_time),
+                sqlx::query("UPDATE anchor SET confirmation_time = $4, kind = 'confirmation_block_time' WHERE block_height = $1 AND block_hash = $2 AND txid = $3 AND (confirmation_time IS NULL OR confirmation_time > $4)")
+                    .bind(height)
+                    .bind(&hash)
+                    .bind(&txid)
+                    .bind(confirmation_time),
+            )
+            .await?;
         }
 
-        Ok(())
+        let mut affected = BTreeSet::new();
+        let written = txs
+            .iter()
+            .map(|tx| tx.compute_txid())
+            .chain(txouts.keys().map(|op| op.txid))
+            .collect::<BTreeSet<_>>();
+        for txid in written {
+            affected.extend(affected_by_tx(conn, &txid.to_string()).await?);
+        }
+        summary.merge(refresh_tx_summaries(conn, &affected).await?);
+
+        Ok(summary)
     }
 
-    /// Write local_chain.
-    pub async fn write_local_chain(
+    pub(crate) async fn write_local_chain_in(
         &self,
+        conn: &mut SqliteConnection,
         local_chain: &local_chain::ChangeSet,
-    ) -> Result<(), Error> {
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = WriteSummary::default();
+        if !local_chain.is_empty() {
+            self.touch_persisted(conn, Component::Chain).await?;
+        }
         for (&height, hash) in &local_chain.blocks {
             match hash {
                 Some(hash) => {
-                    sqlx::query("INSERT OR IGNORE INTO block(height, hash) VALUES($1, $2)")
-                        .bind(height)
-                        .bind(hash.to_string())
-                        .execute(&self.pool)
-                        .await?;
+                    let res =
+                        sqlx::query("INSERT OR IGNORE INTO block(height, hash) VALUES($1, $2)")
+                            .bind(height)
+                            .bind(hash.to_string())
+                            .execute(&mut *conn)
+                            .await?;
+                    summary.table_mut("block").inserted += res.rows_affected();
                 }
                 None => {
-                    sqlx::query("DELETE FROM block WHERE height = $1")
+                    if self.block_tombstones {
+                        let res = sqlx::query("INSERT INTO block_orphaned(height, hash, orphaned_at) SELECT height, hash, $2 FROM block WHERE height = $1")
+                            .bind(height)
+                            .bind(to_sql("block_orphaned.orphaned_at", self.now())?)
+                            .execute(&mut *conn)
+                            .await?;
+                        summary.table_mut("block_orphaned").inserted += res.rows_affected();
+                    }
+                    let res = sqlx::query("DELETE FROM block WHERE height = $1")
                         .bind(height)
-                        .execute(&self.pool)
+                        .execute(&mut *conn)
                         .await?;
+                    summary.table_mut("block").deleted += res.rows_affected();
                 }
             }
         }
 
-        Ok(())
+        Ok(summary)
     }
 
-    /// Write keychain_txout.
-    pub async fn write_keychain_txout(
+    pub(crate) async fn write_keychain_txout_in(
         &self,
+        conn: &mut SqliteConnection,
         keychain_txout: &keychain_txout::ChangeSet,
-    ) -> Result<(), Error> {
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = WriteSummary::default();
+        if !keychain_txout.is_empty() {
+            self.touch_persisted(conn, Component::Indexer).await?;
+        }
         for (descriptor_id, last_revealed) in &keychain_txout.last_revealed {
-            sqlx::query(
-                "INSERT INTO keychain_last_revealed(descriptor_id, last_revealed) VALUES($1, $2) ON CONFLICT DO UPDATE SET last_revealed = $2",
+            let descriptor_id = descriptor_id.to_string();
+            upsert(
+                conn,
+                &mut summary,
+                "keychain_last_revealed",
+                sqlx::query("INSERT OR IGNORE INTO keychain_last_revealed(descriptor_id, last_revealed) VALUES($1, $2)")
+                    .bind(&descriptor_id)
+                    .bind(last_revealed),
+                sqlx::query("UPDATE keychain_last_revealed SET last_revealed = $2 WHERE descriptor_id = $1 AND last_revealed IS NOT $2")
+                    .bind(&descriptor_id)
+                    .bind(last_revealed),
             )
-            .bind(descriptor_id.to_string())
-            .bind(last_revealed)
-            .execute(&self.pool)
             .await?;
         }
+        let mut inserted = Vec::new();
         for (descriptor_id, spk_cache) in &keychain_txout.spk_cache {
-            for (derivation_index, script) in spk_cache {
-                sqlx::query(
-                    "INSERT OR IGNORE INTO keychain_script_pubkey(descriptor_id, derivation_index, script) VALUES($1, $2, $3)",
-                )
-                .bind(descriptor_id.to_string())
-                .bind(*derivation_index)
-                .bind(script.to_bytes())
-                .execute(&self.pool)
-                .await?;
+            let (Some((&first, _)), Some((&last, _))) =
+                (spk_cache.first_key_value(), spk_cache.last_key_value())
+            else {
+                continue;
+            };
+            let descriptor_id = descriptor_id.to_string();
+            // Most of the cache of a wallet changeset is already stored, so only the
+            // missing indices are inserted.
+            let rows = sqlx::query(
+                "SELECT derivation_index FROM keychain_script_pubkey WHERE descriptor_id = $1 AND derivation_index BETWEEN $2 AND $3",
+            )
+            .bind(&descriptor_id)
+            .bind(first)
+            .bind(last)
+            .fetch_all(&mut *conn)
+            .await?;
+            let stored: BTreeSet<u32> =
+                rows.iter().map(|row| row.get("derivation_index")).collect();
+            let missing: Vec<_> = spk_cache
+                .iter()
+                .filter(|(derivation_index, _)| !stored.contains(derivation_index))
+                .collect();
+            // A freshly created wallet caches the whole lookahead, so the missing scripts
+            // are inserted with one statement per chunk rather than per script.
+            for chunk in missing.chunks(MAX_BIND_PARAMS / 3) {
+                let mut query = QueryBuilder::<Sqlite>::new(
+                    "INSERT OR IGNORE INTO keychain_script_pubkey(descriptor_id, derivation_index, script) ",
+                );
+                query.push_values(chunk, |mut row, (derivation_index, script)| {
+                    row.push_bind(&descriptor_id)
+                        .push_bind(**derivation_index)
+                        .push_bind(script.as_bytes());
+                });
+                query.push(" RETURNING script");
+                let rows = query.build().fetch_all(&mut *conn).await?;
+                summary.table_mut("keychain_script_pubkey").inserted += rows.len() as u64;
+                inserted.extend(
+                    rows.iter()
+                        .map(|row| ScriptBuf::from_bytes(row.get("script"))),
+                );
             }
         }
+        let affected = affected_by_scripts(conn, &inserted).await?;
+        summary.merge(refresh_tx_summaries(conn, &affected).await?);
 
-        Ok(())
+        Ok(summary)
+    }
+
+    /// Write indexed_tx_graph.
+    ///
+    /// This persists the changeset of an [`IndexedTxGraph`] indexed by a
+    /// [`KeychainTxOutIndex`], for users composing their own structures from `bdk_chain`
+    /// components rather than using a `Wallet`.
+    ///
+    /// [`IndexedTxGraph`]: bdk_chain::IndexedTxGraph
+    /// [`KeychainTxOutIndex`]: bdk_chain::keychain_txout::KeychainTxOutIndex
+    pub async fn write_indexed_tx_graph(
+        &self,
+        indexed_tx_graph: &indexed_tx_graph::ChangeSet<
+            ConfirmationBlockTime,
+            keychain_txout::ChangeSet,
+        >,
+    ) -> Result<WriteSummary, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let mut summary = self
+                .write_tx_graph_in(conn, &indexed_tx_graph.tx_graph)
+                .await?;
+            summary.merge(
+                self.write_keychain_txout_in(conn, &indexed_tx_graph.indexer)
+                    .await?,
+            );
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Read indexed_tx_graph.
+    pub async fn read_indexed_tx_graph(
+        &self,
+    ) -> Result<indexed_tx_graph::ChangeSet<ConfirmationBlockTime, keychain_txout::ChangeSet>, Error>
+    {
+        Ok(indexed_tx_graph::ChangeSet {
+            tx_graph: self.read_tx_graph().await?,
+            indexer: self.read_keychain_txout().await?,
+        })
     }
 
     /// Read tx_graph.
+    ///
+    /// Anchors with an unknown confirmation time, see
+    /// [`write_untimed_anchors`](Self::write_untimed_anchors), are omitted.
     pub async fn read_tx_graph(&self) -> Result<tx_graph::ChangeSet<ConfirmationBlockTime>, Error> {
+        self.read_tx_graph_with(&mut Progress::none()).await
+    }
+
+    pub(crate) async fn read_tx_graph_with(
+        &self,
+        progress: &mut Progress<'_>,
+    ) -> Result<tx_graph::ChangeSet<ConfirmationBlockTime>, Error> {
         let mut changeset = tx_graph::ChangeSet::default();
 
-        let rows: Vec<TxRow> =
-            sqlx::query_as("SELECT txid, tx, first_seen, last_seen, last_evicted FROM tx")
-                .fetch_all(&self.pool)
-                .await?;
-        for row in rows {
+        // Rows are decoded from the buffers of the fetched rows rather than copied out first,
+        // which matters for the startup time of wallets with many transactions.
+        let mut rows = sqlx::query(
+            "SELECT txid, tx_blob.tx, first_seen, last_seen, last_evicted FROM tx LEFT JOIN tx_blob ON tx_blob.id = tx.blob_id ORDER BY txid",
+        )
+        .fetch(&self.pool);
+        while let Some(row) = rows.try_next().await? {
+            progress.row("tx");
+            let row = TxRow::from_row(&row)?;
             let txid: Txid = row.txid.parse()?;
             if let Some(data) = row.tx {
-                let tx: Transaction = consensus::encode::deserialize(&data)?;
+                let tx: Transaction = consensus::encode::deserialize(data)?;
                 changeset.txs.insert(Arc::new(tx));
             }
             if let Some(first_seen) = row.first_seen {
-                changeset.first_seen.insert(txid, first_seen.try_into()?);
+                changeset
+                    .first_seen
+                    .insert(txid, from_sql("tx.first_seen", first_seen)?);
             }
             if let Some(last_seen) = row.last_seen {
-                changeset.last_seen.insert(txid, last_seen.try_into()?);
+                changeset
+                    .last_seen
+                    .insert(txid, from_sql("tx.last_seen", last_seen)?);
             }
             if let Some(last_evicted) = row.last_evicted {
                 changeset
                     .last_evicted
-                    .insert(txid, last_evicted.try_into()?);
+                    .insert(txid, from_sql("tx.last_evicted", last_evicted)?);
             }
         }
 
-        let rows = sqlx::query("SELECT txid, vout, value, script FROM txout")
-            .fetch_all(&self.pool)
-            .await?;
-        for row in rows {
-            let txid: String = row.get("txid");
-            let txid: Txid = txid.parse()?;
+        let mut rows =
+            sqlx::query("SELECT txid, vout, value, script FROM txout ORDER BY txid, vout")
+                .fetch(&self.pool);
+        while let Some(row) = rows.try_next().await? {
+            progress.row("txout");
+            let txid: Txid = row.get::<&str, _>("txid").parse()?;
             let vout: u32 = row.get("vout");
             let value: i64 = row.get("value");
-            let value = Amount::from_sat(value.try_into()?);
+            let value = Amount::from_sat(from_sql("txout.value", value)?);
             let script: Vec<u8> = row.get("script");
             let script_pubkey = ScriptBuf::from_bytes(script);
             let outpoint = OutPoint { txid, vout };
@@ -234,20 +1218,18 @@ impl Store {
             changeset.txouts.insert(outpoint, txout);
         }
 
-        let rows =
-            sqlx::query("SELECT block_height, block_hash, txid, confirmation_time FROM anchor")
-                .fetch_all(&self.pool)
-                .await?;
-        for row in rows {
+        let mut rows =
+            sqlx::query("SELECT block_height, block_hash, txid, confirmation_time FROM anchor WHERE confirmation_time IS NOT NULL ORDER BY txid, block_height, block_hash")
+                .fetch(&self.pool);
+        while let Some(row) = rows.try_next().await? {
+            progress.row("anchor");
             let height: u32 = row.get("block_height");
-            let hash: String = row.get("block_hash");
-            let hash: BlockHash = hash.parse()?;
-            let txid: String = row.get("txid");
-            let txid: Txid = txid.parse()?;
+            let hash: BlockHash = row.get::<&str, _>("block_hash").parse()?;
+            let txid: Txid = row.get::<&str, _>("txid").parse()?;
             let confirmation_time: i64 = row.get("confirmation_time");
             let anchor = ConfirmationBlockTime {
                 block_id: BlockId { height, hash },
-                confirmation_time: confirmation_time.try_into()?,
+                confirmation_time: from_sql("anchor.confirmation_time", confirmation_time)?,
             };
             changeset.anchors.insert((anchor, txid));
         }
@@ -257,44 +1239,81 @@ impl Store {
 
     /// Read local_chain.
     pub async fn read_local_chain(&self) -> Result<local_chain::ChangeSet, Error> {
+        self.read_local_chain_with(&mut Progress::none()).await
+    }
+
+    pub(crate) async fn read_local_chain_with(
+        &self,
+        progress: &mut Progress<'_>,
+    ) -> Result<local_chain::ChangeSet, Error> {
         let mut changeset = local_chain::ChangeSet::default();
 
-        let rows = sqlx::query("SELECT height, hash FROM block")
-            .fetch_all(&self.pool)
-            .await?;
+        let mut rows =
+            sqlx::query("SELECT height, hash FROM block ORDER BY height").fetch(&self.pool);
+        while let Some(row) = rows.try_next().await? {
+            progress.row("block");
+            let height: u32 = row.get("height");
+            let hash: BlockHash = row.get::<&str, _>("hash").parse()?;
+            changeset.blocks.insert(height, Some(hash));
+        }
+
+        Ok(changeset)
+    }
+
+    /// Read the blocks recorded as orphaned, ordered by time of removal.
+    ///
+    /// Blocks are only recorded when [`with_block_tombstones`](Self::with_block_tombstones)
+    /// is enabled.
+    pub async fn orphaned_blocks(&self) -> Result<Vec<OrphanedBlock>, Error> {
+        let rows = sqlx::query(
+            "SELECT height, hash, orphaned_at FROM block_orphaned ORDER BY orphaned_at, height",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut blocks = vec![];
         for row in rows {
             let height: u32 = row.get("height");
             let hash: String = row.get("hash");
             let hash: BlockHash = hash.parse()?;
-            changeset.blocks.insert(height, Some(hash));
+            let orphaned_at: i64 = row.get("orphaned_at");
+            blocks.push(OrphanedBlock {
+                block_id: BlockId { height, hash },
+                orphaned_at: from_sql("block_orphaned.orphaned_at", orphaned_at)?,
+            });
         }
 
-        Ok(changeset)
+        Ok(blocks)
     }
 
     /// Read keychain_txout.
     pub async fn read_keychain_txout(&self) -> Result<keychain_txout::ChangeSet, Error> {
+        self.read_keychain_txout_with(&mut Progress::none()).await
+    }
+
+    pub(crate) async fn read_keychain_txout_with(
+        &self,
+        progress: &mut Progress<'_>,
+    ) -> Result<keychain_txout::ChangeSet, Error> {
         let mut changeset = keychain_txout::ChangeSet::default();
 
-        let rows = sqlx::query("SELECT descriptor_id, last_revealed FROM keychain_last_revealed")
-            .fetch_all(&self.pool)
-            .await?;
-        for row in rows {
+        let mut rows = sqlx::query("SELECT descriptor_id, last_revealed FROM keychain_last_revealed ORDER BY descriptor_id")
+            .fetch(&self.pool);
+        while let Some(row) = rows.try_next().await? {
+            progress.row("keychain_last_revealed");
             let descriptor_id: String = row.get("descriptor_id");
             let descriptor_id: DescriptorId = descriptor_id.parse()?;
             let last_revealed: u32 = row.get("last_revealed");
             changeset.last_revealed.insert(descriptor_id, last_revealed);
         }
 
-        let rows = sqlx::query(
-            "SELECT descriptor_id, derivation_index, script FROM keychain_script_pubkey",
+        let mut rows = sqlx::query(
+            "SELECT descriptor_id, derivation_index, script FROM keychain_script_pubkey ORDER BY descriptor_id, derivation_index",
         )
-        .fetch_all(&self.pool)
-        .await?;
-
-        for row in rows {
-            let descriptor_id: String = row.get("descriptor_id");
-            let descriptor_id: DescriptorId = descriptor_id.parse()?;
+        .fetch(&self.pool);
+        while let Some(row) = rows.try_next().await? {
+            progress.row("keychain_script_pubkey");
+            let descriptor_id: DescriptorId = row.get::<&str, _>("descriptor_id").parse()?;
             let derivation_index: u32 = row.get("derivation_index");
             let script: Vec<u8> = row.get("script");
             let script = ScriptBuf::from_bytes(script);
@@ -309,13 +1328,213 @@ impl Store {
     }
 }
 
+/// Default pool options, registering the crate's SQL functions on connect.
+pub(crate) fn pool_options() -> SqlitePoolOptions {
+    SqlitePoolOptions::new()
+        .after_connect(|conn, _| Box::pin(async move { crate::register_functions(conn).await }))
+}
+
+/// Fail with [`Error::NetworkMismatch`] if the stored network isn't `network`.
+pub(crate) async fn check_network(
+    conn: &mut SqliteConnection,
+    network: Network,
+    lenient: bool,
+) -> Result<(), Error> {
+    let row = sqlx::query("SELECT network, magic FROM network")
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some(row) = row {
+        let stored = network_from_row(&row, lenient)?;
+        if stored != network {
+            return Err(Error::NetworkMismatch {
+                ours: stored,
+                theirs: network,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the network of a row of the `network` table, see [`Store::with_lenient_network`].
+pub(crate) fn network_from_row(row: &SqliteRow, lenient: bool) -> Result<Network, Error> {
+    let name: String = row.get("network");
+    if let Ok(network) = name.parse() {
+        return Ok(network);
+    }
+    if lenient {
+        let magic = row
+            .get::<Option<Vec<u8>>, _>("magic")
+            .and_then(|magic| <[u8; 4]>::try_from(magic).ok())
+            .and_then(|magic| Network::from_magic(Magic::from_bytes(magic)));
+        if let Some(network) = magic.or_else(|| Network::from_core_arg(&name).ok()) {
+            return Ok(network);
+        }
+    }
+
+    Err(Error::UnknownNetwork(name))
+}
+
+/// Bound parameters of a statement built from a variable number of rows, at most the
+/// `SQLITE_MAX_VARIABLE_NUMBER` of SQLite versions before 3.32.
+pub(crate) const MAX_BIND_PARAMS: usize = 999;
+
+/// Transactions backfilled per write transaction by [`Store::migrate`].
+const BACKFILL_BATCH: i64 = 500;
+
+/// Execute `insert` and, if it didn't insert a row because one already exists, execute
+/// `update`, recording the effect on `table` in `summary`.
+pub(crate) async fn upsert<'q>(
+    conn: &mut SqliteConnection,
+    summary: &mut WriteSummary,
+    table: &'static str,
+    insert: Query<'q, Sqlite, SqliteArguments<'q>>,
+    update: Query<'q, Sqlite, SqliteArguments<'q>>,
+) -> Result<(), Error> {
+    let inserted = insert.execute(&mut *conn).await?.rows_affected();
+    let changes = summary.table_mut(table);
+    if inserted > 0 {
+        changes.inserted += inserted;
+    } else {
+        changes.updated += update.execute(&mut *conn).await?.rows_affected();
+    }
+
+    Ok(())
+}
+
+/// Write the outputs of `tx` to the `tx_output` table, returning the number of rows
+/// inserted.
+async fn write_tx_outputs(
+    conn: &mut SqliteConnection,
+    txid: &str,
+    tx: &Transaction,
+) -> Result<u64, Error> {
+    let mut inserted = 0;
+    for (vout, txout) in tx.output.iter().enumerate() {
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO tx_output(txid, vout, value, script) VALUES($1, $2, $3, $4)",
+        )
+        .bind(txid)
+        .bind(to_sql("tx_output.vout", vout as u64)?)
+        .bind(to_sql("tx_output.value", txout.value.to_sat())?)
+        .bind(txout.script_pubkey.to_bytes())
+        .execute(&mut *conn)
+        .await?;
+        inserted += res.rows_affected();
+    }
+
+    Ok(inserted)
+}
+
+/// Write the previous outputs spent by `tx` to the `txin` table, returning the number of
+/// rows inserted. Nothing is written for a coinbase transaction.
+async fn write_tx_inputs(
+    conn: &mut SqliteConnection,
+    txid: &str,
+    tx: &Transaction,
+) -> Result<u64, Error> {
+    if tx.is_coinbase() {
+        return Ok(0);
+    }
+    let mut inserted = 0;
+    for (vin, txin) in tx.input.iter().enumerate() {
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO txin(txid, vin, prev_txid, prev_vout) VALUES($1, $2, $3, $4)",
+        )
+        .bind(txid)
+        .bind(to_sql("txin.vin", vin as u64)?)
+        .bind(txin.previous_output.txid.to_string())
+        .bind(txin.previous_output.vout)
+        .execute(&mut *conn)
+        .await?;
+        inserted += res.rows_affected();
+    }
+
+    Ok(inserted)
+}
+
+/// Write the raw transaction `data` of `txid` to the `tx_blob` table and its computed
+/// `stats` to the `tx` table, recording the effect on the `tx` table in `summary`.
+async fn write_tx_blob(
+    conn: &mut SqliteConnection,
+    summary: &mut WriteSummary,
+    txid: &str,
+    data: &[u8],
+    stats: &TxStats,
+) -> Result<(), Error> {
+    let row = sqlx::query("SELECT blob_id FROM tx WHERE txid = $1")
+        .bind(txid)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let blob_id: Option<i64> = match row {
+        Some(ref row) => row.get("blob_id"),
+        None => None,
+    };
+    if let Some(blob_id) = blob_id {
+        let res = sqlx::query("UPDATE tx_blob SET tx = $2 WHERE id = $1 AND tx IS NOT $2")
+            .bind(blob_id)
+            .bind(data)
+            .execute(&mut *conn)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Ok(());
+        }
+    }
+
+    let blob_id = match blob_id {
+        Some(blob_id) => blob_id,
+        None => sqlx::query("INSERT INTO tx_blob(tx) VALUES($1) RETURNING id")
+            .bind(data)
+            .fetch_one(&mut *conn)
+            .await?
+            .get("id"),
+    };
+    let query = if row.is_some() {
+        summary.table_mut("tx").updated += 1;
+        "UPDATE tx SET blob_id = $2, weight = $3, vsize = $4, input_count = $5, output_count = $6 WHERE txid = $1"
+    } else {
+        summary.table_mut("tx").inserted += 1;
+        "INSERT INTO tx(txid, blob_id, weight, vsize, input_count, output_count) VALUES($1, $2, $3, $4, $5, $6)"
+    };
+    sqlx::query(query)
+        .bind(txid)
+        .bind(blob_id)
+        .bind(stats.weight)
+        .bind(stats.vsize)
+        .bind(stats.input_count)
+        .bind(stats.output_count)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Computed columns of the tx table.
+struct TxStats {
+    weight: i64,
+    vsize: i64,
+    input_count: i64,
+    output_count: i64,
+}
+
+impl TxStats {
+    fn new(tx: &Transaction) -> Result<Self, Error> {
+        Ok(Self {
+            weight: to_sql("tx.weight", tx.weight().to_wu())?,
+            vsize: to_sql("tx.vsize", tx.vsize() as u64)?,
+            input_count: to_sql("tx.input_count", tx.input.len() as u64)?,
+            output_count: to_sql("tx.output_count", tx.output.len() as u64)?,
+        })
+    }
+}
+
 /// Represents a row in the tx table.
 #[derive(Debug, sqlx::FromRow)]
-struct TxRow {
+struct TxRow<'r> {
     /// Txid
-    txid: String,
+    txid: &'r str,
     /// Raw transaction
-    tx: Option<Vec<u8>>,
+    tx: Option<&'r [u8]>,
     /// First seen
     first_seen: Option<i64>,
     /// Last seen
@@ -328,6 +1547,8 @@ struct TxRow {
 mod test {
     use super::*;
 
+    use std::str::FromStr;
+
     use bitcoin::hashes::Hash;
 
     #[tokio::test]
@@ -389,4 +1610,462 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn operation_times_out() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        let timeout = Duration::from_millis(10);
+        let res = store
+            .timed(Some(timeout), std::future::pending::<Result<(), Error>>())
+            .await;
+        assert!(matches!(res, Err(Error::Timeout(d)) if d == timeout));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removed_blocks_are_tombstoned() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_block_tombstones(true);
+        store.migrate().await?;
+
+        let mut cs = local_chain::ChangeSet::default();
+        cs.blocks.insert(0, Some(Hash::hash(b"0")));
+        cs.blocks.insert(1, Some(Hash::hash(b"1")));
+        store.write_local_chain(&cs).await?;
+
+        // Reorg block 1.
+        let mut cs = local_chain::ChangeSet::default();
+        cs.blocks.insert(1, None);
+        store.write_local_chain(&cs).await?;
+        cs.blocks.insert(1, Some(Hash::hash(b"1a")));
+        store.write_local_chain(&cs).await?;
+
+        let orphaned = store.orphaned_blocks().await?;
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(
+            orphaned[0].block_id,
+            BlockId {
+                height: 1,
+                hash: Hash::hash(b"1"),
+            }
+        );
+        let chain = store.read_local_chain().await?;
+        assert_eq!(chain.blocks.get(&1), Some(&Some(Hash::hash(b"1a"))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn anchor_keeps_earliest_confirmation_time() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let block_id = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        for confirmation_time in [20, 10, 30] {
+            let mut cs = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+            let anchor = ConfirmationBlockTime {
+                block_id,
+                confirmation_time,
+            };
+            cs.anchors.insert((anchor, txid));
+            store.write_tx_graph(&cs).await?;
+        }
+
+        let cs = store.read_tx_graph().await?;
+        let anchors: Vec<_> = cs.anchors.into_iter().collect();
+        assert_eq!(
+            anchors,
+            vec![(
+                ConfirmationBlockTime {
+                    block_id,
+                    confirmation_time: 10,
+                },
+                txid
+            )]
+        );
+
+        // Rows that aren't lowercase hex are rejected.
+        let res = sqlx::query("INSERT INTO anchor(block_height, block_hash, txid, confirmation_time) VALUES($1, $2, $3, $4)")
+            .bind(2)
+            .bind(block_id.hash.to_string().to_uppercase())
+            .bind(txid.to_string())
+            .bind(1)
+            .execute(&store.pool)
+            .await;
+        assert!(res.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn untimed_anchors() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let block_id = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        let summary = store
+            .write_untimed_anchors(&[(block_id, txid)].into())
+            .await?;
+        assert_eq!(summary.table("anchor").inserted, 1);
+        assert!(store.read_tx_graph().await?.anchors.is_empty());
+        let details = store.tx_details(txid).await?.unwrap();
+        assert!(details.anchors.is_empty());
+        assert_eq!(details.untimed_anchors, [block_id].into());
+
+        // A known confirmation time fills in the unknown one.
+        let anchor = ConfirmationBlockTime {
+            block_id,
+            confirmation_time: 100,
+        };
+        let mut cs = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        cs.anchors.insert((anchor, txid));
+        let summary = store.write_tx_graph(&cs).await?;
+        assert_eq!(summary.table("anchor").updated, 1);
+        store
+            .write_untimed_anchors(&[(block_id, txid)].into())
+            .await?;
+        assert_eq!(store.read_tx_graph().await?.anchors, cs.anchors);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tx_stats_are_computed() -> anyhow::Result<()> {
+        use bitcoin::{TxIn, absolute, transaction};
+
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(), TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut cs = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        cs.txs.insert(Arc::new(tx.clone()));
+        store.write_tx_graph(&cs).await?;
+
+        // Clear the computed columns as if the row was written before they existed.
+        sqlx::query(
+            "UPDATE tx SET weight = NULL, vsize = NULL, input_count = NULL, output_count = NULL",
+        )
+        .execute(&store.pool)
+        .await?;
+        sqlx::query("DELETE FROM txin").execute(&store.pool).await?;
+        store.migrate().await?;
+
+        let row = sqlx::query("SELECT weight, vsize, input_count, output_count FROM tx")
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(row.get::<i64, _>("weight"), tx.weight().to_wu() as i64);
+        assert_eq!(row.get::<i64, _>("vsize"), tx.vsize() as i64);
+        assert_eq!(row.get::<i64, _>("input_count"), 2);
+        assert_eq!(row.get::<i64, _>("output_count"), 1);
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM txin")
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(row.get::<i64, _>("count"), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn indexed_tx_graph_round_trip() -> anyhow::Result<()> {
+        use bdk_chain::miniscript::{Descriptor, DescriptorPublicKey};
+        use bdk_chain::{DescriptorExt, IndexedTxGraph, keychain_txout::KeychainTxOutIndex};
+
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let desc: Descriptor<DescriptorPublicKey> = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)".parse()?;
+        let mut graph = IndexedTxGraph::<ConfirmationBlockTime, _>::new(
+            KeychainTxOutIndex::<u8>::new(10, true),
+        );
+        let _ = graph.index.insert_descriptor(0, desc.clone())?;
+        let (_, indexer) = graph.index.reveal_to_target(0, 5).expect("must reveal");
+        let mut cs = indexed_tx_graph::ChangeSet::from(indexer);
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: graph.index.spk_at_index(0, 0).expect("must derive"),
+            }],
+        };
+        cs.tx_graph.txs.insert(Arc::new(tx));
+        store.write_indexed_tx_graph(&cs).await?;
+
+        let read = store.read_indexed_tx_graph().await?;
+        assert_eq!(read.tx_graph, cs.tx_graph);
+        assert_eq!(
+            read.indexer.last_revealed.get(&desc.descriptor_id()),
+            Some(&5)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spk_cache_spanning_chunks() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let script = |i: u32| ScriptBuf::from_bytes(i.to_le_bytes().to_vec());
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: script(900),
+            }],
+        };
+        let txid = tx.compute_txid();
+        store
+            .write_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(tx)].into(),
+                ..Default::default()
+            })
+            .await?;
+
+        let descriptor_id = DescriptorId::from_byte_array([1; 32]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer
+            .spk_cache
+            .insert(descriptor_id, (0..1000).map(|i| (i, script(i))).collect());
+        let summary = store.write_keychain_txout(&indexer).await?;
+        assert_eq!(summary.table("keychain_script_pubkey").inserted, 1000);
+        assert_eq!(
+            store.read_keychain_txout().await?.spk_cache,
+            indexer.spk_cache
+        );
+        // The transaction paying to a script of the last chunk is summarized.
+        let tx_summary = store.tx_summary(txid).await?.expect("must be summarized");
+        assert_eq!(tx_summary.received, Amount::from_sat(1_000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_limits() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let tx = |outputs: u64| Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: (0..outputs)
+                .map(|i| TxOut {
+                    value: Amount::from_sat(1_000 + i),
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        };
+        let graph = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: (1..=3).map(|n| Arc::new(tx(n))).collect(),
+            ..Default::default()
+        };
+
+        let limited = store
+            .clone()
+            .with_write_limits(WriteLimits::default().max_txs(2));
+        assert!(matches!(
+            limited.write_tx_graph(&graph).await,
+            Err(Error::ChangesetTooLarge {
+                limit: "max_txs",
+                size: 3,
+                max: 2
+            })
+        ));
+        assert!(store.read_tx_graph().await?.txs.is_empty());
+
+        let max_tx_size = tx(2).total_size();
+        let limited = store.with_write_limits(WriteLimits::default().max_tx_size(max_tx_size));
+        assert!(matches!(
+            limited.write_tx_graph(&graph).await,
+            Err(Error::ChangesetTooLarge {
+                limit: "max_tx_size",
+                ..
+            })
+        ));
+        let fits = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: (1..=2).map(|n| Arc::new(tx(n))).collect(),
+            ..Default::default()
+        };
+        limited.write_tx_graph(&fits).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_summary_counts_changes() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let mut cs = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        cs.first_seen.insert(txid, 1);
+        cs.last_seen.insert(txid, 1);
+        cs.txouts.insert(
+            OutPoint::new(txid, 0),
+            TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            },
+        );
+
+        let summary = store.write_tx_graph(&cs).await?;
+        assert_eq!(
+            summary.table("tx"),
+            TableChanges {
+                inserted: 1,
+                updated: 1,
+                deleted: 0,
+            }
+        );
+        assert_eq!(summary.table("txout").inserted, 1);
+
+        // Writing the same changeset again changes nothing.
+        assert!(store.write_tx_graph(&cs).await?.is_empty());
+
+        cs.last_seen.insert(txid, 2);
+        let summary = store.write_tx_graph(&cs).await?;
+        assert_eq!(summary.table("tx").updated, 1);
+        assert_eq!(summary.table("txout"), TableChanges::default());
+
+        let mut cs = local_chain::ChangeSet::default();
+        cs.blocks.insert(0, Some(Hash::hash(b"0")));
+        assert_eq!(
+            store.write_local_chain(&cs).await?.table("block").inserted,
+            1
+        );
+        cs.blocks.insert(0, None);
+        assert_eq!(
+            store.write_local_chain(&cs).await?.table("block").deleted,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn new_with_options() -> anyhow::Result<()> {
+        let connect_options =
+            SqliteConnectOptions::from_str("sqlite::memory:")?.pragma("cache_size", "-4096");
+        let pool_options = SqlitePoolOptions::new().max_connections(1);
+        let store = Store::new_with_options(connect_options, pool_options).await?;
+        store.migrate().await?;
+
+        let row = sqlx::query("PRAGMA cache_size")
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(row.get::<i64, _>(0), -4096);
+        assert_eq!(store.pool.options().get_max_connections(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_locked_migrate() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("locked.db");
+        let connect_options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .busy_timeout(Duration::ZERO);
+
+        // Another process holds the database.
+        let mut holder = SqliteConnection::connect_with(&connect_options).await?;
+        sqlx::query("BEGIN EXCLUSIVE").execute(&mut holder).await?;
+
+        let store = Store::new_with_options(connect_options.clone(), SqlitePoolOptions::new())
+            .await?
+            .with_lock_retry(LockRetry::default().attempts(1));
+        assert!(matches!(
+            store.migrate().await,
+            Err(Error::DatabaseLocked(_))
+        ));
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            sqlx::query("COMMIT").execute(&mut holder).await?;
+            Ok::<_, sqlx::Error>(holder)
+        });
+        let store = store.with_lock_retry(
+            LockRetry::default()
+                .attempts(10)
+                .base_delay(Duration::from_millis(50)),
+        );
+        store.migrate().await?;
+        release.await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrate_enabled_features_only() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let table = |name: &'static str| {
+            sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = $1")
+                .bind(name)
+                .fetch_optional(&store.pool)
+        };
+        assert!(table("tx").await?.is_some());
+        assert_eq!(
+            table("http_cache").await?.is_some(),
+            cfg!(feature = "http-cache")
+        );
+
+        // A migration applied by a build with another feature doesn't fail the migration.
+        sqlx::query("INSERT INTO _sqlx_migrations(version, description, success, checksum, execution_time) VALUES(9999, 'other feature', 1, x'00', 0)")
+            .execute(&store.pool)
+            .await?;
+        store.migrate().await?;
+        assert!(store.ready().await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_applies_durability() -> anyhow::Result<()> {
+        async fn synchronous(conn: &mut SqliteConnection) -> Result<i64, Error> {
+            let row = sqlx::query("PRAGMA synchronous")
+                .fetch_one(&mut *conn)
+                .await?;
+            Ok(row.get(0))
+        }
+
+        let store = Store::new_memory()
+            .await?
+            .with_durability(Durability::Normal);
+        store.migrate().await?;
+        let opts = WriteOptions::default();
+        assert_eq!(store.write(opts, synchronous).await?, 1);
+        let opts = WriteOptions::default().durability(Durability::Off);
+        assert_eq!(store.write(opts, synchronous).await?, 0);
+
+        // The connection setting is restored after the write.
+        let row = sqlx::query("PRAGMA synchronous")
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(row.get::<i64, _>(0), 2);
+
+        Ok(())
+    }
 }
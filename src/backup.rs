@@ -0,0 +1,192 @@
+//! Passphrase encrypted backup of a wallet secret.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload, rand_core::RngCore};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use sqlx::Row;
+use zeroize::Zeroizing;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, to_sql};
+use crate::rt;
+
+/// Associated data of the backup ciphertext.
+const AAD: &[u8] = b"bdk_sqlite encrypted_backup";
+
+/// Highest Argon2 memory cost in KiB accepted from a stored backup, 256 MiB.
+const MAX_M_COST: u32 = 256 * 1024;
+/// Highest Argon2 number of iterations accepted from a stored backup.
+const MAX_T_COST: u32 = 16;
+/// Highest Argon2 degree of parallelism accepted from a stored backup.
+const MAX_P_COST: u32 = 16;
+
+impl Store {
+    /// Store `secret`, e.g. a mnemonic or the secret descriptors of the wallet, encrypted
+    /// with a key derived from `passphrase`, replacing any previous backup.
+    ///
+    /// The key is derived by Argon2id with a random salt and the default costs of the
+    /// `argon2` crate, and the secret is encrypted with ChaCha20-Poly1305. This lets mobile
+    /// apps keep the backup of a wallet in its database rather than in a second storage
+    /// mechanism. The backup is kept in its own table, apart from the wallet data, which
+    /// never holds secrets. Deriving the key takes in the order of tens of milliseconds and
+    /// runs on a thread of the runtime for blocking tasks.
+    pub async fn write_backup(&self, passphrase: &str, secret: &[u8]) -> Result<(), Error> {
+        let params = Params::default();
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = cipher(passphrase, &salt, params.clone())
+            .await
+            .map_err(|_| Error::BackupEncryption)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: secret,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| Error::BackupEncryption)?;
+
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO encrypted_backup(id, salt, m_cost, t_cost, p_cost, nonce, ciphertext, created_at) VALUES(0, $1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT DO UPDATE SET salt = $1, m_cost = $2, t_cost = $3, p_cost = $4, nonce = $5, ciphertext = $6, created_at = $7",
+            )
+            .bind(salt.as_slice())
+            .bind(params.m_cost())
+            .bind(params.t_cost())
+            .bind(params.p_cost())
+            .bind(nonce.as_slice())
+            .bind(&ciphertext)
+            .bind(to_sql("encrypted_backup.created_at", self.now())?)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Decrypt the secret stored by [`write_backup`](Self::write_backup) with `passphrase`,
+    /// or `None` if there is no backup.
+    ///
+    /// Fails with [`Error::BackupDecryption`] if the passphrase is wrong or the backup was
+    /// tampered with, including if its Argon2 costs exceed those this crate accepts, so that
+    /// a tampered backup can't make deriving the key exhaust memory or time.
+    pub async fn read_backup(&self, passphrase: &str) -> Result<Option<Vec<u8>>, Error> {
+        let row = sqlx::query(
+            "SELECT salt, m_cost, t_cost, p_cost, nonce, ciphertext FROM encrypted_backup WHERE id = 0",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let salt: Vec<u8> = row.get("salt");
+        let nonce: Vec<u8> = row.get("nonce");
+        let ciphertext: Vec<u8> = row.get("ciphertext");
+        let m_cost: u32 = from_sql("encrypted_backup.m_cost", row.get("m_cost"))?;
+        let t_cost: u32 = from_sql("encrypted_backup.t_cost", row.get("t_cost"))?;
+        let p_cost: u32 = from_sql("encrypted_backup.p_cost", row.get("p_cost"))?;
+        if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+            return Err(Error::BackupDecryption);
+        }
+        let params =
+            Params::new(m_cost, t_cost, p_cost, None).map_err(|_| Error::BackupDecryption)?;
+        if nonce.len() != 12 {
+            return Err(Error::BackupDecryption);
+        }
+        let secret = cipher(passphrase, &salt, params)
+            .await
+            .map_err(|_| Error::BackupDecryption)?
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| Error::BackupDecryption)?;
+
+        Ok(Some(secret))
+    }
+
+    /// Delete the backup, returning whether there was one.
+    pub async fn delete_backup(&self) -> Result<bool, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query("DELETE FROM encrypted_backup")
+                .execute(&mut *conn)
+                .await?;
+            Ok(res.rows_affected() > 0)
+        })
+        .await
+    }
+}
+
+/// Cipher of the key derived from `passphrase`, derived on a blocking task.
+///
+/// The passphrase and the derived key are zeroized once the cipher is created.
+async fn cipher(
+    passphrase: &str,
+    salt: &[u8],
+    params: Params,
+) -> Result<ChaCha20Poly1305, argon2::Error> {
+    let passphrase = Zeroizing::new(passphrase.as_bytes().to_vec());
+    let salt = salt.to_vec();
+    rt::spawn_blocking(move || {
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(
+            &passphrase,
+            &salt,
+            key.as_mut(),
+        )?;
+
+        Ok(ChaCha20Poly1305::new((&*key).into()))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn encrypted_backup() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        assert_eq!(store.read_backup("pass").await?, None);
+
+        let mnemonic = b"abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        store.write_backup("pass", mnemonic).await?;
+        assert_eq!(
+            store.read_backup("pass").await?.as_deref(),
+            Some(&mnemonic[..])
+        );
+        assert!(matches!(
+            store.read_backup("wrong").await,
+            Err(Error::BackupDecryption)
+        ));
+        let ciphertext: Vec<u8> = sqlx::query("SELECT ciphertext FROM encrypted_backup")
+            .fetch_one(&store.pool)
+            .await?
+            .get("ciphertext");
+        assert!(!ciphertext.windows(7).any(|w| w == b"abandon"));
+
+        // Tampered costs are rejected before deriving the key.
+        sqlx::query("UPDATE encrypted_backup SET m_cost = 4194304")
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            store.read_backup("pass").await,
+            Err(Error::BackupDecryption)
+        ));
+
+        assert!(store.delete_backup().await?);
+        assert_eq!(store.read_backup("pass").await?, None);
+
+        Ok(())
+    }
+}
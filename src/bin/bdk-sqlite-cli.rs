@@ -0,0 +1,93 @@
+//! Inspect and maintain bdk_sqlite databases.
+
+use std::process::ExitCode;
+
+use bdk_sqlite::Store;
+use bdk_wallet::ChangeSet;
+
+const USAGE: &str = "\
+Usage: bdk-sqlite-cli <COMMAND> <DB> [ARGS]
+
+Commands:
+  inspect <DB>               Print the row count of every table
+  export-json <DB> [FILE]    Write the wallet changeset as JSON to FILE or stdout
+  import-json <DB> <FILE>    Write the JSON wallet changeset in FILE to the database
+  prune <DB>                 Delete the blocks recorded as orphaned
+  vacuum <DB>                Rebuild the database file to reclaim space
+  migrate <DB>               Apply pending migrations
+  verify <DB>                Check integrity, migrations and that the changeset decodes";
+
+type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: &[&str]) -> Result<()> {
+    let (command, db, rest) = match args {
+        [command, db, rest @ ..] => (*command, *db, rest),
+        _ => return Err(USAGE.into()),
+    };
+    let store = Store::new(db).await?;
+
+    match (command, rest) {
+        ("inspect", []) => {
+            println!("migrations applied: {}", store.ready().await?);
+            for (table, count) in store.row_counts().await? {
+                println!("{table}: {count}");
+            }
+        }
+        ("export-json", [] | [_]) => {
+            let json = serde_json::to_string_pretty(&store.read_changeset().await?)?;
+            match rest.first() {
+                Some(path) => std::fs::write(path, json)?,
+                None => println!("{json}"),
+            }
+        }
+        ("import-json", [path]) => {
+            let changeset: ChangeSet = serde_json::from_slice(&std::fs::read(path)?)?;
+            store.migrate().await?;
+            let summary = store.write_changeset(&changeset).await?;
+            for (table, changes) in summary.tables {
+                println!(
+                    "{table}: {} inserted, {} updated, {} deleted",
+                    changes.inserted, changes.updated, changes.deleted
+                );
+            }
+        }
+        ("prune", []) => {
+            let deleted = store.clear_orphaned_blocks().await?;
+            println!("deleted {deleted} orphaned blocks");
+        }
+        ("vacuum", []) => store.vacuum().await?,
+        ("migrate", []) => store.migrate().await?,
+        ("verify", []) => {
+            let problems = store.integrity_check().await?;
+            for problem in &problems {
+                println!("integrity: {problem}");
+            }
+            let ready = store.ready().await?;
+            if !ready {
+                println!("migrations: pending");
+            }
+            store.read_changeset().await?;
+            if !problems.is_empty() || !ready {
+                return Err("verification failed".into());
+            }
+            println!("ok");
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Builder of a [`Store`] with a configured connection pool.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::Error;
+use crate::Store;
+use crate::async_store::pool_options;
+
+/// Database a [`StoreBuilder`] connects to.
+#[derive(Debug, Clone)]
+enum Target {
+    Path(String),
+    Memory,
+}
+
+/// Builder of a [`Store`], see [`Store::builder`].
+///
+/// Unset options keep the defaults of [`SqlitePoolOptions`]. Use this to size the pool of
+/// a server with many concurrent requests. If no connection becomes available within the
+/// acquire timeout, operations fail with [`Error::PoolTimeout`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct StoreBuilder {
+    target: Target,
+    min_connections: Option<u32>,
+    max_connections: Option<u32>,
+    idle_timeout: Option<Option<Duration>>,
+    acquire_timeout: Option<Duration>,
+    extensions: Vec<(String, Option<String>)>,
+    lazy: bool,
+}
+
+impl Store {
+    /// Build a [`Store`] of the database at `path`, which is created if it doesn't exist.
+    ///
+    /// `path` can be a filename or a URL, as with [`Store::new`].
+    pub fn builder(path: &str) -> StoreBuilder {
+        StoreBuilder::new(Target::Path(path.to_string()))
+    }
+
+    /// Build a [`Store`] of a new in-memory database, as with [`Store::new_memory`].
+    pub fn memory_builder() -> StoreBuilder {
+        StoreBuilder::new(Target::Memory)
+    }
+}
+
+impl StoreBuilder {
+    fn new(target: Target) -> Self {
+        Self {
+            target,
+            min_connections: None,
+            max_connections: None,
+            idle_timeout: None,
+            acquire_timeout: None,
+            extensions: Vec::new(),
+            lazy: false,
+        }
+    }
+
+    /// Set the number of connections the pool keeps open.
+    pub fn min_connections(mut self, min: u32) -> Self {
+        self.min_connections = Some(min);
+        self
+    }
+
+    /// Set the maximum number of connections of the pool.
+    pub fn max_connections(mut self, max: u32) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Set how long a connection may be idle before it is closed, or `None` to never
+    /// close idle connections.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set how long to wait for a connection to become available.
+    pub fn acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.acquire_timeout = Some(timeout);
+        self
+    }
+
+    /// Set whether to defer opening the database until the store is first used, e.g. when
+    /// the store is created at startup but its file is only accessible once the user
+    /// unlocks the device. Defaults to `false`.
+    ///
+    /// A lazy [`build`](Self::build) doesn't fail if the database can't be opened. Instead
+    /// the first operation does, with [`Error::CannotOpen`] if SQLite can't open the file.
+    /// Use [`Store::warm_up`] to open the connections at a time of the application's
+    /// choosing.
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Load the SQLite extension at `path` on every connection, e.g. to provide
+    /// encryption or custom collations.
+    ///
+    /// `path` is passed to `sqlite3_load_extension`, which tries the platform's shared
+    /// library suffix if the file doesn't exist. The entry point is derived from the file
+    /// name unless given by [`StoreBuilder::extension_with_entry_point`]. Building the
+    /// store fails if the extension can't be loaded.
+    pub fn extension(mut self, path: &str) -> Self {
+        self.extensions.push((path.to_string(), None));
+        self
+    }
+
+    /// Load the SQLite extension at `path` on every connection, calling `entry_point` to
+    /// initialize it, see [`StoreBuilder::extension`].
+    pub fn extension_with_entry_point(mut self, path: &str, entry_point: &str) -> Self {
+        self.extensions
+            .push((path.to_string(), Some(entry_point.to_string())));
+        self
+    }
+
+    fn connect_options(&self, url: &str) -> Result<SqliteConnectOptions, Error> {
+        let mut options = SqliteConnectOptions::from_str(url)?;
+        for (path, entry_point) in &self.extensions {
+            options = match entry_point {
+                Some(entry_point) => {
+                    options.extension_with_entrypoint(path.clone(), entry_point.clone())
+                }
+                None => options.extension(path.clone()),
+            };
+        }
+        Ok(options)
+    }
+
+    fn pool_options(&self) -> SqlitePoolOptions {
+        let mut options = pool_options();
+        if let Some(min) = self.min_connections {
+            options = options.min_connections(min);
+        }
+        if let Some(max) = self.max_connections {
+            options = options.max_connections(max);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            options = options.idle_timeout(timeout);
+        }
+        if let Some(timeout) = self.acquire_timeout {
+            options = options.acquire_timeout(timeout);
+        }
+        options
+    }
+
+    /// Connect to the database and build the [`Store`].
+    pub async fn build(self) -> Result<Store, Error> {
+        let mut options = self.pool_options();
+        let connect_options = match &self.target {
+            Target::Path(path) => self.connect_options(path)?.create_if_missing(true),
+            Target::Memory => {
+                // Don't test the health of the connection before returning it.
+                // See docs for `Pool::acquire`.
+                options = options.test_before_acquire(false);
+                self.connect_options("sqlite::memory:")?
+            }
+        };
+        let pool = match self.lazy {
+            true => options.connect_lazy_with(connect_options),
+            false => options.connect_with(connect_options).await?,
+        };
+
+        match self.target {
+            Target::Path(_) => Ok(Store::from_pool(pool)),
+            // Each connection to `sqlite::memory:` opens a distinct database, so
+            // concurrent reads would miss the data written on another connection.
+            Target::Memory => Ok(Store::from_pool(pool).with_parallel_reads(false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn pool_timeout() -> anyhow::Result<()> {
+        let store = Store::memory_builder()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(50))
+            .build()
+            .await?;
+        store.migrate().await?;
+        assert_eq!(store.pool.options().get_max_connections(), 1);
+
+        // Hold the only connection so the write can't acquire one.
+        let _conn = store.pool.acquire().await?;
+        let err = store
+            .write_local_chain(&Default::default())
+            .await
+            .unwrap_err();
+        match err {
+            Error::PoolTimeout {
+                max_connections,
+                acquire_timeout,
+            } => {
+                assert_eq!(max_connections, Some(1));
+                assert_eq!(acquire_timeout, Some(Duration::from_millis(50)));
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        // Reads fail with the same error.
+        assert!(matches!(
+            store.user_version().await,
+            Err(Error::PoolTimeout { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn extension_not_found() -> anyhow::Result<()> {
+        let res = Store::memory_builder()
+            .extension_with_entry_point("./does-not-exist", "sqlite3_extension_init")
+            .build()
+            .await;
+        assert!(matches!(res, Err(Error::Sqlx(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lazy() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let dir = root.path().join("mount");
+        let path = dir.join("wallet.db");
+        let store = Store::builder(path.to_str().unwrap())
+            .lazy(true)
+            .build()
+            .await?;
+        assert!(!path.exists());
+
+        // The directory doesn't exist yet, e.g. the storage isn't mounted.
+        assert!(matches!(store.warm_up().await, Err(Error::CannotOpen(_))));
+
+        std::fs::create_dir_all(&dir)?;
+        store.warm_up().await?;
+        store.migrate().await?;
+        assert!(path.exists());
+
+        store.pool.close().await;
+
+        Ok(())
+    }
+}
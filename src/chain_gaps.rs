@@ -0,0 +1,60 @@
+//! Detection of missing blocks of the local chain.
+
+use std::ops::RangeInclusive;
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+
+impl Store {
+    /// Ranges of heights missing from the stored blocks, ordered by height.
+    ///
+    /// The stored blocks are scanned from the lowest one, usually the genesis block, to the
+    /// tip, and each run of heights between two stored blocks is reported. A local chain
+    /// only holds the checkpoints it was given, so gaps are expected unless the chain
+    /// source syncs every header, e.g. compact block filters. There it tells the sync layer
+    /// exactly which headers to request.
+    pub async fn chain_gaps(&self) -> Result<Vec<RangeInclusive<u32>>, Error> {
+        let rows = sqlx::query(
+            "SELECT prev + 1 AS start, height - 1 AS end
+            FROM (SELECT height, LAG(height) OVER (ORDER BY height) AS prev FROM block)
+            WHERE height > prev + 1
+            ORDER BY height",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| row.get::<u32, _>("start")..=row.get::<u32, _>("end"))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::BlockHash;
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::local_chain;
+
+    #[tokio::test]
+    async fn chain_gaps() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        assert!(store.chain_gaps().await?.is_empty());
+
+        let mut chain = local_chain::ChangeSet::default();
+        for height in [0u32, 1, 2, 5, 6, 8, 20] {
+            chain
+                .blocks
+                .insert(height, Some(BlockHash::hash(&height.to_le_bytes())));
+        }
+        store.write_local_chain(&chain).await?;
+        assert_eq!(store.chain_gaps().await?, [3..=4, 7..=7, 9..=19]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! Chain source configuration.
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+
+/// Kind of [`ChainSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChainSourceKind {
+    /// Esplora HTTP API.
+    Esplora,
+    /// Electrum server.
+    Electrum,
+    /// Bitcoin Core RPC.
+    BitcoindRpc,
+}
+
+impl ChainSourceKind {
+    /// The value of the `kind` column.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Esplora => "esplora",
+            Self::Electrum => "electrum",
+            Self::BitcoindRpc => "bitcoind_rpc",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "esplora" => Some(Self::Esplora),
+            "electrum" => Some(Self::Electrum),
+            "bitcoind_rpc" => Some(Self::BitcoindRpc),
+            _ => None,
+        }
+    }
+}
+
+/// The backend a wallet syncs against, see [`Store::set_chain_source`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainSource {
+    /// Kind
+    pub kind: ChainSourceKind,
+    /// URL or endpoint, e.g. `https://mempool.space/api` or `ssl://electrum.blockstream.info:50002`
+    pub url: String,
+    /// Stop gap used for full scans
+    pub stop_gap: Option<u32>,
+    /// Number of parallel requests
+    pub parallel_requests: Option<u32>,
+}
+
+impl Store {
+    /// Set the chain source, replacing any existing one.
+    pub async fn set_chain_source(&self, source: &ChainSource) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT OR REPLACE INTO chain_source(id, kind, url, stop_gap, parallel_requests) VALUES(0, $1, $2, $3, $4)",
+            )
+            .bind(source.kind.as_str())
+            .bind(&source.url)
+            .bind(source.stop_gap)
+            .bind(source.parallel_requests)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get the chain source, if any.
+    pub async fn chain_source(&self) -> Result<Option<ChainSource>, Error> {
+        let row = sqlx::query("SELECT kind, url, stop_gap, parallel_requests FROM chain_source")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.and_then(|row| {
+            let kind: String = row.get("kind");
+            let Some(kind) = ChainSourceKind::from_str(&kind) else {
+                debug_assert!(false, "unknown chain source kind: {kind}");
+                return None;
+            };
+            Some(ChainSource {
+                kind,
+                url: row.get("url"),
+                stop_gap: row.get("stop_gap"),
+                parallel_requests: row.get("parallel_requests"),
+            })
+        }))
+    }
+
+    /// Remove the chain source.
+    pub async fn remove_chain_source(&self) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query("DELETE FROM chain_source")
+                .execute(&mut *conn)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn chain_source() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        assert_eq!(store.chain_source().await?, None);
+
+        let esplora = ChainSource {
+            kind: ChainSourceKind::Esplora,
+            url: "https://mempool.space/signet/api".to_string(),
+            stop_gap: Some(20),
+            parallel_requests: Some(5),
+        };
+        store.set_chain_source(&esplora).await?;
+        assert_eq!(store.chain_source().await?, Some(esplora));
+
+        let electrum = ChainSource {
+            kind: ChainSourceKind::Electrum,
+            url: "ssl://electrum.blockstream.info:60002".to_string(),
+            stop_gap: None,
+            parallel_requests: None,
+        };
+        store.set_chain_source(&electrum).await?;
+        assert_eq!(store.chain_source().await?, Some(electrum));
+
+        store.remove_chain_source().await?;
+        assert_eq!(store.chain_source().await?, None);
+
+        Ok(())
+    }
+}
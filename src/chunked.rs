@@ -0,0 +1,340 @@
+//! Chunked import of large changesets.
+
+use bdk_chain::Merge;
+use bdk_chain::bitcoin::hashes::{Hash, sha256};
+use bdk_wallet::ChangeSet;
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+use crate::convert::{from_sql, from_sql_opt, to_sql};
+use crate::overflow::with_unmodeled_of;
+use crate::replication::next_sequence;
+
+/// Progress of a chunked import, see [`Store::write_changeset_chunked`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportProgress {
+    /// Id of the import, derived from the changeset and chunk size
+    pub id: String,
+    /// Number of chunks the changeset is split into
+    pub chunk_count: u64,
+    /// Number of chunks committed
+    pub chunks_written: u64,
+    /// Unix time at which the import started
+    pub started_at: u64,
+    /// Unix time at which the import completed, if it did
+    pub completed_at: Option<u64>,
+}
+
+impl Store {
+    /// Write `changeset` in chunks of at most `chunk_size` items, each committed in its own
+    /// transaction.
+    ///
+    /// Use this instead of [`write_changeset`](Self::write_changeset) for a huge changeset,
+    /// e.g. after the first full scan of a busy wallet, so the write doesn't hold a
+    /// transaction open for minutes and starve other connections. An item is e.g. a
+    /// transaction, an anchor or a cached script pubkey.
+    ///
+    /// Progress is recorded in the `import_journal` table along with every chunk. If the
+    /// import is interrupted, calling this again with the same changeset and chunk size
+    /// skips the chunks already committed. A completed import is not written again.
+    pub async fn write_changeset_chunked(
+        &self,
+        changeset: &ChangeSet,
+        chunk_size: usize,
+    ) -> Result<WriteSummary, Error> {
+        if changeset.is_empty() {
+            return Ok(WriteSummary::default());
+        }
+        let chunks = changeset_chunks(changeset, chunk_size)?;
+        let id = import_id(changeset, chunk_size)?;
+        let chunk_count = to_sql("import_journal.chunk_count", chunks.len() as u64)?;
+        let now = to_sql("import_journal.started_at", self.now())?;
+
+        let row = sqlx::query("SELECT chunks_written FROM import_journal WHERE id = $1")
+            .bind(&id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let chunks_written: u64 = match row {
+            Some(row) => from_sql("import_journal.chunks_written", row.get("chunks_written"))?,
+            None => 0,
+        };
+
+        let mut summary = WriteSummary::default();
+        for (i, chunk) in chunks.iter().enumerate().skip(chunks_written as usize) {
+            let written = to_sql("import_journal.chunks_written", i as u64 + 1)?;
+            let (chunk_summary, sequence) = self
+                .write(WriteOptions::default(), async |conn| {
+                    sqlx::query(
+                        "INSERT OR IGNORE INTO import_journal(id, chunk_count, chunks_written, started_at) VALUES($1, $2, 0, $3)",
+                    )
+                    .bind(&id)
+                    .bind(chunk_count)
+                    .bind(now)
+                    .execute(&mut *conn)
+                    .await?;
+                    let summary = self.write_changeset_in(conn, chunk).await?;
+                    sqlx::query("UPDATE import_journal SET chunks_written = $2, completed_at = CASE WHEN $2 = chunk_count THEN $3 END WHERE id = $1")
+                        .bind(&id)
+                        .bind(written)
+                        .bind(to_sql("import_journal.completed_at", self.now())?)
+                        .execute(&mut *conn)
+                        .await?;
+                    let sequence = match self.replication {
+                        Some(_) if !summary.is_empty() => Some(next_sequence(conn).await?),
+                        _ => None,
+                    };
+                    Ok((summary, sequence))
+                })
+                .await?;
+
+            if let (Some(sink), Some(sequence)) = (&self.replication, sequence) {
+                let data = serde_json::to_vec(chunk)?;
+                sink.replicate(sequence, &data).await?;
+            }
+            summary.merge(chunk_summary);
+        }
+
+        Ok(summary)
+    }
+
+    /// Read the progress of the chunked imports, ordered by start time.
+    pub async fn import_journal(&self) -> Result<Vec<ImportProgress>, Error> {
+        let rows = sqlx::query(
+            "SELECT id, chunk_count, chunks_written, started_at, completed_at FROM import_journal ORDER BY started_at, id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut imports = vec![];
+        for row in rows {
+            let chunk_count: i64 = row.get("chunk_count");
+            let chunks_written: i64 = row.get("chunks_written");
+            let started_at: i64 = row.get("started_at");
+            imports.push(ImportProgress {
+                id: row.get("id"),
+                chunk_count: from_sql("import_journal.chunk_count", chunk_count)?,
+                chunks_written: from_sql("import_journal.chunks_written", chunks_written)?,
+                started_at: from_sql("import_journal.started_at", started_at)?,
+                completed_at: from_sql_opt("import_journal.completed_at", row.get("completed_at"))?,
+            });
+        }
+
+        Ok(imports)
+    }
+}
+
+/// Id of the import of `changeset` in chunks of `chunk_size`.
+fn import_id(changeset: &ChangeSet, chunk_size: usize) -> Result<String, Error> {
+    let mut data = serde_json::to_vec(changeset)?;
+    data.extend((chunk_size as u64).to_be_bytes());
+
+    Ok(sha256::Hash::hash(&data).to_string())
+}
+
+/// Split `changeset` into changesets of at most `chunk_size` items.
+///
+/// The network, descriptors and the fields which the schema doesn't model, see
+/// [`overflow`](crate::overflow), go in the first chunk. The split is deterministic, which
+/// resuming an import relies on.
+fn changeset_chunks(changeset: &ChangeSet, chunk_size: usize) -> Result<Vec<ChangeSet>, Error> {
+    let chunk_size = chunk_size.max(1);
+    let first = ChangeSet {
+        network: changeset.network,
+        descriptor: changeset.descriptor.clone(),
+        change_descriptor: changeset.change_descriptor.clone(),
+        ..Default::default()
+    };
+    let mut chunks = vec![with_unmodeled_of(first, changeset)?];
+    let mut len = 0;
+    let mut push = |f: &mut dyn FnMut(&mut ChangeSet)| {
+        match chunks.last_mut() {
+            Some(chunk) if len < chunk_size => f(chunk),
+            _ => {
+                let mut chunk = ChangeSet::default();
+                f(&mut chunk);
+                chunks.push(chunk);
+                len = 0;
+            }
+        }
+        len += 1;
+    };
+
+    let local_chain = &changeset.local_chain;
+    let tx_graph = &changeset.tx_graph;
+    let indexer = &changeset.indexer;
+    for (&height, &hash) in &local_chain.blocks {
+        push(&mut |c| {
+            c.local_chain.blocks.insert(height, hash);
+        });
+    }
+    for tx in &tx_graph.txs {
+        push(&mut |c| {
+            c.tx_graph.txs.insert(tx.clone());
+        });
+    }
+    for (&outpoint, txout) in &tx_graph.txouts {
+        push(&mut |c| {
+            c.tx_graph.txouts.insert(outpoint, txout.clone());
+        });
+    }
+    for anchor in &tx_graph.anchors {
+        push(&mut |c| {
+            c.tx_graph.anchors.insert(*anchor);
+        });
+    }
+    for (&txid, &seen) in &tx_graph.first_seen {
+        push(&mut |c| {
+            c.tx_graph.first_seen.insert(txid, seen);
+        });
+    }
+    for (&txid, &seen) in &tx_graph.last_seen {
+        push(&mut |c| {
+            c.tx_graph.last_seen.insert(txid, seen);
+        });
+    }
+    for (&txid, &evicted) in &tx_graph.last_evicted {
+        push(&mut |c| {
+            c.tx_graph.last_evicted.insert(txid, evicted);
+        });
+    }
+    for (&did, &index) in &indexer.last_revealed {
+        push(&mut |c| {
+            c.indexer.last_revealed.insert(did, index);
+        });
+    }
+    for (&did, spks) in &indexer.spk_cache {
+        for (&index, spk) in spks {
+            push(&mut |c| {
+                c.indexer
+                    .spk_cache
+                    .entry(did)
+                    .or_default()
+                    .insert(index, spk.clone());
+            });
+        }
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::{Network, Transaction, Txid, absolute, transaction};
+
+    use crate::WriteLimits;
+
+    fn changeset() -> ChangeSet {
+        let mut changeset = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        for height in 0..5u32 {
+            changeset
+                .local_chain
+                .blocks
+                .insert(height, Some(Hash::hash(&height.to_le_bytes())));
+        }
+        for i in 0..5u64 {
+            let txid: Txid = Hash::hash(&i.to_le_bytes());
+            changeset.tx_graph.last_seen.insert(txid, i);
+        }
+        changeset
+    }
+
+    #[tokio::test]
+    async fn write_changeset_chunked() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let changeset = changeset();
+        let summary = store.write_changeset_chunked(&changeset, 3).await?;
+        assert_eq!(summary.table("block").inserted, 5);
+        assert_eq!(summary.table("tx").inserted, 5);
+        assert_eq!(store.read_changeset().await?, changeset);
+
+        let journal = store.import_journal().await?;
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0].chunk_count, 4);
+        assert_eq!(journal[0].chunks_written, 4);
+        assert!(journal[0].completed_at.is_some());
+
+        // A completed import isn't written again.
+        assert!(
+            store
+                .write_changeset_chunked(&changeset, 3)
+                .await?
+                .is_empty()
+        );
+
+        // An empty changeset isn't journaled.
+        assert!(
+            store
+                .write_changeset_chunked(&ChangeSet::default(), 3)
+                .await?
+                .is_empty()
+        );
+        assert_eq!(store.import_journal().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chunked_import_within_write_limits() -> anyhow::Result<()> {
+        let store = Store::new_memory()
+            .await?
+            .with_write_limits(WriteLimits::default().max_txs(1));
+        store.migrate().await?;
+
+        let mut changeset = changeset();
+        for i in 0..3u32 {
+            changeset
+                .tx_graph
+                .txs
+                .insert(std::sync::Arc::new(Transaction {
+                    version: transaction::Version::TWO,
+                    lock_time: absolute::LockTime::from_consensus(i),
+                    input: vec![],
+                    output: vec![],
+                }));
+        }
+        assert!(matches!(
+            store.write_changeset(&changeset).await,
+            Err(Error::ChangesetTooLarge { .. })
+        ));
+        store.write_changeset_chunked(&changeset, 1).await?;
+        assert_eq!(store.read_changeset().await?, changeset);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_chunked_import() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        // Simulate an import interrupted after committing the first two chunks.
+        let changeset = changeset();
+        let chunks = changeset_chunks(&changeset, 3)?;
+        for chunk in &chunks[..2] {
+            store.write_changeset(chunk).await?;
+        }
+        sqlx::query("INSERT INTO import_journal(id, chunk_count, chunks_written, started_at) VALUES($1, $2, 2, 0)")
+            .bind(import_id(&changeset, 3)?)
+            .bind(chunks.len() as i64)
+            .execute(&store.pool)
+            .await?;
+
+        let summary = store.write_changeset_chunked(&changeset, 3).await?;
+        assert_eq!(summary.table("block").inserted, 0);
+        assert_eq!(summary.table("tx").inserted, 4);
+        assert_eq!(store.read_changeset().await?, changeset);
+        assert_eq!(store.import_journal().await?[0].chunks_written, 4);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! Source of the timestamps recorded by the store.
+
+use core::fmt;
+
+/// Source of the current time of the timestamps the store records itself, e.g. when a
+/// block is orphaned or an output is frozen, see [`Store::with_clock`].
+///
+/// Implement this to supply a time source on platforms without a reliable clock.
+///
+/// [`Store::with_clock`]: crate::Store::with_clock
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Seconds since the unix epoch.
+    fn now(&self) -> u64;
+}
+
+/// A [`Clock`] reading the system time. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// A [`Clock`] which always returns the same time, for deterministic tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
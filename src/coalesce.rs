@@ -0,0 +1,355 @@
+//! Coalescing of frequent small changeset writes.
+
+use core::pin::{Pin, pin};
+use core::task::{Context, Poll};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bdk_chain::Merge;
+use bdk_wallet::{AsyncWalletPersister, ChangeSet};
+use futures_channel::{mpsc, oneshot};
+use futures_util::StreamExt;
+use futures_util::future::{Either, poll_fn, select};
+
+use crate::Error;
+use crate::Store;
+use crate::rt;
+use crate::wallet::FutureResult;
+
+/// When a [`CoalescingWriter`] flushes, see [`Store::coalescing_writer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CoalesceOptions {
+    /// Longest time a changeset waits for others to be merged with.
+    pub max_delay: Duration,
+    /// Largest number of changesets merged into one write.
+    ///
+    /// This also bounds the changesets queued while a merged write is in progress, enqueueing
+    /// more waits until the write completes.
+    pub max_changesets: usize,
+}
+
+impl Default for CoalesceOptions {
+    /// Flush every 100ms or 64 changesets, whichever comes first.
+    fn default() -> Self {
+        Self {
+            max_delay: Duration::from_millis(100),
+            max_changesets: 64,
+        }
+    }
+}
+
+impl CoalesceOptions {
+    /// Set the longest time a changeset waits for others to be merged with.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the largest number of changesets merged into one write.
+    pub fn max_changesets(mut self, max_changesets: usize) -> Self {
+        self.max_changesets = max_changesets;
+        self
+    }
+}
+
+/// Request to the task of a [`CoalescingWriter`].
+enum Request {
+    Write(Box<ChangeSet>, oneshot::Sender<Result<(), Error>>),
+    Flush(oneshot::Sender<Result<(), Error>>),
+}
+
+/// Writer merging the changesets enqueued within a short time into a single write, see
+/// [`Store::coalescing_writer`].
+#[derive(Debug, Clone)]
+pub struct CoalescingWriter {
+    store: Store,
+    requests: mpsc::Sender<Request>,
+    error: Arc<Mutex<Option<Arc<Error>>>>,
+}
+
+/// Completion of a changeset enqueued to a [`CoalescingWriter`], resolving once it is
+/// written.
+///
+/// Dropping the handle doesn't cancel the write.
+#[derive(Debug)]
+#[must_use = "the changeset may not be written yet"]
+pub struct WriteHandle(oneshot::Receiver<Result<(), Error>>);
+
+impl Future for WriteHandle {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|res| res.unwrap_or(Err(Error::WriterClosed)))
+    }
+}
+
+impl Store {
+    /// Create a writer merging the changesets enqueued within `opts.max_delay`, up to
+    /// `opts.max_changesets`, into a single [`write_changeset`](Self::write_changeset).
+    ///
+    /// This smooths bursts of small writes, e.g. when persisting after every step of a fast
+    /// sync on a slow disk. The returned future is the task doing the writes and must be
+    /// spawned on the application's runtime. It completes once every clone of the writer is
+    /// dropped and the remaining changesets are written.
+    pub fn coalescing_writer(
+        &self,
+        opts: CoalesceOptions,
+    ) -> (CoalescingWriter, impl Future<Output = ()> + Send + 'static) {
+        let (tx, rx) = mpsc::channel(opts.max_changesets);
+        let error = Arc::default();
+        let writer = CoalescingWriter {
+            store: self.clone(),
+            requests: tx,
+            error: Arc::clone(&error),
+        };
+
+        (writer, run(self.clone(), rx, error, opts))
+    }
+}
+
+impl CoalescingWriter {
+    /// Enqueue `changeset`, returning a handle resolving once it is written.
+    ///
+    /// This waits while the queue is full, see [`CoalesceOptions::max_changesets`]. If the
+    /// merged write fails, every changeset of it fails with [`Error::CoalescedWrite`] and the
+    /// error is kept until [taken](Self::take_error).
+    pub async fn enqueue(&self, changeset: ChangeSet) -> WriteHandle {
+        let (tx, rx) = oneshot::channel();
+        self.send(Request::Write(Box::new(changeset), tx)).await;
+        WriteHandle(rx)
+    }
+
+    /// Write the changesets enqueued so far without waiting for more.
+    ///
+    /// This fails with the error of any merged write failed since the error was last
+    /// [taken](Self::take_error), and takes it.
+    pub async fn flush(&self) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Request::Flush(tx)).await;
+        let res = WriteHandle(rx).await;
+        match self.take_error() {
+            Some(e) => Err(e),
+            None => res,
+        }
+    }
+
+    /// Take the error of a merged write failed since the error was last taken.
+    ///
+    /// Only the first failure is kept, later ones are still reported to their
+    /// [`WriteHandle`]s.
+    pub fn take_error(&self) -> Option<Error> {
+        let error = self.error.lock().expect("not poisoned").take();
+        error.map(Error::CoalescedWrite)
+    }
+
+    /// Send `request` to the task once the queue has room.
+    async fn send(&self, request: Request) {
+        // Each clone of the sender has a slot of its own, so this waits only for the bound.
+        let mut requests = self.requests.clone();
+        // If the task is gone, the request is dropped with its sender and the handle fails
+        // with `WriterClosed`.
+        if poll_fn(|cx| requests.poll_ready(cx)).await.is_ok() {
+            let _ = requests.start_send(request);
+        }
+    }
+
+    /// The store written to.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+}
+
+/// Write the changesets of `requests` to `store` until every sender is dropped, keeping the
+/// first failure in `error`.
+async fn run(
+    store: Store,
+    mut requests: mpsc::Receiver<Request>,
+    error: Arc<Mutex<Option<Arc<Error>>>>,
+    opts: CoalesceOptions,
+) {
+    while let Some(request) = requests.next().await {
+        let mut batch = vec![request];
+        let mut deadline = pin!(rt::sleep(opts.max_delay));
+        let mut changesets = 1;
+        while changesets < opts.max_changesets && !matches!(batch.last(), Some(Request::Flush(_))) {
+            match select(requests.next(), deadline.as_mut()).await {
+                Either::Left((Some(request), _)) => {
+                    changesets += matches!(request, Request::Write(..)) as usize;
+                    batch.push(request);
+                }
+                Either::Left((None, _)) | Either::Right(_) => break,
+            }
+        }
+
+        let mut merged = ChangeSet::default();
+        let mut waiters = Vec::with_capacity(batch.len());
+        for request in batch {
+            match request {
+                Request::Write(changeset, tx) => {
+                    merged.merge(*changeset);
+                    waiters.push(tx);
+                }
+                Request::Flush(tx) => waiters.push(tx),
+            }
+        }
+        let res = match merged.is_empty() {
+            true => Ok(()),
+            false => store.write_changeset(&merged).await.map(|_| ()),
+        };
+        let res = res.map_err(Arc::new);
+        if let Err(e) = &res {
+            // Set before answering the waiters, so that a flush sees its own failure.
+            error
+                .lock()
+                .expect("not poisoned")
+                .get_or_insert_with(|| Arc::clone(e));
+        }
+        for tx in waiters {
+            let _ = tx.send(res.clone().map_err(Error::CoalescedWrite));
+        }
+    }
+}
+
+/// Changesets are enqueued without waiting for them to be written, so that
+/// `Wallet::persist_async` returns immediately. A failed merged write is instead reported by
+/// the next persist, flush or initialization, see [`CoalescingWriter::take_error`]. Call
+/// [`CoalescingWriter::flush`] e.g. before shutting down, which fails if the changesets still
+/// enqueued can't be written. Initializing flushes the enqueued changesets before reading
+/// the wallet.
+impl AsyncWalletPersister for CoalescingWriter {
+    type Error = Error;
+
+    fn initialize<'a>(persister: &'a mut Self) -> FutureResult<'a, ChangeSet, Self::Error>
+    where
+        Self: 'a,
+    {
+        Box::pin(async {
+            persister.flush().await?;
+            Store::initialize(&mut persister.store).await
+        })
+    }
+
+    fn persist<'a>(
+        persister: &'a mut Self,
+        changeset: &'a ChangeSet,
+    ) -> FutureResult<'a, (), Self::Error>
+    where
+        Self: 'a,
+    {
+        Box::pin(async {
+            if let Some(e) = persister.take_error() {
+                return Err(e);
+            }
+            // The write completes in the background, see above.
+            drop(persister.enqueue(changeset.clone()).await);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::local_chain;
+
+    fn block(height: u32) -> ChangeSet {
+        ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(height, Some(Hash::hash(&height.to_le_bytes())))].into(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn coalescing_writer() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let (writer, task) = store.coalescing_writer(
+            CoalesceOptions::default()
+                .max_delay(Duration::from_secs(60))
+                .max_changesets(3),
+        );
+        let task = tokio::spawn(task);
+
+        // The third changeset fills the batch.
+        let mut handles = vec![];
+        for i in 0..3 {
+            handles.push(writer.enqueue(block(i)).await);
+        }
+        for handle in handles {
+            handle.await?;
+        }
+        assert_eq!(store.read_local_chain().await?.blocks.len(), 3);
+
+        // A flush doesn't wait for the batch to fill.
+        let handle = writer.enqueue(block(3)).await;
+        writer.flush().await?;
+        handle.await?;
+        assert_eq!(store.read_local_chain().await?.blocks.len(), 4);
+
+        // A failed write fails every changeset of the batch.
+        let mut invalid = block(4);
+        invalid
+            .tx_graph
+            .last_seen
+            .insert(Hash::hash(b"tx"), u64::MAX);
+        let handle = writer.enqueue(invalid).await;
+        writer.flush().await.unwrap_err();
+        assert!(matches!(handle.await, Err(Error::CoalescedWrite(_))));
+        // The flush took the error.
+        assert!(writer.take_error().is_none());
+
+        drop(writer);
+        task.await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_persist() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let (mut writer, task) =
+            store.coalescing_writer(CoalesceOptions::default().max_changesets(1));
+        let task = tokio::spawn(task);
+
+        let mut invalid = block(0);
+        invalid
+            .tx_graph
+            .last_seen
+            .insert(Hash::hash(b"tx"), u64::MAX);
+
+        // The failure is reported by the next flush.
+        CoalescingWriter::persist(&mut writer, &invalid).await?;
+        assert!(matches!(
+            writer.flush().await,
+            Err(Error::CoalescedWrite(_))
+        ));
+        CoalescingWriter::persist(&mut writer, &block(1)).await?;
+        writer.flush().await?;
+
+        // And by the next persist, once the batches are written in order.
+        CoalescingWriter::persist(&mut writer, &invalid).await?;
+        writer.enqueue(block(2)).await.await?;
+        assert!(matches!(
+            CoalescingWriter::persist(&mut writer, &block(3)).await,
+            Err(Error::CoalescedWrite(_))
+        ));
+        CoalescingWriter::persist(&mut writer, &block(3)).await?;
+        writer.flush().await?;
+        assert_eq!(store.read_local_chain().await?.blocks.len(), 3);
+
+        drop(writer);
+        task.await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+//! Serialization hooks for custom anchor and indexer types.
+
+use std::collections::BTreeSet;
+
+use bdk_chain::bitcoin::{BlockHash, Txid};
+use bdk_chain::{Anchor, BlockId, ConfirmationBlockTime, Merge, tx_graph};
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+use crate::async_store::upsert;
+
+/// Encoding of a custom anchor type, e.g. of a fork of `bdk_chain`, see
+/// [`Store::write_tx_graph_with_codec`].
+///
+/// Anchors are stored in the `anchor` table as `block_id` anchors of their
+/// [`anchor_block`](Anchor::anchor_block), so that the views and the other readers see
+/// them as such, with the encoding in the `data` column.
+pub trait AnchorCodec {
+    /// Anchor type.
+    type Anchor: Anchor;
+
+    /// Name of the codec, stored with every anchor it encodes. Anchors are only decoded by
+    /// the codec of the same name.
+    fn name(&self) -> &str;
+
+    /// Encode `anchor`.
+    fn encode(&self, anchor: &Self::Anchor) -> Result<Vec<u8>, Error>;
+
+    /// Decode an anchor of block `block_id` from `data`.
+    fn decode(&self, block_id: BlockId, data: &[u8]) -> Result<Self::Anchor, Error>;
+}
+
+/// Encoding of the changeset of a custom indexer, e.g. a replacement of `keychain_txout`,
+/// see [`Store::write_indexer_with_codec`].
+///
+/// The merged changeset is stored in the `indexer_data` table by the name of the codec.
+pub trait IndexerCodec {
+    /// Changeset type.
+    type ChangeSet: Merge;
+
+    /// Name of the codec, under which the changeset is stored.
+    fn name(&self) -> &str;
+
+    /// Encode `changeset`.
+    fn encode(&self, changeset: &Self::ChangeSet) -> Result<Vec<u8>, Error>;
+
+    /// Decode a changeset from `data`.
+    fn decode(&self, data: &[u8]) -> Result<Self::ChangeSet, Error>;
+}
+
+impl Store {
+    /// Write a tx_graph of which the anchors are encoded by `codec`.
+    ///
+    /// Transactions, txouts and seen times are written as by
+    /// [`write_tx_graph`](Self::write_tx_graph). An anchor replaces the encoding of a
+    /// stored anchor of the same block and transaction unless that anchor has a
+    /// confirmation time, i.e. was written as a [`ConfirmationBlockTime`].
+    pub async fn write_tx_graph_with_codec<C: AnchorCodec>(
+        &self,
+        codec: &C,
+        tx_graph: &tx_graph::ChangeSet<C::Anchor>,
+    ) -> Result<WriteSummary, Error> {
+        let mut anchors = vec![];
+        for (anchor, txid) in &tx_graph.anchors {
+            anchors.push((anchor.anchor_block(), *txid, codec.encode(anchor)?));
+        }
+        let tx_graph = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: tx_graph.txs.clone(),
+            txouts: tx_graph.txouts.clone(),
+            anchors: BTreeSet::new(),
+            first_seen: tx_graph.first_seen.clone(),
+            last_seen: tx_graph.last_seen.clone(),
+            last_evicted: tx_graph.last_evicted.clone(),
+        };
+
+        self.write(WriteOptions::default(), async |conn| {
+            let mut summary = self.write_tx_graph_in(conn, &tx_graph).await?;
+            for (block_id, txid, data) in &anchors {
+                write_encoded_anchor(conn, &mut summary, codec.name(), *block_id, txid, data)
+                    .await?;
+            }
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Read the tx_graph with the anchors encoded by `codec`.
+    ///
+    /// Anchors of other codecs and of the types of `bdk_chain` are not read.
+    pub async fn read_tx_graph_with_codec<C: AnchorCodec>(
+        &self,
+        codec: &C,
+    ) -> Result<tx_graph::ChangeSet<C::Anchor>, Error> {
+        let tx_graph = self.read_tx_graph().await?;
+        let rows = sqlx::query(
+            "SELECT block_height, block_hash, txid, data FROM anchor WHERE codec = $1 ORDER BY txid, block_height, block_hash",
+        )
+        .bind(codec.name())
+        .fetch_all(&self.pool)
+        .await?;
+        let mut anchors = BTreeSet::new();
+        for row in rows {
+            let hash: String = row.get("block_hash");
+            let block_id = BlockId {
+                height: row.get("block_height"),
+                hash: hash.parse::<BlockHash>()?,
+            };
+            let txid: String = row.get("txid");
+            let data: Vec<u8> = row.get("data");
+            anchors.insert((codec.decode(block_id, &data)?, txid.parse()?));
+        }
+
+        Ok(tx_graph::ChangeSet {
+            txs: tx_graph.txs,
+            txouts: tx_graph.txouts,
+            anchors,
+            first_seen: tx_graph.first_seen,
+            last_seen: tx_graph.last_seen,
+            last_evicted: tx_graph.last_evicted,
+        })
+    }
+
+    /// Merge `changeset` into the changeset of `codec`.
+    pub async fn write_indexer_with_codec<C: IndexerCodec>(
+        &self,
+        codec: &C,
+        changeset: &C::ChangeSet,
+    ) -> Result<WriteSummary, Error>
+    where
+        C::ChangeSet: Clone,
+    {
+        if changeset.is_empty() {
+            return Ok(WriteSummary::default());
+        }
+        self.write(WriteOptions::default(), async |conn| {
+            let row = sqlx::query("SELECT data FROM indexer_data WHERE codec = $1")
+                .bind(codec.name())
+                .fetch_optional(&mut *conn)
+                .await?;
+            let mut merged = match row {
+                Some(row) => codec.decode(&row.get::<Vec<u8>, _>("data"))?,
+                None => C::ChangeSet::default(),
+            };
+            merged.merge(changeset.clone());
+            let data = codec.encode(&merged)?;
+
+            let mut summary = WriteSummary::default();
+            upsert(
+                conn,
+                &mut summary,
+                "indexer_data",
+                sqlx::query("INSERT OR IGNORE INTO indexer_data(codec, data) VALUES($1, $2)")
+                    .bind(codec.name())
+                    .bind(&data),
+                sqlx::query(
+                    "UPDATE indexer_data SET data = $2 WHERE codec = $1 AND data IS NOT $2",
+                )
+                .bind(codec.name())
+                .bind(&data),
+            )
+            .await?;
+            Ok(summary)
+        })
+        .await
+    }
+
+    /// Read the changeset of `codec`, empty if none was written.
+    pub async fn read_indexer_with_codec<C: IndexerCodec>(
+        &self,
+        codec: &C,
+    ) -> Result<C::ChangeSet, Error> {
+        let row = sqlx::query("SELECT data FROM indexer_data WHERE codec = $1")
+            .bind(codec.name())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => codec.decode(&row.get::<Vec<u8>, _>("data")),
+            None => Ok(C::ChangeSet::default()),
+        }
+    }
+}
+
+/// Write an anchor encoded by codec `name`.
+async fn write_encoded_anchor(
+    conn: &mut SqliteConnection,
+    summary: &mut WriteSummary,
+    name: &str,
+    block_id: BlockId,
+    txid: &Txid,
+    data: &[u8],
+) -> Result<(), Error> {
+    let txid = txid.to_string();
+    let hash = block_id.hash.to_string();
+    upsert(
+        conn,
+        summary,
+        "anchor",
+        sqlx::query(
+            "INSERT OR IGNORE INTO anchor(block_height, block_hash, txid, kind, codec, data) VALUES($1, $2, $3, 'block_id', $4, $5)",
+        )
+        .bind(block_id.height)
+        .bind(&hash)
+        .bind(&txid)
+        .bind(name)
+        .bind(data),
+        sqlx::query(
+            "UPDATE anchor SET codec = $4, data = $5 WHERE block_height = $1 AND block_hash = $2 AND txid = $3 AND kind = 'block_id' AND (codec IS NOT $4 OR data IS NOT $5)",
+        )
+        .bind(block_id.height)
+        .bind(&hash)
+        .bind(&txid)
+        .bind(name)
+        .bind(data),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+
+    /// An anchor of a fork, with the position of the transaction in the block.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct PositionAnchor {
+        block_id: BlockId,
+        position: u32,
+    }
+
+    impl Anchor for PositionAnchor {
+        fn anchor_block(&self) -> BlockId {
+            self.block_id
+        }
+    }
+
+    struct PositionCodec;
+
+    impl AnchorCodec for PositionCodec {
+        type Anchor = PositionAnchor;
+
+        fn name(&self) -> &str {
+            "position"
+        }
+
+        fn encode(&self, anchor: &PositionAnchor) -> Result<Vec<u8>, Error> {
+            Ok(anchor.position.to_le_bytes().to_vec())
+        }
+
+        fn decode(&self, block_id: BlockId, data: &[u8]) -> Result<PositionAnchor, Error> {
+            let position = data.try_into().map_err(Error::other)?;
+            Ok(PositionAnchor {
+                block_id,
+                position: u32::from_le_bytes(position),
+            })
+        }
+    }
+
+    /// Last used index per label, encoded as JSON.
+    struct LabelIndexCodec;
+
+    impl IndexerCodec for LabelIndexCodec {
+        type ChangeSet = BTreeMap<String, u32>;
+
+        fn name(&self) -> &str {
+            "label_index"
+        }
+
+        fn encode(&self, changeset: &Self::ChangeSet) -> Result<Vec<u8>, Error> {
+            Ok(serde_json::to_vec(changeset)?)
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Self::ChangeSet, Error> {
+            Ok(serde_json::from_slice(data)?)
+        }
+    }
+
+    #[tokio::test]
+    async fn anchor_codec() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let anchor = PositionAnchor {
+            block_id: BlockId {
+                height: 1,
+                hash: Hash::hash(b"1"),
+            },
+            position: 7,
+        };
+        let tx_graph = tx_graph::ChangeSet {
+            anchors: [(anchor, txid)].into(),
+            first_seen: [(txid, 100)].into(),
+            ..Default::default()
+        };
+        let summary = store
+            .write_tx_graph_with_codec(&PositionCodec, &tx_graph)
+            .await?;
+        assert_eq!(summary.table("anchor").inserted, 1);
+        assert_eq!(
+            store.read_tx_graph_with_codec(&PositionCodec).await?,
+            tx_graph
+        );
+        assert!(
+            store
+                .write_tx_graph_with_codec(&PositionCodec, &tx_graph)
+                .await?
+                .is_empty()
+        );
+
+        // Other readers see a block id anchor.
+        assert!(store.read_tx_graph().await?.anchors.is_empty());
+        let anchors = store.read_anchors().await?;
+        assert_eq!(
+            anchors,
+            [(crate::StoredAnchor::BlockId(anchor.block_id), txid)].into()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn indexer_codec() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        assert!(
+            store
+                .read_indexer_with_codec(&LabelIndexCodec)
+                .await?
+                .is_empty()
+        );
+
+        let changeset = BTreeMap::from([("rent".to_string(), 2), ("salary".to_string(), 1)]);
+        store
+            .write_indexer_with_codec(&LabelIndexCodec, &changeset)
+            .await?;
+        let summary = store
+            .write_indexer_with_codec(&LabelIndexCodec, &[("rent".to_string(), 3)].into())
+            .await?;
+        assert_eq!(summary.table("indexer_data").updated, 1);
+        assert_eq!(
+            store.read_indexer_with_codec(&LabelIndexCodec).await?,
+            BTreeMap::from([("rent".to_string(), 3), ("salary".to_string(), 1)])
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Coin control metadata.
+
+use bdk_chain::bitcoin;
+use bitcoin::{OutPoint, Txid};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, to_sql};
+
+/// An output excluded from coin selection by [`Store::freeze_utxo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrozenUtxo {
+    /// Outpoint
+    pub outpoint: OutPoint,
+    /// Reason the output was frozen
+    pub reason: Option<String>,
+    /// Unix time at which the output was frozen
+    pub frozen_at: u64,
+}
+
+impl Store {
+    /// Freeze `outpoint` with an optional `reason`.
+    ///
+    /// Freezing an already frozen output replaces its reason.
+    pub async fn freeze_utxo(&self, outpoint: OutPoint, reason: Option<&str>) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO frozen_utxo(txid, vout, reason, frozen_at) VALUES($1, $2, $3, $4) ON CONFLICT DO UPDATE SET reason = $3",
+            )
+            .bind(outpoint.txid.to_string())
+            .bind(outpoint.vout)
+            .bind(reason)
+            .bind(to_sql("frozen_utxo.frozen_at", self.now())?)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Unfreeze `outpoint`.
+    pub async fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query("DELETE FROM frozen_utxo WHERE txid = $1 AND vout = $2")
+                .bind(outpoint.txid.to_string())
+                .bind(outpoint.vout)
+                .execute(&mut *conn)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read all frozen outputs, ordered by outpoint.
+    pub async fn frozen_utxos(&self) -> Result<Vec<FrozenUtxo>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid, vout, reason, frozen_at FROM frozen_utxo ORDER BY txid, vout",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut frozen = vec![];
+        for row in rows {
+            let txid: String = row.get("txid");
+            let txid: Txid = txid.parse()?;
+            let vout: u32 = row.get("vout");
+            let frozen_at: i64 = row.get("frozen_at");
+            frozen.push(FrozenUtxo {
+                outpoint: OutPoint { txid, vout },
+                reason: row.get("reason"),
+                frozen_at: from_sql("frozen_utxo.frozen_at", frozen_at)?,
+            });
+        }
+
+        Ok(frozen)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bitcoin::hashes::Hash;
+
+    use crate::FixedClock;
+
+    #[tokio::test]
+    async fn freeze_and_unfreeze() -> anyhow::Result<()> {
+        let store = Store::new_memory()
+            .await?
+            .with_clock(FixedClock(1_700_000_000));
+        store.migrate().await?;
+
+        let op_a = OutPoint::new(Hash::hash(b"a"), 0);
+        let op_b = OutPoint::new(Hash::hash(b"b"), 1);
+        store.freeze_utxo(op_a, Some("dust")).await?;
+        store.freeze_utxo(op_b, None).await?;
+        store.freeze_utxo(op_a, Some("kyc")).await?;
+        store.unfreeze_utxo(op_b).await?;
+
+        let frozen = store.frozen_utxos().await?;
+        assert_eq!(frozen.len(), 1);
+        assert_eq!(frozen[0].outpoint, op_a);
+        assert_eq!(frozen[0].reason.as_deref(), Some("kyc"));
+        assert_eq!(frozen[0].frozen_at, 1_700_000_000);
+
+        Ok(())
+    }
+}
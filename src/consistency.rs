@@ -0,0 +1,333 @@
+//! Consistency of the spk cache with the stored descriptors.
+
+use core::fmt;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bdk_chain::bitcoin::ScriptBuf;
+use bdk_chain::{DescriptorExt, DescriptorId, miniscript};
+use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::convert::from_sql;
+
+/// Which cached scripts [`Store::check_spk_cache`] derives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpkCheck {
+    /// Every cached script.
+    Full,
+    /// At most this many scripts per descriptor, spread evenly over the cached indices
+    /// and including the first and the last.
+    Sample(usize),
+    /// At most this many scripts per descriptor, picked at random.
+    Random(usize),
+}
+
+/// A problem of the spk cache found by [`Store::check_spk_cache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpkCacheWarning {
+    /// Scripts are cached for a descriptor which isn't stored, neither as a keychain,
+    /// retired or multipath descriptor.
+    UnknownDescriptor {
+        /// Descriptor id of the cached scripts
+        descriptor_id: DescriptorId,
+        /// Number of cached scripts
+        scripts: u64,
+    },
+    /// A cached script doesn't derive from its descriptor at its index.
+    ScriptMismatch {
+        /// Descriptor id
+        descriptor_id: DescriptorId,
+        /// Derivation index
+        index: u32,
+    },
+}
+
+impl fmt::Display for SpkCacheWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownDescriptor {
+                descriptor_id,
+                scripts,
+            } => write!(
+                f,
+                "{scripts} scripts are cached for unknown descriptor {descriptor_id}"
+            ),
+            Self::ScriptMismatch {
+                descriptor_id,
+                index,
+            } => write!(
+                f,
+                "cached script of {descriptor_id} at index {index} doesn't derive from the descriptor"
+            ),
+        }
+    }
+}
+
+/// Outcome of [`Store::check_spk_cache`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpkCacheReport {
+    /// Number of scripts derived
+    pub checked: u64,
+    /// Problems found
+    pub warnings: Vec<SpkCacheWarning>,
+}
+
+impl SpkCacheReport {
+    /// Whether no problem was found.
+    pub fn is_consistent(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+impl Store {
+    /// Check that the cached scripts derive from the stored descriptors at their indices,
+    /// deriving all or a sample of them as given by `check`.
+    ///
+    /// Meant to be run on load, before the wallet is created from the store, to catch a
+    /// database whose descriptors were tampered with or whose spk cache was mixed up with
+    /// the one of another wallet. The descriptors are those of the keychains, including the
+    /// retired ones, and the expansions of the multipath descriptor. Problems are reported
+    /// rather than returned as an error, so that the caller decides whether to load.
+    pub async fn check_spk_cache(&self, check: SpkCheck) -> Result<SpkCacheReport, Error> {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(
+            "SELECT descriptor FROM keychain
+            UNION SELECT descriptor FROM keychain_history
+            UNION SELECT descriptor FROM multipath_descriptor_path",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut descriptors = BTreeMap::new();
+        for row in rows {
+            let descriptor: String = row.get("descriptor");
+            let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&descriptor)?;
+            descriptors.insert(descriptor.descriptor_id(), descriptor);
+        }
+
+        let rows = sqlx::query(
+            "SELECT descriptor_id, derivation_index, script FROM keychain_script_pubkey ORDER BY descriptor_id, derivation_index",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut spk_cache = BTreeMap::<DescriptorId, Vec<(u32, ScriptBuf)>>::new();
+        for row in rows {
+            let descriptor_id: String = row.get("descriptor_id");
+            let index: u32 = from_sql(
+                "keychain_script_pubkey.derivation_index",
+                row.get::<i64, _>("derivation_index"),
+            )?;
+            let script: Vec<u8> = row.get("script");
+            spk_cache
+                .entry(descriptor_id.parse()?)
+                .or_default()
+                .push((index, ScriptBuf::from_bytes(script)));
+        }
+
+        let mut report = SpkCacheReport::default();
+        for (descriptor_id, scripts) in spk_cache {
+            let Some(descriptor) = descriptors.get(&descriptor_id) else {
+                report.warnings.push(SpkCacheWarning::UnknownDescriptor {
+                    descriptor_id,
+                    scripts: scripts.len() as u64,
+                });
+                continue;
+            };
+            for (index, script) in sample(&scripts, check)? {
+                report.checked += 1;
+                let derived = descriptor
+                    .at_derivation_index(*index)
+                    .map(|d| d.script_pubkey());
+                if derived.as_ref() != Ok(script) {
+                    report.warnings.push(SpkCacheWarning::ScriptMismatch {
+                        descriptor_id,
+                        index: *index,
+                    });
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Set whether [`read_changeset`](Self::read_changeset) first checks the spk cache as
+    /// with [`check_spk_cache`](Self::check_spk_cache), failing with
+    /// [`Error::SpkCacheMismatch`] if there is any problem.
+    ///
+    /// A cached script which doesn't derive from its descriptor means the cache belongs to
+    /// another wallet, e.g. after database files were swapped between wallets with similar
+    /// layouts, and funds could be sent to the wrong keychain. [`SpkCheck::Random`] keeps
+    /// the cost of loading low while still catching a swapped cache. Defaults to `None`.
+    pub fn with_verify_spk_cache(mut self, check: Option<SpkCheck>) -> Self {
+        self.verify_spk_cache = check;
+        self
+    }
+
+    /// Verify the spk cache before a wallet changeset is read, if enabled.
+    pub(crate) async fn verify_spk_cache(&self) -> Result<(), Error> {
+        let Some(check) = self.verify_spk_cache else {
+            return Ok(());
+        };
+        let report = self.check_spk_cache(check).await?;
+        if !report.is_consistent() {
+            return Err(Error::SpkCacheMismatch(report.warnings));
+        }
+
+        Ok(())
+    }
+}
+
+/// The entries of `scripts` to derive for `check`.
+fn sample<T>(scripts: &[T], check: SpkCheck) -> Result<Vec<&T>, Error> {
+    Ok(match check {
+        SpkCheck::Random(n) if n < scripts.len() => {
+            // A partial Fisher-Yates shuffle of the positions, picked in order.
+            let mut positions: Vec<usize> = (0..scripts.len()).collect();
+            for i in 0..n {
+                let mut random = [0; 8];
+                getrandom::getrandom(&mut random)?;
+                let j = i + (u64::from_le_bytes(random) % (scripts.len() - i) as u64) as usize;
+                positions.swap(i, j);
+            }
+            positions.truncate(n);
+            positions.sort_unstable();
+            positions.into_iter().map(|pos| &scripts[pos]).collect()
+        }
+        SpkCheck::Sample(n) if n < scripts.len() => {
+            let last = scripts.len() - 1;
+            let mut picked: Vec<&T> = vec![];
+            for i in 0..n {
+                let pos = if n == 1 { 0 } else { i * last / (n - 1) };
+                picked.push(&scripts[pos]);
+            }
+            picked
+        }
+        _ => scripts.iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::keychain_txout;
+    use bdk_wallet::KeychainKind;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+
+    #[test]
+    fn sample_spreads_evenly() -> anyhow::Result<()> {
+        let scripts: Vec<u32> = (0..10).collect();
+        assert_eq!(sample(&scripts, SpkCheck::Sample(3))?, [&0, &4, &9]);
+        assert_eq!(sample(&scripts, SpkCheck::Sample(1))?, [&0]);
+        assert_eq!(sample(&scripts, SpkCheck::Sample(20))?.len(), 10);
+        assert_eq!(sample(&scripts, SpkCheck::Full)?.len(), 10);
+
+        let picked = sample(&scripts, SpkCheck::Random(4))?;
+        assert_eq!(picked.len(), 4);
+        assert!(picked.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(sample(&scripts, SpkCheck::Random(20))?.len(), 10);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn check_spk_cache() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTOR.parse()?;
+        let descriptor_id = descriptor.descriptor_id();
+        store
+            .write_keychain_descriptors([(KeychainKind::External, descriptor.clone())].into())
+            .await?;
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            descriptor_id,
+            (0..5)
+                .map(|i| Ok((i, descriptor.at_derivation_index(i)?.script_pubkey())))
+                .collect::<anyhow::Result<_>>()?,
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        let report = store.check_spk_cache(SpkCheck::Full).await?;
+        assert!(report.is_consistent());
+        assert_eq!(report.checked, 5);
+        assert_eq!(store.check_spk_cache(SpkCheck::Sample(2)).await?.checked, 2);
+
+        // A script swapped in by another wallet and the scripts of an unknown descriptor.
+        sqlx::query("UPDATE keychain_script_pubkey SET script = x'51' WHERE derivation_index = 3")
+            .execute(&store.pool)
+            .await?;
+        let other = DescriptorId::from_byte_array([1; 32]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer
+            .spk_cache
+            .insert(other, [(0, ScriptBuf::from_bytes(vec![0x52]))].into());
+        store.write_keychain_txout(&indexer).await?;
+
+        let report = store.check_spk_cache(SpkCheck::Full).await?;
+        assert_eq!(
+            report.warnings,
+            [
+                SpkCacheWarning::UnknownDescriptor {
+                    descriptor_id: other,
+                    scripts: 1
+                },
+                SpkCacheWarning::ScriptMismatch {
+                    descriptor_id,
+                    index: 3
+                },
+            ]
+        );
+        // The sample of the first and last index misses the mismatch.
+        let report = store.check_spk_cache(SpkCheck::Sample(2)).await?;
+        assert_eq!(report.warnings.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verify_spk_cache_on_read() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let descriptor: Descriptor<DescriptorPublicKey> = DESCRIPTOR.parse()?;
+        store
+            .write_keychain_descriptors([(KeychainKind::External, descriptor.clone())].into())
+            .await?;
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            descriptor.descriptor_id(),
+            (0..5)
+                .map(|i| Ok((i, descriptor.at_derivation_index(i)?.script_pubkey())))
+                .collect::<anyhow::Result<_>>()?,
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        let verified = store
+            .clone()
+            .with_verify_spk_cache(Some(SpkCheck::Random(2)));
+        verified.read_changeset().await?;
+
+        // The cache of another wallet with the same layout.
+        sqlx::query("UPDATE keychain_script_pubkey SET script = x'51'")
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            verified.read_changeset().await,
+            Err(Error::SpkCacheMismatch(warnings)) if warnings.len() == 2
+        ));
+        assert!(matches!(
+            verified.read_changeset_with_progress(|_| {}).await,
+            Err(Error::SpkCacheMismatch(_))
+        ));
+        store.read_changeset().await?;
+
+        Ok(())
+    }
+}
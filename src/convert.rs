@@ -0,0 +1,93 @@
+//! Conversion of integers to and from the `i64` of SQLite INTEGER columns.
+
+use crate::Error;
+
+/// Convert `value` to be stored in `column`.
+pub(crate) fn to_sql<T>(column: &'static str, value: T) -> Result<i64, Error>
+where
+    T: TryInto<i64> + Into<i128> + Copy,
+{
+    value.try_into().map_err(|_| Error::ValueOutOfRange {
+        column,
+        value: value.into(),
+    })
+}
+
+/// Convert `value` read from `column`.
+pub(crate) fn from_sql<T: TryFrom<i64>>(column: &'static str, value: i64) -> Result<T, Error> {
+    T::try_from(value).map_err(|_| Error::ValueOutOfRange {
+        column,
+        value: value.into(),
+    })
+}
+
+/// Convert the nullable `value` read from `column`.
+pub(crate) fn from_sql_opt<T: TryFrom<i64>>(
+    column: &'static str,
+    value: Option<i64>,
+) -> Result<Option<T>, Error> {
+    value.map(|value| from_sql(column, value)).transpose()
+}
+
+/// Sum `values` to be stored in `column`.
+///
+/// SQLite's `SUM` fails with a generic error on overflow, so aggregates of amounts are
+/// summed here instead, as `i128` which can't overflow for any number of `i64` values a
+/// database can hold.
+pub(crate) fn sum_sql(
+    column: &'static str,
+    values: impl IntoIterator<Item = i64>,
+) -> Result<i64, Error> {
+    to_sql(column, values.into_iter().map(i128::from).sum::<i128>())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boundary_values() {
+        assert_eq!(to_sql("c", 0u64).unwrap(), 0);
+        assert_eq!(to_sql("c", i64::MAX as u64).unwrap(), i64::MAX);
+        assert!(matches!(
+            to_sql("tx.last_seen", i64::MAX as u64 + 1),
+            Err(Error::ValueOutOfRange { column: "tx.last_seen", value }) if value == i64::MAX as i128 + 1
+        ));
+        assert!(matches!(
+            to_sql("c", u64::MAX),
+            Err(Error::ValueOutOfRange { value, .. }) if value == u64::MAX as i128
+        ));
+        assert_eq!(to_sql("c", u32::MAX).unwrap(), u32::MAX as i64);
+        assert_eq!(to_sql("c", i64::MIN).unwrap(), i64::MIN);
+
+        assert_eq!(from_sql::<u64>("c", 0).unwrap(), 0);
+        assert_eq!(from_sql::<u64>("c", i64::MAX).unwrap(), i64::MAX as u64);
+        assert!(matches!(
+            from_sql::<u64>("c", -1),
+            Err(Error::ValueOutOfRange { value: -1, .. })
+        ));
+        assert!(matches!(
+            from_sql::<u64>("c", i64::MIN),
+            Err(Error::ValueOutOfRange { value, .. }) if value == i64::MIN as i128
+        ));
+        assert_eq!(from_sql::<u32>("c", u32::MAX as i64).unwrap(), u32::MAX);
+        assert!(matches!(
+            from_sql::<u32>("c", u32::MAX as i64 + 1),
+            Err(Error::ValueOutOfRange { .. })
+        ));
+
+        assert_eq!(from_sql_opt::<u64>("c", None).unwrap(), None);
+        assert_eq!(from_sql_opt::<u64>("c", Some(1)).unwrap(), Some(1));
+        assert!(from_sql_opt::<u64>("c", Some(-1)).is_err());
+    }
+
+    #[test]
+    fn sum() {
+        assert_eq!(sum_sql("c", []).unwrap(), 0);
+        assert_eq!(sum_sql("c", [i64::MAX, -1, 1]).unwrap(), i64::MAX);
+        assert!(matches!(
+            sum_sql("tx_summary.received", [i64::MAX, 1]),
+            Err(Error::ValueOutOfRange { column: "tx_summary.received", value }) if value == i64::MAX as i128 + 1
+        ));
+    }
+}
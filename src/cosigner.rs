@@ -0,0 +1,205 @@
+//! Registry of the cosigners of multisig wallets.
+
+use std::collections::BTreeSet;
+
+use bdk_chain::bitcoin::bip32::{Fingerprint, Xpub};
+use bdk_chain::miniscript::ForEachKey;
+use bdk_chain::miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql_opt, to_sql};
+
+/// A cosigner of a multisig wallet, see [`Store::set_cosigner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cosigner {
+    /// Master fingerprint of the cosigner's key, as in the key origins of descriptors
+    pub fingerprint: Fingerprint,
+    /// Name shown to the user
+    pub label: String,
+    /// Extended public key contributed to the wallet, if known
+    pub xpub: Option<Xpub>,
+    /// How to reach the cosigner, e.g. an email address or a Nostr public key
+    pub contact: Option<String>,
+    /// Unix time of the last signature of the cosigner, see
+    /// [`Store::record_cosigner_signature`]
+    pub last_signed_at: Option<u64>,
+}
+
+impl Cosigner {
+    /// A cosigner with the given fingerprint and label, and no other metadata.
+    pub fn new(fingerprint: Fingerprint, label: impl Into<String>) -> Self {
+        Self {
+            fingerprint,
+            label: label.into(),
+            xpub: None,
+            contact: None,
+            last_signed_at: None,
+        }
+    }
+}
+
+impl Store {
+    /// Insert `cosigner`, or replace the cosigner with its fingerprint.
+    ///
+    /// This lets coordinator software keep the metadata of cosigners alongside the
+    /// descriptors which reference them, see [`descriptor_cosigners`](Self::descriptor_cosigners).
+    pub async fn set_cosigner(&self, cosigner: &Cosigner) -> Result<(), Error> {
+        let last_signed_at = cosigner
+            .last_signed_at
+            .map(|t| to_sql("cosigner.last_signed_at", t))
+            .transpose()?;
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO cosigner(fingerprint, label, xpub, contact, last_signed_at) VALUES($1, $2, $3, $4, $5) ON CONFLICT DO UPDATE SET label = $2, xpub = $3, contact = $4, last_signed_at = $5",
+            )
+            .bind(cosigner.fingerprint.to_string())
+            .bind(&cosigner.label)
+            .bind(cosigner.xpub.map(|xpub| xpub.to_string()))
+            .bind(&cosigner.contact)
+            .bind(last_signed_at)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read the cosigner with `fingerprint`, if any.
+    pub async fn cosigner(&self, fingerprint: Fingerprint) -> Result<Option<Cosigner>, Error> {
+        let row = sqlx::query(
+            "SELECT fingerprint, label, xpub, contact, last_signed_at FROM cosigner WHERE fingerprint = $1",
+        )
+        .bind(fingerprint.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(cosigner_from_row).transpose()
+    }
+
+    /// Read every cosigner, ordered by label and fingerprint.
+    pub async fn cosigners(&self) -> Result<Vec<Cosigner>, Error> {
+        let rows = sqlx::query(
+            "SELECT fingerprint, label, xpub, contact, last_signed_at FROM cosigner ORDER BY label, fingerprint",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(cosigner_from_row).collect()
+    }
+
+    /// Read the cosigners whose fingerprint is the master fingerprint of a key of
+    /// `descriptor`, ordered by label and fingerprint.
+    pub async fn descriptor_cosigners(
+        &self,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<Vec<Cosigner>, Error> {
+        let mut fingerprints = BTreeSet::new();
+        descriptor.for_each_key(|key| {
+            fingerprints.insert(key.master_fingerprint());
+            true
+        });
+
+        let mut cosigners = vec![];
+        for cosigner in self.cosigners().await? {
+            if fingerprints.contains(&cosigner.fingerprint) {
+                cosigners.push(cosigner);
+            }
+        }
+
+        Ok(cosigners)
+    }
+
+    /// Remove the cosigner with `fingerprint`, returning whether it existed.
+    pub async fn remove_cosigner(&self, fingerprint: Fingerprint) -> Result<bool, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query("DELETE FROM cosigner WHERE fingerprint = $1")
+                .bind(fingerprint.to_string())
+                .execute(&mut *conn)
+                .await?;
+            Ok(res.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Set the last signature time of the cosigner with `fingerprint` to now, returning
+    /// whether it exists.
+    pub async fn record_cosigner_signature(&self, fingerprint: Fingerprint) -> Result<bool, Error> {
+        let now = to_sql("cosigner.last_signed_at", self.now())?;
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query("UPDATE cosigner SET last_signed_at = $2 WHERE fingerprint = $1")
+                .bind(fingerprint.to_string())
+                .bind(now)
+                .execute(&mut *conn)
+                .await?;
+            Ok(res.rows_affected() > 0)
+        })
+        .await
+    }
+}
+
+fn cosigner_from_row(row: SqliteRow) -> Result<Cosigner, Error> {
+    let fingerprint: String = row.get("fingerprint");
+    let xpub: Option<String> = row.get("xpub");
+
+    Ok(Cosigner {
+        fingerprint: fingerprint.parse().map_err(Error::other)?,
+        label: row.get("label"),
+        xpub: xpub
+            .map(|xpub| xpub.parse())
+            .transpose()
+            .map_err(Error::other)?,
+        contact: row.get("contact"),
+        last_signed_at: from_sql_opt("cosigner.last_signed_at", row.get("last_signed_at"))?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::FixedClock;
+
+    const XPUB_A: &str = "tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2";
+    const XPUB_B: &str = "tpubD6NzVbkrYhZ4Xferm7Pz4VnjdcDPFyjVu5K4iZXQ4pVN8Cks4pHVowTBXBKRhX64pkRyJZJN5xAKj4UDNnLPb5p2sSKXhewoYx5GbTdUFWq";
+
+    #[tokio::test]
+    async fn cosigners() -> anyhow::Result<()> {
+        let store = Store::new_memory()
+            .await?
+            .with_clock(FixedClock(1_700_000_000));
+        store.migrate().await?;
+
+        let alice = Fingerprint::from([0xe2, 0x73, 0xfe, 0x42]);
+        let bob = Fingerprint::from([0x01, 0x02, 0x03, 0x04]);
+        let mut cosigner = Cosigner::new(alice, "Alice");
+        cosigner.xpub = Some(XPUB_A.parse()?);
+        store.set_cosigner(&cosigner).await?;
+        cosigner.contact = Some("alice@example.com".to_string());
+        store.set_cosigner(&cosigner).await?;
+        store.set_cosigner(&Cosigner::new(bob, "Bob")).await?;
+        assert_eq!(store.cosigner(alice).await?, Some(cosigner.clone()));
+        assert_eq!(store.cosigners().await?.len(), 2);
+
+        assert!(store.record_cosigner_signature(alice).await?);
+        assert_eq!(
+            store.cosigner(alice).await?.unwrap().last_signed_at,
+            Some(1_700_000_000)
+        );
+
+        let descriptor = format!("wsh(multi(2,[e273fe42/48'/1'/0'/2']{XPUB_A}/0/*,{XPUB_B}/0/*))")
+            .parse::<Descriptor<DescriptorPublicKey>>()?;
+        let referenced = store.descriptor_cosigners(&descriptor).await?;
+        assert_eq!(referenced.len(), 1);
+        assert_eq!(referenced[0].label, "Alice");
+
+        assert!(store.remove_cosigner(bob).await?);
+        assert!(!store.remove_cosigner(bob).await?);
+        assert!(!store.record_cosigner_signature(bob).await?);
+
+        Ok(())
+    }
+}
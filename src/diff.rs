@@ -0,0 +1,157 @@
+//! Diffing the store against an in-memory [`ChangeSet`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bdk_chain::{ConfirmationBlockTime, keychain_txout, local_chain, tx_graph};
+use bdk_wallet::ChangeSet;
+
+use crate::Error;
+use crate::Store;
+
+/// Differences between the store and a [`ChangeSet`], see [`Store::diff_against`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeSetDiff {
+    /// Data in the store which the changeset lacks or has a different value for
+    pub only_in_store: ChangeSet,
+    /// Data in the changeset which the store lacks or has a different value for
+    pub only_in_changeset: ChangeSet,
+}
+
+impl ChangeSetDiff {
+    /// Whether the store and the changeset hold the same data.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_store == ChangeSet::default() && self.only_in_changeset == ChangeSet::default()
+    }
+}
+
+impl Store {
+    /// Report what the store has that `changeset` lacks and vice versa.
+    ///
+    /// This is useful for debugging a wallet which lost data across a restart: diff the
+    /// wallet's aggregate changeset (e.g. from `Wallet::staged` merged over the initial
+    /// changeset) against what was actually persisted.
+    pub async fn diff_against(&self, changeset: &ChangeSet) -> Result<ChangeSetDiff, Error> {
+        let stored = self.read_changeset().await?;
+
+        Ok(ChangeSetDiff {
+            only_in_store: difference(&stored, changeset),
+            only_in_changeset: difference(changeset, &stored),
+        })
+    }
+}
+
+/// Data in `a` which is not in `b`.
+fn difference(a: &ChangeSet, b: &ChangeSet) -> ChangeSet {
+    ChangeSet {
+        descriptor: a
+            .descriptor
+            .clone()
+            .filter(|d| b.descriptor.as_ref() != Some(d)),
+        change_descriptor: a
+            .change_descriptor
+            .clone()
+            .filter(|d| b.change_descriptor.as_ref() != Some(d)),
+        network: a.network.filter(|n| b.network != Some(*n)),
+        local_chain: local_chain::ChangeSet {
+            blocks: map_difference(&a.local_chain.blocks, &b.local_chain.blocks),
+        },
+        tx_graph: tx_graph_difference(&a.tx_graph, &b.tx_graph),
+        indexer: keychain_txout::ChangeSet {
+            last_revealed: map_difference(&a.indexer.last_revealed, &b.indexer.last_revealed),
+            spk_cache: a
+                .indexer
+                .spk_cache
+                .iter()
+                .filter_map(|(did, a_spks)| {
+                    let spks = match b.indexer.spk_cache.get(did) {
+                        Some(b_spks) => map_difference(a_spks, b_spks),
+                        None => a_spks.clone(),
+                    };
+                    (!spks.is_empty()).then_some((*did, spks))
+                })
+                .collect(),
+        },
+    }
+}
+
+fn tx_graph_difference(
+    a: &tx_graph::ChangeSet<ConfirmationBlockTime>,
+    b: &tx_graph::ChangeSet<ConfirmationBlockTime>,
+) -> tx_graph::ChangeSet<ConfirmationBlockTime> {
+    let b_txids: BTreeSet<_> = b.txs.iter().map(|tx| tx.compute_txid()).collect();
+    tx_graph::ChangeSet {
+        txs: a
+            .txs
+            .iter()
+            .filter(|tx| !b_txids.contains(&tx.compute_txid()))
+            .cloned()
+            .collect(),
+        txouts: map_difference(&a.txouts, &b.txouts),
+        anchors: a.anchors.difference(&b.anchors).cloned().collect(),
+        first_seen: map_difference(&a.first_seen, &b.first_seen),
+        last_seen: map_difference(&a.last_seen, &b.last_seen),
+        last_evicted: map_difference(&a.last_evicted, &b.last_evicted),
+    }
+}
+
+/// Entries of `a` which are missing from or have a different value in `b`.
+fn map_difference<K: Ord + Clone, V: PartialEq + Clone>(
+    a: &BTreeMap<K, V>,
+    b: &BTreeMap<K, V>,
+) -> BTreeMap<K, V> {
+    a.iter()
+        .filter(|(k, v)| b.get(k) != Some(v))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::{BlockHash, Network, hashes::Hash};
+
+    #[tokio::test]
+    async fn diff_store_against_changeset() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let mut stored = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        stored.local_chain.blocks.insert(0, Some(Hash::hash(b"0")));
+        stored.local_chain.blocks.insert(1, Some(Hash::hash(b"1")));
+        stored.tx_graph.last_seen.insert(Hash::hash(b"tx"), 5);
+        store.write_changeset(&stored).await?;
+
+        assert!(store.diff_against(&stored).await?.is_empty());
+
+        let mut in_memory = stored.clone();
+        in_memory.local_chain.blocks.remove(&1);
+        let block_2: BlockHash = Hash::hash(b"2");
+        in_memory.local_chain.blocks.insert(2, Some(block_2));
+        in_memory.tx_graph.last_seen.insert(Hash::hash(b"tx"), 6);
+
+        let diff = store.diff_against(&in_memory).await?;
+        assert_eq!(
+            diff.only_in_store.local_chain.blocks,
+            [(1, Some(Hash::hash(b"1")))].into()
+        );
+        assert_eq!(
+            diff.only_in_changeset.local_chain.blocks,
+            [(2, Some(block_2))].into()
+        );
+        assert_eq!(
+            diff.only_in_store.tx_graph.last_seen,
+            [(Hash::hash(b"tx"), 5)].into()
+        );
+        assert_eq!(
+            diff.only_in_changeset.tx_graph.last_seen,
+            [(Hash::hash(b"tx"), 6)].into()
+        );
+        assert_eq!(diff.only_in_store.network, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Discovery of the wallet databases in a directory.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use bdk_chain::bitcoin::Network;
+use bdk_wallet::KeychainKind;
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnectOptions;
+
+use crate::Error;
+use crate::Store;
+use crate::async_store::pool_options;
+use crate::rt;
+
+/// Header of SQLite database files.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// A wallet database found by [`discover_wallets`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredWallet {
+    /// Path of the database file
+    pub path: PathBuf,
+    /// Network of the wallet, if stored
+    pub network: Option<Network>,
+    /// Checksums of the keychain descriptors
+    pub descriptor_checksums: BTreeMap<KeychainKind, String>,
+    /// Height of the tip of the local chain, if any block is stored
+    pub tip_height: Option<u32>,
+}
+
+/// Outcome of [`discover_wallets`].
+#[derive(Debug, Default)]
+pub struct WalletDiscovery {
+    /// Wallet databases found, ordered by path
+    pub wallets: Vec<DiscoveredWallet>,
+    /// Files which couldn't be inspected, e.g. a locked or corrupt database, ordered by path
+    pub errors: Vec<(PathBuf, Error)>,
+}
+
+/// Find the wallet databases in `dir`, opening each read-only.
+///
+/// This lets desktop apps present a wallet picker without hardcoding the names of the
+/// database files. Files which aren't SQLite databases migrated by this crate are skipped,
+/// and those which can't be inspected are reported along with their error rather than
+/// failing the discovery. Subdirectories aren't searched.
+pub async fn discover_wallets(dir: impl AsRef<Path>) -> Result<WalletDiscovery, Error> {
+    let dir = dir.as_ref().to_path_buf();
+    let mut discovery = WalletDiscovery::default();
+    let mut paths = vec![];
+    for (path, is_sqlite) in rt::spawn_blocking(move || list_files(&dir)).await? {
+        match is_sqlite {
+            Ok(true) => paths.push(path),
+            Ok(false) => {}
+            Err(e) => discovery.errors.push((path, e.into())),
+        }
+    }
+
+    for path in paths {
+        match inspect(&path).await {
+            Ok(Some(wallet)) => discovery.wallets.push(wallet),
+            Ok(None) => {}
+            Err(e) => discovery.errors.push((path, e)),
+        }
+    }
+    discovery.errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(discovery)
+}
+
+/// The files in `dir`, ordered by path, each with whether it starts with the SQLite header.
+fn list_files(dir: &Path) -> std::io::Result<Vec<(PathBuf, std::io::Result<bool>)>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let is_sqlite = is_sqlite(&path);
+            files.push((path, is_sqlite));
+        }
+    }
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(files)
+}
+
+/// Whether the file at `path` starts with the SQLite header.
+fn is_sqlite(path: &Path) -> std::io::Result<bool> {
+    let mut header = [0; 16];
+    match std::fs::File::open(path)?.read_exact(&mut header) {
+        Ok(()) => Ok(&header == SQLITE_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read the wallet metadata of the database at `path`, if it was migrated by this crate.
+async fn inspect(path: &Path) -> Result<Option<DiscoveredWallet>, Error> {
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let pool = pool_options()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+    let store = Store::from_pool(pool);
+
+    let rows = sqlx::query(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name IN ('_sqlx_migrations', 'keychain', 'block', 'network')",
+    )
+    .fetch_all(&store.pool)
+    .await?;
+    if rows.len() != 4 {
+        store.pool.close().await;
+        return Ok(None);
+    }
+
+    let network = store.read_network().await?;
+    let descriptor_checksums = store
+        .read_keychain_descriptors()
+        .await?
+        .into_iter()
+        .filter_map(|(keychain, descriptor)| {
+            let descriptor = descriptor.to_string();
+            let (_, checksum) = descriptor.rsplit_once('#')?;
+            Some((keychain, checksum.to_string()))
+        })
+        .collect();
+    let row = sqlx::query("SELECT MAX(height) AS height FROM block")
+        .fetch_one(&store.pool)
+        .await?;
+    let tip_height = row.get("height");
+    store.pool.close().await;
+
+    Ok(Some(DiscoveredWallet {
+        path: path.to_path_buf(),
+        network,
+        descriptor_checksums,
+        tip_height,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::local_chain;
+    use bdk_wallet::Wallet;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+    const CHANGE_DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/1/*)";
+
+    #[tokio::test]
+    async fn discover_wallets() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let path = dir.path().join("savings.db");
+        let mut store = Store::new(&path.to_string_lossy()).await?;
+        store.migrate().await?;
+        let mut wallet = Wallet::create(DESCRIPTOR, CHANGE_DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut store)
+            .await?;
+        wallet.persist_async(&mut store).await?;
+        store
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: [(5, Some(Hash::hash(b"5")))].into(),
+            })
+            .await?;
+        store.pool.close().await;
+
+        // A SQLite database of another app and a file which isn't a database.
+        let other = Store::new(&dir.path().join("other.db").to_string_lossy()).await?;
+        sqlx::query("CREATE TABLE settings(key TEXT)")
+            .execute(&other.pool)
+            .await?;
+        other.pool.close().await;
+        std::fs::write(dir.path().join("notes.txt"), "not a wallet")?;
+        // A corrupt database is reported rather than failing the discovery.
+        let corrupt = dir.path().join("corrupt.db");
+        std::fs::write(&corrupt, [&SQLITE_HEADER[..], &[0xff; 84]].concat())?;
+
+        let discovery = super::discover_wallets(dir.path()).await?;
+        assert_eq!(discovery.errors.len(), 1);
+        assert_eq!(discovery.errors[0].0, corrupt);
+        assert_eq!(discovery.wallets.len(), 1);
+        let found = &discovery.wallets[0];
+        assert_eq!(found.path, path);
+        assert_eq!(found.network, Some(Network::Signet));
+        assert_eq!(found.tip_height, Some(5));
+        let external = wallet.public_descriptor(KeychainKind::External).to_string();
+        assert_eq!(
+            Some(found.descriptor_checksums[&KeychainKind::External].as_str()),
+            external.rsplit_once('#').map(|(_, checksum)| checksum)
+        );
+        assert_eq!(found.descriptor_checksums.len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Type-erased wallet persisters.
+
+use core::fmt;
+
+use bdk_wallet::{AsyncWalletPersister, ChangeSet};
+
+use crate::Store;
+use crate::wallet::FutureResult;
+
+/// Error of a [`DynPersister`], the error of the erased persister boxed.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An [`AsyncWalletPersister`] of an erased type, see [`Store::into_dyn`].
+///
+/// [`AsyncWalletPersister`] takes the persister by `&mut Self` rather than `self`, so it
+/// can't be made into a trait object. Wrapping a persister lets applications which hold
+/// persisters of different types, e.g. a [`Store`] and a
+/// [`CoalescingWriter`](crate::CoalescingWriter), keep them behind a single type.
+pub struct DynPersister(Box<dyn ErasedPersister + Send + Sync>);
+
+impl DynPersister {
+    /// Erase the type of `persister`.
+    pub fn new<P>(persister: P) -> Self
+    where
+        P: AsyncWalletPersister + Send + Sync + 'static,
+        P::Error: std::error::Error + Send + Sync + 'static,
+    {
+        Self(Box::new(persister))
+    }
+}
+
+impl fmt::Debug for DynPersister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynPersister").finish_non_exhaustive()
+    }
+}
+
+impl Store {
+    /// Erase the type of the store, see [`DynPersister`].
+    pub fn into_dyn(self) -> DynPersister {
+        DynPersister::new(self)
+    }
+}
+
+/// The methods of [`AsyncWalletPersister`] taking `self`, with a boxed error.
+trait ErasedPersister {
+    fn initialize(&mut self) -> FutureResult<'_, ChangeSet, BoxError>;
+
+    fn persist<'a>(&'a mut self, changeset: &'a ChangeSet) -> FutureResult<'a, (), BoxError>;
+}
+
+impl<P> ErasedPersister for P
+where
+    P: AsyncWalletPersister + Send,
+    P::Error: std::error::Error + Send + Sync + 'static,
+{
+    fn initialize(&mut self) -> FutureResult<'_, ChangeSet, BoxError> {
+        Box::pin(async { Ok(P::initialize(self).await?) })
+    }
+
+    fn persist<'a>(&'a mut self, changeset: &'a ChangeSet) -> FutureResult<'a, (), BoxError> {
+        Box::pin(async { Ok(P::persist(self, changeset).await?) })
+    }
+}
+
+impl AsyncWalletPersister for DynPersister {
+    type Error = BoxError;
+
+    fn initialize<'a>(persister: &'a mut Self) -> FutureResult<'a, ChangeSet, Self::Error>
+    where
+        Self: 'a,
+    {
+        persister.0.initialize()
+    }
+
+    fn persist<'a>(
+        persister: &'a mut Self,
+        changeset: &'a ChangeSet,
+    ) -> FutureResult<'a, (), Self::Error>
+    where
+        Self: 'a,
+    {
+        persister.0.persist(changeset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::Network;
+    use bdk_wallet::{KeychainKind, Wallet};
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+
+    #[tokio::test]
+    async fn dyn_persister() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        let mut persister = store.clone().into_dyn();
+
+        let mut wallet = Wallet::create_single(DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut persister)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        let _ = wallet.reveal_next_address(KeychainKind::External);
+        wallet
+            .persist_async(&mut persister)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        let wallet = Wallet::load()
+            .load_wallet_async(&mut persister)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?
+            .expect("wallet must exist");
+        assert_eq!(wallet.derivation_index(KeychainKind::External), Some(0));
+        assert_eq!(store.read_changeset().await?.network, Some(Network::Signet));
+
+        Ok(())
+    }
+}
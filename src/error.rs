@@ -1,45 +1,330 @@
 use core::fmt;
 use core::num::TryFromIntError;
+use core::time::Duration;
 
 use bdk_chain::bitcoin;
 use bdk_chain::miniscript;
-use bitcoin::{consensus, hex::error::HexToArrayError, network::ParseNetworkError};
-use sqlx::migrate;
+use bitcoin::{
+    consensus,
+    hex::error::{HexToArrayError, HexToBytesError},
+    network::ParseNetworkError,
+    transaction::ParseOutPointError,
+};
+use sqlx::{error::ErrorKind, migrate};
 
 /// Crate error.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
+    /// The encrypted backup can't be decrypted with the given passphrase, or the backup
+    /// was tampered with.
+    BackupDecryption,
+    /// The secret of an encrypted backup can't be encrypted, e.g. because deriving the key
+    /// from the passphrase failed.
+    BackupEncryption,
+    /// SQLite can't open the database file, e.g. because its directory doesn't exist or
+    /// isn't accessible yet, see [`StoreBuilder::lazy`](crate::StoreBuilder::lazy).
+    CannotOpen(sqlx::Error),
+    /// A write exceeds a limit of the store, see `Store::with_write_limits`. Write a
+    /// changeset with too many transactions with `Store::write_changeset_chunked`.
+    ChangesetTooLarge {
+        /// Limit exceeded, the name of a field of `WriteLimits`
+        limit: &'static str,
+        /// Size of the write
+        size: usize,
+        /// Maximum size allowed by the limit
+        max: usize,
+    },
+    /// The write of a [`CoalescingWriter`](crate::CoalescingWriter) failed, shared by
+    /// every changeset merged into it.
+    #[cfg(feature = "wallet")]
+    CoalescedWrite(std::sync::Arc<Error>),
+    /// The database is locked by another connection or process, e.g. the previous
+    /// instance of an app which is still shutting down.
+    ///
+    /// Retried on [`Store::migrate`](crate::Store::migrate) and when initializing a wallet,
+    /// see [`LockRetry`](crate::LockRetry).
+    DatabaseLocked(sqlx::Error),
+    /// A value of the wrong type was written to a column of a STRICT table.
+    DatatypeViolation {
+        /// Column, of the form `table.column`
+        column: String,
+    },
     /// `bitcoin` consensus encoding error.
     Decode(consensus::encode::Error),
+    /// The store of `Store::merge_from` has another descriptor for a keychain, so it is
+    /// another wallet.
+    #[cfg(feature = "wallet")]
+    DescriptorMismatch(bdk_wallet::KeychainKind),
+    /// A FOREIGN KEY constraint was violated.
+    ///
+    /// SQLite doesn't report which constraint failed.
+    ForeignKeyViolation {
+        /// Message of the database error
+        message: String,
+    },
     /// error converting an integer.
     FromInt(TryFromIntError),
     /// `bitcoin` hex to array error.
     HexToArray(HexToArrayError),
+    /// `bitcoin` hex to bytes error.
+    HexToBytes(HexToBytesError),
+    /// A wallet changeset failed validation, see
+    /// [`Store::with_validation`](crate::Store::with_validation).
+    #[cfg(feature = "wallet")]
+    InvalidChangeset(Vec<crate::Violation>),
+    /// Invalid pagination cursor, see
+    /// [`Store::transactions_page`](crate::Store::transactions_page).
+    InvalidCursor(String),
+    /// Invalid tenant id, see [`TenantDir::create`](crate::TenantDir::create).
+    InvalidTenantId(String),
+    /// I/O error.
+    Io(std::io::Error),
+    /// `serde_json` error.
+    Json(serde_json::Error),
+    /// A label is encrypted and the store has no key or a different key than it was
+    /// encrypted with, or the ciphertext was tampered with.
+    LabelDecryption,
     /// `sqlx` migrate error.
     Migrate(sqlx::migrate::MigrateError),
     /// `miniscript` error.
     Miniscript(miniscript::Error),
+    /// Data for another network than the one of the store, e.g. a wallet changeset or
+    /// the store of `Store::merge_from`, see also `Store::with_network`.
+    NetworkMismatch {
+        /// Network of this store
+        ours: bitcoin::Network,
+        /// Network of the data, or the other store
+        theirs: bitcoin::Network,
+    },
+    /// Other error, see [`Error::other`].
+    Other(Box<dyn std::error::Error + Send + Sync>),
+    /// `parquet` error.
+    #[cfg(feature = "analytics")]
+    Parquet(parquet::errors::ParquetError),
     /// parse `Network` error.
     ParseNetwork(ParseNetworkError),
+    /// parse `OutPoint` error.
+    ParseOutPoint(ParseOutPointError),
+    /// No connection of the pool became available within the acquire timeout.
+    ///
+    /// Raise the maximum number of connections or the acquire timeout with
+    /// [`StoreBuilder`](crate::StoreBuilder) if this happens under normal load. The limits
+    /// of the pool are filled in by the writes and `Store::read_changeset`, which know the
+    /// pool they ran on.
+    PoolTimeout {
+        /// Maximum number of connections of the pool, if known
+        max_connections: Option<u32>,
+        /// Acquire timeout of the pool, if known
+        acquire_timeout: Option<Duration>,
+    },
+    /// The operating system failed to provide random bytes, e.g. for a `WalletIdScheme` or
+    /// `SpkCheck::Random`.
+    Random(getrandom::Error),
+    /// The spk cache doesn't match the stored descriptors, see
+    /// [`Store::with_verify_spk_cache`](crate::Store::with_verify_spk_cache).
+    #[cfg(feature = "wallet")]
+    SpkCacheMismatch(Vec<crate::SpkCacheWarning>),
     /// `sqlx` error.
     Sqlx(sqlx::Error),
+    /// [`Store::migrate`](crate::Store::migrate) rebuilt tables as STRICT tables and set
+    /// aside rows holding a value of the wrong type, see
+    /// [`Store::strict_rejected`](crate::Store::strict_rejected).
+    ///
+    /// The database is migrated, and a later migrate succeeds, but the rows set aside are
+    /// missing from the wallet data until the application repairs them.
+    StrictRejected {
+        /// Number of rows set aside
+        rows: u64,
+    },
+    /// A tenant with the given id already exists.
+    TenantExists(String),
+    /// An operation did not complete within the given duration.
+    Timeout(Duration),
+    /// The authorization token of a tenant is wrong.
+    Unauthorized,
+    /// A UNIQUE or PRIMARY KEY constraint was violated.
+    UniqueViolation {
+        /// Table of the constraint
+        table: String,
+        /// Columns of the constraint
+        key: Vec<String>,
+    },
+    /// The stored network isn't a network this version of `bitcoin` knows, e.g. one added
+    /// by a later version, see `Store::with_lenient_network`.
+    UnknownNetwork(String),
+    /// No tenant with the given id exists.
+    UnknownTenant(String),
+    /// An integer doesn't fit the type it is converted to when stored in or read from
+    /// a column.
+    ValueOutOfRange {
+        /// Column, of the form `table.column`
+        column: &'static str,
+        /// Value
+        value: i128,
+    },
+    /// The authorization token of a new tenant is shorter than
+    /// [`MIN_TENANT_TOKEN_LEN`](crate::MIN_TENANT_TOKEN_LEN).
+    WeakToken,
+    /// The task of a [`CoalescingWriter`](crate::CoalescingWriter) stopped before writing
+    /// the changeset.
+    WriterClosed,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::FromInt(e) => write!(f, "{e}"),
+            Self::BackupDecryption => write!(f, "failed to decrypt backup"),
+            Self::BackupEncryption => write!(f, "failed to encrypt backup"),
+            Self::CannotOpen(e) => write!(f, "cannot open database: {e}"),
+            Self::ChangesetTooLarge { limit, size, max } => {
+                write!(f, "changeset too large: {size} exceeds {limit} of {max}")
+            }
+            #[cfg(feature = "wallet")]
+            Self::CoalescedWrite(e) => write!(f, "coalesced write failed: {e}"),
+            Self::DatabaseLocked(e) => write!(f, "database is locked: {e}"),
+            Self::DatatypeViolation { column } => {
+                write!(f, "value of the wrong type for column {column}")
+            }
             Self::Decode(e) => write!(f, "{e}"),
+            #[cfg(feature = "wallet")]
+            Self::DescriptorMismatch(keychain) => {
+                write!(f, "descriptor mismatch for the {keychain:?} keychain")
+            }
+            Self::ForeignKeyViolation { message } => write!(f, "{message}"),
+            Self::FromInt(e) => write!(f, "{e}"),
             Self::HexToArray(e) => write!(f, "{e}"),
-            Self::Miniscript(e) => write!(f, "{e}"),
+            Self::HexToBytes(e) => write!(f, "{e}"),
+            #[cfg(feature = "wallet")]
+            Self::InvalidChangeset(violations) => {
+                write!(f, "invalid changeset: ")?;
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{violation}")?;
+                }
+                Ok(())
+            }
+            Self::InvalidCursor(cursor) => write!(f, "invalid cursor: {cursor}"),
+            Self::InvalidTenantId(id) => write!(f, "invalid tenant id: {id}"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Json(e) => write!(f, "{e}"),
+            Self::LabelDecryption => write!(f, "failed to decrypt label"),
             Self::Migrate(e) => write!(f, "{e}"),
+            Self::Miniscript(e) => write!(f, "{e}"),
+            Self::NetworkMismatch { ours, theirs } => {
+                write!(f, "network mismatch: ours is {ours}, theirs is {theirs}")
+            }
+            Self::Other(e) => write!(f, "{e}"),
+            #[cfg(feature = "analytics")]
+            Self::Parquet(e) => write!(f, "{e}"),
             Self::ParseNetwork(e) => write!(f, "{e}"),
+            Self::ParseOutPoint(e) => write!(f, "{e}"),
+            Self::PoolTimeout {
+                max_connections: Some(max_connections),
+                acquire_timeout: Some(acquire_timeout),
+            } => write!(
+                f,
+                "timed out after {acquire_timeout:?} acquiring a connection, all {max_connections} connections of the pool are in use"
+            ),
+            Self::PoolTimeout { .. } => write!(
+                f,
+                "timed out acquiring a connection, all connections of the pool are in use"
+            ),
+            Self::Random(e) => write!(f, "failed to get random bytes: {e}"),
+            #[cfg(feature = "wallet")]
+            Self::SpkCacheMismatch(warnings) => {
+                write!(f, "spk cache mismatch: ")?;
+                for (i, warning) in warnings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{warning}")?;
+                }
+                Ok(())
+            }
             Self::Sqlx(e) => write!(f, "{e}"),
+            Self::StrictRejected { rows } => write!(
+                f,
+                "migration set aside {rows} rows holding a value of the wrong type"
+            ),
+            Self::TenantExists(id) => write!(f, "tenant already exists: {id}"),
+            Self::Timeout(d) => write!(f, "operation timed out after {d:?}"),
+            Self::Unauthorized => write!(f, "unauthorized"),
+            Self::UniqueViolation { table, key } => {
+                write!(f, "unique constraint violated: {table}({})", key.join(", "))
+            }
+            Self::UnknownNetwork(network) => write!(f, "unknown network: {network}"),
+            Self::UnknownTenant(id) => write!(f, "unknown tenant: {id}"),
+            Self::ValueOutOfRange { column, value } => {
+                write!(f, "value out of range for column {column}: {value}")
+            }
+            Self::WeakToken => write!(f, "authorization token too short"),
+            Self::WriterClosed => write!(f, "writer closed"),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CannotOpen(e) => Some(e),
+            #[cfg(feature = "wallet")]
+            Self::CoalescedWrite(e) => Some(e.as_ref()),
+            Self::DatabaseLocked(e) => Some(e),
+            Self::Decode(e) => Some(e),
+            #[cfg(feature = "wallet")]
+            Self::DescriptorMismatch(_) => None,
+            Self::FromInt(e) => Some(e),
+            Self::HexToArray(e) => Some(e),
+            Self::HexToBytes(e) => Some(e),
+            #[cfg(feature = "wallet")]
+            Self::InvalidChangeset(_) => None,
+            Self::Io(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Migrate(e) => Some(e),
+            Self::Miniscript(e) => Some(e),
+            Self::Other(e) => Some(e.as_ref()),
+            #[cfg(feature = "analytics")]
+            Self::Parquet(e) => Some(e),
+            Self::ParseNetwork(e) => Some(e),
+            Self::ParseOutPoint(e) => Some(e),
+            Self::Random(e) => Some(e),
+            #[cfg(feature = "wallet")]
+            Self::SpkCacheMismatch(_) => None,
+            Self::Sqlx(e) => Some(e),
+            Self::BackupDecryption
+            | Self::BackupEncryption
+            | Self::ChangesetTooLarge { .. }
+            | Self::DatatypeViolation { .. }
+            | Self::ForeignKeyViolation { .. }
+            | Self::InvalidCursor(_)
+            | Self::InvalidTenantId(_)
+            | Self::LabelDecryption
+            | Self::NetworkMismatch { .. }
+            | Self::PoolTimeout { .. }
+            | Self::StrictRejected { .. }
+            | Self::TenantExists(_)
+            | Self::Timeout(_)
+            | Self::Unauthorized
+            | Self::UniqueViolation { .. }
+            | Self::UnknownNetwork(_)
+            | Self::UnknownTenant(_)
+            | Self::ValueOutOfRange { .. }
+            | Self::WeakToken
+            | Self::WriterClosed => None,
+        }
+    }
+}
+
+impl Error {
+    /// Wrap an error which doesn't originate in this crate, e.g. of an integration built on
+    /// top of the store.
+    pub fn other(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self::Other(err.into())
+    }
+}
 
 macro_rules! impl_error_from {
     ( $from:ty, $to:ident ) => {
@@ -54,7 +339,163 @@ macro_rules! impl_error_from {
 impl_error_from!(consensus::encode::Error, Decode);
 impl_error_from!(TryFromIntError, FromInt);
 impl_error_from!(HexToArrayError, HexToArray);
+impl_error_from!(HexToBytesError, HexToBytes);
+impl_error_from!(std::io::Error, Io);
+impl_error_from!(serde_json::Error, Json);
 impl_error_from!(miniscript::Error, Miniscript);
-impl_error_from!(migrate::MigrateError, Migrate);
+#[cfg(feature = "analytics")]
+impl_error_from!(parquet::errors::ParquetError, Parquet);
 impl_error_from!(ParseNetworkError, ParseNetwork);
-impl_error_from!(sqlx::Error, Sqlx);
+impl_error_from!(ParseOutPointError, ParseOutPoint);
+impl_error_from!(getrandom::Error, Random);
+
+impl From<migrate::MigrateError> for Error {
+    fn from(err: migrate::MigrateError) -> Self {
+        match err {
+            migrate::MigrateError::Execute(e) if is_locked(&e) => Self::DatabaseLocked(e),
+            err => Self::Migrate(err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        if let sqlx::Error::PoolTimedOut = err {
+            return Self::PoolTimeout {
+                max_connections: None,
+                acquire_timeout: None,
+            };
+        }
+        if is_locked(&err) {
+            return Self::DatabaseLocked(err);
+        }
+        if has_code(&err, SQLITE_CANTOPEN) {
+            return Self::CannotOpen(err);
+        }
+        if let sqlx::Error::Database(ref e) = err {
+            // e.g. "cannot store TEXT value in INTEGER column tx.first_seen"
+            if e.code().as_deref() == Some(SQLITE_CONSTRAINT_DATATYPE) {
+                if let Some((_, column)) = e.message().rsplit_once(" column ") {
+                    return Self::DatatypeViolation {
+                        column: column.to_string(),
+                    };
+                }
+            }
+            match e.kind() {
+                ErrorKind::UniqueViolation => {
+                    // e.g. "UNIQUE constraint failed: label.type, label.ref"
+                    if let Some((table, key)) = e
+                        .message()
+                        .strip_prefix("UNIQUE constraint failed: ")
+                        .and_then(parse_unique_key)
+                    {
+                        return Self::UniqueViolation { table, key };
+                    }
+                }
+                ErrorKind::ForeignKeyViolation => {
+                    return Self::ForeignKeyViolation {
+                        message: e.message().to_string(),
+                    };
+                }
+                _ => {}
+            }
+        }
+        Self::Sqlx(err)
+    }
+}
+
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const SQLITE_CANTOPEN: i32 = 14;
+const SQLITE_CONSTRAINT_DATATYPE: &str = "3091";
+
+/// Whether `err` is `SQLITE_BUSY` or `SQLITE_LOCKED`, including their extended codes.
+fn is_locked(err: &sqlx::Error) -> bool {
+    has_code(err, SQLITE_BUSY) || has_code(err, SQLITE_LOCKED)
+}
+
+/// Whether `err` is the primary result code `code`, or one of its extended codes.
+fn has_code(err: &sqlx::Error, code: i32) -> bool {
+    let sqlx::Error::Database(e) = err else {
+        return false;
+    };
+    e.code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|c| c & 0xff == code)
+}
+
+/// Parse the table and columns of a list of `table.column`s.
+fn parse_unique_key(columns: &str) -> Option<(String, Vec<String>)> {
+    let mut table = None;
+    let mut key = vec![];
+    for column in columns.split(", ") {
+        let (t, c) = column.split_once('.')?;
+        if *table.get_or_insert(t) != t {
+            return None;
+        }
+        key.push(c.to_string());
+    }
+
+    Some((table?.to_string(), key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::Store;
+
+    #[tokio::test]
+    async fn constraint_violations() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let insert = "INSERT INTO label(type, ref, label) VALUES('tx', 'a', 'b')";
+        sqlx::query(insert).execute(&store.pool).await?;
+        let err = Error::from(sqlx::query(insert).execute(&store.pool).await.unwrap_err());
+        assert!(matches!(
+            err,
+            Error::UniqueViolation { ref table, ref key } if table == "label" && key == &["type", "ref"]
+        ));
+
+        sqlx::query("CREATE TABLE parent(id INTEGER PRIMARY KEY)")
+            .execute(&store.pool)
+            .await?;
+        sqlx::query("CREATE TABLE child(parent_id INTEGER REFERENCES parent(id))")
+            .execute(&store.pool)
+            .await?;
+        let err = sqlx::query("INSERT INTO child(parent_id) VALUES(1)")
+            .execute(&store.pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            Error::from(err),
+            Error::ForeignKeyViolation { .. }
+        ));
+
+        let err = sqlx::query("INSERT INTO tx(txid, first_seen) VALUES('a', 'soon')")
+            .execute(&store.pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            Error::from(err),
+            Error::DatatypeViolation { ref column } if column == "tx.first_seen"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn error_source() {
+        use std::error::Error as _;
+
+        let err = Error::from(u8::try_from(256u32).unwrap_err());
+        assert!(err.source().unwrap().is::<TryFromIntError>());
+
+        let err = Error::other("integration failed");
+        assert_eq!(err.to_string(), "integration failed");
+        assert!(err.source().is_some());
+
+        assert!(Error::Timeout(Duration::from_secs(1)).source().is_none());
+    }
+}
@@ -0,0 +1,203 @@
+//! Custom SQL functions.
+//!
+//! These are registered on every connection opened by [`Store::new`],
+//! [`Store::new_memory`] and [`Store::builder`]. Users constructing the pool themselves,
+//! e.g. for [`Store::new_pool`] or [`Store::new_with_options`], can register them with
+//! [`register_functions`], e.g. from [`SqlitePoolOptions::after_connect`].
+//!
+//! [`Store::new`]: crate::Store::new
+//! [`Store::new_memory`]: crate::Store::new_memory
+//! [`Store::builder`]: crate::Store::builder
+//! [`Store::new_pool`]: crate::Store::new_pool
+//! [`Store::new_with_options`]: crate::Store::new_with_options
+//! [`SqlitePoolOptions::after_connect`]: sqlx::pool::PoolOptions::after_connect
+
+use std::ffi::{c_char, c_int};
+
+use bdk_chain::bitcoin;
+use bitcoin::{Address, Amount, Denomination, Network, Script, SignedAmount};
+use libsqlite3_sys as ffi;
+use sqlx::sqlite::SqliteConnection;
+
+/// Register the crate's SQL functions on `conn`.
+///
+/// - `spk_to_address(script, network)` returns the address of the script pubkey blob
+///   `script` on `network` (e.g. `'signet'`), or NULL if it has no address form.
+/// - `sats_to_btc(value)` formats the integer amount of satoshis `value` as a decimal
+///   amount of bitcoin, e.g. `'0.00021'`.
+pub async fn register_functions(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let mut handle = conn.lock_handle().await?;
+    let db = handle.as_raw_handle().as_ptr();
+
+    let functions: [(&[u8], c_int, ScalarFn); 2] = [
+        (b"spk_to_address\0", 2, spk_to_address),
+        (b"sats_to_btc\0", 1, sats_to_btc),
+    ];
+    for (name, n_arg, func) in functions {
+        // SAFETY: `db` is a valid connection handle which is locked for the duration of the
+        // call, `name` is nul-terminated and `func` upholds the `xFunc` contract.
+        let rc = unsafe {
+            ffi::sqlite3_create_function_v2(
+                db,
+                name.as_ptr().cast(),
+                n_arg,
+                ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
+                std::ptr::null_mut(),
+                Some(func),
+                None,
+                None,
+                None,
+            )
+        };
+        if rc != ffi::SQLITE_OK {
+            return Err(sqlx::Error::Protocol(format!(
+                "failed to register SQL function {}: error code {rc}",
+                String::from_utf8_lossy(&name[..name.len() - 1]),
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+type ScalarFn =
+    unsafe extern "C" fn(*mut ffi::sqlite3_context, c_int, *mut *mut ffi::sqlite3_value);
+
+/// `spk_to_address(script BLOB, network TEXT) -> TEXT`
+unsafe extern "C" fn spk_to_address(
+    ctx: *mut ffi::sqlite3_context,
+    _n_arg: c_int,
+    args: *mut *mut ffi::sqlite3_value,
+) {
+    // SAFETY: sqlite passes exactly the 2 arguments the function was registered with.
+    unsafe {
+        let (Some(script), Some(network)) =
+            (value_blob(*args.offset(0)), value_text(*args.offset(1)))
+        else {
+            ffi::sqlite3_result_null(ctx);
+            return;
+        };
+        let Ok(network) = network.parse::<Network>() else {
+            result_error(ctx, "spk_to_address: invalid network");
+            return;
+        };
+        match Address::from_script(Script::from_bytes(script), network) {
+            Ok(address) => result_text(ctx, &address.to_string()),
+            Err(_) => ffi::sqlite3_result_null(ctx),
+        }
+    }
+}
+
+/// `sats_to_btc(value INTEGER) -> TEXT`
+unsafe extern "C" fn sats_to_btc(
+    ctx: *mut ffi::sqlite3_context,
+    _n_arg: c_int,
+    args: *mut *mut ffi::sqlite3_value,
+) {
+    // SAFETY: sqlite passes exactly the 1 argument the function was registered with.
+    unsafe {
+        let arg = *args.offset(0);
+        if ffi::sqlite3_value_type(arg) == ffi::SQLITE_NULL {
+            ffi::sqlite3_result_null(ctx);
+            return;
+        }
+        let sats = ffi::sqlite3_value_int64(arg);
+        let text = match u64::try_from(sats) {
+            Ok(sats) => Amount::from_sat(sats)
+                .display_in(Denomination::Bitcoin)
+                .to_string(),
+            Err(_) => SignedAmount::from_sat(sats)
+                .display_in(Denomination::Bitcoin)
+                .to_string(),
+        };
+        result_text(ctx, &text);
+    }
+}
+
+/// The bytes of a BLOB value, or `None` if the value is NULL.
+unsafe fn value_blob<'a>(value: *mut ffi::sqlite3_value) -> Option<&'a [u8]> {
+    // SAFETY: `value` is a valid protected value for the duration of the function call.
+    unsafe {
+        if ffi::sqlite3_value_type(value) == ffi::SQLITE_NULL {
+            return None;
+        }
+        let ptr = ffi::sqlite3_value_blob(value);
+        let len = usize::try_from(ffi::sqlite3_value_bytes(value)).ok()?;
+        if ptr.is_null() || len == 0 {
+            return Some(&[]);
+        }
+        Some(std::slice::from_raw_parts(ptr.cast::<u8>(), len))
+    }
+}
+
+/// The text of a TEXT value, or `None` if the value is NULL or not valid UTF-8.
+unsafe fn value_text<'a>(value: *mut ffi::sqlite3_value) -> Option<&'a str> {
+    // SAFETY: `value` is a valid protected value for the duration of the function call.
+    unsafe {
+        if ffi::sqlite3_value_type(value) == ffi::SQLITE_NULL {
+            return None;
+        }
+        let ptr = ffi::sqlite3_value_text(value);
+        let len = usize::try_from(ffi::sqlite3_value_bytes(value)).ok()?;
+        if ptr.is_null() {
+            return None;
+        }
+        std::str::from_utf8(std::slice::from_raw_parts(ptr, len)).ok()
+    }
+}
+
+unsafe fn result_text(ctx: *mut ffi::sqlite3_context, text: &str) {
+    let Ok(len) = c_int::try_from(text.len()) else {
+        // SAFETY: `ctx` is the valid context of the current function call.
+        unsafe { result_error(ctx, "result too large") };
+        return;
+    };
+    // SAFETY: sqlite copies the text before returning due to `SQLITE_TRANSIENT`.
+    unsafe {
+        ffi::sqlite3_result_text(
+            ctx,
+            text.as_ptr().cast::<c_char>(),
+            len,
+            ffi::SQLITE_TRANSIENT(),
+        )
+    };
+}
+
+unsafe fn result_error(ctx: *mut ffi::sqlite3_context, msg: &str) {
+    let len = c_int::try_from(msg.len()).unwrap_or(c_int::MAX);
+    // SAFETY: sqlite copies the message before returning.
+    unsafe { ffi::sqlite3_result_error(ctx, msg.as_ptr().cast::<c_char>(), len) };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Store;
+
+    use bdk_chain::bitcoin;
+    use bitcoin::{Address, Network, ScriptBuf, WPubkeyHash, hashes::Hash};
+    use sqlx::Row;
+
+    #[tokio::test]
+    async fn custom_functions() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+
+        let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(b"pubkey"));
+        let address = Address::from_script(&script, Network::Signet)?;
+        let row = sqlx::query(
+            "SELECT spk_to_address($1, 'signet') AS address, sats_to_btc(21000) AS btc, sats_to_btc(NULL) AS null_btc",
+        )
+        .bind(script.to_bytes())
+        .fetch_one(&store.pool)
+        .await?;
+        assert_eq!(row.get::<String, _>("address"), address.to_string());
+        assert_eq!(row.get::<String, _>("btc"), "0.00021");
+        assert_eq!(row.get::<Option<String>, _>("null_btc"), None);
+
+        let row = sqlx::query("SELECT spk_to_address(x'6a', 'signet') AS address")
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(row.get::<Option<String>, _>("address"), None);
+
+        Ok(())
+    }
+}
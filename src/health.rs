@@ -0,0 +1,82 @@
+//! Health checks.
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::async_store::migrator;
+
+impl Store {
+    /// Run a trivial query against the database and return how long it took.
+    ///
+    /// Errors with [`Error::Timeout`] if the query doesn't complete within `timeout`.
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration, Error> {
+        let start = Instant::now();
+        self.timed(Some(timeout), async {
+            sqlx::query("SELECT 1").execute(&self.pool).await?;
+            Ok(())
+        })
+        .await?;
+
+        Ok(start.elapsed())
+    }
+
+    /// Open the connections the pool keeps, at least one, failing if the database can't be
+    /// opened.
+    ///
+    /// Use this to open a store built with [`StoreBuilder::lazy`](crate::StoreBuilder::lazy)
+    /// once its file is accessible, instead of on its first use.
+    pub async fn warm_up(&self) -> Result<(), Error> {
+        let options = self.pool.options();
+        let n = options
+            .get_min_connections()
+            .clamp(1, options.get_max_connections());
+        let mut conns = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            conns.push(self.pool.acquire().await?);
+        }
+
+        Ok(())
+    }
+
+    /// Whether every embedded migration has been successfully applied to the database.
+    pub async fn ready(&self) -> Result<bool, Error> {
+        let table = sqlx::query(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        if table.is_none() {
+            return Ok(false);
+        }
+
+        let rows = sqlx::query("SELECT version FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(&self.pool)
+            .await?;
+        let applied: BTreeSet<i64> = rows.iter().map(|row| row.get("version")).collect();
+
+        Ok(migrator()
+            .iter()
+            .all(|migration| applied.contains(&migration.version)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn ping_and_ready() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.ping(Duration::from_secs(5)).await?;
+
+        assert!(!store.ready().await?);
+        store.migrate().await?;
+        assert!(store.ready().await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! Cache of the responses of HTTP chain sources, e.g. Esplora.
+
+use std::time::Duration;
+
+use bdk_chain::bitcoin::hashes::{Hash, sha256};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, to_sql};
+
+/// A response read from the cache, see [`Store::http_cache_get`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// Body of the response
+    pub body: Vec<u8>,
+    /// Unix time at which the response was fetched
+    pub fetched_at: u64,
+}
+
+impl Store {
+    /// Cache `body` as the response to `url`, fetched now, replacing any cached response.
+    ///
+    /// Responses are keyed by the SHA256 of the URL, so the URLs themselves aren't stored.
+    /// Together with [`http_cache_get`](Self::http_cache_get), this lets a sync layer serve
+    /// cached responses while offline and skip refetching data which doesn't change, e.g.
+    /// transactions and blocks.
+    pub async fn http_cache_put(&self, url: &str, body: &[u8]) -> Result<(), Error> {
+        let now = to_sql("http_cache.fetched_at", self.now())?;
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO http_cache(url_hash, body, fetched_at) VALUES($1, $2, $3) ON CONFLICT DO UPDATE SET body = $2, fetched_at = $3",
+            )
+            .bind(url_hash(url))
+            .bind(body)
+            .bind(now)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read the cached response to `url`, if it was fetched at most `ttl` ago, or at any
+    /// time if `ttl` is `None`, e.g. when offline.
+    pub async fn http_cache_get(
+        &self,
+        url: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<CachedResponse>, Error> {
+        let row = sqlx::query("SELECT body, fetched_at FROM http_cache WHERE url_hash = $1")
+            .bind(url_hash(url))
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let fetched_at = from_sql("http_cache.fetched_at", row.get::<i64, _>("fetched_at"))?;
+        if let Some(ttl) = ttl {
+            if self.now().saturating_sub(fetched_at) > ttl.as_secs() {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(CachedResponse {
+            body: row.get("body"),
+            fetched_at,
+        }))
+    }
+
+    /// Delete the cached responses fetched more than `max_age` ago, returning how many were
+    /// deleted.
+    pub async fn http_cache_evict(&self, max_age: Duration) -> Result<u64, Error> {
+        let cutoff = self.now().saturating_sub(max_age.as_secs());
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query("DELETE FROM http_cache WHERE fetched_at < $1")
+                .bind(to_sql("http_cache.fetched_at", cutoff)?)
+                .execute(&mut *conn)
+                .await?;
+            Ok(res.rows_affected())
+        })
+        .await
+    }
+}
+
+/// Key of the response to `url`.
+fn url_hash(url: &str) -> String {
+    sha256::Hash::hash(url.as_bytes()).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::FixedClock;
+
+    #[tokio::test]
+    async fn http_cache() -> anyhow::Result<()> {
+        let url = "https://mempool.space/api/blocks/tip/height";
+        let store = Store::new_memory().await?.with_clock(FixedClock(1_000));
+        store.migrate().await?;
+        assert!(store.http_cache_get(url, None).await?.is_none());
+
+        store.http_cache_put(url, b"850000").await?;
+        let store = store.with_clock(FixedClock(1_060));
+        let cached = store.http_cache_get(url, None).await?.unwrap();
+        assert_eq!(cached.body, b"850000");
+        assert_eq!(cached.fetched_at, 1_000);
+        assert!(
+            store
+                .http_cache_get(url, Some(Duration::from_secs(60)))
+                .await?
+                .is_some()
+        );
+        assert!(
+            store
+                .http_cache_get(url, Some(Duration::from_secs(59)))
+                .await?
+                .is_none()
+        );
+
+        assert_eq!(store.http_cache_evict(Duration::from_secs(60)).await?, 0);
+        assert_eq!(store.http_cache_evict(Duration::from_secs(59)).await?, 1);
+        assert!(store.http_cache_get(url, None).await?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Loading a wallet progressively, without its transaction graph.
+
+use bdk_chain::bitcoin::BlockHash;
+use bdk_chain::{BlockId, CheckPoint, TxUpdate, keychain_txout, local_chain};
+use bdk_wallet::{ChangeSet, KeychainKind, Update};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+
+impl Store {
+    /// Read the part of the changeset needed to load a wallet: the descriptors, network,
+    /// last revealed indices and the genesis and tip blocks of the local chain.
+    ///
+    /// This is for apps with many wallets open, which can show the wallets before reading
+    /// their transactions. To load a wallet progressively:
+    ///
+    /// 1. load it from this changeset with `Wallet::load().load_wallet_no_persist`,
+    /// 2. later, apply the update of [`hydrate_tx_graph`](Self::hydrate_tx_graph) with
+    ///    `Wallet::apply_update`.
+    ///
+    /// Until then the wallet has no transactions, so its balance is zero, and it derives
+    /// the scripts of its keychains instead of reading the spk cache. Addresses are revealed
+    /// after the last revealed ones, as with a fully loaded wallet. The changes of the
+    /// wallet are persisted as usual with `Wallet::persist_async`, and applying the update
+    /// stages no change to write.
+    pub async fn read_changeset_light(&self) -> Result<ChangeSet, Error> {
+        self.timed(self.timeout, async {
+            let network = self.read_network().await?;
+            let descriptors = self.read_keychain_descriptors().await?;
+
+            let mut local_chain = local_chain::ChangeSet::default();
+            let rows = sqlx::query(
+                "SELECT height, hash FROM block WHERE height = 0 OR height = (SELECT MAX(height) FROM block) ORDER BY height",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+            for row in rows {
+                let hash: String = row.get("hash");
+                local_chain
+                    .blocks
+                    .insert(row.get("height"), Some(hash.parse::<BlockHash>()?));
+            }
+
+            let mut indexer = keychain_txout::ChangeSet::default();
+            let rows = sqlx::query("SELECT descriptor_id, last_revealed FROM keychain_last_revealed ORDER BY descriptor_id")
+                .fetch_all(&self.pool)
+                .await?;
+            for row in rows {
+                let descriptor_id: String = row.get("descriptor_id");
+                indexer
+                    .last_revealed
+                    .insert(descriptor_id.parse()?, row.get("last_revealed"));
+            }
+
+            let changeset = ChangeSet {
+                descriptor: descriptors.get(&KeychainKind::External).cloned(),
+                change_descriptor: descriptors.get(&KeychainKind::Internal).cloned(),
+                network,
+                local_chain,
+                indexer,
+                ..Default::default()
+            };
+            self.read_overflow(changeset).await
+        })
+        .await
+    }
+
+    /// Read the transaction graph and local chain left out by
+    /// [`read_changeset_light`](Self::read_changeset_light), as an update to apply to a
+    /// wallet loaded from it.
+    ///
+    /// The update connects to the tip of the light changeset, so it applies as long as the
+    /// stored tip didn't change in between, e.g. by a sync of another instance.
+    pub async fn hydrate_tx_graph(&self) -> Result<Update, Error> {
+        self.timed(self.timeout, async {
+            let tx_graph = self.read_tx_graph().await?;
+            let local_chain = self.read_local_chain().await?;
+
+            let blocks = local_chain.blocks.into_iter().filter_map(|(height, hash)| {
+                Some(BlockId {
+                    height,
+                    hash: hash?,
+                })
+            });
+            let chain = CheckPoint::from_block_ids(blocks).ok();
+
+            let mut tx_update = TxUpdate::default();
+            tx_update.txs = tx_graph.txs.into_iter().collect();
+            tx_update.txouts = tx_graph.txouts;
+            tx_update.anchors = tx_graph.anchors;
+            tx_update.seen_ats = tx_graph
+                .first_seen
+                .into_iter()
+                .chain(tx_graph.last_seen)
+                .collect();
+            tx_update.evicted_ats = tx_graph.last_evicted.into_iter().collect();
+
+            Ok(Update {
+                last_active_indices: Default::default(),
+                tx_update,
+                chain,
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{Amount, Network, Transaction, TxOut, absolute, transaction};
+    use bdk_chain::{ConfirmationBlockTime, tx_graph};
+    use bdk_wallet::Wallet;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+
+    #[tokio::test]
+    async fn load_progressively() -> anyhow::Result<()> {
+        let mut store = Store::new_memory().await?;
+        store.migrate().await?;
+        let mut wallet = Wallet::create_single(DESCRIPTOR)
+            .network(Network::Regtest)
+            .create_wallet_async(&mut store)
+            .await?;
+        let address = wallet.reveal_next_address(KeychainKind::External);
+        wallet.persist_async(&mut store).await?;
+
+        let genesis = wallet.local_chain().genesis_hash();
+        let block = |height: u32| BlockId {
+            height,
+            hash: Hash::hash(&height.to_le_bytes()),
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        store
+            .write_changeset(&ChangeSet {
+                local_chain: local_chain::ChangeSet {
+                    blocks: [1, 2, 3].map(|h| (h, Some(block(h).hash))).into(),
+                },
+                tx_graph: tx_graph::ChangeSet {
+                    txs: [Arc::new(tx)].into(),
+                    anchors: [(
+                        ConfirmationBlockTime {
+                            block_id: block(2),
+                            confirmation_time: 100,
+                        },
+                        txid,
+                    )]
+                    .into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await?;
+
+        let light = store.read_changeset_light().await?;
+        assert!(light.tx_graph.txs.is_empty());
+        assert_eq!(
+            light.local_chain.blocks,
+            [(0, Some(genesis)), (3, Some(block(3).hash))].into()
+        );
+        let mut wallet = Wallet::load()
+            .load_wallet_no_persist(light)?
+            .expect("wallet must exist");
+        assert_eq!(wallet.balance().total(), Amount::ZERO);
+        assert_eq!(wallet.latest_checkpoint().block_id(), block(3));
+
+        wallet.apply_update(store.hydrate_tx_graph().await?)?;
+        assert_eq!(wallet.balance().confirmed, Amount::from_sat(10_000));
+        assert_eq!(wallet.local_chain().iter_checkpoints().count(), 4);
+        let changeset = wallet.take_staged().unwrap_or_default();
+        assert!(store.write_changeset(&changeset).await?.is_empty());
+        assert_ne!(
+            wallet.reveal_next_address(KeychainKind::External).address,
+            address.address
+        );
+
+        Ok(())
+    }
+}
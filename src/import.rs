@@ -0,0 +1,102 @@
+//! Bulk import of raw transactions.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bdk_chain::{ConfirmationBlockTime, bitcoin, tx_graph};
+use bitcoin::{Transaction, consensus};
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+
+impl Store {
+    /// Import consensus encoded transactions, each with an optional anchor.
+    ///
+    /// This is meant for backfilling history from an external source, e.g. the output of a
+    /// node's `getrawtransaction`, rather than by way of a changeset. All transactions are
+    /// decoded before anything is written, duplicates are skipped and everything is written
+    /// in a single transaction.
+    pub async fn import_txs<I, B>(&self, txs: I) -> Result<WriteSummary, Error>
+    where
+        I: IntoIterator<Item = (B, Option<ConfirmationBlockTime>)>,
+        B: AsRef<[u8]>,
+    {
+        let mut decoded = BTreeMap::new();
+        let mut changeset = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        for (data, anchor) in txs {
+            let tx: Transaction = consensus::encode::deserialize(data.as_ref())?;
+            let txid = tx.compute_txid();
+            if let Some(anchor) = anchor {
+                changeset.anchors.insert((anchor, txid));
+            }
+            decoded.entry(txid).or_insert_with(|| Arc::new(tx));
+        }
+        changeset.txs = decoded.into_values().collect();
+
+        self.write(WriteOptions::default(), async |conn| {
+            self.write_tx_graph_in(conn, &changeset).await
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::BlockId;
+    use bitcoin::{Amount, OutPoint, ScriptBuf, TxIn, TxOut, absolute, hashes::Hash, transaction};
+
+    #[tokio::test]
+    async fn import_raw_txs() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txs: Vec<Transaction> = (0..3u32)
+            .map(|i| Transaction {
+                version: transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: OutPoint::new(Hash::hash(&i.to_le_bytes()), 0),
+                    ..Default::default()
+                }],
+                output: vec![TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            })
+            .collect();
+        let anchor = ConfirmationBlockTime {
+            block_id: BlockId {
+                height: 1,
+                hash: Hash::hash(b"1"),
+            },
+            confirmation_time: 100,
+        };
+        let raw: Vec<(Vec<u8>, Option<ConfirmationBlockTime>)> = vec![
+            (consensus::encode::serialize(&txs[0]), Some(anchor)),
+            (consensus::encode::serialize(&txs[1]), None),
+            (consensus::encode::serialize(&txs[2]), None),
+            (consensus::encode::serialize(&txs[1]), None),
+        ];
+
+        let summary = store.import_txs(raw).await?;
+        assert_eq!(summary.table("tx").inserted, 3);
+        assert_eq!(summary.table("anchor").inserted, 1);
+
+        let graph = store.read_tx_graph().await?;
+        assert_eq!(graph.txs.len(), 3);
+        assert_eq!(
+            graph.anchors.into_iter().collect::<Vec<_>>(),
+            vec![(anchor, txs[0].compute_txid())]
+        );
+
+        // Invalid data fails the whole import.
+        let res = store.import_txs([(vec![0u8; 4], None)]).await;
+        assert!(matches!(res, Err(Error::Decode(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,569 @@
+//! Labels for transactions and outputs.
+
+use core::fmt;
+use core::str::FromStr;
+
+use bdk_chain::bitcoin;
+use bitcoin::{OutPoint, Txid};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::to_sql;
+
+/// The item referenced by a label.
+///
+/// The `type` and `ref` of each variant follow the conventions of BIP-329.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LabelRef {
+    /// Transaction label.
+    Tx(Txid),
+    /// Output label.
+    Output(OutPoint),
+}
+
+impl LabelRef {
+    /// The BIP-329 `type` of this reference.
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            Self::Tx(_) => "tx",
+            Self::Output(_) => "output",
+        }
+    }
+}
+
+impl fmt::Display for LabelRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tx(txid) => write!(f, "{txid}"),
+            Self::Output(op) => write!(f, "{op}"),
+        }
+    }
+}
+
+/// A record of a BIP-329 export, see [`Store::export_labels`].
+#[derive(Debug, Serialize, Deserialize)]
+struct Bip329Record {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(rename = "ref")]
+    r: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spendable: Option<bool>,
+}
+
+/// Key encrypting labels, see [`Store::with_label_key`].
+#[cfg(feature = "label-encryption")]
+#[derive(Clone)]
+pub struct LabelKey([u8; 32]);
+
+#[cfg(feature = "label-encryption")]
+impl LabelKey {
+    /// Key of the given bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Encrypt `label` of `label_ref`, returning the hex of the nonce followed by the
+    /// ciphertext. The reference is authenticated so that a ciphertext can't be moved to
+    /// another item.
+    fn seal(&self, label_ref: &LabelRef, label: &str) -> Result<String, Error> {
+        use bitcoin::hex::DisplayHex;
+        use chacha20poly1305::ChaCha20Poly1305;
+        use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+
+        let cipher = ChaCha20Poly1305::new(&self.0.into());
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = format!("{}:{label_ref}", label_ref.type_str());
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: label.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::LabelDecryption)?;
+
+        Ok([nonce.as_slice(), &ciphertext]
+            .concat()
+            .to_lower_hex_string())
+    }
+
+    /// Decrypt a label sealed by [`LabelKey::seal`].
+    fn open(&self, label_ref: &LabelRef, sealed: &str) -> Result<String, Error> {
+        use bitcoin::hex::FromHex;
+        use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+        use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+        let data = Vec::<u8>::from_hex(sealed).map_err(|_| Error::LabelDecryption)?;
+        if data.len() < 12 {
+            return Err(Error::LabelDecryption);
+        }
+        let (nonce, ciphertext) = data.split_at(12);
+        let cipher = ChaCha20Poly1305::new(&self.0.into());
+        let aad = format!("{}:{label_ref}", label_ref.type_str());
+        let label = cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::LabelDecryption)?;
+
+        String::from_utf8(label).map_err(|_| Error::LabelDecryption)
+    }
+}
+
+#[cfg(feature = "label-encryption")]
+impl fmt::Debug for LabelKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LabelKey(..)")
+    }
+}
+
+impl Store {
+    /// Encrypt the labels set from now on with `key`, using ChaCha20-Poly1305.
+    ///
+    /// This is independent of any encryption of the database or of the descriptors, so
+    /// that labels, which often hold personal information such as names or invoices, can
+    /// be kept private while the chain data stays in plaintext. Labels which are already
+    /// stored in plaintext remain readable, see [`encrypt_labels`](Self::encrypt_labels).
+    /// Reading an encrypted label without the key it was encrypted with returns
+    /// [`Error::LabelDecryption`].
+    #[cfg(feature = "label-encryption")]
+    pub fn with_label_key(mut self, key: LabelKey) -> Self {
+        self.label_key = Some(key);
+        self
+    }
+
+    /// Encrypt the labels stored in plaintext with the key set by
+    /// [`with_label_key`](Self::with_label_key), returning how many were encrypted.
+    ///
+    /// Does nothing if no key is set.
+    #[cfg(feature = "label-encryption")]
+    pub async fn encrypt_labels(&self) -> Result<u64, Error> {
+        let Some(key) = &self.label_key else {
+            return Ok(0);
+        };
+        let labels = self.read_labels_raw("WHERE encrypted = 0").await?;
+        self.write(crate::WriteOptions::default(), async |conn| {
+            for (label_ref, label, _) in &labels {
+                sqlx::query(
+                    "UPDATE label SET label = $3, encrypted = 1 WHERE type = $1 AND ref = $2",
+                )
+                .bind(label_ref.type_str())
+                .bind(label_ref.to_string())
+                .bind(key.seal(label_ref, label)?)
+                .execute(&mut *conn)
+                .await?;
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(labels.len() as u64)
+    }
+
+    /// Seal `label` if a label key is set, returning the value to store and whether it's
+    /// encrypted.
+    fn seal_label(&self, label_ref: &LabelRef, label: &str) -> Result<(String, bool), Error> {
+        #[cfg(feature = "label-encryption")]
+        if let Some(key) = &self.label_key {
+            return Ok((key.seal(label_ref, label)?, true));
+        }
+        let _ = label_ref;
+
+        Ok((label.to_string(), false))
+    }
+
+    /// Open a stored label.
+    pub(crate) fn open_label(
+        &self,
+        label_ref: &LabelRef,
+        label: String,
+        encrypted: bool,
+    ) -> Result<String, Error> {
+        if !encrypted {
+            return Ok(label);
+        }
+        #[cfg(feature = "label-encryption")]
+        if let Some(key) = &self.label_key {
+            return key.open(label_ref, &label);
+        }
+        let _ = label_ref;
+
+        Err(Error::LabelDecryption)
+    }
+
+    /// Set the label of `label_ref`, replacing any existing label.
+    pub async fn set_label(&self, label_ref: &LabelRef, label: &str) -> Result<(), Error> {
+        let (label, encrypted) = self.seal_label(label_ref, label)?;
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO label(type, ref, label, encrypted) VALUES($1, $2, $3, $4) ON CONFLICT DO UPDATE SET label = $3, encrypted = $4",
+            )
+            .bind(label_ref.type_str())
+            .bind(label_ref.to_string())
+            .bind(label)
+            .bind(encrypted)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get the label of `label_ref`, if any.
+    pub async fn label(&self, label_ref: &LabelRef) -> Result<Option<String>, Error> {
+        let row = sqlx::query("SELECT label, encrypted FROM label WHERE type = $1 AND ref = $2")
+            .bind(label_ref.type_str())
+            .bind(label_ref.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|row| self.open_label(label_ref, row.get("label"), row.get("encrypted")))
+            .transpose()
+    }
+
+    /// Remove the label of `label_ref`.
+    pub async fn remove_label(&self, label_ref: &LabelRef) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query("DELETE FROM label WHERE type = $1 AND ref = $2")
+                .bind(label_ref.type_str())
+                .bind(label_ref.to_string())
+                .execute(&mut *conn)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read all labels, ordered by type and reference.
+    pub async fn read_labels(&self) -> Result<Vec<(LabelRef, String)>, Error> {
+        self.read_labels_raw("")
+            .await?
+            .into_iter()
+            .map(|(label_ref, label, encrypted)| {
+                let label = self.open_label(&label_ref, label, encrypted)?;
+                Ok((label_ref, label))
+            })
+            .collect()
+    }
+
+    /// Export the labels and the frozen outputs as BIP-329 JSON Lines, one record per line.
+    ///
+    /// Labels are exported in plaintext, decrypted with the key of
+    /// `Store::with_label_key`. Frozen outputs, see
+    /// [`freeze_utxo`](Self::freeze_utxo), are exported with `"spendable": false`, with
+    /// their label if they have one.
+    pub async fn export_labels(&self) -> Result<String, Error> {
+        let mut records = std::collections::BTreeMap::new();
+        for (label_ref, label) in self.read_labels().await? {
+            records.insert(label_ref, (Some(label), None));
+        }
+        for frozen in self.frozen_utxos().await? {
+            records
+                .entry(LabelRef::Output(frozen.outpoint))
+                .or_insert((None, None))
+                .1 = Some(false);
+        }
+
+        let mut jsonl = String::new();
+        for (label_ref, (label, spendable)) in records {
+            let record = Bip329Record {
+                ty: label_ref.type_str().to_string(),
+                r: label_ref.to_string(),
+                label,
+                spendable,
+            };
+            jsonl.push_str(&serde_json::to_string(&record)?);
+            jsonl.push('\n');
+        }
+
+        Ok(jsonl)
+    }
+
+    /// Import the labels and the spendable flags of BIP-329 JSON Lines, as exported by
+    /// [`export_labels`](Self::export_labels), returning the number of records imported.
+    ///
+    /// Labels replace the existing ones and are encrypted if a label key is set. An output
+    /// with `"spendable": false` is frozen and one with `"spendable": true` unfrozen. Records
+    /// of the types other than `tx` and `output` are skipped. The records are imported in a
+    /// single write.
+    pub async fn import_labels(&self, jsonl: &str) -> Result<u64, Error> {
+        let mut records = vec![];
+        for line in jsonl.lines().filter(|line| !line.trim().is_empty()) {
+            let record: Bip329Record = serde_json::from_str(line)?;
+            let label_ref = match record.ty.as_str() {
+                "tx" => LabelRef::Tx(record.r.parse()?),
+                "output" => LabelRef::Output(OutPoint::from_str(&record.r)?),
+                _ => continue,
+            };
+            let label = match &record.label {
+                Some(label) => Some(self.seal_label(&label_ref, label)?),
+                None => None,
+            };
+            records.push((label_ref, label, record.spendable));
+        }
+        let now = to_sql("frozen_utxo.frozen_at", self.now())?;
+
+        self.write(WriteOptions::default(), async |conn| {
+            for (label_ref, label, spendable) in &records {
+                if let Some((label, encrypted)) = label {
+                    sqlx::query(
+                        "INSERT INTO label(type, ref, label, encrypted) VALUES($1, $2, $3, $4) ON CONFLICT DO UPDATE SET label = $3, encrypted = $4",
+                    )
+                    .bind(label_ref.type_str())
+                    .bind(label_ref.to_string())
+                    .bind(label)
+                    .bind(encrypted)
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                let (LabelRef::Output(op), Some(spendable)) = (label_ref, spendable) else {
+                    continue;
+                };
+                let query = match spendable {
+                    true => sqlx::query("DELETE FROM frozen_utxo WHERE txid = $1 AND vout = $2"),
+                    false => sqlx::query(
+                        "INSERT OR IGNORE INTO frozen_utxo(txid, vout, frozen_at) VALUES($1, $2, $3)",
+                    ),
+                };
+                query
+                    .bind(op.txid.to_string())
+                    .bind(op.vout)
+                    .bind(now)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+
+            Ok(records.len() as u64)
+        })
+        .await
+    }
+
+    /// Read the labels matching `filter` as stored, with whether each is encrypted.
+    async fn read_labels_raw(&self, filter: &str) -> Result<Vec<(LabelRef, String, bool)>, Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT type, ref, label, encrypted FROM label {filter} ORDER BY type, ref"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut labels = vec![];
+        for row in rows {
+            let ty: String = row.get("type");
+            let r: String = row.get("ref");
+            let label_ref = match ty.as_str() {
+                "tx" => LabelRef::Tx(r.parse()?),
+                "output" => LabelRef::Output(OutPoint::from_str(&r)?),
+                _ => {
+                    debug_assert!(false, "label type must be one of `tx` or `output`");
+                    continue;
+                }
+            };
+            labels.push((label_ref, row.get("label"), row.get("encrypted")));
+        }
+
+        Ok(labels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn read_labels_in_order() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let refs = [
+            LabelRef::Tx(txid),
+            LabelRef::Output(OutPoint::new(txid, 1)),
+            LabelRef::Output(OutPoint::new(txid, 0)),
+        ];
+        for (i, label_ref) in refs.iter().enumerate() {
+            store.set_label(label_ref, &i.to_string()).await?;
+        }
+
+        let labels = store.read_labels().await?;
+        assert_eq!(
+            labels,
+            vec![
+                (refs[2], "2".to_string()),
+                (refs[1], "1".to_string()),
+                (refs[0], "0".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bip329_round_trip() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let labeled = OutPoint::new(txid, 0);
+        let frozen = OutPoint::new(txid, 1);
+        store.set_label(&LabelRef::Tx(txid), "rent").await?;
+        store
+            .set_label(&LabelRef::Output(labeled), "landlord")
+            .await?;
+        store.freeze_utxo(labeled, Some("disputed")).await?;
+        store.freeze_utxo(frozen, None).await?;
+
+        let jsonl = store.export_labels().await?;
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            lines,
+            [
+                serde_json::json!({"type": "tx", "ref": txid.to_string(), "label": "rent"}),
+                serde_json::json!({"type": "output", "ref": labeled.to_string(), "label": "landlord", "spendable": false}),
+                serde_json::json!({"type": "output", "ref": frozen.to_string(), "spendable": false}),
+            ]
+        );
+
+        let other = Store::new_memory().await?;
+        other.migrate().await?;
+        // Records of other types are skipped.
+        let jsonl = format!("{jsonl}{{\"type\": \"addr\", \"ref\": \"bc1q\", \"label\": \"a\"}}\n");
+        assert_eq!(other.import_labels(&jsonl).await?, 3);
+        assert_eq!(other.read_labels().await?, store.read_labels().await?);
+        let frozen_utxos: Vec<_> = (other.frozen_utxos().await?.into_iter())
+            .map(|f| f.outpoint)
+            .collect();
+        assert_eq!(frozen_utxos, [labeled, frozen]);
+        assert_eq!(other.export_labels().await?, store.export_labels().await?);
+
+        // A spendable output is unfrozen.
+        let spendable =
+            format!("{{\"type\": \"output\", \"ref\": \"{frozen}\", \"spendable\": true}}");
+        other.import_labels(&spendable).await?;
+        assert_eq!(other.frozen_utxos().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "label-encryption")]
+    #[tokio::test]
+    async fn encrypted_labels() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let plain = LabelRef::Tx(txid);
+        let secret = LabelRef::Output(OutPoint::new(txid, 0));
+        store.set_label(&plain, "public").await?;
+
+        let encrypting = store.clone().with_label_key(LabelKey::from_bytes([1; 32]));
+        encrypting.set_label(&secret, "Alice's invoice").await?;
+        assert_eq!(
+            encrypting.label(&secret).await?.as_deref(),
+            Some("Alice's invoice")
+        );
+        let row = sqlx::query("SELECT label FROM label WHERE type = 'output'")
+            .fetch_one(&store.pool)
+            .await?;
+        assert!(!row.get::<String, _>("label").contains("Alice"));
+
+        // Without the key, or with another one, the label can't be read.
+        assert!(matches!(
+            store.label(&secret).await,
+            Err(Error::LabelDecryption)
+        ));
+        let other = store.clone().with_label_key(LabelKey::from_bytes([2; 32]));
+        assert!(matches!(
+            other.label(&secret).await,
+            Err(Error::LabelDecryption)
+        ));
+
+        // Moving the ciphertext to another item is detected.
+        sqlx::query("UPDATE label SET ref = $1 WHERE type = 'output'")
+            .bind(OutPoint::new(txid, 1).to_string())
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            encrypting
+                .label(&LabelRef::Output(OutPoint::new(txid, 1)))
+                .await,
+            Err(Error::LabelDecryption)
+        ));
+
+        assert_eq!(encrypting.encrypt_labels().await?, 1);
+        assert_eq!(encrypting.encrypt_labels().await?, 0);
+        assert_eq!(encrypting.label(&plain).await?.as_deref(), Some("public"));
+        assert!(matches!(
+            store.label(&plain).await,
+            Err(Error::LabelDecryption)
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "label-encryption")]
+    #[tokio::test]
+    async fn encrypted_labels_of_readers() -> anyhow::Result<()> {
+        use std::sync::Arc;
+
+        use bdk_chain::{ConfirmationBlockTime, tx_graph};
+        use bitcoin::{Amount, ScriptBuf, Transaction, TxOut, absolute, transaction};
+
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        let store = store.with_label_key(LabelKey::from_bytes([1; 32]));
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        store
+            .write_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(tx)].into(),
+                ..Default::default()
+            })
+            .await?;
+        store.set_label(&LabelRef::Tx(txid), "rent").await?;
+        store
+            .set_label(&LabelRef::Output(OutPoint::new(txid, 0)), "landlord")
+            .await?;
+
+        let details = store.tx_details(txid).await?.expect("must find tx");
+        assert_eq!(details.label.as_deref(), Some("rent"));
+        assert_eq!(details.output_labels, [(0, "landlord".to_string())].into());
+        assert_eq!(
+            store.transactions().await?[0].label.as_deref(),
+            Some("rent")
+        );
+        let page = store.transactions_page(None, 1).await?;
+        assert_eq!(page.items[0].label.as_deref(), Some("rent"));
+        assert_eq!(
+            store.recent_txs(1).await?[0].row.label.as_deref(),
+            Some("rent")
+        );
+
+        Ok(())
+    }
+}
@@ -1,9 +1,151 @@
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
+mod address_reuse;
+#[cfg(feature = "analytics")]
+mod analytics;
+mod anchor;
+pub use anchor::*;
+mod app_data;
+mod archive;
 mod async_store;
 pub use async_store::*;
+#[cfg(feature = "encrypted-backup")]
+mod backup;
+mod builder;
+pub use builder::*;
+mod chain_gaps;
+mod chain_source;
+pub use chain_source::*;
+mod clock;
+pub use clock::*;
+mod codec;
+pub use codec::*;
+mod coin_control;
+mod convert;
+pub use coin_control::*;
+mod cosigner;
+pub use cosigner::*;
 mod error;
 pub use error::*;
+mod functions;
+pub use functions::register_functions;
+mod health;
+#[cfg(feature = "http-cache")]
+mod http_cache;
+#[cfg(feature = "http-cache")]
+pub use http_cache::*;
+mod import;
+mod label;
+pub use label::*;
+mod lookahead;
+pub use lookahead::*;
+mod maintenance;
+pub use maintenance::*;
+mod migration;
+pub use migration::*;
+mod multipath;
+pub use multipath::*;
+mod output_tag;
+pub use output_tag::*;
+mod persisted;
+pub use persisted::*;
+mod progress;
+pub use progress::*;
+mod provenance;
+pub use provenance::*;
+mod reorg;
+pub use reorg::*;
+mod retention;
+pub use retention::*;
+mod rows;
+mod rt;
+pub use rows::*;
+mod schema;
+pub use schema::*;
+mod spends;
+mod stamp;
+pub use stamp::*;
+mod stream;
+mod sync_log;
+pub use sync_log::*;
+mod sync_update;
+mod tenant;
+pub use tenant::*;
+mod tx_details;
+pub use tx_details::*;
+mod tx_meta;
+pub use tx_meta::*;
+mod tx_summary;
+pub use tx_summary::*;
+mod views;
+pub use views::*;
+mod watch;
+pub use watch::*;
+#[cfg(feature = "wallet")]
+mod addresses;
+mod write_queue;
+#[cfg(feature = "wallet")]
+pub use addresses::*;
+#[cfg(feature = "wallet")]
+mod chunked;
+#[cfg(feature = "wallet")]
+pub use chunked::*;
+#[cfg(feature = "wallet")]
+mod coalesce;
+#[cfg(feature = "wallet")]
+pub use coalesce::*;
+#[cfg(feature = "wallet")]
+mod consistency;
+#[cfg(feature = "wallet")]
+pub use consistency::*;
+#[cfg(feature = "wallet")]
+mod diff;
+#[cfg(feature = "wallet")]
+pub use diff::*;
+#[cfg(feature = "wallet")]
+mod discover;
+#[cfg(feature = "wallet")]
+pub use discover::*;
+#[cfg(feature = "wallet")]
+mod dyn_persister;
+#[cfg(feature = "wallet")]
+pub use dyn_persister::*;
+#[cfg(feature = "wallet")]
+mod hydrate;
+#[cfg(feature = "wallet")]
+mod merge;
+#[cfg(feature = "wallet")]
+pub use merge::*;
+#[cfg(feature = "wallet")]
+mod overflow;
+#[cfg(feature = "wallet")]
+mod plan;
+#[cfg(feature = "wallet")]
+pub use plan::*;
+#[cfg(feature = "wallet")]
+mod prepared;
+#[cfg(feature = "wallet")]
+pub use prepared::*;
+#[cfg(feature = "wallet")]
+mod recovery;
+#[cfg(feature = "wallet")]
+pub use recovery::*;
+#[cfg(feature = "wallet")]
+mod replication;
+#[cfg(feature = "wallet")]
+pub use replication::*;
+#[cfg(feature = "wallet")]
+mod rotation;
+#[cfg(feature = "wallet")]
+pub use rotation::*;
+#[cfg(feature = "wallet")]
+mod validate;
+#[cfg(feature = "wallet")]
+pub use validate::*;
 #[cfg(feature = "wallet")]
 mod wallet;
+#[cfg(feature = "wallet")]
+mod wallet_id;
+#[cfg(feature = "wallet")]
+pub use wallet_id::*;
@@ -0,0 +1,229 @@
+//! Per descriptor indexer settings.
+
+use std::collections::BTreeMap;
+
+use bdk_chain::{DescriptorId, keychain_txout};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+
+/// Indexer settings of a descriptor, see [`Store::set_lookahead`] and
+/// [`Store::set_stop_gap`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DescriptorSettings {
+    /// Lookahead of the keychain txout index
+    pub lookahead: Option<u32>,
+    /// Stop gap of the latest full scan
+    pub stop_gap: Option<u32>,
+    /// Lowest derivation index known to be used, below which scanning can be skipped
+    pub first_used_index: Option<u32>,
+}
+
+/// A keychain txout changeset along with the first used index of its descriptors, see
+/// [`Store::read_keychain_txout_ext`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeychainTxOutExt {
+    /// Keychain txout changeset
+    pub changeset: keychain_txout::ChangeSet,
+    /// First used index by descriptor id, see [`Store::set_first_used_index`]
+    pub first_used_index: BTreeMap<DescriptorId, u32>,
+}
+
+impl Store {
+    /// Record the `lookahead` of the descriptor with id `descriptor_id`.
+    ///
+    /// Returns the previously recorded lookahead if it differs, so the caller can warn
+    /// that outputs beyond the smaller lookahead may have been missed.
+    pub async fn set_lookahead(
+        &self,
+        descriptor_id: DescriptorId,
+        lookahead: u32,
+    ) -> Result<Option<u32>, Error> {
+        let prev = self
+            .write(WriteOptions::default(), async |conn| {
+                let row = sqlx::query(
+                    "SELECT lookahead FROM descriptor_settings WHERE descriptor_id = $1",
+                )
+                .bind(descriptor_id.to_string())
+                .fetch_optional(&mut *conn)
+                .await?;
+                sqlx::query(
+                    "INSERT INTO descriptor_settings(descriptor_id, lookahead) VALUES($1, $2) ON CONFLICT DO UPDATE SET lookahead = $2",
+                )
+                .bind(descriptor_id.to_string())
+                .bind(lookahead)
+                .execute(&mut *conn)
+                .await?;
+                Ok(row.and_then(|row| row.get::<Option<u32>, _>("lookahead")))
+            })
+            .await?;
+
+        Ok(prev.filter(|&prev| prev != lookahead))
+    }
+
+    /// Record the `stop_gap` of the latest full scan of the descriptor with id
+    /// `descriptor_id`.
+    pub async fn set_stop_gap(
+        &self,
+        descriptor_id: DescriptorId,
+        stop_gap: u32,
+    ) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO descriptor_settings(descriptor_id, stop_gap) VALUES($1, $2) ON CONFLICT DO UPDATE SET stop_gap = $2",
+            )
+            .bind(descriptor_id.to_string())
+            .bind(stop_gap)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Record the lowest derivation index of the descriptor with id `descriptor_id` known
+    /// to be used, e.g. when restoring a wallet from a backup which includes it.
+    pub async fn set_first_used_index(
+        &self,
+        descriptor_id: DescriptorId,
+        index: u32,
+    ) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO descriptor_settings(descriptor_id, first_used_index) VALUES($1, $2) ON CONFLICT DO UPDATE SET first_used_index = $2",
+            )
+            .bind(descriptor_id.to_string())
+            .bind(index)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get the first used index of the descriptor with id `descriptor_id`, if recorded.
+    pub async fn first_used_index(
+        &self,
+        descriptor_id: DescriptorId,
+    ) -> Result<Option<u32>, Error> {
+        Ok(self
+            .descriptor_settings(descriptor_id)
+            .await?
+            .first_used_index)
+    }
+
+    /// Read the keychain txout changeset along with the first used index of each
+    /// descriptor.
+    pub async fn read_keychain_txout_ext(&self) -> Result<KeychainTxOutExt, Error> {
+        let changeset = self.read_keychain_txout().await?;
+        let first_used_index = self
+            .read_descriptor_settings()
+            .await?
+            .into_iter()
+            .filter_map(|(descriptor_id, settings)| {
+                settings
+                    .first_used_index
+                    .map(|index| (descriptor_id, index))
+            })
+            .collect();
+
+        Ok(KeychainTxOutExt {
+            changeset,
+            first_used_index,
+        })
+    }
+
+    /// Get the settings of the descriptor with id `descriptor_id`.
+    pub async fn descriptor_settings(
+        &self,
+        descriptor_id: DescriptorId,
+    ) -> Result<DescriptorSettings, Error> {
+        let row = sqlx::query(
+            "SELECT lookahead, stop_gap, first_used_index FROM descriptor_settings WHERE descriptor_id = $1",
+        )
+        .bind(descriptor_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row
+            .map(|row| DescriptorSettings {
+                lookahead: row.get("lookahead"),
+                stop_gap: row.get("stop_gap"),
+                first_used_index: row.get("first_used_index"),
+            })
+            .unwrap_or_default())
+    }
+
+    /// Read the settings of all descriptors.
+    pub async fn read_descriptor_settings(
+        &self,
+    ) -> Result<BTreeMap<DescriptorId, DescriptorSettings>, Error> {
+        let rows = sqlx::query(
+            "SELECT descriptor_id, lookahead, stop_gap, first_used_index FROM descriptor_settings ORDER BY descriptor_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut settings = BTreeMap::new();
+        for row in rows {
+            let descriptor_id: String = row.get("descriptor_id");
+            settings.insert(
+                descriptor_id.parse()?,
+                DescriptorSettings {
+                    lookahead: row.get("lookahead"),
+                    stop_gap: row.get("stop_gap"),
+                    first_used_index: row.get("first_used_index"),
+                },
+            );
+        }
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn descriptor_settings() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let descriptor_id = DescriptorId::from_byte_array([1; 32]);
+        assert_eq!(
+            store.descriptor_settings(descriptor_id).await?,
+            DescriptorSettings::default()
+        );
+
+        assert_eq!(store.set_lookahead(descriptor_id, 25).await?, None);
+        assert_eq!(store.set_lookahead(descriptor_id, 25).await?, None);
+        store.set_stop_gap(descriptor_id, 20).await?;
+        assert_eq!(store.set_lookahead(descriptor_id, 50).await?, Some(25));
+
+        assert_eq!(store.first_used_index(descriptor_id).await?, None);
+        store.set_first_used_index(descriptor_id, 7).await?;
+        assert_eq!(store.first_used_index(descriptor_id).await?, Some(7));
+
+        let expected = DescriptorSettings {
+            lookahead: Some(50),
+            stop_gap: Some(20),
+            first_used_index: Some(7),
+        };
+        assert_eq!(store.descriptor_settings(descriptor_id).await?, expected);
+        assert_eq!(
+            store.read_descriptor_settings().await?,
+            [(descriptor_id, expected)].into()
+        );
+        assert_eq!(
+            store.read_keychain_txout_ext().await?.first_used_index,
+            [(descriptor_id, 7)].into()
+        );
+
+        Ok(())
+    }
+}
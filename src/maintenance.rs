@@ -0,0 +1,380 @@
+//! Database maintenance.
+
+use std::collections::BTreeMap;
+
+use bdk_chain::bitcoin::Txid;
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::from_sql;
+use crate::retention::delete_unreferenced_blobs;
+
+/// Options of [`Store::compact_anchors`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AnchorCompaction {
+    /// Only compact anchors to blocks at least this many blocks below the tip, which are
+    /// unlikely to be reorganized back.
+    pub min_depth: u32,
+    /// Keep the highest anchor of each transaction without an anchor in the local chain
+    /// among those which would be deleted, as a record of where it last confirmed on
+    /// another branch.
+    pub keep_history: bool,
+}
+
+impl AnchorCompaction {
+    /// Set the depth below the tip from which anchors are compacted.
+    pub fn min_depth(mut self, depth: u32) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Set whether to keep an anchor of each transaction without one in the local chain.
+    pub fn keep_history(mut self, keep: bool) -> Self {
+        self.keep_history = keep;
+        self
+    }
+}
+
+/// Disk usage of a table or index, see [`SizeEstimate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSize {
+    /// Name of the table or index
+    pub name: String,
+    /// Table of the index, or the table itself
+    pub table: String,
+    /// Whether this is an index
+    pub is_index: bool,
+    /// Bytes of the pages used
+    pub bytes: u64,
+}
+
+/// Disk usage of the database, see [`Store::estimate_size`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// Bytes of the database file, including the free pages
+    pub total_bytes: u64,
+    /// Bytes of the free pages, which [`Store::vacuum`] reclaims
+    pub free_bytes: u64,
+    /// Disk usage of the tables and indices, largest first. Empty if the SQLite library
+    /// lacks the `dbstat` virtual table, which the bundled one has.
+    pub objects: Vec<ObjectSize>,
+}
+
+impl SizeEstimate {
+    /// Bytes of `table` including its indices.
+    pub fn table_bytes(&self, table: &str) -> u64 {
+        self.objects
+            .iter()
+            .filter(|object| object.table == table)
+            .map(|object| object.bytes)
+            .sum()
+    }
+}
+
+impl Store {
+    /// Delete the anchors which are inconsistent with the local chain, returning how many
+    /// were deleted.
+    ///
+    /// A transaction accumulates anchors to the blocks of every branch it confirmed in.
+    /// This deletes the anchors to blocks which the local chain has another block at the
+    /// height of, and the anchors to heights without a block of transactions which also
+    /// have an anchor in the local chain, so that databases of long running wallets which
+    /// saw many reorgs shrink to about one anchor per transaction.
+    pub async fn compact_anchors(&self, opts: AnchorCompaction) -> Result<u64, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query(
+                "WITH consistent AS (
+                    SELECT DISTINCT a.txid FROM anchor AS a JOIN block AS b ON b.height = a.block_height AND b.hash = a.block_hash
+                ),
+                candidate AS (
+                    SELECT a.rowid AS id, a.txid IN consistent AS has_consistent, ROW_NUMBER() OVER (PARTITION BY a.txid ORDER BY a.block_height DESC, a.block_hash) AS rank
+                    FROM anchor AS a
+                    WHERE a.block_height + $1 <= (SELECT MAX(height) FROM block)
+                    AND (
+                        EXISTS(SELECT 1 FROM block AS b WHERE b.height = a.block_height AND b.hash != a.block_hash)
+                        OR (NOT EXISTS(SELECT 1 FROM block AS b WHERE b.height = a.block_height) AND a.txid IN consistent)
+                    )
+                )
+                DELETE FROM anchor WHERE rowid IN (SELECT id FROM candidate WHERE has_consistent OR NOT ($2 AND rank = 1))",
+            )
+            .bind(opts.min_depth)
+            .bind(opts.keep_history)
+            .execute(&mut *conn)
+            .await?;
+
+            Ok(res.rows_affected())
+        })
+        .await
+    }
+
+    /// Count the rows of every table, excluding SQLite's and sqlx's internal tables.
+    pub async fn row_counts(&self) -> Result<BTreeMap<String, u64>, Error> {
+        let rows = sqlx::query(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '_sqlx_%' ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut counts = BTreeMap::new();
+        for row in rows {
+            let name: String = row.get("name");
+            let row = sqlx::query(&format!("SELECT COUNT(*) FROM \"{name}\""))
+                .fetch_one(&self.pool)
+                .await?;
+            let count: i64 = row.get(0);
+            counts.insert(name, from_sql("COUNT(*)", count)?);
+        }
+
+        Ok(counts)
+    }
+
+    /// Estimate the disk usage of the database and of each table and index, from the page
+    /// counts of the file and the `dbstat` virtual table.
+    ///
+    /// This lets apps show the size of the wallet data and decide whether to prompt users
+    /// to prune, e.g. with [`prune_tx_blobs`](Self::prune_tx_blobs), or to
+    /// [`vacuum`](Self::vacuum). The WAL file isn't counted.
+    pub async fn estimate_size(&self) -> Result<SizeEstimate, Error> {
+        let mut conn = self.pool.acquire().await?;
+        let pragma = async |conn: &mut sqlx::SqliteConnection, name: &'static str| {
+            let row = sqlx::query(&format!("PRAGMA {name}"))
+                .fetch_one(&mut *conn)
+                .await?;
+            from_sql::<u64>(name, row.get::<i64, _>(0))
+        };
+        let page_size = pragma(&mut conn, "page_size").await?;
+        let total_bytes = pragma(&mut conn, "page_count").await? * page_size;
+        let free_bytes = pragma(&mut conn, "freelist_count").await? * page_size;
+
+        let rows = sqlx::query(
+            "SELECT s.name, m.type, m.tbl_name, SUM(s.pgsize) AS bytes
+            FROM dbstat AS s LEFT JOIN sqlite_master AS m ON m.name = s.name
+            GROUP BY s.name ORDER BY bytes DESC, s.name",
+        )
+        .fetch_all(&mut *conn)
+        .await;
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(sqlx::Error::Database(e)) if e.message().contains("no such table: dbstat") => {
+                vec![]
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut objects = vec![];
+        for row in rows {
+            let name: String = row.get("name");
+            let kind: Option<String> = row.get("type");
+            let table: Option<String> = row.get("tbl_name");
+            objects.push(ObjectSize {
+                table: table.unwrap_or_else(|| name.clone()),
+                name,
+                is_index: kind.as_deref() == Some("index"),
+                bytes: from_sql("dbstat.pgsize", row.get::<i64, _>("bytes"))?,
+            });
+        }
+
+        Ok(SizeEstimate {
+            total_bytes,
+            free_bytes,
+            objects,
+        })
+    }
+
+    /// Run `PRAGMA integrity_check`, returning the problems found if any.
+    pub async fn integrity_check(&self) -> Result<Vec<String>, Error> {
+        let rows = sqlx::query("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let problems: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+        if problems == ["ok"] {
+            return Ok(vec![]);
+        }
+
+        Ok(problems)
+    }
+
+    /// Rebuild the database file, reclaiming the space of deleted rows.
+    pub async fn vacuum(&self) -> Result<(), Error> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Delete the raw transactions of `txids`, keeping their metadata such as the seen
+    /// times and computed columns. Returns how many were deleted.
+    ///
+    /// Reading the tx graph afterwards yields no full transaction for these txids.
+    pub async fn prune_tx_blobs(&self, txids: &[Txid]) -> Result<u64, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            for txid in txids {
+                sqlx::query("UPDATE tx SET blob_id = NULL WHERE txid = $1")
+                    .bind(txid.to_string())
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            delete_unreferenced_blobs(conn).await
+        })
+        .await
+    }
+
+    /// Delete the blocks recorded as orphaned, returning how many were deleted.
+    pub async fn clear_orphaned_blocks(&self) -> Result<u64, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query("DELETE FROM block_orphaned")
+                .execute(&mut *conn)
+                .await?;
+            Ok(res.rows_affected())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::{Transaction, absolute, transaction};
+    use bdk_chain::{BlockId, ConfirmationBlockTime, local_chain, tx_graph};
+
+    #[tokio::test]
+    async fn maintenance() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_block_tombstones(true);
+        store.migrate().await?;
+
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(0, Some(Hash::hash(b"0")));
+        chain.blocks.insert(1, Some(Hash::hash(b"1")));
+        store.write_local_chain(&chain).await?;
+        chain.blocks.insert(1, None);
+        store.write_local_chain(&chain).await?;
+
+        let counts = store.row_counts().await?;
+        assert_eq!(counts.get("block"), Some(&1));
+        assert_eq!(counts.get("block_orphaned"), Some(&1));
+        assert!(!counts.contains_key("_sqlx_migrations"));
+
+        assert!(store.integrity_check().await?.is_empty());
+        assert_eq!(store.clear_orphaned_blocks().await?, 1);
+        store.vacuum().await?;
+        assert!(store.orphaned_blocks().await?.is_empty());
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let txid = tx.compute_txid();
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        graph.txs.insert(Arc::new(tx));
+        graph.last_seen.insert(txid, 100);
+        store.write_tx_graph(&graph).await?;
+        assert_eq!(store.prune_tx_blobs(&[txid]).await?, 1);
+        let graph = store.read_tx_graph().await?;
+        assert!(graph.txs.is_empty());
+        assert_eq!(graph.last_seen, [(txid, 100)].into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn estimate_size() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let block = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        let anchors = (0..1_000u32)
+            .map(|i| (block, Txid::hash(&i.to_le_bytes())))
+            .collect();
+        store.write_untimed_anchors(&anchors).await?;
+
+        let size = store.estimate_size().await?;
+        assert!(size.total_bytes > 0);
+        assert!(size.free_bytes <= size.total_bytes);
+        let used: u64 = size.objects.iter().map(|object| object.bytes).sum();
+        assert!(used <= size.total_bytes);
+        let anchor = size.objects.iter().find(|o| o.name == "anchor").unwrap();
+        assert!(!anchor.is_index);
+        assert!(
+            size.objects
+                .iter()
+                .any(|o| o.name == "anchor_txid" && o.is_index && o.table == "anchor")
+        );
+        assert!(size.table_bytes("anchor") > anchor.bytes);
+        assert_eq!(
+            size.objects[0].bytes,
+            size.objects.iter().map(|o| o.bytes).max().unwrap()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compact_anchors() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let hash =
+            |height: u32, branch: &[u8]| Hash::hash(&[&height.to_le_bytes()[..], branch].concat());
+        let chain = local_chain::ChangeSet {
+            blocks: (0..=10)
+                .map(|height| (height, Some(hash(height, b"main"))))
+                .collect(),
+        };
+        store.write_local_chain(&chain).await?;
+
+        let anchor = |height: u32, branch: &[u8]| ConfirmationBlockTime {
+            block_id: BlockId {
+                height,
+                hash: hash(height, branch),
+            },
+            confirmation_time: 0,
+        };
+        let confirmed = Hash::hash(b"confirmed");
+        let reorged = Hash::hash(b"reorged");
+        let recent = Hash::hash(b"recent");
+        let graph = tx_graph::ChangeSet {
+            anchors: [
+                (anchor(5, b"main"), confirmed),
+                (anchor(5, b"fork"), confirmed),
+                (anchor(12, b"fork"), confirmed),
+                (anchor(6, b"fork"), reorged),
+                (anchor(7, b"fork"), reorged),
+                (anchor(9, b"fork"), recent),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        store.write_tx_graph(&graph).await?;
+
+        let compaction = AnchorCompaction::default().min_depth(2);
+        let deleted = store.compact_anchors(compaction.keep_history(true)).await?;
+        assert_eq!(deleted, 2);
+        let anchors = store.read_tx_graph().await?.anchors;
+        assert_eq!(
+            anchors,
+            [
+                (anchor(5, b"main"), confirmed),
+                (anchor(7, b"fork"), reorged),
+                (anchor(9, b"fork"), recent),
+                (anchor(12, b"fork"), confirmed),
+            ]
+            .into()
+        );
+
+        // Without history, the stale anchors of a transaction without a consistent one go.
+        assert_eq!(store.compact_anchors(compaction).await?, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+//! Merging another store into this one.
+
+use std::collections::BTreeMap;
+
+use bdk_chain::bitcoin::BlockHash;
+use bdk_chain::{local_chain, tx_graph};
+use bdk_wallet::{ChangeSet, KeychainKind};
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+
+/// How [`Store::merge_from`] resolves two different blocks at the same height.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockConflictPolicy {
+    /// Keep the block of this store.
+    #[default]
+    KeepOurs,
+    /// Replace the block of this store with the one of the other store.
+    TakeTheirs,
+    /// Keep the blocks of whichever store has the higher tip, this store on a tie.
+    HigherTip,
+}
+
+/// Two different blocks at the same height, see [`MergeReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockConflict {
+    /// Height
+    pub height: u32,
+    /// Hash of the block of this store
+    pub ours: BlockHash,
+    /// Hash of the block of the other store
+    pub theirs: BlockHash,
+    /// Hash of the block kept by the merge
+    pub kept: BlockHash,
+}
+
+/// Outcome of [`Store::merge_from`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Rows changed by the merge
+    pub summary: WriteSummary,
+    /// Heights at which the stores have different blocks
+    pub block_conflicts: Vec<BlockConflict>,
+}
+
+impl Store {
+    /// Merge the wallet data of `other` into this store, e.g. to combine the databases of
+    /// the same wallet on two devices.
+    ///
+    /// Transactions, txouts, anchors and blocks are unioned, as are the revealed indices
+    /// and script pubkeys of the keychains. Of the seen times of a transaction the earliest
+    /// first seen and the latest last seen and last evicted are kept, and of the revealed
+    /// indices of a descriptor the highest. Where the chains of the stores fork, i.e. at
+    /// the lowest height of two different blocks, `policy` picks one of the chains, and the
+    /// blocks of the other chain at and above the fork are dropped. Descriptors and network
+    /// of this store are kept, and only set from `other` if missing here. The merge is
+    /// written in a single transaction.
+    ///
+    /// Labels, app data and the other tables outside of the wallet [`ChangeSet`] are not
+    /// merged.
+    ///
+    /// Returns [`Error::NetworkMismatch`] if the stores are for different networks, and
+    /// [`Error::DescriptorMismatch`] if they have different descriptors for a keychain.
+    pub async fn merge_from(
+        &self,
+        other: &Store,
+        policy: BlockConflictPolicy,
+    ) -> Result<MergeReport, Error> {
+        let ours = self.read_changeset().await?;
+        let theirs = other.read_changeset().await?;
+
+        if let (Some(ours), Some(theirs)) = (ours.network, theirs.network) {
+            if ours != theirs {
+                return Err(Error::NetworkMismatch { ours, theirs });
+            }
+        }
+
+        for (keychain, ours, theirs) in [
+            (KeychainKind::External, &ours.descriptor, &theirs.descriptor),
+            (
+                KeychainKind::Internal,
+                &ours.change_descriptor,
+                &theirs.change_descriptor,
+            ),
+        ] {
+            if matches!((ours, theirs), (Some(a), Some(b)) if a != b) {
+                return Err(Error::DescriptorMismatch(keychain));
+            }
+        }
+
+        let mut report = MergeReport::default();
+
+        let take_theirs = match policy {
+            BlockConflictPolicy::KeepOurs => false,
+            BlockConflictPolicy::TakeTheirs => true,
+            BlockConflictPolicy::HigherTip => tip(&theirs.local_chain) > tip(&ours.local_chain),
+        };
+        for (&height, &hash) in &theirs.local_chain.blocks {
+            let Some(hash) = hash else { continue };
+            if let Some(&Some(our_hash)) = ours.local_chain.blocks.get(&height) {
+                if our_hash != hash {
+                    report.block_conflicts.push(BlockConflict {
+                        height,
+                        ours: our_hash,
+                        theirs: hash,
+                        kept: if take_theirs { hash } else { our_hash },
+                    });
+                }
+            }
+        }
+        // A block commits to the blocks below it, so the blocks of the dropped chain above
+        // the fork don't belong to the kept chain, even where the other chain has none.
+        let fork = report.block_conflicts.first().map(|c| c.height);
+        let above_fork = |height: &u32| fork.is_some_and(|fork| *height >= fork);
+        let mut replaced = local_chain::ChangeSet::default();
+        if take_theirs {
+            replaced.blocks = (ours.local_chain.blocks.iter())
+                .filter(|&(height, hash)| hash.is_some() && above_fork(height))
+                .map(|(&height, _)| (height, None))
+                .collect();
+        }
+        let blocks: BTreeMap<_, _> = (theirs.local_chain.blocks.iter())
+            .filter(|&(height, hash)| hash.is_some() && (take_theirs || !above_fork(height)))
+            .map(|(&height, &hash)| (height, hash))
+            .collect();
+
+        let mut tx_graph: tx_graph::ChangeSet<_> = theirs.tx_graph;
+        for (txid, t) in &mut tx_graph.first_seen {
+            if let Some(&our_t) = ours.tx_graph.first_seen.get(txid) {
+                *t = (*t).min(our_t);
+            }
+        }
+        for (txid, t) in &mut tx_graph.last_seen {
+            if let Some(&our_t) = ours.tx_graph.last_seen.get(txid) {
+                *t = (*t).max(our_t);
+            }
+        }
+        for (txid, t) in &mut tx_graph.last_evicted {
+            if let Some(&our_t) = ours.tx_graph.last_evicted.get(txid) {
+                *t = (*t).max(our_t);
+            }
+        }
+        let mut indexer = theirs.indexer;
+        for (descriptor_id, index) in &mut indexer.last_revealed {
+            if let Some(&our_index) = ours.indexer.last_revealed.get(descriptor_id) {
+                *index = (*index).max(our_index);
+            }
+        }
+
+        let merged = ChangeSet {
+            descriptor: ours
+                .descriptor
+                .is_none()
+                .then_some(theirs.descriptor)
+                .flatten(),
+            change_descriptor: ours
+                .change_descriptor
+                .is_none()
+                .then_some(theirs.change_descriptor)
+                .flatten(),
+            network: ours.network.is_none().then_some(theirs.network).flatten(),
+            local_chain: local_chain::ChangeSet { blocks },
+            tx_graph,
+            indexer,
+        };
+        report.summary = self
+            .write(WriteOptions::default(), async |conn| {
+                let mut summary = self.write_local_chain_in(conn, &replaced).await?;
+                summary.merge(self.write_changeset_in(conn, &merged).await?);
+                Ok(summary)
+            })
+            .await?;
+
+        Ok(report)
+    }
+}
+
+/// Height of the highest block of `local_chain`.
+fn tip(local_chain: &local_chain::ChangeSet) -> Option<u32> {
+    local_chain
+        .blocks
+        .iter()
+        .rev()
+        .find_map(|(&height, hash)| hash.map(|_| height))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::{
+        Amount, Network, Transaction, TxOut, absolute, hashes::Hash, transaction,
+    };
+    use bdk_wallet::Wallet;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+
+    fn tx(value: u64) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: Default::default(),
+            }],
+        }
+    }
+
+    async fn store(
+        network: Network,
+        blocks: &[(u32, &[u8])],
+        tx: Transaction,
+        first_seen: u64,
+    ) -> anyhow::Result<Store> {
+        let mut store = Store::new_memory().await?;
+        store.migrate().await?;
+        let mut wallet = Wallet::create_single(DESCRIPTOR)
+            .network(network)
+            .create_wallet_async(&mut store)
+            .await?;
+        wallet.persist_async(&mut store).await?;
+        let changeset = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: blocks
+                    .iter()
+                    .map(|&(height, data)| (height, Some(Hash::hash(data))))
+                    .collect(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                first_seen: [(tx.compute_txid(), first_seen)].into(),
+                txs: [Arc::new(tx)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        store.write_changeset(&changeset).await?;
+        Ok(store)
+    }
+
+    #[tokio::test]
+    async fn merge_from() -> anyhow::Result<()> {
+        let shared = tx(1);
+        let laptop = store(
+            Network::Signet,
+            &[(1, b"1"), (2, b"2a")],
+            shared.clone(),
+            200,
+        )
+        .await?;
+        laptop
+            .write_tx_graph(&tx_graph::ChangeSet {
+                txs: [Arc::new(tx(2))].into(),
+                ..Default::default()
+            })
+            .await?;
+        let desktop = store(
+            Network::Signet,
+            &[(1, b"1"), (2, b"2b"), (3, b"3b")],
+            shared.clone(),
+            100,
+        )
+        .await?;
+
+        let report = laptop
+            .merge_from(&desktop, BlockConflictPolicy::KeepOurs)
+            .await?;
+        assert_eq!(report.block_conflicts.len(), 1);
+        let conflict = report.block_conflicts[0];
+        assert_eq!(conflict.height, 2);
+        assert_eq!(conflict.kept, Hash::hash(b"2a"));
+        let mut merged = laptop.read_changeset().await?;
+        assert_eq!(merged.tx_graph.txs.len(), 2);
+        assert_eq!(merged.tx_graph.first_seen[&shared.compute_txid()], 100);
+        // 3b builds on the rejected 2b.
+        // Above the genesis block of the wallet.
+        assert_eq!(
+            merged.local_chain.blocks.split_off(&1),
+            [(1, Some(Hash::hash(b"1"))), (2, Some(Hash::hash(b"2a"))),].into()
+        );
+
+        // 4a builds on 2a which is replaced.
+        laptop
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: [(4, Some(Hash::hash(b"4a")))].into(),
+            })
+            .await?;
+        let report = laptop
+            .merge_from(&desktop, BlockConflictPolicy::TakeTheirs)
+            .await?;
+        assert_eq!(report.block_conflicts.len(), 1);
+        assert_eq!(report.block_conflicts[0].kept, Hash::hash(b"2b"));
+        let mut merged = laptop.read_changeset().await?;
+        assert_eq!(
+            merged.local_chain.blocks.split_off(&1),
+            [
+                (1, Some(Hash::hash(b"1"))),
+                (2, Some(Hash::hash(b"2b"))),
+                (3, Some(Hash::hash(b"3b"))),
+            ]
+            .into()
+        );
+
+        let regtest = store(Network::Regtest, &[], tx(3), 0).await?;
+        assert!(matches!(
+            laptop
+                .merge_from(&regtest, BlockConflictPolicy::KeepOurs)
+                .await,
+            Err(Error::NetworkMismatch { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_other_wallet() -> anyhow::Result<()> {
+        let ours = store(Network::Signet, &[], tx(1), 0).await?;
+        let mut theirs = Store::new_memory().await?;
+        theirs.migrate().await?;
+        let mut wallet = Wallet::create_single(DESCRIPTOR.replace("/0/*", "/1/*"))
+            .network(Network::Signet)
+            .create_wallet_async(&mut theirs)
+            .await?;
+        wallet.persist_async(&mut theirs).await?;
+        theirs
+            .write_tx_graph(&tx_graph::ChangeSet {
+                txs: [Arc::new(tx(2))].into(),
+                ..Default::default()
+            })
+            .await?;
+
+        assert!(matches!(
+            ours.merge_from(&theirs, BlockConflictPolicy::KeepOurs)
+                .await,
+            Err(Error::DescriptorMismatch(KeychainKind::External))
+        ));
+        // Nothing of the other wallet is merged.
+        assert_eq!(ours.read_changeset().await?.tx_graph.txs.len(), 1);
+
+        Ok(())
+    }
+}
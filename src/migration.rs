@@ -0,0 +1,121 @@
+//! Introspection of the schema migrations.
+
+use std::time::Duration;
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::async_store::migrator;
+use crate::convert::from_sql;
+
+/// A migration embedded in the crate, see [`Store::migrations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaMigration {
+    /// Version, e.g. `1` for `0001_schema.up.sql`
+    pub version: i64,
+    /// Description
+    pub description: String,
+    /// SHA-384 checksum of the SQL
+    pub checksum: Vec<u8>,
+}
+
+/// A migration applied to the database, see [`Store::applied_migrations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    /// Version
+    pub version: i64,
+    /// Description
+    pub description: String,
+    /// SHA-384 checksum of the SQL at the time it was applied
+    pub checksum: Vec<u8>,
+    /// Unix time at which the migration was applied
+    pub applied_at: u64,
+    /// Whether the migration succeeded
+    pub success: bool,
+    /// How long the migration took
+    pub execution_time: Duration,
+}
+
+impl Store {
+    /// The migrations embedded in the crate for the enabled features, ordered by version.
+    ///
+    /// These are the migrations [`migrate`](Self::migrate) applies, see
+    /// [`applied_migrations`](Self::applied_migrations) for the state of a database.
+    pub fn migrations() -> Vec<SchemaMigration> {
+        migrator()
+            .iter()
+            .map(|migration| SchemaMigration {
+                version: migration.version,
+                description: migration.description.to_string(),
+                checksum: migration.checksum.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Read the migrations applied to the database, ordered by version, or none if it was
+    /// never migrated.
+    ///
+    /// Deployment tooling can compare these with [`migrations`](Self::migrations) to assert
+    /// the state of the schema, including that no applied migration was changed since.
+    pub async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, Error> {
+        let table = sqlx::query(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        if table.is_none() {
+            return Ok(vec![]);
+        }
+
+        let rows = sqlx::query(
+            "SELECT version, description, checksum, CAST(strftime('%s', installed_on) AS INTEGER) AS applied_at, success, execution_time
+            FROM _sqlx_migrations ORDER BY version",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let applied_at: i64 = row.get("applied_at");
+                let execution_time: i64 = row.get("execution_time");
+                Ok(AppliedMigration {
+                    version: row.get("version"),
+                    description: row.get("description"),
+                    checksum: row.get("checksum"),
+                    applied_at: from_sql("_sqlx_migrations.installed_on", applied_at)?,
+                    success: row.get("success"),
+                    execution_time: Duration::from_nanos(from_sql(
+                        "_sqlx_migrations.execution_time",
+                        execution_time,
+                    )?),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn applied_migrations() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        assert!(store.applied_migrations().await?.is_empty());
+        store.migrate().await?;
+
+        let migrations = Store::migrations();
+        let applied = store.applied_migrations().await?;
+        assert_eq!(applied.len(), migrations.len());
+        for (applied, migration) in applied.iter().zip(&migrations) {
+            assert_eq!(applied.version, migration.version);
+            assert_eq!(applied.description, migration.description);
+            assert_eq!(applied.checksum, migration.checksum);
+            assert!(applied.success);
+            assert!(applied.applied_at > 1_700_000_000);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Storage of multipath descriptors and their expansions.
+
+use std::str::FromStr;
+
+use bdk_chain::{DescriptorExt, DescriptorId, miniscript};
+use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::to_sql;
+
+/// A multipath descriptor together with its expanded single-path descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipathDescriptor {
+    /// The original multipath descriptor, e.g. `wpkh(xpub/<0;1>/*)`.
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    /// Expanded descriptors and their ids, ordered by path index.
+    pub expanded: Vec<(DescriptorId, Descriptor<DescriptorPublicKey>)>,
+}
+
+impl Store {
+    /// Write multipath descriptor.
+    ///
+    /// The descriptor is expanded into its single-path descriptors, which are stored
+    /// alongside the original. Like the keychain descriptors, a multipath descriptor is only
+    /// written once and subsequent writes are ignored.
+    pub async fn write_multipath_descriptor(
+        &self,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<(), Error> {
+        let expanded = descriptor.clone().into_single_descriptors()?;
+
+        self.write(WriteOptions::default(), async |conn| {
+            let res =
+                sqlx::query("INSERT OR IGNORE INTO multipath_descriptor(descriptor) VALUES($1)")
+                    .bind(descriptor.to_string())
+                    .execute(&mut *conn)
+                    .await?;
+            if res.rows_affected() == 0 {
+                return Ok(());
+            }
+            for (path_index, descriptor) in expanded.into_iter().enumerate() {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO multipath_descriptor_path(path_index, descriptor_id, descriptor) VALUES($1, $2, $3)",
+                )
+                .bind(to_sql("multipath_descriptor_path.path_index", path_index as u64)?)
+                .bind(descriptor.descriptor_id().to_string())
+                .bind(descriptor.to_string())
+                .execute(&mut *conn)
+                .await?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read multipath descriptor.
+    pub async fn read_multipath_descriptor(&self) -> Result<Option<MultipathDescriptor>, Error> {
+        let row = sqlx::query("SELECT descriptor FROM multipath_descriptor")
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let descriptor: String = row.get("descriptor");
+        let descriptor = Descriptor::from_str(&descriptor)?;
+
+        let rows = sqlx::query(
+            "SELECT descriptor_id, descriptor FROM multipath_descriptor_path ORDER BY path_index",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut expanded = vec![];
+        for row in rows {
+            let descriptor_id: String = row.get("descriptor_id");
+            let descriptor_id: DescriptorId = descriptor_id.parse()?;
+            let descriptor: String = row.get("descriptor");
+            let descriptor = Descriptor::from_str(&descriptor)?;
+            expanded.push((descriptor_id, descriptor));
+        }
+
+        Ok(Some(MultipathDescriptor {
+            descriptor,
+            expanded,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MULTIPATH_DESC: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/<0;1>/*)";
+
+    #[tokio::test]
+    async fn multipath_descriptor_round_trip() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        assert!(store.read_multipath_descriptor().await?.is_none());
+
+        let descriptor: Descriptor<DescriptorPublicKey> = MULTIPATH_DESC.parse()?;
+        store.write_multipath_descriptor(&descriptor).await?;
+
+        let multipath = store
+            .read_multipath_descriptor()
+            .await?
+            .expect("must read multipath descriptor");
+        assert_eq!(multipath.descriptor, descriptor);
+        let expected: Vec<_> = descriptor
+            .into_single_descriptors()?
+            .into_iter()
+            .map(|desc| (desc.descriptor_id(), desc))
+            .collect();
+        assert_eq!(multipath.expanded.len(), 2);
+        assert_eq!(multipath.expanded, expected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Tags of outputs for protocols layered on top of the wallet.
+
+use bdk_chain::bitcoin;
+use bitcoin::{OutPoint, Txid};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+
+/// A value attached to an output, see [`Store::set_output_tag`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTag {
+    /// Outpoint
+    pub outpoint: OutPoint,
+    /// Namespace of the protocol owning the tag, e.g. `rgb`
+    pub namespace: String,
+    /// Key within the namespace
+    pub key: String,
+    /// Value, opaque to the store
+    pub value: Vec<u8>,
+}
+
+impl Store {
+    /// Set the tag `key` of `namespace` on `outpoint` to `value`, replacing any previous
+    /// value.
+    ///
+    /// Tags let protocols layered on top of the wallet, e.g. RGB, ordinals or runes
+    /// trackers, keep their per-output data in the same store as the wallet, for instance to
+    /// mark outputs which must not be spent by regular coin selection. The store doesn't
+    /// interpret tags, and an output doesn't need to be known to be tagged.
+    pub async fn set_output_tag(
+        &self,
+        outpoint: OutPoint,
+        namespace: &str,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO output_tag(txid, vout, namespace, key, value) VALUES($1, $2, $3, $4, $5) ON CONFLICT DO UPDATE SET value = $5",
+            )
+            .bind(outpoint.txid.to_string())
+            .bind(outpoint.vout)
+            .bind(namespace)
+            .bind(key)
+            .bind(value)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Remove the tag `key` of `namespace` from `outpoint`, returning whether it was set.
+    pub async fn remove_output_tag(
+        &self,
+        outpoint: OutPoint,
+        namespace: &str,
+        key: &str,
+    ) -> Result<bool, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query(
+                "DELETE FROM output_tag WHERE txid = $1 AND vout = $2 AND namespace = $3 AND key = $4",
+            )
+            .bind(outpoint.txid.to_string())
+            .bind(outpoint.vout)
+            .bind(namespace)
+            .bind(key)
+            .execute(&mut *conn)
+            .await?;
+            Ok(res.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Read the tags of `outpoint`, ordered by namespace and key.
+    pub async fn output_tags(&self, outpoint: OutPoint) -> Result<Vec<OutputTag>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid, vout, namespace, key, value FROM output_tag WHERE txid = $1 AND vout = $2 ORDER BY namespace, key",
+        )
+        .bind(outpoint.txid.to_string())
+        .bind(outpoint.vout)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(tag_from_row).collect()
+    }
+
+    /// Read the tags of `namespace`, only those with `key` if given, ordered by outpoint
+    /// and key.
+    pub async fn tagged_outputs(
+        &self,
+        namespace: &str,
+        key: Option<&str>,
+    ) -> Result<Vec<OutputTag>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid, vout, namespace, key, value FROM output_tag WHERE namespace = $1 AND ($2 IS NULL OR key = $2) ORDER BY txid, vout, key",
+        )
+        .bind(namespace)
+        .bind(key)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(tag_from_row).collect()
+    }
+}
+
+fn tag_from_row(row: SqliteRow) -> Result<OutputTag, Error> {
+    let txid: String = row.get("txid");
+    let txid: Txid = txid.parse()?;
+
+    Ok(OutputTag {
+        outpoint: OutPoint {
+            txid,
+            vout: row.get("vout"),
+        },
+        namespace: row.get("namespace"),
+        key: row.get("key"),
+        value: row.get("value"),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn output_tags() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let op_a = OutPoint::new(Hash::hash(b"a"), 0);
+        let op_b = OutPoint::new(Hash::hash(b"b"), 1);
+        store.set_output_tag(op_a, "rgb", "contract", b"c1").await?;
+        store.set_output_tag(op_a, "rgb", "contract", b"c2").await?;
+        store
+            .set_output_tag(op_a, "ord", "inscription", b"i")
+            .await?;
+        store.set_output_tag(op_b, "rgb", "frozen", b"").await?;
+
+        let tags = store.output_tags(op_a).await?;
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].namespace, "ord");
+        assert_eq!(tags[1].value, b"c2");
+
+        assert_eq!(store.tagged_outputs("rgb", None).await?.len(), 2);
+        let frozen = store.tagged_outputs("rgb", Some("frozen")).await?;
+        assert_eq!(frozen.len(), 1);
+        assert_eq!(frozen[0].outpoint, op_b);
+
+        assert!(store.remove_output_tag(op_b, "rgb", "frozen").await?);
+        assert!(!store.remove_output_tag(op_b, "rgb", "frozen").await?);
+        assert!(store.output_tags(op_b).await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+//! Persistence of changeset fields which the schema doesn't model.
+//!
+//! A newer `bdk_wallet` may add fields to its `ChangeSet` before this crate models them.
+//! Rather than silently dropping them, the changeset is round-tripped through serde and
+//! any field not listed in [`MODELED`] is kept as JSON in the `changeset_overflow` table,
+//! then merged back into the changeset when it is read.
+
+use std::sync::OnceLock;
+
+use bdk_wallet::ChangeSet;
+use serde_json::{Map, Value};
+use sqlx::{Row, sqlite::SqliteConnection};
+
+use crate::Error;
+use crate::Store;
+use crate::WriteSummary;
+
+/// The fields of the serialized changeset which the schema models, along with the
+/// modeled fields of each of them, if it is a struct.
+const MODELED: &[(&str, &[&str])] = &[
+    ("descriptor", &[]),
+    ("change_descriptor", &[]),
+    ("network", &[]),
+    ("local_chain", &["blocks"]),
+    (
+        "tx_graph",
+        &[
+            "txs",
+            "txouts",
+            "anchors",
+            "first_seen",
+            "last_seen",
+            "last_evicted",
+        ],
+    ),
+    ("indexer", &["last_revealed", "spk_cache"]),
+];
+
+impl Store {
+    /// Merge the fields of `changeset` which the schema doesn't model into the
+    /// `changeset_overflow` table.
+    pub(crate) async fn write_overflow_in(
+        &self,
+        conn: &mut SqliteConnection,
+        changeset: &ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = WriteSummary::default();
+        if all_modeled() {
+            return Ok(summary);
+        }
+        let Some(overflow) = unmodeled(serde_json::to_value(changeset)?) else {
+            return Ok(summary);
+        };
+
+        let row = sqlx::query("SELECT data FROM changeset_overflow WHERE id = 0")
+            .fetch_optional(&mut *conn)
+            .await?;
+        let data = match row {
+            Some(row) => {
+                let data: String = row.get("data");
+                let mut data: Value = serde_json::from_str(&data)?;
+                merge_json(&mut data, overflow);
+                summary.table_mut("changeset_overflow").updated += 1;
+                data
+            }
+            None => {
+                summary.table_mut("changeset_overflow").inserted += 1;
+                overflow
+            }
+        };
+        sqlx::query(
+            "INSERT INTO changeset_overflow(id, data) VALUES(0, $1) ON CONFLICT DO UPDATE SET data = $1",
+        )
+        .bind(data.to_string())
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(summary)
+    }
+
+    /// Merge the stored fields which the schema doesn't model into `changeset`.
+    pub(crate) async fn read_overflow(&self, changeset: ChangeSet) -> Result<ChangeSet, Error> {
+        let row = sqlx::query("SELECT data FROM changeset_overflow WHERE id = 0")
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(changeset);
+        };
+        let data: String = row.get("data");
+        let mut value = serde_json::to_value(changeset)?;
+        merge_json(&mut value, serde_json::from_str(&data)?);
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// `changeset` with the fields of `source` which the schema doesn't model merged in, e.g.
+/// so that they are kept by a chunk of `source`.
+pub(crate) fn with_unmodeled_of(
+    changeset: ChangeSet,
+    source: &ChangeSet,
+) -> Result<ChangeSet, Error> {
+    if all_modeled() {
+        return Ok(changeset);
+    }
+    let Some(overflow) = unmodeled(serde_json::to_value(source)?) else {
+        return Ok(changeset);
+    };
+    let mut value = serde_json::to_value(changeset)?;
+    merge_json(&mut value, overflow);
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Whether every field of the `ChangeSet` of the linked `bdk_wallet` is [`MODELED`], so
+/// that no changeset has fields to overflow and writes needn't serialize it.
+///
+/// Checked once on the keys of the serialized default changeset, which has all fields as
+/// none of them is skipped when serializing.
+fn all_modeled() -> bool {
+    static ALL_MODELED: OnceLock<bool> = OnceLock::new();
+    *ALL_MODELED.get_or_init(|| {
+        let Ok(Value::Object(fields)) = serde_json::to_value(ChangeSet::default()) else {
+            return false;
+        };
+        fields.iter().all(|(key, value)| {
+            let Some((_, modeled)) = MODELED.iter().find(|(k, _)| k == key) else {
+                return false;
+            };
+            match value {
+                Value::Object(sub_fields) if !modeled.is_empty() => {
+                    sub_fields.keys().all(|k| modeled.contains(&k.as_str()))
+                }
+                _ => true,
+            }
+        })
+    })
+}
+
+/// The fields of the serialized changeset `value` which aren't [`MODELED`], or `None` if
+/// there are none. Fields with a null or empty value are ignored.
+fn unmodeled(value: Value) -> Option<Value> {
+    let Value::Object(fields) = value else {
+        return None;
+    };
+
+    let mut overflow = Map::new();
+    for (key, value) in fields {
+        if is_empty(&value) {
+            continue;
+        }
+        match MODELED.iter().find(|(k, _)| *k == key) {
+            None => {
+                overflow.insert(key, value);
+            }
+            Some((_, modeled)) if !modeled.is_empty() => {
+                let Value::Object(sub_fields) = value else {
+                    continue;
+                };
+                let sub_overflow: Map<String, Value> = sub_fields
+                    .into_iter()
+                    .filter(|(k, v)| !modeled.contains(&k.as_str()) && !is_empty(v))
+                    .collect();
+                if !sub_overflow.is_empty() {
+                    overflow.insert(key, Value::Object(sub_overflow));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    (!overflow.is_empty()).then_some(Value::Object(overflow))
+}
+
+/// Whether `value` is null or an empty array or object, i.e. the value of a field which
+/// wasn't changed.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        _ => false,
+    }
+}
+
+/// Merge `src` into `dst`: objects are merged by key, arrays get the items they lack
+/// appended and any other value is replaced.
+fn merge_json(dst: &mut Value, src: Value) {
+    match (dst, src) {
+        (Value::Object(dst), Value::Object(src)) => {
+            for (key, value) in src {
+                match dst.get_mut(&key) {
+                    Some(dst_value) => merge_json(dst_value, value),
+                    None => {
+                        dst.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(dst), Value::Array(src)) => {
+            for item in src {
+                if !dst.contains(&item) {
+                    dst.push(item);
+                }
+            }
+        }
+        (dst, src) => {
+            if !src.is_null() {
+                *dst = src;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::{Network, hashes::Hash};
+    use serde_json::json;
+
+    #[test]
+    fn current_changeset_is_modeled() {
+        assert!(all_modeled());
+    }
+
+    #[test]
+    fn split_and_merge_unmodeled_fields() {
+        let mut changeset = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        changeset
+            .local_chain
+            .blocks
+            .insert(0, Some(Hash::hash(b"0")));
+        let value = serde_json::to_value(&changeset).unwrap();
+        assert_eq!(unmodeled(value.clone()), None);
+
+        // Fields added by a newer version at the top level and within a modeled struct.
+        let mut newer = value.clone();
+        newer["birthday"] = json!(800_000);
+        newer["tx_graph"]["first_evicted"] = json!({ "ab": 1 });
+        newer["indexer"]["empty"] = json!([]);
+        let overflow = unmodeled(newer.clone()).unwrap();
+        assert_eq!(
+            overflow,
+            json!({ "birthday": 800_000, "tx_graph": { "first_evicted": { "ab": 1 } } })
+        );
+
+        let mut merged = value;
+        merge_json(&mut merged, overflow);
+        newer["indexer"]
+            .as_object_mut()
+            .unwrap()
+            .remove("empty")
+            .unwrap();
+        assert_eq!(merged, newer);
+
+        let mut a = json!({ "set": [1, 2], "map": { "a": 1 }, "n": 1 });
+        merge_json(
+            &mut a,
+            json!({ "set": [2, 3], "map": { "b": 2 }, "n": 2, "m": null }),
+        );
+        assert_eq!(
+            a,
+            json!({ "set": [1, 2, 3], "map": { "a": 1, "b": 2 }, "n": 2, "m": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn write_and_read_overflow() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let changeset = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        let summary = store.write_changeset(&changeset).await?;
+        assert_eq!(summary.table("changeset_overflow").inserted, 0);
+
+        // Fields this version doesn't know are kept, but dropped when deserialized.
+        sqlx::query("INSERT INTO changeset_overflow(id, data) VALUES(0, $1)")
+            .bind(json!({ "birthday": 800_000 }).to_string())
+            .execute(&store.pool)
+            .await?;
+        assert_eq!(store.read_changeset().await?, changeset);
+
+        Ok(())
+    }
+}
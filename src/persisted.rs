@@ -0,0 +1,136 @@
+//! Timestamps of the last write of each component of the wallet data.
+
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+use crate::convert::{from_sql, to_sql};
+
+/// A component of the wallet data, see [`LastPersisted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Component {
+    /// Blocks of the local chain
+    Chain,
+    /// Transactions, txouts, anchors and seen times of the tx graph
+    Graph,
+    /// Revealed indices and script pubkeys of the keychains
+    Indexer,
+    /// Keychain descriptors
+    Descriptors,
+}
+
+impl Component {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Chain => "chain",
+            Self::Graph => "graph",
+            Self::Indexer => "indexer",
+            Self::Descriptors => "descriptors",
+        }
+    }
+}
+
+/// Unix time of the last write of each [`Component`], see [`Store::last_persisted`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LastPersisted {
+    /// Last write of the local chain
+    pub chain: Option<u64>,
+    /// Last write of the tx graph
+    pub graph: Option<u64>,
+    /// Last write of the keychain indexer
+    pub indexer: Option<u64>,
+    /// Last write of the descriptors
+    pub descriptors: Option<u64>,
+}
+
+impl LastPersisted {
+    /// Last write of `component`.
+    pub fn get(&self, component: Component) -> Option<u64> {
+        match component {
+            Component::Chain => self.chain,
+            Component::Graph => self.graph,
+            Component::Indexer => self.indexer,
+            Component::Descriptors => self.descriptors,
+        }
+    }
+}
+
+impl Store {
+    /// When each component was last written, according to the store's
+    /// [`Clock`](crate::Clock).
+    ///
+    /// A component counts as written by any committed write with data for it, even if the
+    /// data was already stored, so that e.g. a dashboard can alert when a wallet hasn't
+    /// persisted the result of a sync for some time.
+    pub async fn last_persisted(&self) -> Result<LastPersisted, Error> {
+        let rows = sqlx::query("SELECT component, persisted_at FROM persisted")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut last = LastPersisted::default();
+        for row in rows {
+            let persisted_at: i64 = row.get("persisted_at");
+            let persisted_at = Some(from_sql("persisted.persisted_at", persisted_at)?);
+            match row.get::<String, _>("component").as_str() {
+                "chain" => last.chain = persisted_at,
+                "graph" => last.graph = persisted_at,
+                "indexer" => last.indexer = persisted_at,
+                "descriptors" => last.descriptors = persisted_at,
+                _ => debug_assert!(false, "component is constrained by the schema"),
+            }
+        }
+
+        Ok(last)
+    }
+
+    /// Record that `component` is written at the current time.
+    pub(crate) async fn touch_persisted(
+        &self,
+        conn: &mut SqliteConnection,
+        component: Component,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO persisted(component, persisted_at) VALUES($1, $2) ON CONFLICT DO UPDATE SET persisted_at = $2",
+        )
+        .bind(component.as_str())
+        .bind(to_sql("persisted.persisted_at", self.now())?)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn last_persisted() -> anyhow::Result<()> {
+        use bdk_chain::bitcoin::hashes::Hash;
+        use bdk_chain::local_chain;
+
+        let store = Store::new_memory()
+            .await?
+            .with_clock(crate::FixedClock(100));
+        store.migrate().await?;
+        assert_eq!(store.last_persisted().await?, LastPersisted::default());
+
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(0, Some(Hash::hash(b"0")));
+        store.write_local_chain(&chain).await?;
+        // Rewriting known blocks counts as persisting the chain.
+        let store = store.with_clock(crate::FixedClock(200));
+        store.write_local_chain(&chain).await?;
+        store
+            .write_local_chain(&local_chain::ChangeSet::default())
+            .await?;
+
+        let last = store.last_persisted().await?;
+        assert_eq!(last.get(Component::Chain), Some(200));
+        assert_eq!(last.graph, None);
+
+        Ok(())
+    }
+}
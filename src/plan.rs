@@ -0,0 +1,242 @@
+//! Dry runs of wallet changeset writes.
+
+use std::ffi::{CStr, c_char, c_int, c_uint, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bdk_wallet::ChangeSet;
+use libsqlite3_sys as ffi;
+use sqlx::Connection;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteSummary;
+use crate::async_store::check_network;
+
+/// A statement of a [`WritePlan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedStatement {
+    /// SQL with the parameter placeholders, as passed to SQLite
+    pub sql: String,
+    /// Number of times the statement is executed
+    pub executions: u64,
+}
+
+/// What a write would do, see [`Store::plan_changeset`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WritePlan {
+    /// Distinct statements in the order of their first execution
+    pub statements: Vec<PlannedStatement>,
+    /// Rows the write would change, per table
+    pub summary: WriteSummary,
+}
+
+impl Store {
+    /// Plan the write of `changeset` without changing the store.
+    ///
+    /// The write is run in a transaction which is rolled back, recording the parameterized
+    /// statements it executes and the rows it changes, so that the effect of a changeset on
+    /// a production database can be reviewed before it is written. Retention, changeset
+    /// deduplication and replication are not part of the plan.
+    pub async fn plan_changeset(&self, changeset: &ChangeSet) -> Result<WritePlan, Error> {
+        self.timed(self.timeout, async {
+            let _turn = self.write_turn().await;
+            let mut conn = self.pool.acquire().await?;
+            let mut tx = conn.begin().await?;
+            if let Some(network) = self.network {
+                check_network(&mut tx, network, self.lenient_network).await?;
+            }
+
+            let trace = Trace::install(&mut tx).await?;
+            let res = self.write_changeset_in(&mut tx, changeset).await;
+            let statements = trace.uninstall(&mut tx).await?;
+            tx.rollback().await?;
+
+            Ok(WritePlan {
+                statements,
+                summary: res?,
+            })
+        })
+        .await
+    }
+}
+
+/// State of the trace callback.
+#[derive(Default)]
+struct TraceState {
+    /// Whether to record statements. Cleared when the [`Trace`] is dropped, e.g. because
+    /// the plan was cancelled before the callback was uninstalled.
+    active: AtomicBool,
+    statements: Mutex<Vec<PlannedStatement>>,
+}
+
+/// A trace callback recording the statements executed on a connection.
+struct Trace(Arc<TraceState>);
+
+impl Trace {
+    async fn install(conn: &mut SqliteConnection) -> Result<Self, Error> {
+        let state = Arc::new(TraceState::default());
+        state.active.store(true, Ordering::SeqCst);
+        let mut handle = conn.lock_handle().await?;
+        let db = handle.as_raw_handle().as_ptr();
+        // The callback holds a reference which is only released on uninstall. If the trace
+        // is never uninstalled, it leaks so that the callback never dangles.
+        let ctx = Arc::into_raw(Arc::clone(&state))
+            .cast_mut()
+            .cast::<c_void>();
+        // SAFETY: `db` is a valid connection handle which is locked for the duration of the
+        // call, and `ctx` points to a `TraceState` which outlives the callback.
+        let rc = unsafe {
+            ffi::sqlite3_trace_v2(db, ffi::SQLITE_TRACE_STMT as c_uint, Some(trace_stmt), ctx)
+        };
+        if rc != ffi::SQLITE_OK {
+            // SAFETY: the callback wasn't installed, so this is the only use of `ctx`.
+            drop(unsafe { Arc::from_raw(ctx.cast::<TraceState>()) });
+            return Err(Error::Sqlx(sqlx::Error::Protocol(format!(
+                "failed to install trace callback: error code {rc}"
+            ))));
+        }
+
+        Ok(Self(state))
+    }
+
+    async fn uninstall(self, conn: &mut SqliteConnection) -> Result<Vec<PlannedStatement>, Error> {
+        let mut handle = conn.lock_handle().await?;
+        let db = handle.as_raw_handle().as_ptr();
+        // SAFETY: `db` is a valid connection handle which is locked for the duration of the
+        // call.
+        let rc = unsafe { ffi::sqlite3_trace_v2(db, 0, None, std::ptr::null_mut()) };
+        if rc == ffi::SQLITE_OK {
+            // SAFETY: the callback is uninstalled, releasing its reference of `install`.
+            drop(unsafe { Arc::from_raw(Arc::as_ptr(&self.0)) });
+        }
+        let statements = std::mem::take(&mut *self.0.statements.lock().expect("not poisoned"));
+
+        Ok(statements)
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        self.0.active.store(false, Ordering::SeqCst);
+    }
+}
+
+/// `xCallback` of `sqlite3_trace_v2` for `SQLITE_TRACE_STMT` events, where `x` is the
+/// unexpanded SQL of the statement.
+unsafe extern "C" fn trace_stmt(
+    _: c_uint,
+    ctx: *mut c_void,
+    _: *mut c_void,
+    x: *mut c_void,
+) -> c_int {
+    // SAFETY: `ctx` is the `TraceState` passed on install, kept alive by the callback's
+    // reference, and `x` is a nul-terminated string for `SQLITE_TRACE_STMT` events.
+    let (state, sql) = unsafe {
+        (
+            &*ctx.cast::<TraceState>(),
+            CStr::from_ptr(x.cast::<c_char>()),
+        )
+    };
+    if !state.active.load(Ordering::SeqCst) {
+        return 0;
+    }
+    let sql = sql.to_string_lossy();
+    // Statements run by triggers are reported as comments.
+    if sql.starts_with("--") {
+        return 0;
+    }
+    let Ok(mut statements) = state.statements.lock() else {
+        return 0;
+    };
+    match statements.iter_mut().find(|s| s.sql == sql) {
+        Some(statement) => statement.executions += 1,
+        None => statements.push(PlannedStatement {
+            sql: sql.into_owned(),
+            executions: 1,
+        }),
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::ScriptBuf;
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::{DescriptorId, keychain_txout, local_chain};
+
+    #[tokio::test]
+    async fn plan_changeset() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let changeset = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(Hash::hash(b"0"))), (1, Some(Hash::hash(b"1")))].into(),
+            },
+            ..Default::default()
+        };
+        let plan = store.plan_changeset(&changeset).await?;
+        assert_eq!(plan.summary.table("block").inserted, 2);
+        let insert = plan
+            .statements
+            .iter()
+            .find(|s| s.sql.starts_with("INSERT OR IGNORE INTO block"))
+            .unwrap();
+        assert_eq!(insert.executions, 2);
+        assert!(insert.sql.contains("$1"));
+
+        // Nothing was written, and the connection is no longer traced.
+        assert!(store.read_local_chain().await?.blocks.is_empty());
+        let summary = store.write_changeset(&changeset).await?;
+        assert_eq!(summary, plan.summary);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stored_spks_are_not_reinserted() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let descriptor_id = DescriptorId::from_byte_array([1; 32]);
+        let spk_cache = |range: core::ops::Range<u32>| {
+            let mut indexer = keychain_txout::ChangeSet::default();
+            indexer.spk_cache.insert(
+                descriptor_id,
+                range
+                    .map(|i| (i, ScriptBuf::from_bytes(i.to_le_bytes().to_vec())))
+                    .collect(),
+            );
+            ChangeSet {
+                indexer,
+                ..Default::default()
+            }
+        };
+        store.write_changeset(&spk_cache(0..10)).await?;
+
+        // Only the indices which aren't stored yet are inserted, by a single statement.
+        let plan = store.plan_changeset(&spk_cache(0..12)).await?;
+        let insert = plan
+            .statements
+            .iter()
+            .find(|s| {
+                s.sql
+                    .starts_with("INSERT OR IGNORE INTO keychain_script_pubkey")
+            })
+            .unwrap();
+        assert_eq!(insert.executions, 1);
+        assert_eq!(plan.summary.table("keychain_script_pubkey").inserted, 2);
+        let plan = store.plan_changeset(&spk_cache(0..10)).await?;
+        assert!(!plan.statements.iter().any(|s| {
+            s.sql
+                .starts_with("INSERT OR IGNORE INTO keychain_script_pubkey")
+        }));
+
+        Ok(())
+    }
+}
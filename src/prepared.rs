@@ -0,0 +1,218 @@
+//! Two-phase persistence of changesets.
+
+use bdk_chain::bitcoin::hashes::{Hash, sha256};
+use bdk_wallet::ChangeSet;
+use sqlx::{Sqlite, Transaction, sqlite::SqliteConnection};
+
+use crate::Error;
+use crate::Store;
+use crate::WriteSummary;
+use crate::async_store::check_network;
+use crate::replication::next_sequence;
+use crate::validate::validate_in;
+use crate::write_queue::WriteGuard;
+
+/// A changeset written to an open transaction, see [`Store::prepare_changeset`].
+///
+/// Dropping it without calling [`commit`](Self::commit) rolls back the transaction.
+#[derive(Debug)]
+pub struct PreparedWrite<'a> {
+    store: &'a Store,
+    tx: Transaction<'static, Sqlite>,
+    summary: WriteSummary,
+    /// Serialized changeset, if it is to be replicated.
+    replicate: Option<Vec<u8>>,
+    /// Turn of the write, see [`Store::with_fair_writes`].
+    _turn: Option<WriteGuard>,
+}
+
+impl Store {
+    /// Write `changeset` to a new transaction without committing it.
+    ///
+    /// Callers can execute their own statements on [`PreparedWrite::conn`] so that they
+    /// commit or roll back together with the changeset. The transaction holds the database's
+    /// write lock until it completes, so it should be committed promptly.
+    ///
+    /// Like any other write, the stored network is first checked, see
+    /// [`with_network`](Store::with_network), and the changeset is validated if
+    /// [`with_validation`](Store::with_validation) is set. The store's default timeout
+    /// applies to preparing the write only and its default durability does not apply.
+    pub async fn prepare_changeset(
+        &self,
+        changeset: &ChangeSet,
+    ) -> Result<PreparedWrite<'_>, Error> {
+        self.timed(self.timeout, async {
+            let turn = self.write_turn().await;
+            let mut tx = self.pool.begin().await?;
+            if let Some(network) = self.network {
+                check_network(&mut tx, network, self.lenient_network).await?;
+            }
+            if self.validate {
+                let violations = validate_in(&mut tx, changeset).await?;
+                if !violations.is_empty() {
+                    return Err(Error::InvalidChangeset(violations));
+                }
+            }
+            // The store no longer matches the last changeset written to it, but this one if
+            // it commits.
+            match self.changeset_dedup {
+                true => {
+                    let hash = sha256::Hash::hash(&serde_json::to_vec(changeset)?).to_string();
+                    sqlx::query(
+                        "INSERT INTO changeset_hash(id, hash) VALUES(0, $1) ON CONFLICT DO UPDATE SET hash = $1",
+                    )
+                    .bind(hash)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                false => {
+                    sqlx::query("DELETE FROM changeset_hash")
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            let summary = self.write_changeset_in(&mut tx, changeset).await?;
+            let replicate = match self.replication {
+                Some(_) if !summary.is_empty() => Some(serde_json::to_vec(changeset)?),
+                _ => None,
+            };
+            Ok(PreparedWrite {
+                store: self,
+                tx,
+                summary,
+                replicate,
+                _turn: turn,
+            })
+        })
+        .await
+    }
+}
+
+impl PreparedWrite<'_> {
+    /// The connection of the open transaction.
+    pub fn conn(&mut self) -> &mut SqliteConnection {
+        &mut self.tx
+    }
+
+    /// The changes made by the changeset.
+    pub fn summary(&self) -> &WriteSummary {
+        &self.summary
+    }
+
+    /// Apply the store's retention policy, if any, and commit the transaction, then pass the
+    /// changeset to the [`ReplicationSink`](crate::ReplicationSink) if one is set.
+    pub async fn commit(mut self) -> Result<WriteSummary, Error> {
+        let sequence = match self.replicate {
+            Some(_) => Some(next_sequence(&mut self.tx).await?),
+            None => None,
+        };
+        if let Some(policy) = &self.store.retention {
+            policy.apply(&mut self.tx, self.store.now()).await?;
+        }
+        self.tx.commit().await?;
+
+        if let (Some(sink), Some(sequence), Some(data)) =
+            (&self.store.replication, sequence, &self.replicate)
+        {
+            sink.replicate(sequence, data).await?;
+        }
+
+        Ok(self.summary)
+    }
+
+    /// Roll back the transaction.
+    pub async fn rollback(self) -> Result<(), Error> {
+        self.tx.rollback().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use sqlx::Row;
+
+    async fn orders(store: &Store) -> anyhow::Result<i64> {
+        let row = sqlx::query("SELECT COUNT(*) FROM orders")
+            .fetch_one(&store.pool)
+            .await?;
+        Ok(row.get(0))
+    }
+
+    #[tokio::test]
+    async fn prepare_and_commit_changeset() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        sqlx::query("CREATE TABLE orders(id INTEGER PRIMARY KEY)")
+            .execute(&store.pool)
+            .await?;
+
+        let mut changeset = ChangeSet::default();
+        changeset
+            .local_chain
+            .blocks
+            .insert(0, Some(Hash::hash(b"0")));
+
+        let mut prepared = store.prepare_changeset(&changeset).await?;
+        sqlx::query("INSERT INTO orders(id) VALUES(1)")
+            .execute(prepared.conn())
+            .await?;
+        prepared.rollback().await?;
+        assert_eq!(orders(&store).await?, 0);
+        assert!(store.read_changeset().await?.local_chain.blocks.is_empty());
+
+        let mut prepared = store.prepare_changeset(&changeset).await?;
+        sqlx::query("INSERT INTO orders(id) VALUES(1)")
+            .execute(prepared.conn())
+            .await?;
+        let summary = prepared.commit().await?;
+        assert_eq!(summary.table("block").inserted, 1);
+        assert_eq!(orders(&store).await?, 1);
+        assert_eq!(
+            store.read_changeset().await?.local_chain,
+            changeset.local_chain
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prepare_updates_changeset_hash() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_changeset_dedup(true);
+        store.migrate().await?;
+
+        let mut block = ChangeSet::default();
+        block.local_chain.blocks.insert(1, Some(Hash::hash(b"1")));
+        let mut removal = ChangeSet::default();
+        removal.local_chain.blocks.insert(1, None);
+
+        store.write_changeset(&block).await?;
+        store.prepare_changeset(&removal).await?.commit().await?;
+        // The block is written again, rather than skipped as the last changeset written.
+        assert_eq!(
+            store.write_changeset(&block).await?.table("block").inserted,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prepare_checks_network() -> anyhow::Result<()> {
+        use bdk_chain::bitcoin::Network;
+
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        store.write_network(Network::Regtest).await?;
+
+        let signet = store.clone().with_network(Network::Signet);
+        assert!(matches!(
+            signet.prepare_changeset(&ChangeSet::default()).await,
+            Err(Error::NetworkMismatch { .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Progress of loading big databases.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "wallet")]
+use sqlx::{Row, sqlite::SqlitePool as Pool};
+
+#[cfg(feature = "wallet")]
+use crate::Error;
+
+/// Rows loaded between two reports of a table.
+const REPORT_INTERVAL: u64 = 1_000;
+
+/// The tables read when loading a changeset, along with the query counting the rows read.
+#[cfg(feature = "wallet")]
+const TABLES: &[(&str, &str)] = &[
+    ("network", "SELECT COUNT(*) FROM network"),
+    ("keychain", "SELECT COUNT(*) FROM keychain"),
+    ("tx", "SELECT COUNT(*) FROM tx"),
+    ("txout", "SELECT COUNT(*) FROM txout"),
+    (
+        "anchor",
+        "SELECT COUNT(*) FROM anchor WHERE confirmation_time IS NOT NULL",
+    ),
+    ("block", "SELECT COUNT(*) FROM block"),
+    (
+        "keychain_last_revealed",
+        "SELECT COUNT(*) FROM keychain_last_revealed",
+    ),
+    (
+        "keychain_script_pubkey",
+        "SELECT COUNT(*) FROM keychain_script_pubkey",
+    ),
+];
+
+/// Progress of loading a changeset, see [`Store::read_changeset_with_progress`].
+///
+/// [`Store::read_changeset_with_progress`]: crate::Store::read_changeset_with_progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Table being loaded
+    pub table: &'static str,
+    /// Rows of `table` loaded so far
+    pub table_loaded: u64,
+    /// Rows of `table` to load
+    pub table_total: u64,
+    /// Rows of all tables loaded so far
+    pub loaded: u64,
+    /// Rows of all tables to load
+    pub total: u64,
+}
+
+impl LoadProgress {
+    /// Percentage of the rows of all tables loaded so far, 100 if there are none.
+    pub fn percent(&self) -> f64 {
+        match self.total {
+            0 => 100.0,
+            total => self.loaded as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+/// Reports the rows read to a progress callback, if any.
+pub(crate) struct Progress<'a> {
+    callback: Option<&'a mut (dyn FnMut(LoadProgress) + Send)>,
+    tables: BTreeMap<&'static str, u64>,
+    loaded: u64,
+    total: u64,
+    table: &'static str,
+    table_loaded: u64,
+}
+
+impl<'a> Progress<'a> {
+    /// A [`Progress`] without a callback.
+    pub(crate) fn none() -> Self {
+        Self {
+            callback: None,
+            tables: BTreeMap::new(),
+            loaded: 0,
+            total: 0,
+            table: "",
+            table_loaded: 0,
+        }
+    }
+
+    /// A [`Progress`] reporting to `callback`, counting the rows to load up front.
+    #[cfg(feature = "wallet")]
+    pub(crate) async fn count(
+        pool: &Pool,
+        callback: &'a mut (dyn FnMut(LoadProgress) + Send),
+    ) -> Result<Self, Error> {
+        let mut progress = Self::none();
+        for &(table, query) in TABLES {
+            let row = sqlx::query(query).fetch_one(pool).await?;
+            let count: i64 = row.get(0);
+            let count = count as u64;
+            progress.tables.insert(table, count);
+            progress.total += count;
+        }
+        progress.callback = Some(callback);
+
+        Ok(progress)
+    }
+
+    /// Record that a row of `table` was read, reporting every [`REPORT_INTERVAL`] rows and
+    /// once the table is loaded.
+    pub(crate) fn row(&mut self, table: &'static str) {
+        let Some(callback) = self.callback.as_mut() else {
+            return;
+        };
+        if self.table != table {
+            self.table = table;
+            self.table_loaded = 0;
+        }
+        self.table_loaded += 1;
+        self.loaded += 1;
+        // Rows written since counting are reported as they come, beyond the total.
+        let table_total = self.tables.get(table).copied().unwrap_or_default();
+        let table_total = table_total.max(self.table_loaded);
+        self.total = self.total.max(self.loaded);
+        if self.table_loaded % REPORT_INTERVAL == 0 || self.table_loaded == table_total {
+            callback(LoadProgress {
+                table,
+                table_loaded: self.table_loaded,
+                table_total,
+                loaded: self.loaded,
+                total: self.total,
+            });
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Provenance of transaction data.
+
+use bdk_chain::bitcoin::{OutPoint, Txid};
+use bdk_chain::{BlockId, ConfirmationBlockTime, tx_graph};
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+
+/// Source of the data of a write, see [`WriteOptions::source`](crate::WriteOptions::source).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DataSource {
+    /// Esplora HTTP API.
+    Esplora,
+    /// Electrum server.
+    Electrum,
+    /// Bitcoin Core RPC.
+    BitcoindRpc,
+    /// Compact block filters.
+    Cbf,
+    /// Entered by the user or imported.
+    Manual,
+}
+
+impl DataSource {
+    /// The value of the `source` columns.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Esplora => "esplora",
+            Self::Electrum => "electrum",
+            Self::BitcoindRpc => "bitcoind_rpc",
+            Self::Cbf => "cbf",
+            Self::Manual => "manual",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "esplora" => Some(Self::Esplora),
+            "electrum" => Some(Self::Electrum),
+            "bitcoind_rpc" => Some(Self::BitcoindRpc),
+            "cbf" => Some(Self::Cbf),
+            "manual" => Some(Self::Manual),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the value of a `source` column.
+fn parse_source(source: Option<String>) -> Option<DataSource> {
+    let source = source?;
+    let parsed = DataSource::from_str(&source);
+    debug_assert!(parsed.is_some(), "unknown data source: {source}");
+    parsed
+}
+
+impl Store {
+    /// Read the source of the last write which provided `txid`, if it said so.
+    pub async fn tx_source(&self, txid: Txid) -> Result<Option<DataSource>, Error> {
+        let row = sqlx::query("SELECT source FROM tx WHERE txid = $1")
+            .bind(txid.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.and_then(|row| parse_source(row.get("source"))))
+    }
+
+    /// Read the source of the last write which provided the txout at `outpoint`, if it said
+    /// so.
+    pub async fn txout_source(&self, outpoint: OutPoint) -> Result<Option<DataSource>, Error> {
+        let row = sqlx::query("SELECT source FROM txout WHERE txid = $1 AND vout = $2")
+            .bind(outpoint.txid.to_string())
+            .bind(outpoint.vout)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.and_then(|row| parse_source(row.get("source"))))
+    }
+
+    /// Read the anchors of `txid` along with the source of the last write which provided
+    /// each, if it said so, ordered by height.
+    ///
+    /// Use this to debug backends which disagree on where a transaction confirmed.
+    pub async fn anchor_sources(
+        &self,
+        txid: Txid,
+    ) -> Result<Vec<(BlockId, Option<DataSource>)>, Error> {
+        let rows = sqlx::query(
+            "SELECT block_height, block_hash, source FROM anchor WHERE txid = $1 ORDER BY block_height, block_hash",
+        )
+        .bind(txid.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let hash: String = row.get("block_hash");
+                let block_id = BlockId {
+                    height: row.get("block_height"),
+                    hash: hash.parse()?,
+                };
+                Ok((block_id, parse_source(row.get("source"))))
+            })
+            .collect()
+    }
+}
+
+/// Record `source` as the provider of the transactions, txouts and anchors of `tx_graph`.
+///
+/// This doesn't count as a change of the rows in a [`WriteSummary`](crate::WriteSummary).
+pub(crate) async fn record_source_in(
+    conn: &mut SqliteConnection,
+    tx_graph: &tx_graph::ChangeSet<ConfirmationBlockTime>,
+    source: DataSource,
+) -> Result<(), Error> {
+    let source = source.as_str();
+    for tx in &tx_graph.txs {
+        sqlx::query("UPDATE tx SET source = $2 WHERE txid = $1")
+            .bind(tx.compute_txid().to_string())
+            .bind(source)
+            .execute(&mut *conn)
+            .await?;
+    }
+    for outpoint in tx_graph.txouts.keys() {
+        sqlx::query("UPDATE txout SET source = $3 WHERE txid = $1 AND vout = $2")
+            .bind(outpoint.txid.to_string())
+            .bind(outpoint.vout)
+            .bind(source)
+            .execute(&mut *conn)
+            .await?;
+    }
+    for (anchor, txid) in &tx_graph.anchors {
+        sqlx::query(
+            "UPDATE anchor SET source = $4 WHERE block_height = $1 AND block_hash = $2 AND txid = $3",
+        )
+        .bind(anchor.block_id.height)
+        .bind(anchor.block_id.hash.to_string())
+        .bind(txid.to_string())
+        .bind(source)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{Amount, ScriptBuf, TxOut};
+
+    use crate::WriteOptions;
+
+    #[tokio::test]
+    async fn provenance() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let txid = Hash::hash(b"tx");
+        let anchor = |data: &[u8]| ConfirmationBlockTime {
+            block_id: BlockId {
+                height: 1,
+                hash: Hash::hash(data),
+            },
+            confirmation_time: 100,
+        };
+        let outpoint = OutPoint::new(txid, 0);
+        let graph = |data: &[u8]| tx_graph::ChangeSet {
+            txouts: [(
+                outpoint,
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+            )]
+            .into(),
+            anchors: [(anchor(data), txid)].into(),
+            first_seen: [(txid, 100)].into(),
+            ..Default::default()
+        };
+
+        store
+            .write_tx_graph_with(
+                &graph(b"a"),
+                WriteOptions::default().source(DataSource::Esplora),
+            )
+            .await?;
+        store
+            .write_tx_graph_with(
+                &graph(b"b"),
+                WriteOptions::default().source(DataSource::Electrum),
+            )
+            .await?;
+        store.write_tx_graph(&graph(b"c")).await?;
+
+        assert_eq!(
+            store.txout_source(outpoint).await?,
+            Some(DataSource::Electrum)
+        );
+        let sources = store.anchor_sources(txid).await?;
+        assert_eq!(sources.len(), 3);
+        assert!(sources.contains(&(anchor(b"a").block_id, Some(DataSource::Esplora))));
+        assert!(sources.contains(&(anchor(b"b").block_id, Some(DataSource::Electrum))));
+        assert!(sources.contains(&(anchor(b"c").block_id, None)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! Minimal recovery kit of a wallet.
+
+use bdk_chain::bitcoin::Network;
+use bdk_chain::bitcoin::constants::genesis_block;
+use bdk_chain::local_chain;
+use bdk_chain::miniscript::{Descriptor, DescriptorPublicKey};
+use bdk_wallet::{ChangeSet, CreateParams, KeychainKind};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteSummary;
+
+/// All that is needed to recreate a wallet elsewhere, see [`Store::export_recovery_kit`].
+///
+/// Serializes to a compact JSON object, e.g.
+/// `{"descriptor":"wpkh(...)","network":"signet","birthday":210000}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryKit {
+    /// Public descriptor of the external keychain
+    pub descriptor: String,
+    /// Public descriptor of the internal keychain, if the wallet has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_descriptor: Option<String>,
+    /// Network
+    pub network: Network,
+    /// Height of the earliest block a transaction of the wallet is anchored in, from which a
+    /// scan of the recreated wallet can start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub birthday: Option<u32>,
+}
+
+impl RecoveryKit {
+    /// Serialize the kit as JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("serializing a recovery kit can't fail")
+    }
+
+    /// Deserialize a kit serialized by [`RecoveryKit::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Parameters to create the wallet of the kit, e.g. to create it in another store
+    /// with `CreateParams::create_wallet_async`.
+    ///
+    /// The birthday isn't part of the wallet and is to be passed to the chain source.
+    pub fn create_params(&self) -> CreateParams {
+        let params = match &self.change_descriptor {
+            Some(change_descriptor) => {
+                CreateParams::new(self.descriptor.clone(), change_descriptor.clone())
+            }
+            None => CreateParams::new_single(self.descriptor.clone()),
+        };
+        params.network(self.network)
+    }
+}
+
+impl Store {
+    /// Export the descriptors, network and birthday of the wallet, or `None` if the store
+    /// has no wallet.
+    ///
+    /// The birthday is the height of the earliest anchor, as the store doesn't know the
+    /// creation time of the wallet.
+    pub async fn export_recovery_kit(&self) -> Result<Option<RecoveryKit>, Error> {
+        let mut descriptors = self.read_keychain_descriptors().await?;
+        let (Some(descriptor), Some(network)) = (
+            descriptors.remove(&KeychainKind::External),
+            self.read_network().await?,
+        ) else {
+            return Ok(None);
+        };
+        let row = sqlx::query("SELECT MIN(block_height) AS birthday FROM anchor")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(Some(RecoveryKit {
+            descriptor: descriptor.to_string(),
+            change_descriptor: descriptors
+                .remove(&KeychainKind::Internal)
+                .map(|descriptor| descriptor.to_string()),
+            network,
+            birthday: row.get("birthday"),
+        }))
+    }
+
+    /// Write the descriptors and network of `kit` along with the genesis block to a new
+    /// store, so that the wallet can be loaded from it and then synced from the birthday.
+    ///
+    /// Fails with [`Error::NetworkMismatch`] if the store is for another network.
+    pub async fn import_recovery_kit(&self, kit: &RecoveryKit) -> Result<WriteSummary, Error> {
+        let descriptor: Descriptor<DescriptorPublicKey> = kit.descriptor.parse()?;
+        let change_descriptor = kit
+            .change_descriptor
+            .as_deref()
+            .map(str::parse::<Descriptor<DescriptorPublicKey>>)
+            .transpose()?;
+
+        self.write_changeset(&ChangeSet {
+            descriptor: Some(descriptor),
+            change_descriptor,
+            network: Some(kit.network),
+            local_chain: local_chain::ChangeSet {
+                blocks: [(0, Some(genesis_block(kit.network).block_hash()))].into(),
+            },
+            ..Default::default()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::{BlockId, ConfirmationBlockTime, tx_graph};
+    use bdk_wallet::Wallet;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+    const CHANGE_DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/1/*)";
+
+    #[tokio::test]
+    async fn recovery_kit() -> anyhow::Result<()> {
+        let mut store = Store::new_memory().await?;
+        store.migrate().await?;
+        assert_eq!(store.export_recovery_kit().await?, None);
+
+        let mut wallet = Wallet::create(DESCRIPTOR, CHANGE_DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut store)
+            .await?;
+        wallet.persist_async(&mut store).await?;
+        let anchor = |height| ConfirmationBlockTime {
+            block_id: BlockId {
+                height,
+                hash: Hash::hash(&height.to_le_bytes()),
+            },
+            confirmation_time: 0,
+        };
+        store
+            .write_tx_graph(&tx_graph::ChangeSet {
+                anchors: [
+                    (anchor(300), Hash::hash(b"a")),
+                    (anchor(200), Hash::hash(b"b")),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .await?;
+
+        let kit = store.export_recovery_kit().await?.unwrap();
+        assert_eq!(kit.network, Network::Signet);
+        assert_eq!(kit.birthday, Some(200));
+        let kit = RecoveryKit::from_json(&kit.to_json())?;
+
+        let mut restored = Store::new_memory().await?;
+        restored.migrate().await?;
+        restored.import_recovery_kit(&kit).await?;
+        let loaded = Wallet::load()
+            .load_wallet_async(&mut restored)
+            .await?
+            .expect("wallet must exist");
+        assert_eq!(
+            loaded.public_descriptor(KeychainKind::Internal),
+            wallet.public_descriptor(KeychainKind::Internal)
+        );
+
+        let mut created = Store::new_memory().await?;
+        created.migrate().await?;
+        let created = kit
+            .create_params()
+            .create_wallet_async(&mut created)
+            .await?;
+        assert_eq!(created.network(), Network::Signet);
+
+        Ok(())
+    }
+}
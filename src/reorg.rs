@@ -0,0 +1,226 @@
+//! Handling of reorgs of the local chain.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bdk_chain::bitcoin::{BlockHash, Txid};
+use bdk_chain::{BlockId, local_chain};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+
+/// Outcome of [`Store::handle_reorg`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReorgReport {
+    /// Blocks removed from the local chain, ordered by height
+    pub invalidated_blocks: Vec<BlockId>,
+    /// Transactions which were confirmed in an invalidated block and aren't confirmed in
+    /// the corrected chain, ordered by txid
+    pub affected_txids: Vec<Txid>,
+    /// Rows changed by the reorg
+    pub summary: WriteSummary,
+}
+
+impl Store {
+    /// Replace the stored blocks which conflict with the corrected chain segment
+    /// `new_blocks`, e.g. as reported by a chain source after a reorg.
+    ///
+    /// `new_blocks` is taken as the chain from its lowest height up to the tip, which may
+    /// be sparse. The stored blocks from that height up which aren't in `new_blocks` are
+    /// invalidated, as they can't be told to belong to the corrected chain, and the blocks of
+    /// `new_blocks` are inserted, in a single transaction. The anchors to the invalidated
+    /// blocks are kept, but no longer confirm their transactions, which are unconfirmed
+    /// unless anchored in the corrected chain. The transactions which lost their
+    /// confirmation are reported so that callers can e.g. rebroadcast them or notify the
+    /// user. See [`compact_anchors`](Self::compact_anchors) to delete the stale anchors.
+    ///
+    /// A segment which extends the stored tip is inserted as by
+    /// [`write_local_chain`](Self::write_local_chain).
+    pub async fn handle_reorg(
+        &self,
+        new_blocks: impl IntoIterator<Item = BlockId>,
+    ) -> Result<ReorgReport, Error> {
+        let new_blocks: BTreeMap<u32, BlockHash> = new_blocks
+            .into_iter()
+            .map(|block| (block.height, block.hash))
+            .collect();
+        let Some(&from) = new_blocks.keys().next() else {
+            return Ok(ReorgReport::default());
+        };
+
+        self.write(WriteOptions::default(), async |conn| {
+            let rows = sqlx::query("SELECT height, hash FROM block WHERE height >= $1 ORDER BY height")
+                .bind(from)
+                .fetch_all(&mut *conn)
+                .await?;
+            let mut stored = vec![];
+            for row in rows {
+                let hash: String = row.get("hash");
+                stored.push(BlockId {
+                    height: row.get("height"),
+                    hash: hash.parse()?,
+                });
+            }
+            let invalidated_blocks: Vec<BlockId> = stored
+                .into_iter()
+                .filter(|block| new_blocks.get(&block.height) != Some(&block.hash))
+                .collect();
+
+            let mut affected = BTreeSet::new();
+            for block in &invalidated_blocks {
+                let rows = sqlx::query(
+                    "SELECT txid FROM anchor WHERE block_height = $1 AND block_hash = $2",
+                )
+                .bind(block.height)
+                .bind(block.hash.to_string())
+                .fetch_all(&mut *conn)
+                .await?;
+                affected.extend(rows.into_iter().map(|row| row.get::<String, _>("txid")));
+            }
+
+            let removed = local_chain::ChangeSet {
+                blocks: invalidated_blocks
+                    .iter()
+                    .map(|block| (block.height, None))
+                    .collect(),
+            };
+            let inserted = local_chain::ChangeSet {
+                blocks: new_blocks
+                    .iter()
+                    .map(|(&height, &hash)| (height, Some(hash)))
+                    .collect(),
+            };
+            let mut summary = self.write_local_chain_in(conn, &removed).await?;
+            summary.merge(self.write_local_chain_in(conn, &inserted).await?);
+
+            let mut affected_txids = vec![];
+            for txid in affected {
+                let row = sqlx::query(
+                    "SELECT 1 FROM anchor AS a JOIN block AS b ON b.height = a.block_height AND b.hash = a.block_hash WHERE a.txid = $1",
+                )
+                .bind(&txid)
+                .fetch_optional(&mut *conn)
+                .await?;
+                if row.is_none() {
+                    affected_txids.push(txid.parse()?);
+                }
+            }
+
+            Ok(ReorgReport {
+                invalidated_blocks,
+                affected_txids,
+                summary,
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::{ConfirmationBlockTime, tx_graph};
+
+    #[tokio::test]
+    async fn handle_reorg() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let block = |height: u32, branch: &[u8]| BlockId {
+            height,
+            hash: Hash::hash(&[&height.to_le_bytes()[..], branch].concat()),
+        };
+        store
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: (0..=5)
+                    .map(|height| (height, Some(block(height, b"a").hash)))
+                    .collect(),
+            })
+            .await?;
+        let anchor = |block_id: BlockId, txid: Txid| {
+            (
+                ConfirmationBlockTime {
+                    block_id,
+                    confirmation_time: 100,
+                },
+                txid,
+            )
+        };
+        let stays: Txid = Hash::hash(b"stays");
+        let reconfirms: Txid = Hash::hash(b"reconfirms");
+        let unconfirmed: Txid = Hash::hash(b"unconfirmed");
+        store
+            .write_tx_graph(&tx_graph::ChangeSet {
+                anchors: [
+                    anchor(block(3, b"a"), stays),
+                    anchor(block(4, b"a"), reconfirms),
+                    anchor(block(5, b"b"), reconfirms),
+                    anchor(block(5, b"a"), unconfirmed),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .await?;
+
+        // Blocks 4 and 5 are replaced, and block 6 extends the new branch.
+        let report = store
+            .handle_reorg([
+                block(3, b"a"),
+                block(4, b"b"),
+                block(5, b"b"),
+                block(6, b"b"),
+            ])
+            .await?;
+        assert_eq!(report.invalidated_blocks, [block(4, b"a"), block(5, b"a")]);
+        assert_eq!(report.affected_txids, [unconfirmed]);
+        assert_eq!(report.summary.table("block").deleted, 2);
+        assert_eq!(report.summary.table("block").inserted, 3);
+        let chain = store.read_local_chain().await?;
+        assert_eq!(chain.blocks.len(), 7);
+        assert_eq!(chain.blocks[&4], Some(block(4, b"b").hash));
+        assert_eq!(store.read_tx_graph().await?.anchors.len(), 4);
+
+        // Handling the same segment again changes nothing.
+        let report = store
+            .handle_reorg([block(4, b"b"), block(5, b"b"), block(6, b"b")])
+            .await?;
+        assert!(report.invalidated_blocks.is_empty());
+        assert!(report.summary.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handle_sparse_reorg() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let block = |height: u32, branch: &[u8]| BlockId {
+            height,
+            hash: Hash::hash(&[&height.to_le_bytes()[..], branch].concat()),
+        };
+        store
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: [0, 5, 10]
+                    .into_iter()
+                    .map(|height| (height, Some(block(height, b"a").hash)))
+                    .collect(),
+            })
+            .await?;
+
+        // No stored block is at the height of a new one, yet block 10 may not build on them.
+        let report = store.handle_reorg([block(7, b"b"), block(8, b"b")]).await?;
+        assert_eq!(report.invalidated_blocks, [block(10, b"a")]);
+        let chain = store.read_local_chain().await?;
+        assert_eq!(
+            chain.blocks.keys().copied().collect::<Vec<_>>(),
+            [0, 5, 7, 8]
+        );
+
+        Ok(())
+    }
+}
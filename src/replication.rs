@@ -0,0 +1,147 @@
+//! Replication of committed changesets.
+
+use core::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use sqlx::{Row, sqlite::SqliteConnection};
+
+use crate::Error;
+use crate::convert::from_sql;
+use crate::rt;
+
+/// Future returned by [`ReplicationSink::replicate`].
+pub type ReplicateFuture<'a> = Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>>;
+
+/// Receiver of every changeset committed by [`Store::write_changeset`].
+///
+/// The changeset is passed serialized as JSON along with its sequence number, which starts
+/// at 1 and increases by one with every changeset which changed the store. The sink is
+/// called after the changeset is committed, so an error doesn't undo the write, but it is
+/// returned to the caller of `write_changeset`.
+///
+/// Implement this to e.g. encrypt and forward changesets to a server or a nostr relay.
+///
+/// [`Store::write_changeset`]: crate::Store::write_changeset
+pub trait ReplicationSink: fmt::Debug + Send + Sync {
+    /// Replicate the serialized `changeset` with sequence number `sequence`.
+    fn replicate<'a>(&'a self, sequence: u64, changeset: &'a [u8]) -> ReplicateFuture<'a>;
+}
+
+/// A [`ReplicationSink`] which discards every changeset.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopSink;
+
+impl ReplicationSink for NoopSink {
+    fn replicate<'a>(&'a self, _sequence: u64, _changeset: &'a [u8]) -> ReplicateFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A [`ReplicationSink`] which appends every changeset to a file.
+///
+/// Each changeset is written as one line holding the sequence number, a space and the
+/// JSON changeset. The file is created if missing and synced after every append.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    /// New [`FileSink`] appending to the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ReplicationSink for FileSink {
+    fn replicate<'a>(&'a self, sequence: u64, changeset: &'a [u8]) -> ReplicateFuture<'a> {
+        Box::pin(async move {
+            let mut line = format!("{sequence} ").into_bytes();
+            line.extend_from_slice(changeset);
+            line.push(b'\n');
+
+            rt::append(&self.path, &line).await
+        })
+    }
+}
+
+/// Increment the replication sequence number, returning the new value.
+pub(crate) async fn next_sequence(conn: &mut SqliteConnection) -> Result<u64, Error> {
+    let row = sqlx::query(
+        "INSERT INTO replication_sequence(id, sequence) VALUES(0, 1) ON CONFLICT DO UPDATE SET sequence = sequence + 1 RETURNING sequence",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let sequence: i64 = row.get("sequence");
+
+    from_sql("replication_sequence.sequence", sequence)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_wallet::ChangeSet;
+
+    use crate::Store;
+
+    #[derive(Debug, Default, Clone)]
+    struct TestSink(Arc<Mutex<Vec<(u64, ChangeSet)>>>);
+
+    impl ReplicationSink for TestSink {
+        fn replicate<'a>(&'a self, sequence: u64, changeset: &'a [u8]) -> ReplicateFuture<'a> {
+            Box::pin(async move {
+                let changeset = serde_json::from_slice(changeset)?;
+                self.0.lock().unwrap().push((sequence, changeset));
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn replicate_committed_changesets() -> anyhow::Result<()> {
+        let sink = TestSink::default();
+        let store = Store::new_memory().await?.with_replication(sink.clone());
+        store.migrate().await?;
+
+        let mut changeset = ChangeSet::default();
+        changeset
+            .local_chain
+            .blocks
+            .insert(0, Some(Hash::hash(b"0")));
+        store.write_changeset(&changeset).await?;
+        // Unchanged data is not replicated.
+        store.write_changeset(&changeset).await?;
+        changeset
+            .local_chain
+            .blocks
+            .insert(1, Some(Hash::hash(b"1")));
+        store.write_changeset(&changeset).await?;
+
+        let replicated = sink.0.lock().unwrap().clone();
+        assert_eq!(replicated.len(), 2);
+        assert_eq!(replicated[0].0, 1);
+        assert_eq!(replicated[1], (2, changeset));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_sink_appends_lines() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("replication.log");
+        let sink = FileSink::new(&path);
+        sink.replicate(1, b"{}").await?;
+        sink.replicate(2, b"{\"network\":\"signet\"}").await?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        assert_eq!(contents, "1 {}\n2 {\"network\":\"signet\"}\n");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Retention policy bounding the growth of the database.
+
+use std::time::Duration;
+
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::convert::to_sql;
+
+/// Data to discard after every write, see [`Store::with_retention`].
+///
+/// [`Store::with_retention`]: crate::Store::with_retention
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RetentionPolicy {
+    /// Discard anchors to blocks more than this many blocks below the tip, always keeping
+    /// the anchor to the highest block of each transaction.
+    pub anchor_depth: Option<u32>,
+    /// Discard unconfirmed transactions which were evicted from the mempool longer ago
+    /// than this.
+    pub evicted_max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Set the depth below the tip beyond which anchors are discarded.
+    pub fn anchor_depth(mut self, depth: u32) -> Self {
+        self.anchor_depth = Some(depth);
+        self
+    }
+
+    /// Set the age after which evicted transactions are discarded.
+    pub fn evicted_max_age(mut self, age: Duration) -> Self {
+        self.evicted_max_age = Some(age);
+        self
+    }
+
+    /// Apply the policy on `conn` at unix time `now`.
+    pub(crate) async fn apply(&self, conn: &mut SqliteConnection, now: u64) -> Result<(), Error> {
+        if let Some(depth) = self.anchor_depth {
+            sqlx::query(
+                "DELETE FROM anchor WHERE block_height + $1 < (SELECT MAX(height) FROM block) AND EXISTS(SELECT 1 FROM anchor AS a WHERE a.txid = anchor.txid AND a.block_height > anchor.block_height)",
+            )
+            .bind(depth)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        if let Some(age) = self.evicted_max_age {
+            let cutoff = to_sql("tx.last_evicted", now.saturating_sub(age.as_secs()))?;
+            let evicted = "SELECT txid FROM tx WHERE last_evicted < $1 AND last_evicted >= COALESCE(last_seen, 0) AND txid NOT IN (SELECT txid FROM anchor)";
+            sqlx::query(&format!("DELETE FROM tx_output WHERE txid IN ({evicted})"))
+                .bind(cutoff)
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(&format!("DELETE FROM txin WHERE txid IN ({evicted})"))
+                .bind(cutoff)
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(&format!("DELETE FROM tx WHERE txid IN ({evicted})"))
+                .bind(cutoff)
+                .execute(&mut *conn)
+                .await?;
+            delete_unreferenced_blobs(conn).await?;
+            sqlx::query("DELETE FROM tx_summary WHERE txid NOT IN (SELECT txid FROM tx_output UNION SELECT txid FROM txout)")
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Delete the rows of the `tx_blob` table which no `tx` row references.
+pub(crate) async fn delete_unreferenced_blobs(conn: &mut SqliteConnection) -> Result<u64, Error> {
+    let res = sqlx::query(
+        "DELETE FROM tx_blob WHERE id NOT IN (SELECT blob_id FROM tx WHERE blob_id IS NOT NULL)",
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::{Txid, hashes::Hash};
+    use bdk_chain::{BlockId, ConfirmationBlockTime, local_chain, tx_graph};
+
+    use crate::{FixedClock, Store};
+
+    #[tokio::test]
+    async fn retention_policy() -> anyhow::Result<()> {
+        let policy = RetentionPolicy::default()
+            .anchor_depth(10)
+            .evicted_max_age(Duration::from_secs(3600));
+        let now = 1_700_000_000;
+        let store = Store::new_memory()
+            .await?
+            .with_retention(policy)
+            .with_clock(FixedClock(now));
+        store.migrate().await?;
+
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(100, Some(Hash::hash(b"100")));
+        store.write_local_chain(&chain).await?;
+
+        let anchor = |height: u32| ConfirmationBlockTime {
+            block_id: BlockId {
+                height,
+                hash: Hash::hash(&height.to_le_bytes()),
+            },
+            confirmation_time: 100,
+        };
+        let reorged: Txid = Hash::hash(b"reorged");
+        let deep: Txid = Hash::hash(b"deep");
+        let evicted: Txid = Hash::hash(b"evicted");
+        let recent: Txid = Hash::hash(b"recent");
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        graph.anchors.insert((anchor(50), reorged));
+        graph.anchors.insert((anchor(51), reorged));
+        graph.anchors.insert((anchor(50), deep));
+        graph.last_seen.insert(evicted, now - 7200);
+        graph.last_evicted.insert(evicted, now - 7200);
+        graph.last_seen.insert(recent, now - 60);
+        graph.last_evicted.insert(recent, now - 60);
+        store.write_tx_graph(&graph).await?;
+
+        let graph = store.read_tx_graph().await?;
+        assert_eq!(
+            graph.anchors.into_iter().collect::<Vec<_>>(),
+            vec![(anchor(50), deep), (anchor(51), reorged)]
+        );
+        assert!(!graph.last_evicted.contains_key(&evicted));
+        assert!(graph.last_evicted.contains_key(&recent));
+
+        Ok(())
+    }
+}
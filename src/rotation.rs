@@ -0,0 +1,162 @@
+//! Descriptor rotation.
+
+use std::str::FromStr;
+
+use bdk_chain::{DescriptorExt, DescriptorId, miniscript};
+use bdk_wallet::KeychainKind;
+use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, from_sql_opt, to_sql};
+use crate::wallet::{keychain_from_int, keychain_to_int};
+
+/// A keychain descriptor which was replaced by [`Store::rotate_descriptors`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetiredDescriptor {
+    /// Keychain
+    pub keychain: KeychainKind,
+    /// Descriptor
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    /// Descriptor id
+    pub descriptor_id: DescriptorId,
+    /// Unix time at which the descriptor became active, if known
+    pub activated_at: Option<u64>,
+    /// Unix time at which the descriptor was retired
+    pub retired_at: u64,
+}
+
+impl Store {
+    /// Replace the keychain descriptors, e.g. when upgrading a wallet from `wpkh` to `tr`.
+    ///
+    /// The current descriptors are archived to the `keychain_history` table. Cached scripts
+    /// and last revealed indexes are keyed by descriptor id and remain attributed to the
+    /// retired descriptors. If `new_internal` is `None` the internal keychain is retired
+    /// without replacement.
+    pub async fn rotate_descriptors(
+        &self,
+        new_external: &Descriptor<DescriptorPublicKey>,
+        new_internal: Option<&Descriptor<DescriptorPublicKey>>,
+    ) -> Result<(), Error> {
+        let now = to_sql("keychain_history.retired_at", self.now())?;
+        self.write(WriteOptions::default(), async |conn| {
+            let rows = sqlx::query(
+                "SELECT keychain, descriptor, activated_at FROM keychain ORDER BY keychain",
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            for row in rows {
+                let descriptor: String = row.get("descriptor");
+                let descriptor_id =
+                    Descriptor::<DescriptorPublicKey>::from_str(&descriptor)?.descriptor_id();
+                sqlx::query("INSERT INTO keychain_history(keychain, descriptor, descriptor_id, activated_at, retired_at) VALUES($1, $2, $3, $4, $5)")
+                    .bind(row.get::<u8, _>("keychain"))
+                    .bind(descriptor)
+                    .bind(descriptor_id.to_string())
+                    .bind(row.get::<Option<i64>, _>("activated_at"))
+                    .bind(now)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            sqlx::query("DELETE FROM keychain")
+                .execute(&mut *conn)
+                .await?;
+
+            let new = [
+                (KeychainKind::External, Some(new_external)),
+                (KeychainKind::Internal, new_internal),
+            ];
+            for (keychain, descriptor) in new {
+                let Some(descriptor) = descriptor else {
+                    continue;
+                };
+                sqlx::query(
+                    "INSERT INTO keychain(keychain, descriptor, activated_at) VALUES($1, $2, $3)",
+                )
+                .bind(keychain_to_int(keychain))
+                .bind(descriptor.to_string())
+                .bind(now)
+                .execute(&mut *conn)
+                .await?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read the descriptors retired by [`rotate_descriptors`](Self::rotate_descriptors),
+    /// oldest first.
+    pub async fn read_keychain_history(&self) -> Result<Vec<RetiredDescriptor>, Error> {
+        let rows = sqlx::query(
+            "SELECT keychain, descriptor, descriptor_id, activated_at, retired_at FROM keychain_history ORDER BY retired_at, rowid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut history = vec![];
+        for row in rows {
+            let Some(keychain) = keychain_from_int(row.get("keychain")) else {
+                continue;
+            };
+            let descriptor: String = row.get("descriptor");
+            let descriptor_id: String = row.get("descriptor_id");
+            let activated_at: Option<i64> = row.get("activated_at");
+            let retired_at: i64 = row.get("retired_at");
+            history.push(RetiredDescriptor {
+                keychain,
+                descriptor: Descriptor::from_str(&descriptor)?,
+                descriptor_id: descriptor_id.parse()?,
+                activated_at: from_sql_opt("keychain_history.activated_at", activated_at)?,
+                retired_at: from_sql("keychain_history.retired_at", retired_at)?,
+            });
+        }
+
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    const EXTERNAL_DESC: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+    const INTERNAL_DESC: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/1/*)";
+    const TR_DESC: &str = "tr([e273fe42/86'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+
+    #[tokio::test]
+    async fn rotate_descriptors_archives_old() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let external: Descriptor<DescriptorPublicKey> = EXTERNAL_DESC.parse()?;
+        let internal: Descriptor<DescriptorPublicKey> = INTERNAL_DESC.parse()?;
+        let tr: Descriptor<DescriptorPublicKey> = TR_DESC.parse()?;
+        store
+            .write_keychain_descriptors(BTreeMap::from([
+                (KeychainKind::External, external.clone()),
+                (KeychainKind::Internal, internal.clone()),
+            ]))
+            .await?;
+
+        store.rotate_descriptors(&tr, None).await?;
+
+        let descriptors = store.read_keychain_descriptors().await?;
+        assert_eq!(descriptors, BTreeMap::from([(KeychainKind::External, tr)]));
+
+        let history = store.read_keychain_history().await?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].keychain, KeychainKind::External);
+        assert_eq!(history[0].descriptor, external);
+        assert_eq!(history[0].descriptor_id, external.descriptor_id());
+        assert_eq!(history[0].activated_at, None);
+        assert_eq!(history[1].keychain, KeychainKind::Internal);
+        assert_eq!(history[1].descriptor, internal);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! Typed rows of individual tables.
+
+use bdk_chain::{BlockId, DescriptorId, bitcoin};
+use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, Txid};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::convert::{from_sql, from_sql_opt};
+
+/// A row of the `anchor` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnchorRow {
+    /// Txid of the anchored transaction
+    pub txid: Txid,
+    /// Block the transaction is anchored to
+    pub block_id: BlockId,
+    /// Confirmation time, if known
+    pub confirmation_time: Option<u64>,
+}
+
+/// A row of the `txout` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxOutRow {
+    /// Outpoint
+    pub outpoint: OutPoint,
+    /// Value
+    pub value: Amount,
+    /// Script pubkey
+    pub script_pubkey: ScriptBuf,
+}
+
+/// A row of the `keychain_script_pubkey` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScriptPubkeyRow {
+    /// Id of the descriptor deriving the script pubkey
+    pub descriptor_id: DescriptorId,
+    /// Derivation index
+    pub derivation_index: u32,
+    /// Script pubkey
+    pub script_pubkey: ScriptBuf,
+}
+
+impl Store {
+    /// Read all anchors, ordered by txid and block.
+    pub async fn all_anchors(&self) -> Result<Vec<AnchorRow>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid, block_height, block_hash, confirmation_time FROM anchor ORDER BY txid, block_height, block_hash",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut anchors = vec![];
+        for row in rows {
+            let txid: String = row.get("txid");
+            let hash: String = row.get("block_hash");
+            anchors.push(AnchorRow {
+                txid: txid.parse()?,
+                block_id: BlockId {
+                    height: row.get("block_height"),
+                    hash: hash.parse::<BlockHash>()?,
+                },
+                confirmation_time: from_sql_opt(
+                    "anchor.confirmation_time",
+                    row.get("confirmation_time"),
+                )?,
+            });
+        }
+
+        Ok(anchors)
+    }
+
+    /// Read all blocks, ordered by height.
+    pub async fn all_blocks(&self) -> Result<Vec<BlockId>, Error> {
+        let rows = sqlx::query("SELECT height, hash FROM block ORDER BY height")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut blocks = vec![];
+        for row in rows {
+            let hash: String = row.get("hash");
+            blocks.push(BlockId {
+                height: row.get("height"),
+                hash: hash.parse()?,
+            });
+        }
+
+        Ok(blocks)
+    }
+
+    /// Read all floating txouts, ordered by outpoint.
+    pub async fn all_txouts(&self) -> Result<Vec<TxOutRow>, Error> {
+        let rows = sqlx::query("SELECT txid, vout, value, script FROM txout ORDER BY txid, vout")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut txouts = vec![];
+        for row in rows {
+            let txid: String = row.get("txid");
+            let value: i64 = row.get("value");
+            txouts.push(TxOutRow {
+                outpoint: OutPoint {
+                    txid: txid.parse()?,
+                    vout: row.get("vout"),
+                },
+                value: Amount::from_sat(from_sql("txout.value", value)?),
+                script_pubkey: ScriptBuf::from_bytes(row.get("script")),
+            });
+        }
+
+        Ok(txouts)
+    }
+
+    /// Read all cached script pubkeys, ordered by descriptor id and derivation index.
+    pub async fn all_script_pubkeys(&self) -> Result<Vec<ScriptPubkeyRow>, Error> {
+        let rows = sqlx::query(
+            "SELECT descriptor_id, derivation_index, script FROM keychain_script_pubkey ORDER BY descriptor_id, derivation_index",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut spks = vec![];
+        for row in rows {
+            let descriptor_id: String = row.get("descriptor_id");
+            spks.push(ScriptPubkeyRow {
+                descriptor_id: descriptor_id.parse()?,
+                derivation_index: row.get("derivation_index"),
+                script_pubkey: ScriptBuf::from_bytes(row.get("script")),
+            });
+        }
+
+        Ok(spks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use bdk_chain::{ConfirmationBlockTime, keychain_txout, local_chain, tx_graph};
+    use bitcoin::{TxOut, hashes::Hash};
+
+    #[tokio::test]
+    async fn read_typed_rows() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let block = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(block.height, Some(block.hash));
+        store.write_local_chain(&chain).await?;
+
+        let txid: Txid = Hash::hash(b"tx");
+        let outpoint = OutPoint::new(txid, 0);
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        graph.anchors.insert((
+            ConfirmationBlockTime {
+                block_id: block,
+                confirmation_time: 100,
+            },
+            txid,
+        ));
+        graph.txouts.insert(
+            outpoint,
+            TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            },
+        );
+        store.write_tx_graph(&graph).await?;
+
+        let descriptor_id = DescriptorId::from_byte_array([1; 32]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            descriptor_id,
+            BTreeMap::from([(0, ScriptBuf::from_bytes(vec![0x51]))]),
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        assert_eq!(store.all_blocks().await?, vec![block]);
+        assert_eq!(
+            store.all_anchors().await?,
+            vec![AnchorRow {
+                txid,
+                block_id: block,
+                confirmation_time: Some(100),
+            }]
+        );
+        assert_eq!(
+            store.all_txouts().await?,
+            vec![TxOutRow {
+                outpoint,
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }]
+        );
+        assert_eq!(
+            store.all_script_pubkeys().await?,
+            vec![ScriptPubkeyRow {
+                descriptor_id,
+                derivation_index: 0,
+                script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+            }]
+        );
+
+        Ok(())
+    }
+}
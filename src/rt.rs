@@ -0,0 +1,226 @@
+//! The async runtime selected by the `runtime-*` features.
+//!
+//! As with `sqlx`, `runtime-tokio` takes precedence if both runtimes are enabled, and
+//! without a runtime the crate builds but using a [`Store`](crate::Store) panics.
+
+#[cfg(feature = "runtime-tokio")]
+pub(crate) use tokio_rt::*;
+#[cfg(feature = "runtime-tokio")]
+mod tokio_rt {
+    use std::time::Duration;
+
+    use super::Elapsed;
+
+    /// Wait for `duration`.
+    pub(crate) async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    /// Run `fut` to completion, failing if it takes longer than `duration`.
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        fut: F,
+    ) -> Result<F::Output, Elapsed> {
+        tokio::time::timeout(duration, fut)
+            .await
+            .map_err(|_| Elapsed)
+    }
+
+    /// Append `data` to the file at `path`, creating it if missing, and sync it.
+    #[cfg(feature = "wallet")]
+    pub(crate) async fn append(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(data).await?;
+        file.sync_data().await
+    }
+
+    /// Create the directory at `path` and its missing parents.
+    #[cfg(feature = "analytics")]
+    pub(crate) async fn create_dir_all(path: &std::path::Path) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(path).await
+    }
+
+    /// Write `data` to the file at `path`, replacing it if it exists.
+    #[cfg(feature = "analytics")]
+    pub(crate) async fn write(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+        tokio::fs::write(path, data).await
+    }
+
+    /// Whether a file or directory exists at `path`.
+    pub(crate) async fn exists(path: &std::path::Path) -> std::io::Result<bool> {
+        tokio::fs::try_exists(path).await
+    }
+
+    /// Create a hard link at `dst` to the file at `src`, failing if `dst` exists.
+    pub(crate) async fn hard_link(
+        src: &std::path::Path,
+        dst: &std::path::Path,
+    ) -> std::io::Result<()> {
+        tokio::fs::hard_link(src, dst).await
+    }
+
+    /// Remove the file at `path`.
+    pub(crate) async fn remove_file(path: &std::path::Path) -> std::io::Result<()> {
+        tokio::fs::remove_file(path).await
+    }
+
+    /// Run the blocking `f` on a thread where blocking is acceptable.
+    #[cfg(any(feature = "encrypted-backup", feature = "wallet"))]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        match tokio::task::spawn_blocking(f).await {
+            Ok(t) => t,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+pub(crate) use async_std_rt::*;
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+mod async_std_rt {
+    use std::time::Duration;
+
+    use super::Elapsed;
+
+    /// Wait for `duration`.
+    pub(crate) async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+
+    /// Run `fut` to completion, failing if it takes longer than `duration`.
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        fut: F,
+    ) -> Result<F::Output, Elapsed> {
+        async_std::future::timeout(duration, fut)
+            .await
+            .map_err(|_| Elapsed)
+    }
+
+    /// Append `data` to the file at `path`, creating it if missing, and sync it.
+    #[cfg(feature = "wallet")]
+    pub(crate) async fn append(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+        use async_std::io::WriteExt;
+
+        let mut file = async_std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(data).await?;
+        file.sync_data().await
+    }
+
+    /// Create the directory at `path` and its missing parents.
+    #[cfg(feature = "analytics")]
+    pub(crate) async fn create_dir_all(path: &std::path::Path) -> std::io::Result<()> {
+        async_std::fs::create_dir_all(path).await
+    }
+
+    /// Write `data` to the file at `path`, replacing it if it exists.
+    #[cfg(feature = "analytics")]
+    pub(crate) async fn write(path: &std::path::Path, data: &[u8]) -> std::io::Result<()> {
+        async_std::fs::write(path, data).await
+    }
+
+    /// Whether a file or directory exists at `path`.
+    pub(crate) async fn exists(path: &std::path::Path) -> std::io::Result<bool> {
+        match async_std::fs::metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create a hard link at `dst` to the file at `src`, failing if `dst` exists.
+    pub(crate) async fn hard_link(
+        src: &std::path::Path,
+        dst: &std::path::Path,
+    ) -> std::io::Result<()> {
+        async_std::fs::hard_link(src, dst).await
+    }
+
+    /// Remove the file at `path`.
+    pub(crate) async fn remove_file(path: &std::path::Path) -> std::io::Result<()> {
+        async_std::fs::remove_file(path).await
+    }
+
+    /// Run the blocking `f` on a thread where blocking is acceptable.
+    #[cfg(any(feature = "encrypted-backup", feature = "wallet"))]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        async_std::task::spawn_blocking(f).await
+    }
+}
+
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-std")))]
+pub(crate) use missing_rt::*;
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-std")))]
+mod missing_rt {
+    use std::time::Duration;
+
+    use super::Elapsed;
+
+    const MISSING_RT: &str =
+        "either the `runtime-tokio` or `runtime-async-std` feature must be enabled";
+
+    pub(crate) async fn sleep(_duration: Duration) {
+        panic!("{MISSING_RT}")
+    }
+
+    pub(crate) async fn timeout<F: Future>(
+        _duration: Duration,
+        _fut: F,
+    ) -> Result<F::Output, Elapsed> {
+        panic!("{MISSING_RT}")
+    }
+
+    #[cfg(feature = "wallet")]
+    pub(crate) async fn append(_path: &std::path::Path, _data: &[u8]) -> std::io::Result<()> {
+        panic!("{MISSING_RT}")
+    }
+
+    #[cfg(feature = "analytics")]
+    pub(crate) async fn create_dir_all(_path: &std::path::Path) -> std::io::Result<()> {
+        panic!("{MISSING_RT}")
+    }
+
+    #[cfg(feature = "analytics")]
+    pub(crate) async fn write(_path: &std::path::Path, _data: &[u8]) -> std::io::Result<()> {
+        panic!("{MISSING_RT}")
+    }
+
+    pub(crate) async fn exists(_path: &std::path::Path) -> std::io::Result<bool> {
+        panic!("{MISSING_RT}")
+    }
+
+    pub(crate) async fn hard_link(
+        _src: &std::path::Path,
+        _dst: &std::path::Path,
+    ) -> std::io::Result<()> {
+        panic!("{MISSING_RT}")
+    }
+
+    pub(crate) async fn remove_file(_path: &std::path::Path) -> std::io::Result<()> {
+        panic!("{MISSING_RT}")
+    }
+
+    #[cfg(any(feature = "encrypted-backup", feature = "wallet"))]
+    pub(crate) async fn spawn_blocking<T: Send + 'static>(
+        _f: impl FnOnce() -> T + Send + 'static,
+    ) -> T {
+        panic!("{MISSING_RT}")
+    }
+}
+
+/// Error of a [`timeout`] which elapsed.
+pub(crate) struct Elapsed;
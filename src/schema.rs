@@ -0,0 +1,213 @@
+//! Description of the database schema.
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+
+/// A table of the schema, see [`Store::schema_tables`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableSchema {
+    /// Name
+    pub name: String,
+    /// Columns in order of declaration
+    pub columns: Vec<ColumnSchema>,
+}
+
+/// A column of a [`TableSchema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnSchema {
+    /// Name
+    pub name: String,
+    /// Declared type, e.g. `INTEGER`
+    pub ty: String,
+    /// Whether the column is declared `NOT NULL`
+    pub not_null: bool,
+    /// Position of the column in the primary key starting at 1, or 0 if not part of it
+    pub primary_key: u32,
+}
+
+/// A row set aside when its table was rebuilt as a STRICT table, see
+/// [`Store::strict_rejected`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRow {
+    /// Table of the row
+    pub table: String,
+    /// JSON object of the row, mapping each column to the SQL literal of its value
+    pub data: String,
+}
+
+impl Store {
+    /// The rows of a database created by an earlier version which held a value of the
+    /// wrong type, in the order they were found.
+    ///
+    /// The tables of heights, timestamps and amounts are STRICT tables, so that writing a
+    /// value of the wrong type fails with [`Error::DatatypeViolation`]. When the migration
+    /// rebuilt them, rows which SQLite had stored with a value of the wrong type, e.g. text
+    /// written into an INTEGER column by an external writer, couldn't be copied and are
+    /// kept here instead, for the application to repair or discard. The migration which
+    /// sets rows aside fails with [`Error::StrictRejected`].
+    pub async fn strict_rejected(&self) -> Result<Vec<RejectedRow>, Error> {
+        let rows = sqlx::query("SELECT table_name, data FROM strict_rejected ORDER BY rowid")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RejectedRow {
+                table: row.get("table_name"),
+                data: row.get("data"),
+            })
+            .collect())
+    }
+
+    /// Number of rows in the `strict_rejected` table, 0 if it doesn't exist yet.
+    pub(crate) async fn strict_rejected_count(&self) -> Result<u64, Error> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS count FROM sqlite_master WHERE type = 'table' AND name = 'strict_rejected'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if row.get::<i64, _>("count") == 0 {
+            return Ok(0);
+        }
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM strict_rejected")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get::<i64, _>("count") as u64)
+    }
+
+    /// The DDL of the schema created by the embedded migrations.
+    ///
+    /// The schema is read from a new in-memory database with every migration applied, and
+    /// consists of a `CREATE` statement per table, index and view ordered by type and name.
+    pub async fn schema_sql() -> Result<String, Error> {
+        let store = Self::new_memory().await?;
+        store.migrate().await?;
+
+        let rows = sqlx::query(
+            "SELECT sql FROM sqlite_master WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '_sqlx_%' ORDER BY CASE type WHEN 'table' THEN 0 WHEN 'index' THEN 1 ELSE 2 END, name",
+        )
+        .fetch_all(&store.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| format!("{};\n", row.get::<String, _>("sql")))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// The tables of the schema created by the embedded migrations, ordered by name.
+    pub async fn schema_tables() -> Result<Vec<TableSchema>, Error> {
+        let store = Self::new_memory().await?;
+        store.migrate().await?;
+
+        let rows = sqlx::query(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '_sqlx_%' ORDER BY name",
+        )
+        .fetch_all(&store.pool)
+        .await?;
+
+        let mut tables = vec![];
+        for row in rows {
+            let name: String = row.get("name");
+            let columns =
+                sqlx::query("SELECT name, type, \"notnull\", pk FROM pragma_table_info($1)")
+                    .bind(&name)
+                    .fetch_all(&store.pool)
+                    .await?
+                    .iter()
+                    .map(|row| ColumnSchema {
+                        name: row.get("name"),
+                        ty: row.get("type"),
+                        not_null: row.get("notnull"),
+                        primary_key: row.get("pk"),
+                    })
+                    .collect();
+            tables.push(TableSchema { name, columns });
+        }
+
+        Ok(tables)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn schema() -> anyhow::Result<()> {
+        let sql = Store::schema_sql().await?;
+        assert!(sql.contains("CREATE TABLE label("));
+        assert!(sql.contains("CREATE VIEW v_utxos"));
+        assert!(!sql.contains("_sqlx_migrations"));
+
+        let tables = Store::schema_tables().await?;
+        let label = tables.iter().find(|table| table.name == "label").unwrap();
+        assert_eq!(
+            label.columns,
+            [
+                ("type", "TEXT"),
+                ("ref", "TEXT"),
+                ("label", "TEXT"),
+                ("encrypted", "INTEGER")
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, ty))| ColumnSchema {
+                name: name.to_string(),
+                ty: ty.to_string(),
+                not_null: true,
+                primary_key: if i < 2 { i as u32 + 1 } else { 0 },
+            })
+            .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strict_tables() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        let mut migrator = crate::async_store::migrator();
+        migrator.migrations = migrator
+            .migrations
+            .iter()
+            .filter(|migration| migration.version < 35)
+            .cloned()
+            .collect::<Vec<_>>()
+            .into();
+        migrator.run(&store.pool).await?;
+
+        // An external writer stores text in an INTEGER column of a non-strict table.
+        sqlx::query("INSERT INTO tx(txid, first_seen) VALUES('aa', 'soon'), ('bb', 5)")
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            store.migrate().await,
+            Err(Error::StrictRejected { rows: 1 })
+        ));
+        store.migrate().await?;
+
+        let rejected = store.strict_rejected().await?;
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].table, "tx");
+        assert!(rejected[0].data.contains(r#""first_seen":"'soon'""#));
+        let rows = sqlx::query("SELECT txid FROM tx")
+            .fetch_all(&store.pool)
+            .await?;
+        assert_eq!(rows.len(), 1);
+
+        let res = sqlx::query("UPDATE tx SET last_seen = 'later'")
+            .execute(&store.pool)
+            .await;
+        assert!(matches!(
+            res.map_err(Error::from),
+            Err(Error::DatatypeViolation { ref column }) if column == "tx.last_seen"
+        ));
+
+        Ok(())
+    }
+}
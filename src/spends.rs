@@ -0,0 +1,134 @@
+//! Queries of the `txin` table.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bdk_chain::bitcoin::{OutPoint, Txid};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::Error;
+use crate::Store;
+
+impl Store {
+    /// Map each outpoint spent by a stored transaction to the txids spending it.
+    pub async fn spent_outpoints(&self) -> Result<BTreeMap<OutPoint, BTreeSet<Txid>>, Error> {
+        let rows = sqlx::query(
+            "SELECT prev_txid, prev_vout, txid FROM txin ORDER BY prev_txid, prev_vout, txid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        spends_from_rows(rows)
+    }
+
+    /// The txids of the stored transactions spending `outpoint`.
+    ///
+    /// More than one txid means the transactions conflict, at most one of them can confirm.
+    pub async fn spenders_of(&self, outpoint: OutPoint) -> Result<BTreeSet<Txid>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid FROM txin WHERE prev_txid = $1 AND prev_vout = $2 ORDER BY txid",
+        )
+        .bind(outpoint.txid.to_string())
+        .bind(outpoint.vout)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut spenders = BTreeSet::new();
+        for row in rows {
+            let txid: String = row.get("txid");
+            spenders.insert(txid.parse()?);
+        }
+
+        Ok(spenders)
+    }
+
+    /// Map each outpoint spent by more than one stored transaction to the txids spending
+    /// it, e.g. to flag a double spend attempt as soon as it is persisted.
+    pub async fn double_spends(&self) -> Result<BTreeMap<OutPoint, BTreeSet<Txid>>, Error> {
+        let rows = sqlx::query(
+            "SELECT prev_txid, prev_vout, txid FROM txin WHERE (prev_txid, prev_vout) IN (SELECT prev_txid, prev_vout FROM txin GROUP BY prev_txid, prev_vout HAVING COUNT(DISTINCT txid) > 1) ORDER BY prev_txid, prev_vout, txid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        spends_from_rows(rows)
+    }
+}
+
+/// Collect rows of `prev_txid`, `prev_vout` and `txid` by outpoint.
+fn spends_from_rows(rows: Vec<SqliteRow>) -> Result<BTreeMap<OutPoint, BTreeSet<Txid>>, Error> {
+    let mut spends = BTreeMap::<OutPoint, BTreeSet<Txid>>::new();
+    for row in rows {
+        let prev_txid: String = row.get("prev_txid");
+        let txid: String = row.get("txid");
+        let outpoint = OutPoint::new(prev_txid.parse()?, row.get("prev_vout"));
+        spends.entry(outpoint).or_default().insert(txid.parse()?);
+    }
+
+    Ok(spends)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::{
+        Amount, ScriptBuf, Transaction, TxIn, TxOut, absolute, hashes::Hash, transaction,
+    };
+    use bdk_chain::{ConfirmationBlockTime, tx_graph};
+
+    fn spend(prevouts: &[OutPoint], value: u64) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: prevouts
+                .iter()
+                .map(|&previous_output| TxIn {
+                    previous_output,
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn spenders_and_double_spends() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let a = OutPoint::new(Hash::hash(b"a"), 0);
+        let b = OutPoint::new(Hash::hash(b"b"), 1);
+        let original = spend(&[a, b], 10_000);
+        let replacement = spend(&[a], 9_000);
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        graph.txs.insert(Arc::new(original.clone()));
+        graph.txs.insert(Arc::new(replacement.clone()));
+        store.write_tx_graph(&graph).await?;
+
+        let both: BTreeSet<Txid> = [original.compute_txid(), replacement.compute_txid()].into();
+        assert_eq!(store.spenders_of(a).await?, both);
+        assert_eq!(
+            store.spenders_of(b).await?,
+            [original.compute_txid()].into()
+        );
+        assert!(
+            store
+                .spenders_of(OutPoint::new(Hash::hash(b"c"), 0))
+                .await?
+                .is_empty()
+        );
+        assert_eq!(
+            store.spent_outpoints().await?,
+            [(a, both.clone()), (b, [original.compute_txid()].into())].into()
+        );
+        assert_eq!(store.double_spends().await?, [(a, both)].into());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Identification of bdk-sqlite databases by the SQLite file header.
+
+use std::io::Read;
+use std::path::Path;
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::async_store::migrator;
+
+/// The `application_id` of the databases of this crate, the ASCII bytes of `BDKS`.
+pub const APPLICATION_ID: i32 = 0x4244_4b53;
+
+/// The identification of a database read from its file header, see
+/// [`DatabaseStamp::read`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseStamp {
+    /// `PRAGMA application_id`, [`APPLICATION_ID`] for the databases of this crate
+    pub application_id: i32,
+    /// `PRAGMA user_version`, the version of the last migration applied by this crate
+    pub user_version: u32,
+}
+
+impl DatabaseStamp {
+    /// Read the stamp of the database file at `path` without opening it with SQLite,
+    /// returning `None` if the file isn't an SQLite database.
+    ///
+    /// Only the 100-byte header is read, so tools can cheaply recognize a database of this
+    /// crate and its version before opening it. The header is written by checkpoints, so
+    /// in WAL mode a database stamped since the last checkpoint still reads as unstamped.
+    pub fn read(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let mut header = [0; 100];
+        let mut file = std::fs::File::open(path)?;
+        if file.read_exact(&mut header).is_err() || !header.starts_with(b"SQLite format 3\0") {
+            return Ok(None);
+        }
+        let be = |offset: usize| {
+            u32::from_be_bytes(
+                header[offset..offset + 4]
+                    .try_into()
+                    .expect("must be 4 bytes"),
+            )
+        };
+
+        Ok(Some(Self {
+            application_id: be(68) as i32,
+            user_version: be(60),
+        }))
+    }
+
+    /// Whether the database was stamped by this crate.
+    pub fn is_bdk_sqlite(&self) -> bool {
+        self.application_id == APPLICATION_ID
+    }
+}
+
+impl Store {
+    /// The `application_id` of the database, [`APPLICATION_ID`] once migrated, or 0 if it
+    /// wasn't stamped.
+    pub async fn application_id(&self) -> Result<i32, Error> {
+        let row = sqlx::query("PRAGMA application_id")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// The `user_version` of the database, the version of the last migration applied, or
+    /// 0 if it wasn't stamped.
+    pub async fn user_version(&self) -> Result<u32, Error> {
+        let row = sqlx::query("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get(0))
+    }
+
+    /// Stamp the database with [`APPLICATION_ID`] and the version of the last migration.
+    ///
+    /// The `application_id` of a database stamped by another application is kept, and the
+    /// `user_version` is never lowered, e.g. when a database migrated by a later version is
+    /// opened by this one.
+    pub(crate) async fn stamp(&self) -> Result<(), Error> {
+        let version = migrator()
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or(0);
+        let mut conn = self.pool.acquire().await?;
+        let row = sqlx::query("PRAGMA application_id")
+            .fetch_one(&mut *conn)
+            .await?;
+        if row.get::<i32, _>(0) == 0 {
+            sqlx::query(&format!("PRAGMA application_id = {APPLICATION_ID}"))
+                .execute(&mut *conn)
+                .await?;
+        }
+        let row = sqlx::query("PRAGMA user_version")
+            .fetch_one(&mut *conn)
+            .await?;
+        if row.get::<i64, _>(0) < version {
+            sqlx::query(&format!("PRAGMA user_version = {version}"))
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn stamp() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        assert_eq!(store.application_id().await?, 0);
+        store.migrate().await?;
+        assert_eq!(store.application_id().await?, APPLICATION_ID);
+        let version = store.user_version().await?;
+        assert!(version >= 40);
+
+        // The database was stamped by a later version and by another application.
+        sqlx::query("PRAGMA user_version = 1000")
+            .execute(&store.pool)
+            .await?;
+        sqlx::query("PRAGMA application_id = 7")
+            .execute(&store.pool)
+            .await?;
+        store.migrate().await?;
+        assert_eq!(store.user_version().await?, 1000);
+        assert_eq!(store.application_id().await?, 7);
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stamp.db");
+        let file = Store::new(&path.to_string_lossy()).await?;
+        file.migrate().await?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&file.pool)
+            .await?;
+        let stamp = DatabaseStamp::read(&path)?.expect("must be an SQLite database");
+        assert!(stamp.is_bdk_sqlite());
+        assert_eq!(stamp.user_version, version);
+        drop(file);
+
+        std::fs::write(&path, b"not a database")?;
+        assert_eq!(DatabaseStamp::read(&path)?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Streaming reads.
+
+use std::sync::Arc;
+
+use bdk_chain::bitcoin;
+use bitcoin::{Transaction, Txid, consensus};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+
+impl Store {
+    /// Stream the stored transactions ordered by txid.
+    ///
+    /// Rows are fetched from the database as the stream is polled rather than read into
+    /// memory up front, so this is suitable for exporting or reindexing a large tx table.
+    /// The stream holds a pooled connection until it is dropped.
+    pub fn stream_txs(&self) -> impl Stream<Item = Result<(Txid, Arc<Transaction>), Error>> + '_ {
+        sqlx::query(
+            "SELECT txid, tx_blob.tx FROM tx JOIN tx_blob ON tx_blob.id = tx.blob_id ORDER BY txid",
+        )
+        .fetch(&self.pool)
+        .map(|row| {
+            let row = row?;
+            let txid: Txid = row.get::<&str, _>("txid").parse()?;
+            let tx: Transaction = consensus::encode::deserialize(row.get::<&[u8], _>("tx"))?;
+            Ok((txid, Arc::new(tx)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::{ConfirmationBlockTime, tx_graph};
+    use bitcoin::{Amount, ScriptBuf, TxOut, absolute, hashes::Hash, transaction};
+    use futures_util::TryStreamExt;
+
+    #[tokio::test]
+    async fn stream_txs() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        for value in 1..=3 {
+            graph.txs.insert(Arc::new(Transaction {
+                version: transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![],
+                output: vec![TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            }));
+        }
+        // A tx row without a transaction is skipped.
+        graph.last_seen.insert(Hash::hash(b"missing"), 100);
+        store.write_tx_graph(&graph).await?;
+
+        let streamed: Vec<_> = store.stream_txs().try_collect().await?;
+        let mut expected: Vec<_> = graph
+            .txs
+            .iter()
+            .map(|tx| (tx.compute_txid(), tx.clone()))
+            .collect();
+        expected.sort_by_key(|(txid, _)| txid.to_string());
+        assert_eq!(streamed, expected);
+
+        Ok(())
+    }
+}
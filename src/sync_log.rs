@@ -0,0 +1,112 @@
+//! Log of sync attempts.
+
+use std::time::Duration;
+
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, to_sql};
+
+/// A sync attempt, see [`Store::record_sync`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncAttempt {
+    /// Unix time at which the sync started
+    pub started_at: u64,
+    /// Backend synced against, e.g. an Esplora URL
+    pub backend: String,
+    /// How long the sync took
+    pub duration: Duration,
+    /// Number of script pubkeys scanned
+    pub spks_scanned: u64,
+    /// Error which failed the sync, if any
+    pub error: Option<String>,
+}
+
+impl Store {
+    /// Record a sync `attempt`.
+    ///
+    /// Only the latest [`with_sync_log_capacity`](Self::with_sync_log_capacity) attempts
+    /// are kept, older ones are deleted.
+    pub async fn record_sync(&self, attempt: &SyncAttempt) -> Result<(), Error> {
+        let capacity = to_sql("sync_log.id", self.sync_log_capacity as u64)?;
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO sync_log(started_at, backend, duration_ms, spks_scanned, error) VALUES($1, $2, $3, $4, $5)",
+            )
+            .bind(to_sql("sync_log.started_at", attempt.started_at)?)
+            .bind(&attempt.backend)
+            .bind(to_sql(
+                "sync_log.duration_ms",
+                u64::try_from(attempt.duration.as_millis()).unwrap_or(u64::MAX),
+            )?)
+            .bind(to_sql("sync_log.spks_scanned", attempt.spks_scanned)?)
+            .bind(&attempt.error)
+            .execute(&mut *conn)
+            .await?;
+            sqlx::query(
+                "DELETE FROM sync_log WHERE id NOT IN (SELECT id FROM sync_log ORDER BY id DESC LIMIT $1)",
+            )
+            .bind(capacity)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read the recorded sync attempts, latest first.
+    pub async fn sync_log(&self) -> Result<Vec<SyncAttempt>, Error> {
+        let rows = sqlx::query(
+            "SELECT started_at, backend, duration_ms, spks_scanned, error FROM sync_log ORDER BY id DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut attempts = vec![];
+        for row in rows {
+            let started_at: i64 = row.get("started_at");
+            let duration_ms: i64 = row.get("duration_ms");
+            let spks_scanned: i64 = row.get("spks_scanned");
+            attempts.push(SyncAttempt {
+                started_at: from_sql("sync_log.started_at", started_at)?,
+                backend: row.get("backend"),
+                duration: Duration::from_millis(from_sql("sync_log.duration_ms", duration_ms)?),
+                spks_scanned: from_sql("sync_log.spks_scanned", spks_scanned)?,
+                error: row.get("error"),
+            });
+        }
+
+        Ok(attempts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn sync_log_is_capped() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_sync_log_capacity(2);
+        store.migrate().await?;
+
+        let attempt = |started_at: u64, error: Option<&str>| SyncAttempt {
+            started_at,
+            backend: "https://mempool.space/signet/api".to_string(),
+            duration: Duration::from_millis(1500),
+            spks_scanned: 40,
+            error: error.map(str::to_string),
+        };
+        for (started_at, error) in [(1, None), (2, Some("connection refused")), (3, None)] {
+            store.record_sync(&attempt(started_at, error)).await?;
+        }
+
+        assert_eq!(
+            store.sync_log().await?,
+            vec![attempt(3, None), attempt(2, Some("connection refused"))]
+        );
+
+        Ok(())
+    }
+}
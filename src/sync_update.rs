@@ -0,0 +1,166 @@
+//! Persisting the output of a sync without a wallet changeset.
+
+use std::collections::BTreeSet;
+
+use bdk_chain::{ConfirmationBlockTime, TxUpdate, local_chain, tx_graph};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+use crate::convert::from_sql_opt;
+use crate::provenance::record_source_in;
+
+impl Store {
+    /// Write the transaction data of a sync or full scan along with the changes to the
+    /// local chain, e.g. as returned by `LocalChain::apply_update` for the sync's chain
+    /// update, in a single transaction.
+    ///
+    /// This lets users of the chain source clients persist incremental sync results
+    /// without constructing a wallet changeset. As when applying the update to a
+    /// `TxGraph`, a seen time only moves the stored first seen time earlier and the last
+    /// seen time later, and an evicted time only moves the last evicted time later.
+    pub async fn write_sync_update(
+        &self,
+        update: &TxUpdate<ConfirmationBlockTime>,
+        chain: &local_chain::ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        self.write_sync_update_with(update, chain, WriteOptions::default())
+            .await
+    }
+
+    /// Write the update of a sync with the given [`WriteOptions`], e.g. to record the chain
+    /// source it was fetched from with [`WriteOptions::source`].
+    pub async fn write_sync_update_with(
+        &self,
+        update: &TxUpdate<ConfirmationBlockTime>,
+        chain: &local_chain::ChangeSet,
+        opts: WriteOptions,
+    ) -> Result<WriteSummary, Error> {
+        let mut tx_graph = tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: update.txs.iter().cloned().collect(),
+            txouts: update.txouts.clone(),
+            anchors: update.anchors.clone(),
+            ..Default::default()
+        };
+        for &(txid, seen_at) in &update.seen_ats {
+            let first_seen = tx_graph.first_seen.entry(txid).or_insert(seen_at);
+            *first_seen = (*first_seen).min(seen_at);
+            let last_seen = tx_graph.last_seen.entry(txid).or_insert(seen_at);
+            *last_seen = (*last_seen).max(seen_at);
+        }
+        for &(txid, evicted_at) in &update.evicted_ats {
+            let last_evicted = tx_graph.last_evicted.entry(txid).or_insert(evicted_at);
+            *last_evicted = (*last_evicted).max(evicted_at);
+        }
+
+        self.write(opts, async |conn| {
+            let txids: BTreeSet<_> = tx_graph
+                .first_seen
+                .keys()
+                .chain(tx_graph.last_evicted.keys())
+                .copied()
+                .collect();
+            let mut tx_graph = tx_graph.clone();
+            for txid in txids {
+                let Some(row) = sqlx::query(
+                    "SELECT first_seen, last_seen, last_evicted FROM tx WHERE txid = $1",
+                )
+                .bind(txid.to_string())
+                .fetch_optional(&mut *conn)
+                .await?
+                else {
+                    continue;
+                };
+                let first_seen: Option<u64> = from_sql_opt("tx.first_seen", row.get("first_seen"))?;
+                let last_seen: Option<u64> = from_sql_opt("tx.last_seen", row.get("last_seen"))?;
+                let last_evicted: Option<u64> =
+                    from_sql_opt("tx.last_evicted", row.get("last_evicted"))?;
+                if let (Some(t), Some(stored)) = (tx_graph.first_seen.get_mut(&txid), first_seen) {
+                    *t = (*t).min(stored);
+                }
+                if let (Some(t), Some(stored)) = (tx_graph.last_seen.get_mut(&txid), last_seen) {
+                    *t = (*t).max(stored);
+                }
+                if let (Some(t), Some(stored)) =
+                    (tx_graph.last_evicted.get_mut(&txid), last_evicted)
+                {
+                    *t = (*t).max(stored);
+                }
+            }
+
+            let mut summary = self.write_local_chain_in(conn, chain).await?;
+            summary.merge(self.write_tx_graph_in(conn, &tx_graph).await?);
+            if let Some(source) = opts.source {
+                record_source_in(conn, &tx_graph, source).await?;
+            }
+            Ok(summary)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::DataSource;
+
+    use bdk_chain::bitcoin::{
+        Amount, ScriptBuf, Transaction, TxOut, absolute, hashes::Hash, transaction,
+    };
+
+    #[tokio::test]
+    async fn write_sync_update() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        let mut update = TxUpdate::<ConfirmationBlockTime>::default();
+        update.txs.push(Arc::new(tx));
+        update.seen_ats.insert((txid, 200));
+        update.seen_ats.insert((txid, 100));
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(0, Some(Hash::hash(b"0")));
+
+        let summary = store
+            .write_sync_update_with(
+                &update,
+                &chain,
+                WriteOptions::default().source(DataSource::Electrum),
+            )
+            .await?;
+        assert_eq!(summary.table("block").inserted, 1);
+        assert_eq!(store.tx_source(txid).await?, Some(DataSource::Electrum));
+        let graph = store.read_tx_graph().await?;
+        assert_eq!(graph.txs.len(), 1);
+        assert_eq!(graph.first_seen[&txid], 100);
+        assert_eq!(graph.last_seen[&txid], 200);
+
+        // A later sync sees the transaction again and then evicted.
+        let mut update = TxUpdate::<ConfirmationBlockTime>::default();
+        update.seen_ats.insert((txid, 150));
+        update.evicted_ats.insert((txid, 300));
+        store
+            .write_sync_update(&update, &local_chain::ChangeSet::default())
+            .await?;
+        let graph = store.read_tx_graph().await?;
+        assert_eq!(graph.first_seen[&txid], 100);
+        assert_eq!(graph.last_seen[&txid], 200);
+        assert_eq!(graph.last_evicted[&txid], 300);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! Isolation of tenants of a hosted service.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bdk_chain::bitcoin;
+use bitcoin::hashes::{Hash, sha256};
+use sqlx::Row;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+
+use crate::Error;
+use crate::Store;
+use crate::async_store::pool_options;
+use crate::rt;
+
+/// Shortest authorization token accepted by [`TenantDir::create`], in bytes.
+pub const MIN_TENANT_TOKEN_LEN: usize = 32;
+
+/// Number of the next database set up by [`TenantDir::create`] in this process.
+static NEXT_SETUP: AtomicU64 = AtomicU64::new(0);
+
+/// A directory holding one database per tenant.
+///
+/// Each tenant's data lives in its own database file, so a [`Store`] opened for one tenant
+/// can't read or write the data of another by construction. Opening a tenant's store
+/// requires the authorization token it was created with, of which only an unsalted
+/// SHA-256 hash is persisted. Tokens must therefore be high-entropy secrets, e.g. 32
+/// random bytes encoded as hex, not passwords chosen by users, which a leaked hash would
+/// let an attacker guess offline.
+#[derive(Debug, Clone)]
+pub struct TenantDir {
+    root: PathBuf,
+}
+
+impl TenantDir {
+    /// New [`TenantDir`] at `root`, which must exist.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Create the database of `tenant_id`, migrated and protected by `token`.
+    ///
+    /// Tenant ids consist of 1 to 64 ASCII alphanumerics, `-` or `_`. Fails with
+    /// [`Error::WeakToken`] if `token` is shorter than [`MIN_TENANT_TOKEN_LEN`].
+    ///
+    /// The database is set up under a temporary name and then linked into place, so that
+    /// a failed setup leaves no database behind and of concurrent creates of the same
+    /// tenant only one succeeds, the others failing with [`Error::TenantExists`].
+    pub async fn create(&self, tenant_id: &str, token: &str) -> Result<Store, Error> {
+        let path = self.path(tenant_id)?;
+        if token.len() < MIN_TENANT_TOKEN_LEN {
+            return Err(Error::WeakToken);
+        }
+        if rt::exists(&path).await? {
+            return Err(Error::TenantExists(tenant_id.to_string()));
+        }
+
+        let setup = NEXT_SETUP.fetch_add(1, Ordering::Relaxed);
+        let tmp = (self.root).join(format!(".{tenant_id}.{}.{setup}.tmp", std::process::id()));
+        let res = match Self::set_up(&tmp, tenant_id, token).await {
+            // Unlike a rename, linking fails rather than replacing an existing database.
+            Ok(()) => rt::hard_link(&tmp, &path)
+                .await
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::AlreadyExists => Error::TenantExists(tenant_id.to_string()),
+                    _ => e.into(),
+                }),
+            Err(e) => Err(e),
+        };
+        for suffix in ["", "-journal"] {
+            let _ = rt::remove_file(Path::new(&format!("{}{suffix}", tmp.display()))).await;
+        }
+        res?;
+
+        let store = Self::connect(&path).await?;
+        Ok(store.with_tenant(tenant_id))
+    }
+
+    /// Open the database of `tenant_id`, failing with [`Error::Unauthorized`] unless
+    /// `token` is the token it was created with.
+    pub async fn open(&self, tenant_id: &str, token: &str) -> Result<Store, Error> {
+        let path = self.path(tenant_id)?;
+        if !rt::exists(&path).await? {
+            return Err(Error::UnknownTenant(tenant_id.to_string()));
+        }
+
+        let store = Self::connect(&path).await?;
+        let row = sqlx::query("SELECT tenant_id, token_hash FROM tenant")
+            .fetch_optional(&store.pool)
+            .await?;
+        let authorized = row.is_some_and(|row| {
+            let stored: String = row.get("token_hash");
+            row.get::<String, _>("tenant_id") == tenant_id
+                && constant_time_eq(stored.as_bytes(), token_hash(token).as_bytes())
+        });
+        if !authorized {
+            return Err(Error::Unauthorized);
+        }
+        store.migrate().await?;
+
+        Ok(store.with_tenant(tenant_id))
+    }
+
+    fn path(&self, tenant_id: &str) -> Result<PathBuf, Error> {
+        let valid = (1..=64).contains(&tenant_id.len())
+            && tenant_id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(Error::InvalidTenantId(tenant_id.to_string()));
+        }
+
+        Ok(self.root.join(format!("{tenant_id}.sqlite")))
+    }
+
+    /// Migrate a new database at `path` and record `tenant_id` and `token` in it.
+    async fn set_up(path: &Path, tenant_id: &str, token: &str) -> Result<(), Error> {
+        // Without a WAL, the database is complete in its file once the pool is closed.
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Delete);
+        let store = Store::from_pool(pool_options().connect_with(options).await?);
+        let res = async {
+            store.migrate().await?;
+            sqlx::query("INSERT INTO tenant(id, tenant_id, token_hash) VALUES(0, $1, $2)")
+                .bind(tenant_id)
+                .bind(token_hash(token))
+                .execute(&store.pool)
+                .await?;
+            Ok(())
+        }
+        .await;
+        store.pool.close().await;
+
+        res
+    }
+
+    async fn connect(path: &Path) -> Result<Store, Error> {
+        let options = SqliteConnectOptions::new().filename(path);
+        let pool = pool_options().connect_with(options).await?;
+
+        Ok(Store::from_pool(pool))
+    }
+}
+
+impl Store {
+    /// The id of the tenant this store was opened for by [`TenantDir`], if any.
+    pub fn tenant_id(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    fn with_tenant(mut self, tenant_id: &str) -> Self {
+        self.tenant = Some(tenant_id.to_string());
+        self
+    }
+}
+
+fn token_hash(token: &str) -> String {
+    sha256::Hash::hash(token.as_bytes()).to_string()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::LabelRef;
+
+    #[tokio::test]
+    async fn tenants_are_isolated() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let tenants = TenantDir::new(root.path());
+        let token = |name: &str| format!("{name}-token-{}", "0".repeat(32));
+
+        assert!(matches!(
+            tenants.create("alice", "alice-token").await,
+            Err(Error::WeakToken)
+        ));
+        // Of concurrent creates of a tenant only one succeeds.
+        let alice_token = token("alice");
+        let (a, b) = tokio::join!(
+            tenants.create("alice", &alice_token),
+            tenants.create("alice", &alice_token),
+        );
+        let alice = match (a, b) {
+            (Ok(alice), Err(Error::TenantExists(_))) | (Err(Error::TenantExists(_)), Ok(alice)) => {
+                alice
+            }
+            res => panic!("unexpected result: {res:?}"),
+        };
+        assert_eq!(alice.tenant_id(), Some("alice"));
+        let bob = tenants.create("bob", &token("bob")).await?;
+        let label_ref = LabelRef::Tx(Hash::hash(b"tx"));
+        alice.set_label(&label_ref, "alice's").await?;
+        assert_eq!(bob.label(&label_ref).await?, None);
+
+        assert!(matches!(
+            tenants.create("alice", &token("carol")).await,
+            Err(Error::TenantExists(_))
+        ));
+        assert!(matches!(
+            tenants.open("alice", &token("bob")).await,
+            Err(Error::Unauthorized)
+        ));
+        assert!(matches!(
+            tenants.open("carol", &token("carol")).await,
+            Err(Error::UnknownTenant(_))
+        ));
+        assert!(matches!(
+            tenants.open("../alice", &token("alice")).await,
+            Err(Error::InvalidTenantId(_))
+        ));
+
+        let alice = tenants.open("alice", &token("alice")).await?;
+        assert_eq!(alice.label(&label_ref).await?.as_deref(), Some("alice's"));
+        // No temporary database is left behind.
+        for entry in std::fs::read_dir(root.path())? {
+            assert!(!entry?.file_name().to_string_lossy().ends_with(".tmp"));
+        }
+
+        Ok(())
+    }
+}
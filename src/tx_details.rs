@@ -0,0 +1,237 @@
+//! Detailed view of a single transaction.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bdk_chain::{BlockId, ConfirmationBlockTime, bitcoin};
+use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, TxOut, Txid};
+use sqlx::Row;
+
+use crate::Error;
+use crate::LabelRef;
+use crate::Store;
+use crate::convert::{from_sql, from_sql_opt};
+
+/// Everything the store knows about a single transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxDetails {
+    /// Txid
+    pub txid: Txid,
+    /// Raw (consensus encoded) transaction, if the full transaction is stored
+    pub tx: Option<Vec<u8>>,
+    /// Anchors
+    pub anchors: BTreeSet<ConfirmationBlockTime>,
+    /// Blocks of anchors with an unknown confirmation time
+    pub untimed_anchors: BTreeSet<BlockId>,
+    /// First seen
+    pub first_seen: Option<u64>,
+    /// Last seen
+    pub last_seen: Option<u64>,
+    /// Last evicted
+    pub last_evicted: Option<u64>,
+    /// Known (floating) txouts of this transaction, keyed by vout
+    pub txouts: BTreeMap<u32, TxOut>,
+    /// Txids of stored transactions spending at least one of the same prevouts
+    pub conflicts: BTreeSet<Txid>,
+    /// Transaction label
+    pub label: Option<String>,
+    /// Output labels, keyed by vout
+    pub output_labels: BTreeMap<u32, String>,
+}
+
+impl Store {
+    /// Read the [`TxDetails`] of `txid`.
+    ///
+    /// Returns `None` if the store has no row in the `tx`, `txout` or `anchor` table for
+    /// the given `txid`.
+    pub async fn tx_details(&self, txid: Txid) -> Result<Option<TxDetails>, Error> {
+        let txid_str = txid.to_string();
+
+        let mut details = TxDetails {
+            txid,
+            tx: None,
+            anchors: BTreeSet::new(),
+            untimed_anchors: BTreeSet::new(),
+            first_seen: None,
+            last_seen: None,
+            last_evicted: None,
+            txouts: BTreeMap::new(),
+            conflicts: BTreeSet::new(),
+            label: None,
+            output_labels: BTreeMap::new(),
+        };
+        let mut found = false;
+
+        let row =
+            sqlx::query("SELECT tx_blob.tx, first_seen, last_seen, last_evicted FROM tx LEFT JOIN tx_blob ON tx_blob.id = tx.blob_id WHERE txid = $1")
+                .bind(&txid_str)
+                .fetch_optional(&self.pool)
+                .await?;
+        if let Some(row) = row {
+            found = true;
+            details.tx = row.get("tx");
+            let first_seen: Option<i64> = row.get("first_seen");
+            details.first_seen = from_sql_opt("tx.first_seen", first_seen)?;
+            let last_seen: Option<i64> = row.get("last_seen");
+            details.last_seen = from_sql_opt("tx.last_seen", last_seen)?;
+            let last_evicted: Option<i64> = row.get("last_evicted");
+            details.last_evicted = from_sql_opt("tx.last_evicted", last_evicted)?;
+        }
+
+        let rows = sqlx::query(
+            "SELECT block_height, block_hash, confirmation_time FROM anchor WHERE txid = $1",
+        )
+        .bind(&txid_str)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            found = true;
+            let height: u32 = row.get("block_height");
+            let hash: String = row.get("block_hash");
+            let hash: BlockHash = hash.parse()?;
+            let block_id = BlockId { height, hash };
+            match from_sql_opt("anchor.confirmation_time", row.get("confirmation_time"))? {
+                Some(confirmation_time) => {
+                    details.anchors.insert(ConfirmationBlockTime {
+                        block_id,
+                        confirmation_time,
+                    });
+                }
+                None => {
+                    details.untimed_anchors.insert(block_id);
+                }
+            }
+        }
+
+        let rows =
+            sqlx::query("SELECT vout, value, script FROM txout WHERE txid = $1 ORDER BY vout")
+                .bind(&txid_str)
+                .fetch_all(&self.pool)
+                .await?;
+        for row in rows {
+            found = true;
+            let vout: u32 = row.get("vout");
+            let value: i64 = row.get("value");
+            let script: Vec<u8> = row.get("script");
+            details.txouts.insert(
+                vout,
+                TxOut {
+                    value: Amount::from_sat(from_sql("txout.value", value)?),
+                    script_pubkey: ScriptBuf::from_bytes(script),
+                },
+            );
+        }
+
+        if !found {
+            return Ok(None);
+        }
+
+        details.conflicts = self.read_conflicts(txid).await?;
+
+        details.label = self.label(&LabelRef::Tx(txid)).await?;
+        let rows = sqlx::query(
+            "SELECT ref, label, encrypted FROM label WHERE type = 'output' AND ref LIKE $1",
+        )
+        .bind(format!("{txid_str}:%"))
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            let r: String = row.get("ref");
+            let op: OutPoint = r.parse()?;
+            let label = self.open_label(
+                &LabelRef::Output(op),
+                row.get("label"),
+                row.get("encrypted"),
+            )?;
+            details.output_labels.insert(op.vout, label);
+        }
+
+        Ok(Some(details))
+    }
+
+    /// Find stored transactions that spend any of the prevouts of the transaction `txid`.
+    async fn read_conflicts(&self, txid: Txid) -> Result<BTreeSet<Txid>, Error> {
+        let rows = sqlx::query(
+            "SELECT DISTINCT other.txid FROM txin JOIN txin AS other ON other.prev_txid = txin.prev_txid AND other.prev_vout = txin.prev_vout WHERE txin.txid = $1 AND other.txid != $1 ORDER BY other.txid",
+        )
+        .bind(txid.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut conflicts = BTreeSet::new();
+        for row in rows {
+            let other: String = row.get("txid");
+            conflicts.insert(other.parse()?);
+        }
+
+        Ok(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::tx_graph;
+    use bitcoin::{Transaction, TxIn, absolute, consensus, hashes::Hash, transaction};
+
+    fn spend(prevout: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn read_tx_details() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let prevout = OutPoint::new(Hash::hash(b"prev"), 0);
+        let tx = spend(prevout, 10_000);
+        let txid = tx.compute_txid();
+        let conflict = spend(prevout, 9_000);
+        let unrelated = spend(OutPoint::new(Hash::hash(b"other"), 0), 8_000);
+        let anchor = ConfirmationBlockTime {
+            block_id: BlockId {
+                height: 100,
+                hash: Hash::hash(b"100"),
+            },
+            confirmation_time: 1_700_000_000,
+        };
+
+        let mut cs = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        cs.txs.insert(Arc::new(tx.clone()));
+        cs.txs.insert(Arc::new(conflict.clone()));
+        cs.txs.insert(Arc::new(unrelated));
+        cs.anchors.insert((anchor, txid));
+        cs.first_seen.insert(txid, 1_699_999_000);
+        store.write_tx_graph(&cs).await?;
+        store.set_label(&LabelRef::Tx(txid), "rent").await?;
+        store
+            .set_label(&LabelRef::Output(OutPoint::new(txid, 0)), "landlord")
+            .await?;
+
+        let details = store.tx_details(txid).await?.expect("must find tx");
+        assert_eq!(details.tx, Some(consensus::encode::serialize(&tx)));
+        assert_eq!(details.anchors, [anchor].into());
+        assert_eq!(details.first_seen, Some(1_699_999_000));
+        assert_eq!(details.last_seen, None);
+        assert_eq!(details.conflicts, [conflict.compute_txid()].into());
+        assert_eq!(details.label.as_deref(), Some("rent"));
+        assert_eq!(details.output_labels, [(0, "landlord".to_string())].into());
+
+        assert!(store.tx_details(Hash::hash(b"missing")).await?.is_none());
+
+        Ok(())
+    }
+}
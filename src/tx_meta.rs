@@ -0,0 +1,173 @@
+//! Application metadata of transactions.
+
+use bdk_chain::bitcoin::Txid;
+use serde_json::Value;
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, to_sql};
+
+/// Metadata of a transaction, see [`Store::set_tx_meta`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxMeta {
+    /// Metadata
+    pub value: Value,
+    /// Version of the metadata schema the value was written with, see
+    /// [`Store::with_tx_meta_version`]
+    pub version: u32,
+    /// Unix timestamp of the last write of the value
+    pub updated_at: u64,
+}
+
+impl Store {
+    /// Set the version of the metadata schema of the application, recorded along with
+    /// every value written by [`set_tx_meta`](Self::set_tx_meta). Defaults to 0.
+    ///
+    /// Bump it when the shape of the metadata changes, then find the values to migrate
+    /// with [`outdated_tx_meta`](Self::outdated_tx_meta).
+    pub fn with_tx_meta_version(mut self, version: u32) -> Self {
+        self.tx_meta_version = version;
+        self
+    }
+
+    /// Set the metadata of `txid` to `value`, replacing any existing metadata.
+    ///
+    /// The value is stored as JSON in the `tx_meta` table, so that it can be queried in
+    /// SQL with the JSON functions of SQLite, e.g. `json_extract(meta, '$.invoice')`. The
+    /// transaction doesn't have to be stored.
+    pub async fn set_tx_meta(&self, txid: Txid, value: &Value) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO tx_meta(txid, meta, version, updated_at) VALUES($1, $2, $3, $4) ON CONFLICT DO UPDATE SET meta = $2, version = $3, updated_at = $4",
+            )
+            .bind(txid.to_string())
+            .bind(serde_json::to_string(value)?)
+            .bind(self.tx_meta_version)
+            .bind(to_sql("tx_meta.updated_at", self.now())?)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get the metadata of `txid`, if any.
+    pub async fn get_tx_meta(&self, txid: Txid) -> Result<Option<TxMeta>, Error> {
+        let row = sqlx::query("SELECT meta, version, updated_at FROM tx_meta WHERE txid = $1")
+            .bind(txid.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|row| {
+            let meta: String = row.get("meta");
+            Ok(TxMeta {
+                value: serde_json::from_str(&meta)?,
+                version: row.get("version"),
+                updated_at: from_sql("tx_meta.updated_at", row.get("updated_at"))?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Remove the metadata of `txid`.
+    pub async fn remove_tx_meta(&self, txid: Txid) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query("DELETE FROM tx_meta WHERE txid = $1")
+                .bind(txid.to_string())
+                .execute(&mut *conn)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The transactions whose metadata holds `value` at the JSON `path`, e.g.
+    /// `$.order.id`, ordered by txid.
+    ///
+    /// Values are compared as by SQLite's `json_extract`, so a JSON string matches a
+    /// string and a JSON number a number of the same value.
+    pub async fn txs_by_meta(&self, path: &str, value: &Value) -> Result<Vec<Txid>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid FROM tx_meta WHERE json_extract(meta, $1) = json_extract($2, '$') ORDER BY txid",
+        )
+        .bind(path)
+        .bind(serde_json::to_string(value)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| Ok(row.get::<String, _>("txid").parse()?))
+            .collect()
+    }
+
+    /// The transactions whose metadata was written with a schema version before `version`,
+    /// ordered by txid.
+    pub async fn outdated_tx_meta(&self, version: u32) -> Result<Vec<Txid>, Error> {
+        let rows = sqlx::query("SELECT txid FROM tx_meta WHERE version < $1 ORDER BY txid")
+            .bind(version)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| Ok(row.get::<String, _>("txid").parse()?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use serde_json::json;
+
+    use crate::FixedClock;
+
+    #[tokio::test]
+    async fn tx_meta() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_clock(FixedClock(1_000));
+        store.migrate().await?;
+
+        let a = Txid::hash(b"a");
+        let b = Txid::hash(b"b");
+        assert_eq!(store.get_tx_meta(a).await?, None);
+
+        store
+            .set_tx_meta(a, &json!({ "invoice": "INV-1", "category": 4 }))
+            .await?;
+        store
+            .set_tx_meta(b, &json!({ "invoice": "INV-2", "category": 4 }))
+            .await?;
+        assert_eq!(
+            store.get_tx_meta(a).await?,
+            Some(TxMeta {
+                value: json!({ "invoice": "INV-1", "category": 4 }),
+                version: 0,
+                updated_at: 1_000,
+            })
+        );
+
+        assert_eq!(store.txs_by_meta("$.invoice", &json!("INV-2")).await?, [b]);
+        let mut both = vec![a, b];
+        both.sort();
+        assert_eq!(store.txs_by_meta("$.category", &json!(4)).await?, both);
+        assert!(
+            store
+                .txs_by_meta("$.category", &json!("4"))
+                .await?
+                .is_empty()
+        );
+
+        // Metadata written before the schema version was bumped.
+        let store = store.with_tx_meta_version(1);
+        store.set_tx_meta(b, &json!({ "invoice": "INV-2" })).await?;
+        assert_eq!(store.outdated_tx_meta(1).await?, [a]);
+
+        store.remove_tx_meta(a).await?;
+        assert_eq!(store.get_tx_meta(a).await?, None);
+
+        Ok(())
+    }
+}
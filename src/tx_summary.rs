@@ -0,0 +1,391 @@
+//! Net amounts of transactions relative to the scripts of the spk cache.
+
+use std::collections::BTreeSet;
+
+use bdk_chain::bitcoin::{Amount, ScriptBuf, SignedAmount, Txid};
+use sqlx::sqlite::{SqliteConnection, SqliteRow};
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+use crate::async_store::{MAX_BIND_PARAMS, upsert};
+use crate::convert::{from_sql, sum_sql};
+
+/// Whether a transaction moves value to or from the wallet, see [`TxSummary::direction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TxDirection {
+    /// The wallet receives more than it spends.
+    Incoming,
+    /// The wallet spends more than it receives.
+    Outgoing,
+    /// The wallet receives as much as it spends, including not at all.
+    Neutral,
+}
+
+/// A row of the `tx_summary` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxSummary {
+    /// Txid
+    pub txid: Txid,
+    /// Value of the outputs paying to a script of the spk cache
+    pub received: Amount,
+    /// Value of the known previous outputs spent which pay to a script of the spk cache
+    pub sent: Amount,
+    /// `received - sent`
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "bdk_chain::bitcoin::amount::serde::as_sat")
+    )]
+    pub net: SignedAmount,
+}
+
+impl TxSummary {
+    /// Direction of the transaction relative to the wallet.
+    pub fn direction(&self) -> TxDirection {
+        match self.net.cmp(&SignedAmount::ZERO) {
+            core::cmp::Ordering::Greater => TxDirection::Incoming,
+            core::cmp::Ordering::Less => TxDirection::Outgoing,
+            core::cmp::Ordering::Equal => TxDirection::Neutral,
+        }
+    }
+}
+
+impl Store {
+    /// Read the summary of `txid`, if it has any stored output.
+    pub async fn tx_summary(&self, txid: Txid) -> Result<Option<TxSummary>, Error> {
+        let row = sqlx::query("SELECT txid, received, sent, net FROM tx_summary WHERE txid = $1")
+            .bind(txid.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(summary_from_row).transpose()
+    }
+
+    /// Read the summaries of all transactions with a stored output, ordered by net amount
+    /// and txid.
+    ///
+    /// A transaction spending a previous output which isn't stored doesn't count that
+    /// output as sent, so the net amount of a transaction is only exact if the wallet's
+    /// transactions it spends are stored.
+    pub async fn tx_summaries(&self) -> Result<Vec<TxSummary>, Error> {
+        let rows =
+            sqlx::query("SELECT txid, received, sent, net FROM tx_summary ORDER BY net, txid")
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.into_iter().map(summary_from_row).collect()
+    }
+
+    /// Populate the `tx_summary` table for transactions written by earlier versions.
+    pub(crate) async fn backfill_tx_summary(&self) -> Result<(), Error> {
+        let rows = sqlx::query(
+            "SELECT txid FROM tx_output UNION SELECT txid FROM txout EXCEPT SELECT txid FROM tx_summary",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let txids: BTreeSet<String> = rows.iter().map(|row| row.get("txid")).collect();
+        self.write(WriteOptions::default(), async |conn| {
+            refresh_tx_summaries(conn, &txids).await
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn summary_from_row(row: SqliteRow) -> Result<TxSummary, Error> {
+    let txid: String = row.get("txid");
+    let received: u64 = from_sql("tx_summary.received", row.get::<i64, _>("received"))?;
+    let sent: u64 = from_sql("tx_summary.sent", row.get::<i64, _>("sent"))?;
+
+    Ok(TxSummary {
+        txid: txid.parse()?,
+        received: Amount::from_sat(received),
+        sent: Amount::from_sat(sent),
+        net: SignedAmount::from_sat(row.get("net")),
+    })
+}
+
+/// Recompute the summaries of `txids`.
+///
+/// Fails with [`Error::ValueOutOfRange`] if the amount received or sent by a transaction
+/// doesn't fit an INTEGER column.
+pub(crate) async fn refresh_tx_summaries(
+    conn: &mut SqliteConnection,
+    txids: &BTreeSet<String>,
+) -> Result<WriteSummary, Error> {
+    let mut summary = WriteSummary::default();
+    for txid in txids {
+        // These select the same rows as `v_utxos`, which can't be filtered by the outputs
+        // spent by a transaction without scanning every output.
+        let rows = sqlx::query(
+            "SELECT o.value FROM (
+                SELECT txid, vout, value, script FROM tx_output WHERE txid = $1
+                UNION
+                SELECT txid, vout, value, script FROM txout WHERE txid = $1
+            ) AS o
+            WHERE o.script IN (SELECT script FROM keychain_script_pubkey)",
+        )
+        .bind(txid)
+        .fetch_all(&mut *conn)
+        .await?;
+        let received = sum_sql(
+            "tx_summary.received",
+            rows.iter().map(|row| row.get("value")),
+        )?;
+        let rows = sqlx::query(
+            "SELECT o.value FROM (
+                SELECT o.txid, o.vout, o.value, o.script FROM txin
+                JOIN tx_output AS o ON o.txid = txin.prev_txid AND o.vout = txin.prev_vout
+                WHERE txin.txid = $1
+                UNION
+                SELECT o.txid, o.vout, o.value, o.script FROM txin
+                JOIN txout AS o ON o.txid = txin.prev_txid AND o.vout = txin.prev_vout
+                WHERE txin.txid = $1
+            ) AS o
+            WHERE o.script IN (SELECT script FROM keychain_script_pubkey)",
+        )
+        .bind(txid)
+        .fetch_all(&mut *conn)
+        .await?;
+        let sent = sum_sql("tx_summary.sent", rows.iter().map(|row| row.get("value")))?;
+        // Both are non-negative, so the difference can't overflow.
+        let net = received - sent;
+
+        upsert(
+            conn,
+            &mut summary,
+            "tx_summary",
+            sqlx::query(
+                "INSERT OR IGNORE INTO tx_summary(txid, received, sent, net) VALUES($1, $2, $3, $4)",
+            )
+            .bind(txid)
+            .bind(received)
+            .bind(sent)
+            .bind(net),
+            sqlx::query(
+                "UPDATE tx_summary SET received = $2, sent = $3, net = $4 WHERE txid = $1 AND (received IS NOT $2 OR sent IS NOT $3)",
+            )
+            .bind(txid)
+            .bind(received)
+            .bind(sent)
+            .bind(net),
+        )
+        .await?;
+    }
+
+    Ok(summary)
+}
+
+/// The txids whose summary depends on the outputs of `txid`, i.e. `txid` itself and the
+/// transactions spending its outputs.
+pub(crate) async fn affected_by_tx(
+    conn: &mut SqliteConnection,
+    txid: &str,
+) -> Result<BTreeSet<String>, Error> {
+    let rows = sqlx::query("SELECT $1 AS txid UNION SELECT txid FROM txin WHERE prev_txid = $1")
+        .bind(txid)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(rows.iter().map(|row| row.get("txid")).collect())
+}
+
+/// The txids whose summary depends on whether `scripts` are in the spk cache, i.e. the
+/// transactions paying to them and the transactions spending those outputs.
+pub(crate) async fn affected_by_scripts(
+    conn: &mut SqliteConnection,
+    scripts: &[ScriptBuf],
+) -> Result<BTreeSet<String>, Error> {
+    let mut affected = BTreeSet::new();
+    for chunk in scripts.chunks(MAX_BIND_PARAMS) {
+        let mut query = QueryBuilder::<Sqlite>::new("WITH script(script) AS (");
+        query.push_values(chunk, |mut row, script| {
+            row.push_bind(script.as_bytes());
+        });
+        query.push(
+            "), output AS (
+                SELECT txid, vout FROM tx_output WHERE script IN (SELECT script FROM script)
+                UNION
+                SELECT txid, vout FROM txout WHERE script IN (SELECT script FROM script)
+            )
+            SELECT txid FROM output
+            UNION
+            SELECT txin.txid FROM txin JOIN output ON output.txid = txin.prev_txid AND output.vout = txin.prev_vout",
+        );
+        let rows = query.build().fetch_all(&mut *conn).await?;
+        affected.extend(rows.iter().map(|row| row.get::<String, _>("txid")));
+    }
+
+    Ok(affected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{OutPoint, Transaction, TxIn, TxOut, absolute, transaction};
+    use bdk_chain::{ConfirmationBlockTime, DescriptorId, keychain_txout, tx_graph};
+
+    fn tx(prevout: OutPoint, outputs: &[(ScriptBuf, u64)]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: outputs
+                .iter()
+                .map(|(script_pubkey, value)| TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: script_pubkey.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn tx_summary() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let ours = ScriptBuf::from_bytes(vec![0x51]);
+        let theirs = ScriptBuf::from_bytes(vec![0x52]);
+        let receive = tx(
+            OutPoint::new(Hash::hash(b"in"), 0),
+            &[(ours.clone(), 10_000)],
+        );
+        let receive_txid = receive.compute_txid();
+        let spend = tx(
+            OutPoint::new(receive_txid, 0),
+            &[(theirs.clone(), 6_000), (ours.clone(), 3_000)],
+        );
+        let spend_txid = spend.compute_txid();
+
+        // The spend is written before the transaction it spends and the spk cache.
+        let graph = |tx: Transaction| tx_graph::ChangeSet::<ConfirmationBlockTime> {
+            txs: [Arc::new(tx)].into(),
+            ..Default::default()
+        };
+        store.write_tx_graph(&graph(spend)).await?;
+        let summary = store.tx_summary(spend_txid).await?.unwrap();
+        assert_eq!(summary.direction(), TxDirection::Neutral);
+        store.write_tx_graph(&graph(receive)).await?;
+
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            DescriptorId::from_byte_array([1; 32]),
+            BTreeMap::from([(0, ours.clone())]),
+        );
+        let write = store.write_keychain_txout(&indexer).await?;
+        assert_eq!(write.table("tx_summary").updated, 2);
+
+        let summaries = store.tx_summaries().await?;
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].txid, spend_txid);
+        assert_eq!(summaries[0].received, Amount::from_sat(3_000));
+        assert_eq!(summaries[0].sent, Amount::from_sat(10_000));
+        assert_eq!(summaries[0].net, SignedAmount::from_sat(-7_000));
+        assert_eq!(summaries[0].direction(), TxDirection::Outgoing);
+        assert_eq!(summaries[1].txid, receive_txid);
+        assert_eq!(summaries[1].net, SignedAmount::from_sat(10_000));
+        assert_eq!(summaries[1].direction(), TxDirection::Incoming);
+
+        // Rewriting the same data leaves the summaries as they are.
+        let write = store.write_keychain_txout(&indexer).await?;
+        assert!(write.is_empty());
+
+        sqlx::query("DELETE FROM tx_summary")
+            .execute(&store.pool)
+            .await?;
+        store.migrate().await?;
+        assert_eq!(store.tx_summaries().await?, summaries);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn script_of_two_descriptors() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        // A script cached under two descriptors, e.g. a rotated one, counts once.
+        let ours = ScriptBuf::from_bytes(vec![0x51]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        for descriptor in [1, 2] {
+            indexer.spk_cache.insert(
+                DescriptorId::from_byte_array([descriptor; 32]),
+                BTreeMap::from([(0, ours.clone())]),
+            );
+        }
+        store.write_keychain_txout(&indexer).await?;
+
+        let receive = tx(
+            OutPoint::new(Hash::hash(b"in"), 0),
+            &[(ours.clone(), 10_000)],
+        );
+        let spend = tx(OutPoint::new(receive.compute_txid(), 0), &[]);
+        store
+            .write_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(receive.clone()), Arc::new(spend.clone())].into(),
+                ..Default::default()
+            })
+            .await?;
+
+        let receive = store.tx_summary(receive.compute_txid()).await?.unwrap();
+        assert_eq!(receive.received, Amount::from_sat(10_000));
+        let spend = store.tx_summary(spend.compute_txid()).await?;
+        assert_eq!(spend.map(|s| s.sent), Some(Amount::from_sat(10_000)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tx_summary_overflow() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let ours = ScriptBuf::from_bytes(vec![0x51]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            DescriptorId::from_byte_array([1; 32]),
+            BTreeMap::from([(0, ours.clone())]),
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        // Each output fits a column, but not their sum.
+        let max = i64::MAX as u64;
+        let tx = tx(
+            OutPoint::new(Hash::hash(b"in"), 0),
+            &[(ours.clone(), max), (ours, 1)],
+        );
+        let res = store
+            .write_tx_graph(&tx_graph::ChangeSet::<ConfirmationBlockTime> {
+                txs: [Arc::new(tx)].into(),
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::ValueOutOfRange {
+                column: "tx_summary.received",
+                ..
+            })
+        ));
+        assert!(store.read_tx_graph().await?.txs.is_empty());
+
+        Ok(())
+    }
+}
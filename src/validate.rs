@@ -0,0 +1,380 @@
+//! Validation of wallet changesets before they are written.
+
+use core::fmt;
+use std::collections::HashMap;
+
+use bdk_chain::bitcoin::{Amount, BlockHash, OutPoint, Transaction, TxOut, Txid};
+use bdk_chain::{BlockId, DescriptorId};
+use bdk_wallet::ChangeSet;
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+use crate::convert::from_sql;
+
+/// A problem of a changeset found by [`Store::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Violation {
+    /// A txout differs from the output of the full transaction with its txid, in the
+    /// changeset or stored, or the transaction has no output at its index.
+    TxOutMismatch {
+        /// Outpoint of the txout
+        outpoint: OutPoint,
+    },
+    /// The block of an anchor is neither in the local chain of the changeset nor stored.
+    AnchorBlockMissing {
+        /// Anchored transaction
+        txid: Txid,
+        /// Block of the anchor
+        block: BlockId,
+    },
+    /// The block of an anchor differs from the block at its height, in the local chain of
+    /// the changeset or stored.
+    AnchorBlockMismatch {
+        /// Anchored transaction
+        txid: Txid,
+        /// Block of the anchor
+        block: BlockId,
+        /// Hash of the block at the height of the anchor
+        hash: BlockHash,
+    },
+    /// The last revealed index of a descriptor is lower than the stored one.
+    LastRevealedDecreased {
+        /// Descriptor
+        descriptor_id: DescriptorId,
+        /// Stored last revealed index
+        stored: u32,
+        /// Last revealed index of the changeset
+        revealed: u32,
+    },
+    /// A value is outside of the range it can take, e.g. an output value above the
+    /// maximum supply or a time which doesn't fit an INTEGER column.
+    ValueOutOfRange {
+        /// Field of the changeset, e.g. `tx_graph.last_seen`
+        field: &'static str,
+        /// Value
+        value: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TxOutMismatch { outpoint } => {
+                write!(f, "txout {outpoint} differs from its transaction")
+            }
+            Self::AnchorBlockMissing { txid, block } => write!(
+                f,
+                "anchor of {txid} references unknown block {}:{}",
+                block.height, block.hash
+            ),
+            Self::AnchorBlockMismatch { txid, block, hash } => write!(
+                f,
+                "anchor of {txid} references block {}:{}, but the block at that height is {hash}",
+                block.height, block.hash
+            ),
+            Self::LastRevealedDecreased {
+                descriptor_id,
+                stored,
+                revealed,
+            } => write!(
+                f,
+                "last revealed index of {descriptor_id} decreases from {stored} to {revealed}"
+            ),
+            Self::ValueOutOfRange { field, value } => {
+                write!(f, "value out of range for {field}: {value}")
+            }
+        }
+    }
+}
+
+impl Store {
+    /// Check `changeset` against itself and the stored data, returning the problems found.
+    ///
+    /// This checks that txouts match the full transactions with their txid, that the blocks
+    /// of anchors are in the local chain, that last revealed indices don't decrease and
+    /// that values and times are in range. A changeset passing these checks can still be
+    /// rejected by the write, e.g. by [`Error::NetworkMismatch`].
+    ///
+    /// See [`with_validation`](Self::with_validation) to validate every changeset written.
+    pub async fn validate(&self, changeset: &ChangeSet) -> Result<Vec<Violation>, Error> {
+        let mut conn = self.pool.acquire().await?;
+        validate_in(&mut conn, changeset).await
+    }
+
+    /// Set whether [`write_changeset`](Self::write_changeset) and
+    /// [`prepare_changeset`](Self::prepare_changeset) validate the changeset as with
+    /// [`validate`](Self::validate) in the transaction of the write, failing with
+    /// [`Error::InvalidChangeset`] instead of writing it if there is any violation.
+    /// Defaults to `false`.
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+}
+
+/// The violations of `changeset` on `conn`.
+pub(crate) async fn validate_in(
+    conn: &mut SqliteConnection,
+    changeset: &ChangeSet,
+) -> Result<Vec<Violation>, Error> {
+    let mut violations = vec![];
+    let tx_graph = &changeset.tx_graph;
+
+    let max_time = i64::MAX as u64;
+    for (field, times) in [
+        ("tx_graph.first_seen", &tx_graph.first_seen),
+        ("tx_graph.last_seen", &tx_graph.last_seen),
+        ("tx_graph.last_evicted", &tx_graph.last_evicted),
+    ] {
+        for &value in times.values().filter(|&&t| t > max_time) {
+            violations.push(Violation::ValueOutOfRange { field, value });
+        }
+    }
+    for (anchor, _) in &tx_graph.anchors {
+        if anchor.confirmation_time > max_time {
+            violations.push(Violation::ValueOutOfRange {
+                field: "tx_graph.anchors",
+                value: anchor.confirmation_time,
+            });
+        }
+    }
+    let outputs = tx_graph
+        .txs
+        .iter()
+        .flat_map(|tx| &tx.output)
+        .map(|txout| ("tx_graph.txs", txout));
+    let txouts = tx_graph
+        .txouts
+        .values()
+        .map(|txout| ("tx_graph.txouts", txout));
+    for (field, txout) in outputs.chain(txouts) {
+        if txout.value > Amount::MAX_MONEY {
+            violations.push(Violation::ValueOutOfRange {
+                field,
+                value: txout.value.to_sat(),
+            });
+        }
+    }
+
+    let txs: HashMap<Txid, &Transaction> = tx_graph
+        .txs
+        .iter()
+        .map(|tx| (tx.compute_txid(), tx.as_ref()))
+        .collect();
+    for (&outpoint, txout) in &tx_graph.txouts {
+        let output = match txs.get(&outpoint.txid) {
+            Some(tx) => Some(tx.output.get(outpoint.vout as usize).cloned()),
+            None => stored_output(conn, outpoint).await?,
+        };
+        if matches!(output, Some(output) if output.as_ref() != Some(txout)) {
+            violations.push(Violation::TxOutMismatch { outpoint });
+        }
+    }
+
+    for (anchor, txid) in &tx_graph.anchors {
+        let block = anchor.block_id;
+        let hash = match changeset.local_chain.blocks.get(&block.height) {
+            Some(hash) => *hash,
+            None => {
+                let row = sqlx::query("SELECT hash FROM block WHERE height = $1")
+                    .bind(block.height)
+                    .fetch_optional(&mut *conn)
+                    .await?;
+                row.map(|row| row.get::<String, _>("hash").parse())
+                    .transpose()?
+            }
+        };
+        match hash {
+            None => violations.push(Violation::AnchorBlockMissing { txid: *txid, block }),
+            Some(hash) if hash != block.hash => violations.push(Violation::AnchorBlockMismatch {
+                txid: *txid,
+                block,
+                hash,
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (&descriptor_id, &revealed) in &changeset.indexer.last_revealed {
+        let row = sqlx::query(
+            "SELECT last_revealed FROM keychain_last_revealed WHERE descriptor_id = $1",
+        )
+        .bind(descriptor_id.to_string())
+        .fetch_optional(&mut *conn)
+        .await?;
+        let Some(row) = row else { continue };
+        let stored: u32 = row.get("last_revealed");
+        if revealed < stored {
+            violations.push(Violation::LastRevealedDecreased {
+                descriptor_id,
+                stored,
+                revealed,
+            });
+        }
+    }
+
+    Ok(violations)
+}
+
+/// The output at `outpoint` of the stored full transaction, `Some(None)` if the transaction
+/// has no such output, or `None` if the transaction isn't stored.
+async fn stored_output(
+    conn: &mut SqliteConnection,
+    outpoint: OutPoint,
+) -> Result<Option<Option<TxOut>>, Error> {
+    let txid = outpoint.txid.to_string();
+    let row = sqlx::query("SELECT 1 FROM tx WHERE txid = $1 AND blob_id IS NOT NULL")
+        .bind(&txid)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if row.is_none() {
+        return Ok(None);
+    }
+    let row = sqlx::query("SELECT value, script FROM tx_output WHERE txid = $1 AND vout = $2")
+        .bind(&txid)
+        .bind(outpoint.vout)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    row.map(|row| {
+        Ok(TxOut {
+            value: Amount::from_sat(from_sql("tx_output.value", row.get("value"))?),
+            script_pubkey: row.get::<Vec<u8>, _>("script").into(),
+        })
+    })
+    .transpose()
+    .map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::bitcoin::{ScriptBuf, Transaction, absolute, transaction};
+    use bdk_chain::{ConfirmationBlockTime, keychain_txout, local_chain, tx_graph};
+
+    #[tokio::test]
+    async fn validate() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_validation(true);
+        store.migrate().await?;
+
+        let descriptor_id = DescriptorId::from_byte_array([1; 32]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.last_revealed.insert(descriptor_id, 5);
+        store.write_keychain_txout(&indexer).await?;
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let txid = tx.compute_txid();
+        let block = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        let valid = ChangeSet {
+            local_chain: local_chain::ChangeSet {
+                blocks: [(block.height, Some(block.hash))].into(),
+            },
+            tx_graph: tx_graph::ChangeSet {
+                txouts: [(OutPoint::new(txid, 0), tx.output[0].clone())].into(),
+                txs: [Arc::new(tx.clone())].into(),
+                anchors: [(
+                    ConfirmationBlockTime {
+                        block_id: block,
+                        confirmation_time: 100,
+                    },
+                    txid,
+                )]
+                .into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(store.validate(&valid).await?.is_empty());
+        store.write_changeset(&valid).await?;
+
+        let other = BlockId {
+            height: 2,
+            hash: Hash::hash(b"2"),
+        };
+        let invalid = ChangeSet {
+            tx_graph: tx_graph::ChangeSet {
+                txouts: [(
+                    OutPoint::new(txid, 0),
+                    TxOut {
+                        value: Amount::from_sat(2_000),
+                        script_pubkey: ScriptBuf::new(),
+                    },
+                )]
+                .into(),
+                anchors: [
+                    (
+                        ConfirmationBlockTime {
+                            block_id: BlockId {
+                                height: 1,
+                                hash: Hash::hash(b"1b"),
+                            },
+                            confirmation_time: 100,
+                        },
+                        txid,
+                    ),
+                    (
+                        ConfirmationBlockTime {
+                            block_id: other,
+                            confirmation_time: 200,
+                        },
+                        txid,
+                    ),
+                ]
+                .into(),
+                last_seen: [(txid, u64::MAX)].into(),
+                ..Default::default()
+            },
+            indexer: keychain_txout::ChangeSet {
+                last_revealed: [(descriptor_id, 4)].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let violations = store.validate(&invalid).await?;
+        assert_eq!(violations.len(), 5);
+        assert!(violations.contains(&Violation::TxOutMismatch {
+            outpoint: OutPoint::new(txid, 0)
+        }));
+        assert!(violations.contains(&Violation::AnchorBlockMissing { txid, block: other }));
+        assert!(violations.contains(&Violation::LastRevealedDecreased {
+            descriptor_id,
+            stored: 5,
+            revealed: 4,
+        }));
+        assert!(violations.contains(&Violation::ValueOutOfRange {
+            field: "tx_graph.last_seen",
+            value: u64::MAX,
+        }));
+
+        // Nothing of the invalid changeset is written.
+        assert!(matches!(
+            store.write_changeset(&invalid).await,
+            Err(Error::InvalidChangeset(v)) if v == violations
+        ));
+        assert!(store.read_tx_graph().await?.last_seen.is_empty());
+        assert!(matches!(
+            store.prepare_changeset(&invalid).await,
+            Err(Error::InvalidChangeset(v)) if v == violations
+        ));
+
+        Ok(())
+    }
+}
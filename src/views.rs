@@ -0,0 +1,598 @@
+//! Typed readers of the `v_transactions`, `v_utxos` and `v_addresses` views.
+
+use std::sync::Arc;
+
+use bdk_chain::{BlockId, DescriptorId, bitcoin};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::{Amount, BlockHash, OutPoint, ScriptBuf, SignedAmount, Transaction, Txid, consensus};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::Error;
+use crate::LabelRef;
+use crate::Store;
+use crate::convert::{from_sql, from_sql_opt, to_sql};
+
+/// A row of the `v_transactions` view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransactionRow {
+    /// Txid
+    pub txid: Txid,
+    /// First seen
+    pub first_seen: Option<u64>,
+    /// Last seen
+    pub last_seen: Option<u64>,
+    /// Last evicted
+    pub last_evicted: Option<u64>,
+    /// Weight, if the full transaction is stored
+    pub weight: Option<u64>,
+    /// Virtual size, if the full transaction is stored
+    pub vsize: Option<u64>,
+    /// Block the transaction is confirmed in, if anchored to a block of the local chain
+    pub block_id: Option<BlockId>,
+    /// Confirmation time, if anchored to a block of the local chain
+    pub confirmation_time: Option<u64>,
+    /// Label
+    pub label: Option<String>,
+}
+
+/// An unspent output of the `v_utxos` view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UtxoRow {
+    /// Outpoint
+    pub outpoint: OutPoint,
+    /// Value
+    pub value: Amount,
+    /// Script pubkey
+    pub script_pubkey: ScriptBuf,
+    /// Id of the descriptor deriving the script pubkey
+    pub descriptor_id: DescriptorId,
+    /// Derivation index of the script pubkey
+    pub derivation_index: u32,
+}
+
+/// A page of rows, see [`Store::transactions_page`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Page<T> {
+    /// Rows of the page
+    pub items: Vec<T>,
+    /// Cursor to read the next page with, `None` if this is the last page
+    pub next_cursor: Option<String>,
+}
+
+/// A transaction of the first history screen, see [`Store::recent_txs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentTx {
+    /// Row of the `v_transactions` view
+    pub row: TransactionRow,
+    /// Full transaction, if stored
+    pub tx: Option<Arc<Transaction>>,
+    /// Net amount of the transaction relative to the spk cache, if it has a stored output
+    pub net: Option<SignedAmount>,
+}
+
+/// A row of the `v_addresses` view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressRow {
+    /// Id of the descriptor deriving the script pubkey
+    pub descriptor_id: DescriptorId,
+    /// Derivation index
+    pub derivation_index: u32,
+    /// Script pubkey
+    pub script_pubkey: ScriptBuf,
+    /// Whether the derivation index is revealed
+    pub revealed: bool,
+    /// Whether any known output pays to the script pubkey
+    pub used: bool,
+}
+
+impl Store {
+    /// Read the `v_transactions` view, ordered by txid.
+    pub async fn transactions(&self) -> Result<Vec<TransactionRow>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid, first_seen, last_seen, last_evicted, weight, vsize, block_height, block_hash, confirmation_time, label, label_encrypted FROM v_transactions ORDER BY txid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| self.transaction_from_row(row))
+            .collect()
+    }
+
+    /// Read a page of at most `limit` rows of the `v_transactions` view, newest first.
+    ///
+    /// Rows are ordered by first seen time, those never seen last, then by txid. Pass the
+    /// [`Page::next_cursor`] of a page as `after` to read the next one, or `None` to read
+    /// the first. The cursor is opaque and stays valid as transactions are written, so
+    /// that a wallet server can hand it out to back a "load more" endpoint.
+    pub async fn transactions_page(
+        &self,
+        after: Option<&str>,
+        limit: u32,
+    ) -> Result<Page<TransactionRow>, Error> {
+        let (first_seen, txid) = match after {
+            Some(cursor) => decode_cursor(cursor)?,
+            None => (i64::MAX, String::new()),
+        };
+        // Seen transactions, then those never seen, each a range of the `tx_first_seen`
+        // index.
+        let mut rows = vec![];
+        if first_seen >= 0 {
+            let query = tx_page_query("(tx.first_seen, tx.txid) < ($1, $2)", limit);
+            rows = sqlx::query(&query)
+                .bind(first_seen)
+                .bind(&txid)
+                .fetch_all(&self.pool)
+                .await?;
+        }
+        if rows.len() <= limit as usize {
+            let limit = limit - rows.len() as u32;
+            let after_unseen = first_seen == -1;
+            let query = match after_unseen {
+                true => tx_page_query("tx.first_seen IS NULL AND tx.txid < $1", limit),
+                false => tx_page_query("tx.first_seen IS NULL", limit),
+            };
+            let mut query = sqlx::query(&query);
+            if after_unseen {
+                query = query.bind(&txid);
+            }
+            rows.extend(query.fetch_all(&self.pool).await?);
+        }
+
+        let mut items = rows
+            .into_iter()
+            .map(|row| self.transaction_from_row(row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let next_cursor = match items.len() > limit as usize {
+            true => {
+                items.truncate(limit as usize);
+                items.last().map(encode_cursor).transpose()?
+            }
+            false => None,
+        };
+
+        Ok(Page { items, next_cursor })
+    }
+
+    /// Read the `n` most recent transactions, newest first.
+    ///
+    /// Unconfirmed transactions come first ordered by last seen time, then confirmed ones
+    /// by height of the block they are confirmed in. Unconfirmed transactions evicted from
+    /// the mempool since they were last seen are left out. The full transactions are decoded
+    /// for the returned rows only, so that a UI can render the first history screen without
+    /// loading the transaction graph. Ordering still scans every stored transaction.
+    pub async fn recent_txs(&self, n: u32) -> Result<Vec<RecentTx>, Error> {
+        let rows = sqlx::query(
+            "SELECT v.txid, v.first_seen, v.last_seen, v.last_evicted, v.weight, v.vsize, v.block_height, v.block_hash, v.confirmation_time, v.label, v.label_encrypted, tx_blob.tx AS raw, tx_summary.net
+            FROM v_transactions AS v
+            JOIN tx ON tx.txid = v.txid
+            LEFT JOIN tx_blob ON tx_blob.id = tx.blob_id
+            LEFT JOIN tx_summary ON tx_summary.txid = v.txid
+            WHERE v.block_height IS NOT NULL OR v.last_evicted IS NULL OR v.last_evicted < COALESCE(v.last_seen, 0)
+            ORDER BY v.block_height IS NULL DESC, v.block_height DESC, COALESCE(v.last_seen, v.first_seen, -1) DESC, v.txid DESC
+            LIMIT $1",
+        )
+        .bind(n)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut txs = vec![];
+        for row in rows {
+            let tx = match row.get::<Option<Vec<u8>>, _>("raw") {
+                Some(data) => Some(Arc::new(consensus::encode::deserialize(&data)?)),
+                None => None,
+            };
+            let net = row.get::<Option<i64>, _>("net").map(SignedAmount::from_sat);
+            txs.push(RecentTx {
+                row: self.transaction_from_row(row)?,
+                tx,
+                net,
+            });
+        }
+
+        Ok(txs)
+    }
+
+    /// Read the unspent outputs of the `v_utxos` view, ordered by outpoint.
+    ///
+    /// An output is considered spent if any stored transaction spends it.
+    pub async fn utxos(&self) -> Result<Vec<UtxoRow>, Error> {
+        let rows = sqlx::query(
+            "SELECT txid, vout, value, script, descriptor_id, derivation_index FROM v_utxos WHERE spent_by IS NULL ORDER BY txid, vout",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut utxos = vec![];
+        for row in rows {
+            let txid: String = row.get("txid");
+            let outpoint = OutPoint {
+                txid: txid.parse()?,
+                vout: row.get("vout"),
+            };
+            let value: i64 = row.get("value");
+            let descriptor_id: String = row.get("descriptor_id");
+            utxos.push(UtxoRow {
+                outpoint,
+                value: Amount::from_sat(from_sql("v_utxos.value", value)?),
+                script_pubkey: ScriptBuf::from_bytes(row.get("script")),
+                descriptor_id: descriptor_id.parse()?,
+                derivation_index: row.get("derivation_index"),
+            });
+        }
+
+        Ok(utxos)
+    }
+
+    /// Read the `v_addresses` view, ordered by descriptor id and derivation index.
+    pub async fn addresses(&self) -> Result<Vec<AddressRow>, Error> {
+        let rows = sqlx::query(
+            "SELECT descriptor_id, derivation_index, script, revealed, used FROM v_addresses ORDER BY descriptor_id, derivation_index",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut addresses = vec![];
+        for row in rows {
+            let descriptor_id: String = row.get("descriptor_id");
+            addresses.push(AddressRow {
+                descriptor_id: descriptor_id.parse()?,
+                derivation_index: row.get("derivation_index"),
+                script_pubkey: ScriptBuf::from_bytes(row.get("script")),
+                revealed: row.get("revealed"),
+                used: row.get("used"),
+            });
+        }
+
+        Ok(addresses)
+    }
+
+    /// Decode a row of the `v_transactions` view, opening its label.
+    fn transaction_from_row(&self, row: SqliteRow) -> Result<TransactionRow, Error> {
+        let txid: String = row.get("txid");
+        let block_height: Option<u32> = row.get("block_height");
+        let block_hash: Option<String> = row.get("block_hash");
+        let block_id = match (block_height, block_hash) {
+            (Some(height), Some(hash)) => Some(BlockId {
+                height,
+                hash: hash.parse::<BlockHash>()?,
+            }),
+            _ => None,
+        };
+
+        let txid: Txid = txid.parse()?;
+        let label = match row.get::<Option<String>, _>("label") {
+            Some(label) => {
+                Some(self.open_label(&LabelRef::Tx(txid), label, row.get("label_encrypted"))?)
+            }
+            None => None,
+        };
+
+        Ok(TransactionRow {
+            txid,
+            first_seen: from_sql_opt("v_transactions.first_seen", row.get("first_seen"))?,
+            last_seen: from_sql_opt("v_transactions.last_seen", row.get("last_seen"))?,
+            last_evicted: from_sql_opt("v_transactions.last_evicted", row.get("last_evicted"))?,
+            weight: from_sql_opt("v_transactions.weight", row.get("weight"))?,
+            vsize: from_sql_opt("v_transactions.vsize", row.get("vsize"))?,
+            block_id,
+            confirmation_time: from_sql_opt(
+                "v_transactions.confirmation_time",
+                row.get("confirmation_time"),
+            )?,
+            label,
+        })
+    }
+}
+
+/// Encode the position after `row` as a cursor, the hex of `first_seen:txid` with `-1` for
+/// a transaction never seen.
+fn encode_cursor(row: &TransactionRow) -> Result<String, Error> {
+    let first_seen = match row.first_seen {
+        Some(t) => to_sql("v_transactions.first_seen", t)?,
+        None => -1,
+    };
+
+    Ok(format!("{first_seen}:{}", row.txid)
+        .as_bytes()
+        .to_lower_hex_string())
+}
+
+/// Query of a page of [`Store::transactions_page`] of the transactions matching `filter`,
+/// reading one row more than `limit` to tell whether there is a next page.
+///
+/// Selects the columns of `v_transactions` from the `tx` table, so that the page is read
+/// from the `tx_first_seen` index rather than the whole view.
+fn tx_page_query(filter: &str, limit: u32) -> String {
+    format!(
+        "SELECT tx.txid, tx.first_seen, tx.last_seen, tx.last_evicted, tx.weight, tx.vsize, anchor.block_height, anchor.block_hash, anchor.confirmation_time, label.label, label.encrypted AS label_encrypted
+        FROM tx
+        LEFT JOIN anchor ON anchor.rowid = (
+            SELECT a.rowid FROM anchor AS a
+            JOIN block ON block.height = a.block_height AND block.hash = a.block_hash
+            WHERE a.txid = tx.txid
+            ORDER BY a.block_height
+            LIMIT 1
+        )
+        LEFT JOIN label ON label.type = 'tx' AND label.ref = tx.txid
+        WHERE {filter}
+        ORDER BY tx.first_seen DESC, tx.txid DESC
+        LIMIT {}",
+        i64::from(limit) + 1
+    )
+}
+
+/// Decode a cursor of [`encode_cursor`].
+fn decode_cursor(cursor: &str) -> Result<(i64, String), Error> {
+    let invalid = || Error::InvalidCursor(cursor.to_string());
+    let data = Vec::<u8>::from_hex(cursor).map_err(|_| invalid())?;
+    let data = String::from_utf8(data).map_err(|_| invalid())?;
+    let (first_seen, txid) = data.split_once(':').ok_or_else(invalid)?;
+    let first_seen = first_seen.parse().map_err(|_| invalid())?;
+    let txid: Txid = txid.parse().map_err(|_| invalid())?;
+
+    Ok((first_seen, txid.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use bdk_chain::{ConfirmationBlockTime, keychain_txout, local_chain, tx_graph};
+    use bitcoin::{Transaction, TxIn, TxOut, absolute, hashes::Hash, transaction};
+
+    fn tx(prevout: OutPoint, script_pubkey: ScriptBuf, value: u64) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey,
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn read_views() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let descriptor_id = DescriptorId::from_byte_array([1; 32]);
+        let spk_0 = ScriptBuf::from_bytes(vec![0x51]);
+        let spk_1 = ScriptBuf::from_bytes(vec![0x52]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.last_revealed.insert(descriptor_id, 0);
+        indexer.spk_cache.insert(
+            descriptor_id,
+            BTreeMap::from([(0, spk_0.clone()), (1, spk_1.clone())]),
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        let receive = tx(OutPoint::new(Hash::hash(b"in"), 0), spk_0.clone(), 10_000);
+        let receive_txid = receive.compute_txid();
+        let spend = tx(OutPoint::new(receive_txid, 0), spk_0.clone(), 9_000);
+        let spend_txid = spend.compute_txid();
+        let block = BlockId {
+            height: 1,
+            hash: Hash::hash(b"1"),
+        };
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(block.height, Some(block.hash));
+        store.write_local_chain(&chain).await?;
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        graph.txs.insert(Arc::new(receive));
+        graph.txs.insert(Arc::new(spend));
+        graph.anchors.insert((
+            ConfirmationBlockTime {
+                block_id: block,
+                confirmation_time: 100,
+            },
+            receive_txid,
+        ));
+        store.write_tx_graph(&graph).await?;
+
+        let transactions = store.transactions().await?;
+        assert_eq!(transactions.len(), 2);
+        let confirmed = transactions
+            .iter()
+            .find(|row| row.txid == receive_txid)
+            .unwrap();
+        assert_eq!(confirmed.block_id, Some(block));
+        assert_eq!(confirmed.confirmation_time, Some(100));
+        let unconfirmed = transactions
+            .iter()
+            .find(|row| row.txid == spend_txid)
+            .unwrap();
+        assert_eq!(unconfirmed.block_id, None);
+
+        let utxos = store.utxos().await?;
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint, OutPoint::new(spend_txid, 0));
+        assert_eq!(utxos[0].value, Amount::from_sat(9_000));
+        assert_eq!(utxos[0].derivation_index, 0);
+        let row = sqlx::query("SELECT spent_by FROM v_utxos WHERE txid = $1")
+            .bind(receive_txid.to_string())
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(row.get::<String, _>("spent_by"), spend_txid.to_string());
+
+        let addresses = store.addresses().await?;
+        assert_eq!(addresses.len(), 2);
+        assert!(addresses[0].revealed && addresses[0].used);
+        assert!(!addresses[1].revealed && !addresses[1].used);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transactions_page() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        for i in 0..5u32 {
+            let tx = tx(
+                OutPoint::new(Hash::hash(&i.to_le_bytes()), 0),
+                ScriptBuf::new(),
+                1_000,
+            );
+            // Two transactions are never seen.
+            if i < 3 {
+                graph
+                    .first_seen
+                    .insert(tx.compute_txid(), 100 + u64::from(i % 2));
+            }
+            // One is confirmed in two blocks and labeled.
+            if i == 0 {
+                let txid = tx.compute_txid();
+                for height in [2, 1] {
+                    let block_id = BlockId {
+                        height,
+                        hash: Hash::hash(&height.to_le_bytes()),
+                    };
+                    store
+                        .write_local_chain(&local_chain::ChangeSet {
+                            blocks: [(height, Some(block_id.hash))].into(),
+                        })
+                        .await?;
+                    let anchor = ConfirmationBlockTime {
+                        block_id,
+                        confirmation_time: u64::from(height),
+                    };
+                    graph.anchors.insert((anchor, txid));
+                }
+                store.set_label(&LabelRef::Tx(txid), "rent").await?;
+            }
+            graph.txs.insert(Arc::new(tx));
+        }
+        store.write_tx_graph(&graph).await?;
+
+        let mut pages = vec![];
+        let mut cursor = None;
+        loop {
+            let page = store.transactions_page(cursor.as_deref(), 2).await?;
+            pages.push(page.items);
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 1]);
+        let rows: Vec<_> = pages.into_iter().flatten().collect();
+        let first_seen: Vec<_> = rows.iter().map(|row| row.first_seen).collect();
+        assert_eq!(first_seen, [Some(101), Some(100), Some(100), None, None]);
+        // Txids are ordered as displayed.
+        let txid = |i: usize| rows[i].txid.to_string();
+        assert!(txid(1) > txid(2) && txid(3) > txid(4));
+        // The rows are those of the view.
+        let mut sorted = rows.clone();
+        sorted.sort_by_key(|row| row.txid.to_string());
+        assert_eq!(sorted, store.transactions().await?);
+        assert!(rows.iter().any(|row| row.confirmation_time == Some(1)));
+
+        // A transaction seen later than the cursor doesn't shift the pages after it.
+        let first = store.transactions_page(None, 2).await?;
+        let mut newer = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        newer.first_seen.insert(Hash::hash(b"newer"), 200);
+        store.write_tx_graph(&newer).await?;
+        let second = store
+            .transactions_page(first.next_cursor.as_deref(), 2)
+            .await?;
+        assert_eq!(second.items, rows[2..4]);
+
+        assert!(matches!(
+            store.transactions_page(Some("zz"), 2).await,
+            Err(Error::InvalidCursor(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recent_txs() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let ours = ScriptBuf::from_bytes(vec![0x51]);
+        let mut indexer = keychain_txout::ChangeSet::default();
+        indexer.spk_cache.insert(
+            DescriptorId::from_byte_array([1; 32]),
+            BTreeMap::from([(0, ours.clone())]),
+        );
+        store.write_keychain_txout(&indexer).await?;
+
+        let mut chain = local_chain::ChangeSet::default();
+        let mut graph = tx_graph::ChangeSet::<ConfirmationBlockTime>::default();
+        let mut txids = vec![];
+        for i in 0..5u32 {
+            let tx = tx(
+                OutPoint::new(Hash::hash(&i.to_le_bytes()), 0),
+                ours.clone(),
+                1_000,
+            );
+            let txid = tx.compute_txid();
+            // Transactions 0 to 2 are confirmed at heights 1 to 3, 3 and 4 are unconfirmed.
+            if i < 3 {
+                let block_id = BlockId {
+                    height: i + 1,
+                    hash: Hash::hash(&i.to_le_bytes()),
+                };
+                chain.blocks.insert(block_id.height, Some(block_id.hash));
+                graph.anchors.insert((
+                    ConfirmationBlockTime {
+                        block_id,
+                        confirmation_time: 100,
+                    },
+                    txid,
+                ));
+            } else {
+                graph.last_seen.insert(txid, 200 + u64::from(i));
+            }
+            graph.txs.insert(Arc::new(tx));
+            txids.push(txid);
+        }
+        // Transaction 4 was evicted since.
+        graph.last_evicted.insert(txids[4], 300);
+        store.write_local_chain(&chain).await?;
+        store.write_tx_graph(&graph).await?;
+
+        let recent = store.recent_txs(3).await?;
+        let order: Vec<_> = recent.iter().map(|tx| tx.row.txid).collect();
+        assert_eq!(order, [txids[3], txids[2], txids[1]]);
+        assert_eq!(
+            recent[0].tx.as_ref().map(|tx| tx.compute_txid()),
+            Some(txids[3])
+        );
+        assert_eq!(recent[0].net, Some(SignedAmount::from_sat(1_000)));
+        assert_eq!(store.recent_txs(10).await?.len(), 4);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() -> anyhow::Result<()> {
+        let row = UtxoRow {
+            outpoint: OutPoint::new(Hash::hash(b"tx"), 1),
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+            descriptor_id: DescriptorId::from_byte_array([1; 32]),
+            derivation_index: 2,
+        };
+        let json = serde_json::to_string(&row)?;
+        assert_eq!(serde_json::from_str::<UtxoRow>(&json)?, row);
+
+        Ok(())
+    }
+}
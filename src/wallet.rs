@@ -1,22 +1,162 @@
 //! [`AsyncWalletPersister`] implementation for the async [`Store`].
 
-use std::{collections::BTreeMap, pin::Pin, str::FromStr};
+use std::{collections::BTreeMap, pin::Pin, str::FromStr, time::Duration};
 
 use bdk_chain::bitcoin;
+use bdk_chain::bitcoin::hashes::{Hash, sha256};
 use bdk_chain::miniscript;
 use bdk_wallet::{AsyncWalletPersister, ChangeSet, KeychainKind};
 use bitcoin::Network;
 use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
-use sqlx::Row;
+use sqlx::{Row, sqlite::SqliteConnection};
 
+use crate::Component;
 use crate::Error;
+use crate::LoadProgress;
 use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+use crate::async_store::{check_network, network_from_row};
+use crate::convert::to_sql;
+use crate::progress::Progress;
+use crate::provenance::record_source_in;
+use crate::replication::next_sequence;
+use crate::validate::validate_in;
 
 impl Store {
     /// Write changeset.
-    pub async fn write_changeset(&self, changeset: &ChangeSet) -> Result<(), Error> {
+    pub async fn write_changeset(&self, changeset: &ChangeSet) -> Result<WriteSummary, Error> {
+        self.write_changeset_with(changeset, WriteOptions::default())
+            .await
+    }
+
+    /// Write changeset with the given [`WriteOptions`].
+    ///
+    /// The changeset is written in a single transaction. If it changed the store, it is
+    /// then passed to the [`ReplicationSink`](crate::ReplicationSink) if one is set.
+    ///
+    /// With [`with_changeset_dedup`](Self::with_changeset_dedup), a changeset identical to
+    /// the last one committed is skipped and an empty summary is returned. With
+    /// [`with_validation`](Self::with_validation), an invalid changeset fails with
+    /// [`Error::InvalidChangeset`].
+    pub async fn write_changeset_with(
+        &self,
+        changeset: &ChangeSet,
+        opts: WriteOptions,
+    ) -> Result<WriteSummary, Error> {
+        self.write_changeset_keyed(changeset, opts, None).await
+    }
+
+    /// Write changeset unless a changeset with the idempotency key `key` was already
+    /// committed, in which case an empty summary is returned.
+    ///
+    /// The key is recorded in the transaction writing the changeset, so a changeset is
+    /// applied exactly once however often it is delivered, e.g. by a message queue with
+    /// at-least-once delivery. Keys are kept until pruned with
+    /// [`prune_idempotency_keys`](Self::prune_idempotency_keys).
+    pub async fn write_changeset_idempotent(
+        &self,
+        key: &str,
+        changeset: &ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        self.write_changeset_keyed(changeset, WriteOptions::default(), Some(key))
+            .await
+    }
+
+    /// Remove the idempotency keys committed more than `max_age` ago, returning the number
+    /// of keys removed.
+    ///
+    /// A changeset delivered again after its key is removed is applied again.
+    pub async fn prune_idempotency_keys(&self, max_age: Duration) -> Result<u64, Error> {
+        let cutoff = self.now().saturating_sub(max_age.as_secs());
+        self.write(WriteOptions::default(), async |conn| {
+            let res = sqlx::query("DELETE FROM idempotency_key WHERE committed_at < $1")
+                .bind(to_sql("idempotency_key.committed_at", cutoff)?)
+                .execute(&mut *conn)
+                .await?;
+            Ok(res.rows_affected())
+        })
+        .await
+    }
+
+    async fn write_changeset_keyed(
+        &self,
+        changeset: &ChangeSet,
+        opts: WriteOptions,
+        key: Option<&str>,
+    ) -> Result<WriteSummary, Error> {
+        let hash = match self.changeset_dedup {
+            true => Some(sha256::Hash::hash(&serde_json::to_vec(changeset)?).to_string()),
+            false => None,
+        };
+
+        let opts = WriteOptions {
+            keep_changeset_hash: hash.is_some(),
+            ..opts
+        };
+        let (summary, sequence) = self
+            .write(opts, async |conn| {
+                if let Some(key) = key {
+                    let res = sqlx::query(
+                        "INSERT OR IGNORE INTO idempotency_key(key, committed_at) VALUES($1, $2)",
+                    )
+                    .bind(key)
+                    .bind(to_sql("idempotency_key.committed_at", self.now())?)
+                    .execute(&mut *conn)
+                    .await?;
+                    if res.rows_affected() == 0 {
+                        return Ok((WriteSummary::default(), None));
+                    }
+                }
+                if let Some(hash) = &hash {
+                    let row = sqlx::query("SELECT 1 FROM changeset_hash WHERE id = 0 AND hash = $1")
+                        .bind(hash)
+                        .fetch_optional(&mut *conn)
+                        .await?;
+                    if row.is_some() {
+                        return Ok((WriteSummary::default(), None));
+                    }
+                    sqlx::query(
+                        "INSERT INTO changeset_hash(id, hash) VALUES(0, $1) ON CONFLICT DO UPDATE SET hash = $1",
+                    )
+                    .bind(hash)
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                if self.validate {
+                    let violations = validate_in(conn, changeset).await?;
+                    if !violations.is_empty() {
+                        return Err(Error::InvalidChangeset(violations));
+                    }
+                }
+                let summary = self.write_changeset_in(conn, changeset).await?;
+                if let Some(source) = opts.source {
+                    record_source_in(conn, &changeset.tx_graph, source).await?;
+                }
+                let sequence = match self.replication {
+                    Some(_) if !summary.is_empty() => Some(next_sequence(conn).await?),
+                    _ => None,
+                };
+                Ok((summary, sequence))
+            })
+            .await?;
+
+        if let (Some(sink), Some(sequence)) = (&self.replication, sequence) {
+            let data = serde_json::to_vec(changeset)?;
+            sink.replicate(sequence, &data).await?;
+        }
+
+        Ok(summary)
+    }
+
+    pub(crate) async fn write_changeset_in(
+        &self,
+        conn: &mut SqliteConnection,
+        changeset: &ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = WriteSummary::default();
         if let Some(network) = changeset.network {
-            self.write_network(network).await?;
+            summary.merge(self.write_network_in(conn, network).await?);
         }
 
         let mut descriptors = BTreeMap::new();
@@ -26,98 +166,225 @@ impl Store {
         if let Some(ref change_descriptor) = changeset.change_descriptor {
             descriptors.insert(KeychainKind::Internal, change_descriptor.clone());
         }
-        self.write_keychain_descriptors(descriptors).await?;
+        summary.merge(
+            self.write_keychain_descriptors_in(conn, descriptors)
+                .await?,
+        );
+        summary.merge(self.assign_wallet_id_in(conn, changeset).await?);
 
-        self.write_local_chain(&changeset.local_chain).await?;
-        self.write_tx_graph(&changeset.tx_graph).await?;
-        self.write_keychain_txout(&changeset.indexer).await?;
+        summary.merge(
+            self.write_local_chain_in(conn, &changeset.local_chain)
+                .await?,
+        );
+        summary.merge(self.write_tx_graph_in(conn, &changeset.tx_graph).await?);
+        summary.merge(
+            self.write_keychain_txout_in(conn, &changeset.indexer)
+                .await?,
+        );
+        summary.merge(self.write_overflow_in(conn, changeset).await?);
 
-        Ok(())
+        Ok(summary)
     }
 
     /// Write network.
-    pub async fn write_network(&self, network: Network) -> Result<(), Error> {
-        sqlx::query("INSERT OR IGNORE INTO network(network) VALUES($1)")
-            .bind(network.to_string())
-            .execute(&self.pool)
-            .await?;
+    ///
+    /// Fails with [`Error::NetworkMismatch`] if another network is stored.
+    pub async fn write_network(&self, network: Network) -> Result<WriteSummary, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            self.write_network_in(conn, network).await
+        })
+        .await
+    }
 
-        Ok(())
+    async fn write_network_in(
+        &self,
+        conn: &mut SqliteConnection,
+        network: Network,
+    ) -> Result<WriteSummary, Error> {
+        if let Some(ours) = self.network.filter(|&ours| ours != network) {
+            return Err(Error::NetworkMismatch {
+                ours,
+                theirs: network,
+            });
+        }
+        check_network(conn, network, self.lenient_network).await?;
+        let mut summary = WriteSummary::default();
+        let res = sqlx::query(
+            "INSERT INTO network(network, magic) SELECT $1, $2 WHERE NOT EXISTS(SELECT 1 FROM network)",
+        )
+        .bind(network.to_string())
+        .bind(network.magic().to_bytes().as_slice())
+        .execute(&mut *conn)
+        .await?;
+        summary.table_mut("network").inserted += res.rows_affected();
+
+        Ok(summary)
     }
 
     /// Write keychain descriptors.
     pub async fn write_keychain_descriptors(
         &self,
         descriptors: BTreeMap<KeychainKind, Descriptor<DescriptorPublicKey>>,
-    ) -> Result<(), Error> {
+    ) -> Result<WriteSummary, Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            self.write_keychain_descriptors_in(conn, descriptors).await
+        })
+        .await
+    }
+
+    async fn write_keychain_descriptors_in(
+        &self,
+        conn: &mut SqliteConnection,
+        descriptors: BTreeMap<KeychainKind, Descriptor<DescriptorPublicKey>>,
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = WriteSummary::default();
+        if !descriptors.is_empty() {
+            self.touch_persisted(conn, Component::Descriptors).await?;
+        }
         for (keychain, descriptor) in descriptors {
-            let keychain = match keychain {
-                KeychainKind::External => 0u8,
-                KeychainKind::Internal => 1,
-            };
-            sqlx::query("INSERT OR IGNORE INTO keychain(keychain, descriptor) VALUES($1, $2)")
-                .bind(keychain)
-                .bind(descriptor.to_string())
-                .execute(&self.pool)
-                .await?;
+            let res =
+                sqlx::query("INSERT OR IGNORE INTO keychain(keychain, descriptor) VALUES($1, $2)")
+                    .bind(keychain_to_int(keychain))
+                    .bind(descriptor.to_string())
+                    .execute(&mut *conn)
+                    .await?;
+            summary.table_mut("keychain").inserted += res.rows_affected();
         }
 
-        Ok(())
+        Ok(summary)
     }
 
     /// Read changeset.
+    ///
+    /// With [`with_verify_spk_cache`](Self::with_verify_spk_cache), fails with
+    /// [`Error::SpkCacheMismatch`] if a sample of the cached scripts doesn't derive from
+    /// the stored descriptors.
     pub async fn read_changeset(&self) -> Result<ChangeSet, Error> {
-        let network = self.read_network().await?;
+        self.timed(self.timeout, async {
+            self.verify_spk_cache().await?;
+            self.read_changeset_inner().await
+        })
+        .await
+    }
+
+    /// Read changeset, reporting the rows loaded to `progress`.
+    ///
+    /// The rows of each table are counted up front, then reported as they are loaded,
+    /// every thousand rows and once a table is loaded, e.g. to show a loading bar while
+    /// opening a big wallet database. Tables are read one after the other regardless of
+    /// [`with_parallel_reads`](Self::with_parallel_reads).
+    pub async fn read_changeset_with_progress(
+        &self,
+        mut progress: impl FnMut(LoadProgress) + Send,
+    ) -> Result<ChangeSet, Error> {
+        self.timed(self.timeout, async {
+            self.verify_spk_cache().await?;
+            let mut progress = Progress::count(&self.pool, &mut progress).await?;
+            let network = self.read_network_with(&mut progress).await?;
+            let descriptors = self.read_keychain_descriptors_with(&mut progress).await?;
+            let changeset = ChangeSet {
+                network,
+                descriptor: descriptors.get(&KeychainKind::External).cloned(),
+                change_descriptor: descriptors.get(&KeychainKind::Internal).cloned(),
+                tx_graph: self.read_tx_graph_with(&mut progress).await?,
+                local_chain: self.read_local_chain_with(&mut progress).await?,
+                indexer: self.read_keychain_txout_with(&mut progress).await?,
+            };
+
+            self.read_overflow(changeset).await
+        })
+        .await
+    }
 
-        let descriptors = self.read_keychain_descriptors().await?;
+    async fn read_changeset_inner(&self) -> Result<ChangeSet, Error> {
+        let (network, descriptors, tx_graph, local_chain, indexer) = if self.parallel_reads {
+            futures_util::try_join!(
+                self.read_network(),
+                self.read_keychain_descriptors(),
+                self.read_tx_graph(),
+                self.read_local_chain(),
+                self.read_keychain_txout(),
+            )?
+        } else {
+            (
+                self.read_network().await?,
+                self.read_keychain_descriptors().await?,
+                self.read_tx_graph().await?,
+                self.read_local_chain().await?,
+                self.read_keychain_txout().await?,
+            )
+        };
         let descriptor = descriptors.get(&KeychainKind::External).cloned();
         let change_descriptor = descriptors.get(&KeychainKind::Internal).cloned();
 
-        let tx_graph = self.read_tx_graph().await?;
-        let local_chain = self.read_local_chain().await?;
-        let indexer = self.read_keychain_txout().await?;
-
-        Ok(ChangeSet {
+        let changeset = ChangeSet {
             network,
             descriptor,
             change_descriptor,
             tx_graph,
             local_chain,
             indexer,
-        })
+        };
+
+        self.read_overflow(changeset).await
     }
 
     /// Read network.
+    ///
+    /// Fails with [`Error::UnknownNetwork`] if the stored network isn't known, unless the
+    /// store is [lenient](Self::with_lenient_network).
     pub async fn read_network(&self) -> Result<Option<Network>, Error> {
-        let row = sqlx::query("SELECT network FROM network")
+        self.read_network_with(&mut Progress::none()).await
+    }
+
+    async fn read_network_with(
+        &self,
+        progress: &mut Progress<'_>,
+    ) -> Result<Option<Network>, Error> {
+        let row = sqlx::query("SELECT network, magic FROM network")
             .fetch_optional(&self.pool)
             .await?;
 
         row.map(|row| {
-            let s: String = row.get("network");
-            s.parse().map_err(Error::ParseNetwork)
+            progress.row("network");
+            network_from_row(&row, self.lenient_network)
         })
         .transpose()
     }
 
+    /// Whether a change (internal) descriptor is stored.
+    ///
+    /// Wallets created from a single descriptor have no change keychain.
+    pub async fn has_change_keychain(&self) -> Result<bool, Error> {
+        let row = sqlx::query("SELECT 1 FROM keychain WHERE keychain = $1")
+            .bind(keychain_to_int(KeychainKind::Internal))
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
     /// Read keychain descriptors.
     pub async fn read_keychain_descriptors(
         &self,
+    ) -> Result<BTreeMap<KeychainKind, Descriptor<DescriptorPublicKey>>, Error> {
+        self.read_keychain_descriptors_with(&mut Progress::none())
+            .await
+    }
+
+    async fn read_keychain_descriptors_with(
+        &self,
+        progress: &mut Progress<'_>,
     ) -> Result<BTreeMap<KeychainKind, Descriptor<DescriptorPublicKey>>, Error> {
         let mut descriptors = BTreeMap::new();
 
-        let rows = sqlx::query("SELECT keychain, descriptor FROM keychain")
+        let rows = sqlx::query("SELECT keychain, descriptor FROM keychain ORDER BY keychain")
             .fetch_all(&self.pool)
             .await?;
         for row in rows {
-            let keychain: u8 = row.get("keychain");
-            let keychain = match keychain {
-                0 => KeychainKind::External,
-                1 => KeychainKind::Internal,
-                _ => {
-                    debug_assert!(false, "keychain must map to a value of 0 or 1");
-                    continue;
-                }
+            progress.row("keychain");
+            let Some(keychain) = keychain_from_int(row.get("keychain")) else {
+                continue;
             };
             let descriptor: String = row.get("descriptor");
             let descriptor = Descriptor::from_str(&descriptor)?;
@@ -128,7 +395,27 @@ impl Store {
     }
 }
 
-type FutureResult<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + 'a + Send>>;
+/// The value of `keychain` in the `keychain` column.
+pub(crate) fn keychain_to_int(keychain: KeychainKind) -> u8 {
+    match keychain {
+        KeychainKind::External => 0,
+        KeychainKind::Internal => 1,
+    }
+}
+
+/// The [`KeychainKind`] of a value in the `keychain` column.
+pub(crate) fn keychain_from_int(keychain: u8) -> Option<KeychainKind> {
+    match keychain {
+        0 => Some(KeychainKind::External),
+        1 => Some(KeychainKind::Internal),
+        _ => {
+            debug_assert!(false, "keychain must map to a value of 0 or 1");
+            None
+        }
+    }
+}
+
+pub(crate) type FutureResult<'a, T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + 'a + Send>>;
 
 impl AsyncWalletPersister for Store {
     type Error = crate::Error;
@@ -139,7 +426,7 @@ impl AsyncWalletPersister for Store {
     {
         Box::pin(async {
             persister.migrate().await?;
-            persister.read_changeset().await
+            persister.retry_locked(|| persister.read_changeset()).await
         })
     }
 
@@ -150,6 +437,298 @@ impl AsyncWalletPersister for Store {
     where
         Self: 'a,
     {
-        Box::pin(async { persister.write_changeset(changeset).await })
+        Box::pin(async {
+            persister.write_changeset(changeset).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::local_chain;
+    use bdk_wallet::Wallet;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    use crate::FixedClock;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+    const CHANGE_DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/1/*)";
+
+    #[tokio::test]
+    async fn single_descriptor_wallet() -> anyhow::Result<()> {
+        let mut store = Store::new_memory().await?;
+
+        let mut wallet = Wallet::create_single(DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut store)
+            .await?;
+        let address = wallet.reveal_next_address(KeychainKind::External);
+        wallet.persist_async(&mut store).await?;
+        assert!(!store.has_change_keychain().await?);
+
+        let changeset = store.read_changeset().await?;
+        assert!(changeset.descriptor.is_some());
+        assert!(changeset.change_descriptor.is_none());
+
+        let wallet = Wallet::load()
+            .load_wallet_async(&mut store)
+            .await?
+            .expect("wallet must exist");
+        assert_eq!(wallet.keychains().count(), 1);
+        assert_eq!(
+            wallet
+                .peek_address(KeychainKind::External, address.index)
+                .address,
+            address.address
+        );
+
+        let mut store = Store::new_memory().await?;
+        Wallet::create(DESCRIPTOR, CHANGE_DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut store)
+            .await?;
+        assert!(store.has_change_keychain().await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_identical_changeset() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_changeset_dedup(true);
+        store.migrate().await?;
+
+        let mut changeset = ChangeSet::default();
+        changeset
+            .local_chain
+            .blocks
+            .insert(0, Some(Hash::hash(b"0")));
+        assert!(!store.write_changeset(&changeset).await?.is_empty());
+
+        // Remove the block behind the store's back, the identical changeset isn't rewritten.
+        sqlx::query("DELETE FROM block")
+            .execute(&store.pool)
+            .await?;
+        assert!(store.write_changeset(&changeset).await?.is_empty());
+        assert!(store.read_local_chain().await?.blocks.is_empty());
+
+        changeset
+            .local_chain
+            .blocks
+            .insert(1, Some(Hash::hash(b"1")));
+        let summary = store.write_changeset(&changeset).await?;
+        assert_eq!(summary.table("block").inserted, 2);
+
+        // A write of a component clears the hash, so the changeset is written again.
+        store
+            .write_local_chain(&local_chain::ChangeSet {
+                blocks: [(1, None)].into(),
+            })
+            .await?;
+        let summary = store.write_changeset(&changeset).await?;
+        assert_eq!(summary.table("block").inserted, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_changeset_idempotent() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?.with_clock(FixedClock(1_000));
+        store.migrate().await?;
+
+        let mut changeset = ChangeSet::default();
+        changeset
+            .local_chain
+            .blocks
+            .insert(0, Some(Hash::hash(b"0")));
+        let summary = store
+            .write_changeset_idempotent("msg-1", &changeset)
+            .await?;
+        assert_eq!(summary.table("block").inserted, 1);
+
+        // A redelivery isn't applied again, even if the store changed since.
+        sqlx::query("DELETE FROM block")
+            .execute(&store.pool)
+            .await?;
+        assert!(
+            store
+                .write_changeset_idempotent("msg-1", &changeset)
+                .await?
+                .is_empty()
+        );
+        assert!(store.read_local_chain().await?.blocks.is_empty());
+        let summary = store
+            .write_changeset_idempotent("msg-2", &changeset)
+            .await?;
+        assert_eq!(summary.table("block").inserted, 1);
+
+        // A failed write doesn't record its key.
+        let store = store.with_network(Network::Bitcoin);
+        store.write_network(Network::Bitcoin).await?;
+        let signet = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        assert!(
+            store
+                .write_changeset_idempotent("msg-3", &signet)
+                .await
+                .is_err()
+        );
+        let rows = sqlx::query("SELECT key FROM idempotency_key ORDER BY key")
+            .fetch_all(&store.pool)
+            .await?;
+        assert_eq!(rows.len(), 2);
+
+        let store = store.with_clock(FixedClock(1_000 + 3_600));
+        assert_eq!(
+            store
+                .prune_idempotency_keys(Duration::from_secs(60))
+                .await?,
+            2
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_changeset_in_parallel() -> anyhow::Result<()> {
+        // A shared cache database is visible to every connection of the pool.
+        let connect_options =
+            SqliteConnectOptions::from_str("sqlite:file:parallel_reads?mode=memory&cache=shared")?;
+        let store =
+            Store::new_with_options(connect_options, SqlitePoolOptions::new().max_connections(4))
+                .await?;
+        store.migrate().await?;
+
+        let mut changeset = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        changeset
+            .local_chain
+            .blocks
+            .insert(0, Some(Hash::hash(b"0")));
+        changeset.tx_graph.last_seen.insert(Hash::hash(b"tx"), 5);
+        store.write_changeset(&changeset).await?;
+
+        assert_eq!(store.read_changeset().await?, changeset);
+        let store = store.with_parallel_reads(false);
+        assert_eq!(store.read_changeset().await?, changeset);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_changeset_with_progress() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let mut changeset = ChangeSet {
+            network: Some(Network::Signet),
+            ..Default::default()
+        };
+        for height in 0..2_500u32 {
+            changeset
+                .local_chain
+                .blocks
+                .insert(height, Some(Hash::hash(&height.to_le_bytes())));
+        }
+        changeset.tx_graph.last_seen.insert(Hash::hash(b"tx"), 5);
+        store.write_changeset(&changeset).await?;
+
+        let mut reports = vec![];
+        let read = store
+            .read_changeset_with_progress(|progress| reports.push(progress))
+            .await?;
+        assert_eq!(read, changeset);
+        let blocks: Vec<_> = reports
+            .iter()
+            .filter(|p| p.table == "block")
+            .map(|p| (p.table_loaded, p.table_total))
+            .collect();
+        assert_eq!(blocks, [(1_000, 2_500), (2_000, 2_500), (2_500, 2_500)]);
+        let last = reports.last().unwrap();
+        assert_eq!((last.loaded, last.total), (2_502, 2_502));
+        assert_eq!(last.percent(), 100.0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn network_isolation() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        store.write_network(Network::Signet).await?;
+        assert!(store.write_network(Network::Signet).await?.is_empty());
+        assert!(matches!(
+            store.write_network(Network::Bitcoin).await,
+            Err(Error::NetworkMismatch {
+                ours: Network::Signet,
+                theirs: Network::Bitcoin
+            })
+        ));
+
+        // A store restricted to mainnet refuses any write to the signet database.
+        let mainnet = store.clone().with_network(Network::Bitcoin);
+        let mut chain = local_chain::ChangeSet::default();
+        chain.blocks.insert(0, Some(Hash::hash(b"0")));
+        assert!(matches!(
+            mainnet.write_local_chain(&chain).await,
+            Err(Error::NetworkMismatch { .. })
+        ));
+        assert!(store.read_local_chain().await?.blocks.is_empty());
+        store
+            .with_network(Network::Signet)
+            .write_local_chain(&chain)
+            .await?;
+
+        let empty = Store::new_memory().await?.with_network(Network::Bitcoin);
+        empty.migrate().await?;
+        assert!(matches!(
+            empty.write_network(Network::Signet).await,
+            Err(Error::NetworkMismatch { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_network() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+        store.write_network(Network::Testnet4).await?;
+        assert_eq!(store.read_network().await?, Some(Network::Testnet4));
+
+        // A name written by a later version, but with the magic of a known network.
+        sqlx::query("UPDATE network SET network = 'testnet4-renamed'")
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            store.read_network().await,
+            Err(Error::UnknownNetwork(name)) if name == "testnet4-renamed"
+        ));
+        let lenient = store.clone().with_lenient_network(true);
+        assert_eq!(lenient.read_network().await?, Some(Network::Testnet4));
+        lenient.write_network(Network::Testnet4).await?;
+
+        // An alias of the `-chain` argument of Bitcoin Core, with an unknown magic.
+        sqlx::query("UPDATE network SET network = 'main', magic = X'00000000'")
+            .execute(&store.pool)
+            .await?;
+        assert_eq!(lenient.read_network().await?, Some(Network::Bitcoin));
+        // Neither the name nor the magic is known.
+        sqlx::query("UPDATE network SET network = 'testnet5'")
+            .execute(&store.pool)
+            .await?;
+        assert!(matches!(
+            lenient.read_network().await,
+            Err(Error::UnknownNetwork(_))
+        ));
+
+        Ok(())
     }
 }
@@ -0,0 +1,280 @@
+//! Identifiers of wallets.
+
+use core::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bdk_chain::bitcoin::{self, Network};
+use bdk_wallet::ChangeSet;
+use bdk_wallet::descriptor::Descriptor;
+use bdk_wallet::miniscript::DescriptorPublicKey;
+use sqlx::Row;
+use sqlx::sqlite::SqliteConnection;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::WriteSummary;
+use crate::async_store::network_from_row;
+use crate::convert::{from_sql, to_sql};
+
+/// Scheme generating the id of a wallet, see [`Store::with_wallet_id_scheme`].
+///
+/// Implement this to derive ids from data of your own, e.g. to align them with the ids of
+/// the accounts of a service.
+pub trait WalletIdScheme: fmt::Debug + Send + Sync {
+    /// Generate the id of the wallet first written with `changeset`, which holds at least
+    /// its descriptor.
+    ///
+    /// An error fails the write of the changeset.
+    fn generate(&self, changeset: &ChangeSet) -> Result<String, Error>;
+}
+
+/// Random version 4 UUIDs in their hyphenated lowercase form.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidV4;
+
+impl WalletIdScheme for UuidV4 {
+    fn generate(&self, _changeset: &ChangeSet) -> Result<String, Error> {
+        let mut bytes = random::<16>()?;
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex = bitcoin::hex::DisplayHex::to_lower_hex_string(&bytes[..]);
+
+        Ok(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ))
+    }
+}
+
+/// ULIDs, which sort by the time they were generated at.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ulid;
+
+impl WalletIdScheme for Ulid {
+    fn generate(&self, _changeset: &ChangeSet) -> Result<String, Error> {
+        const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let value = ((millis & ((1 << 48) - 1)) << 80) | u128::from_be_bytes(pad(random::<10>()?));
+
+        Ok((0..26)
+            .rev()
+            .map(|i| char::from(ALPHABET[(value >> (i * 5)) as usize & 31]))
+            .collect())
+    }
+}
+
+/// Ids derived from the checksums of the descriptors of the wallet, the checksum of the
+/// external descriptor followed by `-` and that of the internal one, if any.
+///
+/// The same descriptors always yield the same id, e.g. when a wallet is restored into a new
+/// database.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DescriptorChecksum;
+
+impl WalletIdScheme for DescriptorChecksum {
+    fn generate(&self, changeset: &ChangeSet) -> Result<String, Error> {
+        Ok([&changeset.descriptor, &changeset.change_descriptor]
+            .into_iter()
+            .flatten()
+            .map(checksum)
+            .collect::<Vec<_>>()
+            .join("-"))
+    }
+}
+
+/// A wallet of the store, see [`Store::list_wallets`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletInfo {
+    /// Id of the wallet
+    pub id: String,
+    /// Unix timestamp of when the id was assigned
+    pub created_at: u64,
+    /// Network of the wallet, if written
+    pub network: Option<Network>,
+    /// Number of keychains of the wallet
+    pub keychains: u32,
+}
+
+impl Store {
+    /// Assign ids generated by `scheme` to wallets, see [`WalletIdScheme`].
+    ///
+    /// A wallet is assigned an id by the write of the first changeset holding its
+    /// descriptor, e.g. when it is created, unless it already has one. By default wallets
+    /// are only assigned an id with [`set_wallet_id`](Self::set_wallet_id).
+    pub fn with_wallet_id_scheme(mut self, scheme: impl WalletIdScheme + 'static) -> Self {
+        self.wallet_id_scheme = Some(std::sync::Arc::new(scheme));
+        self
+    }
+
+    /// Set the id of the wallet to `id`, replacing any id it was assigned.
+    pub async fn set_wallet_id(&self, id: &str) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO wallet(id, wallet_id, created_at) VALUES(0, $1, $2) ON CONFLICT DO UPDATE SET wallet_id = $1",
+            )
+            .bind(id)
+            .bind(to_sql("wallet.created_at", self.now())?)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The wallets of the store which were assigned an id.
+    ///
+    /// A database holds a single wallet, so at most one wallet is returned. Services with
+    /// many wallets keep each in its own database, e.g. of a [`TenantDir`](crate::TenantDir).
+    pub async fn list_wallets(&self) -> Result<Vec<WalletInfo>, Error> {
+        let rows = sqlx::query(
+            "SELECT wallet.wallet_id, wallet.created_at, network.network, network.magic, (SELECT COUNT(*) FROM keychain) AS keychains
+            FROM wallet
+            LEFT JOIN network
+            ORDER BY wallet.wallet_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut wallets = vec![];
+        for row in rows {
+            let network = match row.get::<Option<String>, _>("network") {
+                Some(_) => Some(network_from_row(&row, self.lenient_network)?),
+                None => None,
+            };
+            wallets.push(WalletInfo {
+                id: row.get("wallet_id"),
+                created_at: from_sql("wallet.created_at", row.get("created_at"))?,
+                network,
+                keychains: row.get("keychains"),
+            });
+        }
+
+        Ok(wallets)
+    }
+
+    /// Assign the wallet first written with `changeset` an id, if a scheme is set.
+    pub(crate) async fn assign_wallet_id_in(
+        &self,
+        conn: &mut SqliteConnection,
+        changeset: &ChangeSet,
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = WriteSummary::default();
+        let Some(scheme) = &self.wallet_id_scheme else {
+            return Ok(summary);
+        };
+        if changeset.descriptor.is_none() {
+            return Ok(summary);
+        }
+        let res = sqlx::query(
+            "INSERT OR IGNORE INTO wallet(id, wallet_id, created_at) VALUES(0, $1, $2)",
+        )
+        .bind(scheme.generate(changeset)?)
+        .bind(to_sql("wallet.created_at", self.now())?)
+        .execute(&mut *conn)
+        .await?;
+        summary.table_mut("wallet").inserted += res.rows_affected();
+
+        Ok(summary)
+    }
+}
+
+/// The checksum of `descriptor`.
+fn checksum(descriptor: &Descriptor<DescriptorPublicKey>) -> String {
+    let s = descriptor.to_string();
+    match s.rsplit_once('#') {
+        Some((_, checksum)) => checksum.to_string(),
+        None => s,
+    }
+}
+
+/// `N` random bytes from the operating system.
+fn random<const N: usize>() -> Result<[u8; N], Error> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Left pad `bytes` with zeros to 16 bytes.
+fn pad(bytes: [u8; 10]) -> [u8; 16] {
+    let mut padded = [0; 16];
+    padded[6..].copy_from_slice(&bytes);
+    padded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk_wallet::{KeychainKind, Wallet};
+
+    use crate::FixedClock;
+
+    const DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+    const CHANGE_DESCRIPTOR: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/1/*)";
+
+    #[tokio::test]
+    async fn wallet_ids() -> anyhow::Result<()> {
+        let mut store = Store::new_memory()
+            .await?
+            .with_clock(FixedClock(1_000))
+            .with_wallet_id_scheme(DescriptorChecksum);
+        store.migrate().await?;
+        assert!(store.list_wallets().await?.is_empty());
+
+        let mut wallet = Wallet::create(DESCRIPTOR, CHANGE_DESCRIPTOR)
+            .network(Network::Signet)
+            .create_wallet_async(&mut store)
+            .await?;
+        let changeset = store.read_changeset().await?;
+        let id = format!(
+            "{}-{}",
+            checksum(changeset.descriptor.as_ref().unwrap()),
+            checksum(changeset.change_descriptor.as_ref().unwrap())
+        );
+        assert_eq!(
+            store.list_wallets().await?,
+            [WalletInfo {
+                id,
+                created_at: 1_000,
+                network: Some(Network::Signet),
+                keychains: 2,
+            }]
+        );
+
+        // Later writes keep the id.
+        let _ = wallet.reveal_next_address(KeychainKind::External);
+        wallet.persist_async(&mut store).await?;
+        assert_eq!(store.list_wallets().await?.len(), 1);
+
+        store.set_wallet_id("account-42").await?;
+        assert_eq!(store.list_wallets().await?[0].id, "account-42");
+
+        Ok(())
+    }
+
+    #[test]
+    fn id_formats() -> anyhow::Result<()> {
+        let changeset = ChangeSet::default();
+
+        let uuid = UuidV4.generate(&changeset)?;
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(uuid.as_bytes()[14], b'4');
+        assert!(matches!(uuid.as_bytes()[19], b'8' | b'9' | b'a' | b'b'));
+        assert_ne!(uuid, UuidV4.generate(&changeset)?);
+
+        let ulid = Ulid.generate(&changeset)?;
+        assert_eq!(ulid.len(), 26);
+        assert!(ulid.as_bytes()[0] <= b'7');
+        assert_ne!(ulid, Ulid.generate(&changeset)?);
+
+        Ok(())
+    }
+}
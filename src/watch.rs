@@ -0,0 +1,177 @@
+//! Watch list of scripts and outpoints outside of any descriptor.
+
+use bdk_chain::bitcoin;
+use bitcoin::{OutPoint, ScriptBuf};
+use sqlx::Row;
+
+use crate::Error;
+use crate::Store;
+use crate::WriteOptions;
+use crate::convert::{from_sql, to_sql};
+
+/// An item of the watch list.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WatchItem {
+    /// Script pubkey, e.g. of a donation address.
+    Script(ScriptBuf),
+    /// Outpoint, e.g. of a counterparty's output.
+    OutPoint(OutPoint),
+}
+
+impl WatchItem {
+    /// The value of the `type` column.
+    fn type_str(&self) -> &'static str {
+        match self {
+            Self::Script(_) => "script",
+            Self::OutPoint(_) => "outpoint",
+        }
+    }
+
+    /// The value of the `ref` column.
+    fn ref_string(&self) -> String {
+        match self {
+            Self::Script(script) => script.to_hex_string(),
+            Self::OutPoint(op) => op.to_string(),
+        }
+    }
+}
+
+/// An entry of the watch list, see [`Store::watch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEntry {
+    /// Watched item
+    pub item: WatchItem,
+    /// Label
+    pub label: Option<String>,
+    /// Unix time at which the item was added
+    pub added_at: u64,
+}
+
+impl Store {
+    /// Add `item` to the watch list with an optional `label`.
+    ///
+    /// Watching an already watched item replaces its label.
+    pub async fn watch(&self, item: &WatchItem, label: Option<&str>) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query(
+                "INSERT INTO watch(type, ref, label, added_at) VALUES($1, $2, $3, $4) ON CONFLICT DO UPDATE SET label = $3",
+            )
+            .bind(item.type_str())
+            .bind(item.ref_string())
+            .bind(label)
+            .bind(to_sql("watch.added_at", self.now())?)
+            .execute(&mut *conn)
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Remove `item` from the watch list.
+    pub async fn unwatch(&self, item: &WatchItem) -> Result<(), Error> {
+        self.write(WriteOptions::default(), async |conn| {
+            sqlx::query("DELETE FROM watch WHERE type = $1 AND ref = $2")
+                .bind(item.type_str())
+                .bind(item.ref_string())
+                .execute(&mut *conn)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Read the watch list, ordered by type and reference.
+    pub async fn watch_list(&self) -> Result<Vec<WatchEntry>, Error> {
+        let rows = sqlx::query("SELECT type, ref, label, added_at FROM watch ORDER BY type, ref")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut entries = vec![];
+        for row in rows {
+            let ty: String = row.get("type");
+            let r: String = row.get("ref");
+            let item = match ty.as_str() {
+                "script" => WatchItem::Script(ScriptBuf::from_hex(&r)?),
+                "outpoint" => WatchItem::OutPoint(r.parse()?),
+                _ => {
+                    debug_assert!(false, "unknown watch type: {ty}");
+                    continue;
+                }
+            };
+            let added_at: i64 = row.get("added_at");
+            entries.push(WatchEntry {
+                item,
+                label: row.get("label"),
+                added_at: from_sql("watch.added_at", added_at)?,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// The watched script pubkeys, e.g. for `SyncRequestBuilder::spks`.
+    pub async fn watched_scripts(&self) -> Result<Vec<ScriptBuf>, Error> {
+        Ok(self
+            .watch_list()
+            .await?
+            .into_iter()
+            .filter_map(|entry| match entry.item {
+                WatchItem::Script(script) => Some(script),
+                WatchItem::OutPoint(_) => None,
+            })
+            .collect())
+    }
+
+    /// The watched outpoints, e.g. for `SyncRequestBuilder::outpoints`.
+    pub async fn watched_outpoints(&self) -> Result<Vec<OutPoint>, Error> {
+        Ok(self
+            .watch_list()
+            .await?
+            .into_iter()
+            .filter_map(|entry| match entry.item {
+                WatchItem::OutPoint(op) => Some(op),
+                WatchItem::Script(_) => None,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn watch_list() -> anyhow::Result<()> {
+        let store = Store::new_memory().await?;
+        store.migrate().await?;
+
+        let script = WatchItem::Script(ScriptBuf::from_bytes(vec![0x51]));
+        let outpoint = WatchItem::OutPoint(OutPoint::new(Hash::hash(b"tx"), 1));
+        store.watch(&script, Some("donations")).await?;
+        store.watch(&outpoint, None).await?;
+        store.watch(&outpoint, Some("counterparty")).await?;
+
+        let entries = store.watch_list().await?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].item, outpoint);
+        assert_eq!(entries[0].label.as_deref(), Some("counterparty"));
+        assert_eq!(entries[1].item, script);
+        assert_eq!(entries[1].label.as_deref(), Some("donations"));
+
+        assert_eq!(
+            store.watched_scripts().await?,
+            vec![ScriptBuf::from_bytes(vec![0x51])]
+        );
+        assert_eq!(
+            store.watched_outpoints().await?,
+            vec![OutPoint::new(Hash::hash(b"tx"), 1)]
+        );
+
+        store.unwatch(&script).await?;
+        assert!(store.watched_scripts().await?.is_empty());
+
+        Ok(())
+    }
+}
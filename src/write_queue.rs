@@ -0,0 +1,220 @@
+//! Fair queueing of the writes of a store.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use crate::Store;
+
+/// A lock granted in the order it was requested, see [`Store::with_fair_writes`].
+///
+/// Each request draws a ticket and the lock is granted to the lowest ticket which wasn't
+/// served yet. A request dropped before it was granted, e.g. by a timeout, gives up its
+/// ticket so the requests queued behind it aren't blocked.
+#[derive(Debug, Default)]
+pub(crate) struct WriteQueue {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Ticket of the next request
+    next: u64,
+    /// Ticket holding or next to be granted the lock
+    serving: u64,
+    /// Wakers of the requests waiting for the lock, by ticket
+    waiting: BTreeMap<u64, Waker>,
+    /// Tickets of the requests dropped while waiting
+    abandoned: BTreeSet<u64>,
+}
+
+impl State {
+    /// Pass the lock to the next ticket which wasn't abandoned.
+    fn advance(&mut self) {
+        self.serving += 1;
+        while self.abandoned.remove(&self.serving) {
+            self.serving += 1;
+        }
+        if let Some(waker) = self.waiting.remove(&self.serving) {
+            waker.wake();
+        }
+    }
+}
+
+impl WriteQueue {
+    /// Wait for the lock, which is held until the returned guard is dropped.
+    pub(crate) fn lock(self: &Arc<Self>) -> Acquire {
+        let mut state = self.state.lock().expect("must not be poisoned");
+        let ticket = state.next;
+        state.next += 1;
+
+        Acquire {
+            queue: Arc::clone(self),
+            ticket,
+            granted: false,
+        }
+    }
+}
+
+/// Future of [`WriteQueue::lock`].
+pub(crate) struct Acquire {
+    queue: Arc<WriteQueue>,
+    ticket: u64,
+    granted: bool,
+}
+
+impl Future for Acquire {
+    type Output = WriteGuard;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<WriteGuard> {
+        let mut state = self.queue.state.lock().expect("must not be poisoned");
+        if state.serving != self.ticket {
+            state.waiting.insert(self.ticket, cx.waker().clone());
+            return Poll::Pending;
+        }
+        drop(state);
+        self.granted = true;
+
+        Poll::Ready(WriteGuard {
+            queue: Arc::clone(&self.queue),
+        })
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        let mut state = self.queue.state.lock().expect("must not be poisoned");
+        state.waiting.remove(&self.ticket);
+        if state.serving == self.ticket {
+            state.advance();
+        } else {
+            state.abandoned.insert(self.ticket);
+        }
+    }
+}
+
+/// Guard of the lock of a [`WriteQueue`], passing it on when dropped.
+#[derive(Debug)]
+pub(crate) struct WriteGuard {
+    queue: Arc<WriteQueue>,
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        self.queue
+            .state
+            .lock()
+            .expect("must not be poisoned")
+            .advance();
+    }
+}
+
+impl Store {
+    /// Set whether the writes of the store and of its clones are queued in the order they
+    /// were requested.
+    ///
+    /// SQLite allows a single writer per database, and writers competing for the lock are
+    /// retried until the busy timeout, in no particular order, so under load a write can
+    /// starve or fail with [`Error::DatabaseLocked`](crate::Error::DatabaseLocked). Queued
+    /// writes instead wait their turn without holding a pooled connection.
+    ///
+    /// The queue is a FIFO per database, not a lock per wallet: every write of the store
+    /// waits its turn, whichever table it touches, while stores of different databases,
+    /// e.g. the wallets of a [`TenantDir`](crate::TenantDir), have separate queues. Only
+    /// the writes of this process are queued. Defaults to `false`.
+    pub fn with_fair_writes(mut self, enabled: bool) -> Self {
+        self.write_queue = enabled.then(Arc::default);
+        self
+    }
+
+    /// Wait for the turn of a write if [`with_fair_writes`](Self::with_fair_writes) is set,
+    /// which lasts until the returned guard is dropped.
+    pub(crate) async fn write_turn(&self) -> Option<WriteGuard> {
+        match &self.write_queue {
+            Some(queue) => Some(queue.lock().await),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::task::Wake;
+
+    use bdk_chain::bitcoin::hashes::Hash;
+    use bdk_chain::local_chain;
+
+    struct Flag(Mutex<bool>);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            *self.0.lock().unwrap() = true;
+        }
+    }
+
+    fn poll(acquire: &mut Acquire, flag: &Arc<Flag>) -> Option<WriteGuard> {
+        let waker = Waker::from(Arc::clone(flag));
+        match Pin::new(acquire).poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(guard) => Some(guard),
+            Poll::Pending => None,
+        }
+    }
+
+    #[test]
+    fn granted_in_order() {
+        let queue = Arc::new(WriteQueue::default());
+        let flags: Vec<_> = (0..3).map(|_| Arc::new(Flag(Mutex::new(false)))).collect();
+        let mut first = queue.lock();
+        let mut second = queue.lock();
+        let mut third = queue.lock();
+
+        let guard = poll(&mut first, &flags[0]).expect("first must be granted");
+        assert!(poll(&mut third, &flags[2]).is_none());
+        assert!(poll(&mut second, &flags[1]).is_none());
+
+        // The second request is abandoned, so the lock passes on to the third.
+        drop(second);
+        assert!(!*flags[2].0.lock().unwrap());
+        drop(guard);
+        assert!(*flags[2].0.lock().unwrap());
+        let guard = poll(&mut third, &flags[2]).expect("third must be granted");
+
+        // A request made after the lock is released is granted right away.
+        drop(guard);
+        assert!(poll(&mut queue.lock(), &flags[0]).is_some());
+    }
+
+    #[tokio::test]
+    async fn fair_writes() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fair_writes.db");
+        let store = Store::new(&path.to_string_lossy())
+            .await?
+            .with_fair_writes(true);
+        store.migrate().await?;
+
+        let writes = (0..20u32).map(|height| {
+            let store = store.clone();
+            async move {
+                let mut chain = local_chain::ChangeSet::default();
+                chain
+                    .blocks
+                    .insert(height, Some(Hash::hash(&height.to_le_bytes())));
+                store.write_local_chain(&chain).await
+            }
+        });
+        for res in futures_util::future::join_all(writes).await {
+            res?;
+        }
+        assert_eq!(store.read_local_chain().await?.blocks.len(), 20);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//! Integration tests against live regtest chain sources.
+//!
+//! Each test runs a full scan, persist, reload, incremental sync and persist cycle of a
+//! wallet backed by a [`Store`] on disk, catching regressions of the schema or of its
+//! semantics which tests on hand-made changesets can't.
+//!
+//! Run with `cargo test --features regtest --test regtest` against a regtest `bitcoind`
+//! with a loaded wallet and an `electrs` (esplora flavor) indexing it, configured by:
+//!
+//! - `BITCOIND_RPC_URL`, e.g. `http://127.0.0.1:18443`
+//! - `BITCOIND_RPC_COOKIE`, the path to the cookie file, or `BITCOIND_RPC_USER` and
+//!   `BITCOIND_RPC_PASS`
+//! - `ELECTRUM_URL`, e.g. `tcp://127.0.0.1:60401`
+//! - `ESPLORA_URL`, e.g. `http://127.0.0.1:3002`
+//!
+//! A test whose chain source isn't configured is skipped.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bdk_electrum::{BdkElectrumClient, electrum_client};
+use bdk_esplora::{EsploraAsyncExt, esplora_client};
+use bdk_sqlite::Store;
+use bdk_wallet::bitcoin::{Address, Amount, Network};
+use bdk_wallet::{KeychainKind, Update, Wallet};
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use electrum_client::ElectrumApi;
+
+const EXTERNAL_DESC: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/0/*)";
+const INTERNAL_DESC: &str = "wpkh([e273fe42/84'/1'/0']tpubDCmr3Luq75npLaYmRqqW1rLfSbfpnBXwLwAmUbR333fp95wjCHar3zoc9zSWovZFwrWr53mm3NTVqt6d1Pt6G26uf4etQjc3Pr5Hxe9QEQ2/1/*)";
+const STOP_GAP: usize = 20;
+
+/// A chain source to scan and sync against.
+enum ChainSource {
+    Electrum(Box<BdkElectrumClient<electrum_client::Client>>),
+    Esplora(esplora_client::AsyncClient),
+}
+
+impl ChainSource {
+    fn electrum() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("ELECTRUM_URL") else {
+            return Ok(None);
+        };
+        let client = electrum_client::Client::new(&url)?;
+        Ok(Some(Self::Electrum(Box::new(BdkElectrumClient::new(
+            client,
+        )))))
+    }
+
+    fn esplora() -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("ESPLORA_URL") else {
+            return Ok(None);
+        };
+        let client = esplora_client::Builder::new(&url).build_async()?;
+        Ok(Some(Self::Esplora(client)))
+    }
+
+    async fn tip_height(&self) -> anyhow::Result<u64> {
+        Ok(match self {
+            Self::Electrum(client) => client.inner.block_headers_subscribe()?.height as u64,
+            Self::Esplora(client) => client.get_height().await?.into(),
+        })
+    }
+
+    async fn full_scan(&self, wallet: &Wallet) -> anyhow::Result<Update> {
+        let request = wallet.start_full_scan();
+        Ok(match self {
+            Self::Electrum(client) => client.full_scan(request, STOP_GAP, 10, true)?.into(),
+            Self::Esplora(client) => client.full_scan(request, STOP_GAP, 1).await?.into(),
+        })
+    }
+
+    async fn sync(&self, wallet: &Wallet) -> anyhow::Result<Update> {
+        let request = wallet.start_sync_with_revealed_spks();
+        Ok(match self {
+            Self::Electrum(client) => client.sync(request, 10, true)?.into(),
+            Self::Esplora(client) => client.sync(request, 1).await?.into(),
+        })
+    }
+}
+
+/// The regtest `bitcoind` configured by the environment.
+fn bitcoind() -> anyhow::Result<Client> {
+    let url = std::env::var("BITCOIND_RPC_URL")?;
+    let auth = match std::env::var("BITCOIND_RPC_COOKIE") {
+        Ok(cookie) => Auth::CookieFile(cookie.into()),
+        Err(_) => Auth::UserPass(
+            std::env::var("BITCOIND_RPC_USER")?,
+            std::env::var("BITCOIND_RPC_PASS")?,
+        ),
+    };
+    Ok(Client::new(&url, auth)?)
+}
+
+/// Mine `count` blocks and wait for `source` to index them.
+async fn mine(bitcoind: &Client, source: &ChainSource, count: u64) -> anyhow::Result<()> {
+    let address = bitcoind.get_new_address(None, None)?.assume_checked();
+    bitcoind.generate_to_address(count, &address)?;
+    let height = bitcoind.get_block_count()?;
+    while source.tip_height().await? < height {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Ok(())
+}
+
+/// Path of a fresh database file.
+fn db_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "bdk_sqlite_regtest_{name}_{}.sqlite",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+async fn open(path: &Path) -> anyhow::Result<Store> {
+    Ok(Store::new(path.to_str().expect("path must be valid utf-8")).await?)
+}
+
+/// Run the full scan, persist, reload, sync and persist cycle against `source`.
+async fn scan_persist_reload_sync(name: &str, source: ChainSource) -> anyhow::Result<()> {
+    let bitcoind = bitcoind()?;
+    if bitcoind.get_balance(None, None)? < Amount::from_btc(1.0)? {
+        mine(&bitcoind, &source, 101).await?;
+    }
+
+    let path = db_path(name);
+    let mut store = open(&path).await?;
+    let mut wallet = Wallet::create(EXTERNAL_DESC, INTERNAL_DESC)
+        .network(Network::Regtest)
+        .create_wallet_async(&mut store)
+        .await?;
+    let address = wallet.reveal_next_address(KeychainKind::External).address;
+    send(&bitcoind, &address, Amount::from_sat(50_000))?;
+    mine(&bitcoind, &source, 1).await?;
+
+    let update = source.full_scan(&wallet).await?;
+    wallet.apply_update(update)?;
+    wallet.persist_async(&mut store).await?;
+    assert_eq!(wallet.balance().confirmed, Amount::from_sat(50_000));
+
+    // Reload from a new connection to the same database.
+    drop(store);
+    let mut store = open(&path).await?;
+    let mut reloaded = Wallet::load()
+        .descriptor(KeychainKind::External, Some(EXTERNAL_DESC))
+        .descriptor(KeychainKind::Internal, Some(INTERNAL_DESC))
+        .check_network(Network::Regtest)
+        .load_wallet_async(&mut store)
+        .await?
+        .expect("wallet must be persisted");
+    assert_eq!(reloaded.balance(), wallet.balance());
+    assert_eq!(reloaded.latest_checkpoint(), wallet.latest_checkpoint());
+    assert_eq!(
+        reloaded.derivation_index(KeychainKind::External),
+        Some(0),
+        "revealed index must be persisted"
+    );
+
+    // Receive an unconfirmed and a confirmed payment, then sync incrementally.
+    let address = reloaded.reveal_next_address(KeychainKind::External).address;
+    send(&bitcoind, &address, Amount::from_sat(20_000))?;
+    mine(&bitcoind, &source, 1).await?;
+    send(&bitcoind, &address, Amount::from_sat(10_000))?;
+    // Give the indexer a moment to see the mempool transaction.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let update = source.sync(&reloaded).await?;
+    reloaded.apply_update(update)?;
+    reloaded.persist_async(&mut store).await?;
+    let balance = reloaded.balance();
+    assert_eq!(balance.confirmed, Amount::from_sat(70_000));
+    assert_eq!(balance.untrusted_pending, Amount::from_sat(10_000));
+
+    drop(store);
+    let mut store = open(&path).await?;
+    let wallet = Wallet::load()
+        .load_wallet_async(&mut store)
+        .await?
+        .expect("wallet must be persisted");
+    assert_eq!(wallet.balance(), balance);
+    assert_eq!(wallet.latest_checkpoint(), reloaded.latest_checkpoint());
+    assert_eq!(
+        wallet.transactions().count(),
+        reloaded.transactions().count()
+    );
+
+    drop(store);
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+fn send(bitcoind: &Client, address: &Address, amount: Amount) -> anyhow::Result<()> {
+    bitcoind.send_to_address(address, amount, None, None, None, None, None, None)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn electrum() -> anyhow::Result<()> {
+    match ChainSource::electrum()? {
+        Some(source) => scan_persist_reload_sync("electrum", source).await,
+        None => Ok(()),
+    }
+}
+
+#[tokio::test]
+async fn esplora() -> anyhow::Result<()> {
+    match ChainSource::esplora()? {
+        Some(source) => scan_persist_reload_sync("esplora", source).await,
+        None => Ok(()),
+    }
+}